@@ -0,0 +1,81 @@
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// NOTE: Builds from a source tarball have no git, or no repository, to describe.
+const UNKNOWN: &str = "unknown";
+
+fn main() {
+    if Path::new(".git/HEAD").exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        println!("cargo:rerun-if-changed=.git/refs");
+        println!("cargo:rerun-if-changed=.git/index");
+    }
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git = command_output("git", &["describe", "--always", "--dirty", "--tags"]);
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc = command_output(&rustc, &["--version"]);
+    let target = std::env::var("TARGET").unwrap_or_else(|_| UNKNOWN.to_string());
+
+    println!("cargo:rustc-env=CW_GIT_DESCRIBE={}", git);
+    println!("cargo:rustc-env=CW_BUILD_DATE={}", build_date());
+    println!("cargo:rustc-env=CW_TARGET={}", target);
+    println!("cargo:rustc-env=CW_RUSTC_VERSION={}", rustc);
+}
+
+/// The first line `program` prints, `unknown` when it can't run or fails.
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|stdout| stdout.lines().next().map(|line| line.trim().to_string()))
+        .filter(|line| !line.is_empty())
+        .unwrap_or_else(|| UNKNOWN.to_string())
+}
+
+/// The UTC build time as `2024-06-01T12:34:56Z`, taken from SOURCE_DATE_EPOCH when set so
+/// reproducible builds stay reproducible.
+fn build_date() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs())
+        });
+    let Some(seconds) = seconds else {
+        return UNKNOWN.to_string();
+    };
+
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3_600,
+        (time % 3_600) / 60,
+        time % 60
+    )
+}
+
+/// The date `days` after 1970-01-01, ref: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,6 @@
+-- Store what is needed to reproduce a query run
+alter table query_history add column group_names text;
+alter table query_history add column start_time integer;
+alter table query_history add column end_time integer;
+alter table query_history add column profile text;
+alter table query_history add column region text;
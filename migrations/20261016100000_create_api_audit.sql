@@ -0,0 +1,12 @@
+-- Audit trail of the CloudWatch Logs calls cw made, only written when audit.enabled is set
+create table if not exists api_audit (
+    id integer primary key autoincrement,
+    operation text not null,
+    parameters text not null,
+    request_id text,
+    duration_ms integer not null,
+    outcome text not null,
+    created_at timestamp not null
+);
+
+create index if not exists idx_api_audit_created_at on api_audit(created_at);
@@ -0,0 +1,31 @@
+-- Events captured with `cw tail --archive <name>`, searchable after they expired in CloudWatch
+create table if not exists archive_events (
+    id integer primary key autoincrement,
+    archive text not null,
+    group_name text not null,
+    stream_name text,
+    timestamp integer,
+    ingestion_time integer,
+    message text,
+    event_id text,
+    archived_at timestamp not null,
+
+    unique(archive, event_id)
+);
+
+create index if not exists idx_archive_events_archive_timestamp on archive_events(archive, timestamp);
+create index if not exists idx_archive_events_archive_archived_at on archive_events(archive, archived_at);
+
+create virtual table if not exists archive_events_fts using fts5(
+    message,
+    content = 'archive_events',
+    content_rowid = 'id'
+);
+
+create trigger if not exists archive_events_fts_insert after insert on archive_events begin
+    insert into archive_events_fts(rowid, message) values (new.id, new.message);
+end;
+
+create trigger if not exists archive_events_fts_delete after delete on archive_events begin
+    insert into archive_events_fts(archive_events_fts, rowid, message) values ('delete', old.id, old.message);
+end;
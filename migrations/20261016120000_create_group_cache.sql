@@ -0,0 +1,18 @@
+-- Log group names seen by `cw ls groups`, per profile and region
+create table if not exists group_cache (
+    profile text not null,
+    region text not null,
+    group_name text not null,
+    seen_at timestamp not null,
+
+    primary key (profile, region, group_name)
+);
+
+-- When the last full listing of a profile and region completed
+create table if not exists group_cache_refresh (
+    profile text not null,
+    region text not null,
+    refreshed_at timestamp not null,
+
+    primary key (profile, region)
+);
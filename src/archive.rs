@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::db::Database;
+use crate::event::LogEvent;
+
+// NOTE: Events are written in batches, one transaction per event would slow a busy tail down
+// to the speed of the disk.
+const ARCHIVE_BATCH_SIZE: usize = 500;
+const ARCHIVE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where and how long `cw tail --archive` keeps events.
+#[derive(Debug, Clone)]
+pub struct ArchiveSink {
+    pub name: String,
+    pub max_events: u64,
+    pub retention: Duration,
+}
+
+impl ArchiveSink {
+    /// Passes every event from `receiver` on to `sender`, writing them to the archive along the
+    /// way. The archive is pruned before the first event and once all events are in.
+    pub async fn run(
+        self,
+        db: impl Database,
+        mut receiver: UnboundedReceiver<LogEvent>,
+        sender: UnboundedSender<LogEvent>,
+    ) -> eyre::Result<()> {
+        // NOTE: Pruning up front as well, a --follow usually ends with ctrl-c and never gets to
+        // the prune at the end.
+        self.prune(&db).await?;
+
+        let mut batch = Vec::with_capacity(ARCHIVE_BATCH_SIZE);
+        let mut archived = 0;
+
+        loop {
+            let event = if batch.is_empty() {
+                receiver.recv().await
+            } else {
+                match tokio::time::timeout(ARCHIVE_FLUSH_INTERVAL, receiver.recv()).await {
+                    Ok(event) => event,
+                    Err(_) => {
+                        archived += self.save(&db, &mut batch).await?;
+                        continue;
+                    }
+                }
+            };
+
+            let Some(event) = event else {
+                break;
+            };
+
+            batch.push(event.clone());
+            // NOTE: This only errors when the writer is gone, which already failed the command.
+            if sender.send(event).is_err() {
+                break;
+            }
+
+            if batch.len() >= ARCHIVE_BATCH_SIZE {
+                archived += self.save(&db, &mut batch).await?;
+            }
+        }
+
+        archived += self.save(&db, &mut batch).await?;
+        tracing::debug!(target: "cw", "archive {}: wrote {} events", self.name, archived);
+        self.prune(&db).await
+    }
+
+    async fn prune(&self, db: &impl Database) -> eyre::Result<()> {
+        let before = Utc::now() - chrono::TimeDelta::from_std(self.retention)?;
+        let pruned = db
+            .prune_archive(&self.name, self.max_events, before)
+            .await?;
+        tracing::debug!(target: "cw", "archive {}: pruned {} events", self.name, pruned);
+        Ok(())
+    }
+
+    async fn save(&self, db: &impl Database, batch: &mut Vec<LogEvent>) -> eyre::Result<usize> {
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        db.save_archive(&self.name, batch).await?;
+        let count = batch.len();
+        batch.clear();
+        Ok(count)
+    }
+}
@@ -0,0 +1,302 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use eyre::Context;
+use serde_json::{Map, Value};
+
+// NOTE: Enough rows to see what went wrong without turning a CI log into the query result.
+const MAX_FAILURE_ROWS: usize = 5;
+
+/// The most result rows `cw query --assert-max` allows, `--assert-empty` allows none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowAssertion {
+    max: usize,
+}
+
+/// The outcome of a [`RowAssertion`], with the first rows over the limit when it failed.
+#[derive(Debug, Clone)]
+pub struct AssertionOutcome {
+    pub rows: usize,
+    pub max: usize,
+    pub offending: Vec<Map<String, Value>>,
+}
+
+impl RowAssertion {
+    pub fn max(max: usize) -> Self {
+        Self { max }
+    }
+
+    /// An outcome without any rows yet, see [`AssertionOutcome::record`].
+    pub fn start(&self) -> AssertionOutcome {
+        AssertionOutcome {
+            rows: 0,
+            max: self.max,
+            offending: Vec::new(),
+        }
+    }
+}
+
+impl AssertionOutcome {
+    /// Counts `row`, keeping a copy when it is one of the first rows over the limit.
+    pub fn record(&mut self, row: &Map<String, Value>) {
+        self.rows += 1;
+        if self.rows > self.max && self.offending.len() < MAX_FAILURE_ROWS {
+            self.offending.push(row.clone());
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.rows <= self.max
+    }
+
+    pub fn message(&self) -> String {
+        match (self.passed(), self.max) {
+            (true, _) => format!("{} rows, at most {} allowed", self.rows, self.max),
+            (false, 0) => format!("expected no rows, got {}", self.rows),
+            (false, max) => format!("expected at most {} rows, got {}", max, self.rows),
+        }
+    }
+
+    /// The message followed by the first offending rows, one JSON object per line.
+    fn details(&self) -> String {
+        let mut details = self.message();
+        for row in &self.offending {
+            details.push('\n');
+            details.push_str(&Value::Object(row.clone()).to_string());
+        }
+        let more = self
+            .rows
+            .saturating_sub(self.max)
+            .saturating_sub(self.offending.len());
+        if more > 0 {
+            details.push_str(&format!("\n... and {} more", more));
+        }
+        details
+    }
+}
+
+/// Where `--report` writes the assertion outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Report {
+    /// A JUnit XML file with a single test case.
+    Junit(PathBuf),
+    /// GitHub Actions workflow commands on stdout, an `::error::` annotation when it failed.
+    Github,
+}
+
+impl FromStr for Report {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> eyre::Result<Self> {
+        match value.split_once(':') {
+            Some(("junit", path)) if !path.is_empty() => Ok(Self::Junit(PathBuf::from(path))),
+            None if value == "github" => Ok(Self::Github),
+            _ => Err(eyre::eyre!(
+                "Invalid report '{}', expected junit:<path> or github",
+                value
+            )),
+        }
+    }
+}
+
+impl Report {
+    /// Writes `outcome` of the test case `name`, e.g. the query file.
+    pub fn write(&self, name: &str, outcome: &AssertionOutcome) -> eyre::Result<()> {
+        match self {
+            Report::Junit(path) => write_junit(path, name, outcome),
+            Report::Github => {
+                if !outcome.passed() {
+                    println!("{}", github_annotation(name, outcome));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_junit(path: &Path, name: &str, outcome: &AssertionOutcome) -> eyre::Result<()> {
+    std::fs::write(path, junit_xml(name, outcome))
+        .with_context(|| format!("Failed writing the JUnit report to {}", path.display()))
+}
+
+/// A JUnit document with one test suite holding a single test case for the assertion.
+fn junit_xml(name: &str, outcome: &AssertionOutcome) -> String {
+    let failures = usize::from(!outcome.passed());
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuite name=\"cw query\" tests=\"1\" failures=\"{}\">\n",
+        failures
+    ));
+    xml.push_str(&format!(
+        "  <testcase classname=\"cw.query\" name=\"{}\"",
+        escape_xml(name)
+    ));
+    if outcome.passed() {
+        xml.push_str("/>\n");
+    } else {
+        xml.push_str(&format!(
+            ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>\n",
+            escape_xml(&outcome.message()),
+            escape_xml(&outcome.details())
+        ));
+    }
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+/// A GitHub Actions `::error::` workflow command, ref:
+/// https://docs.github.com/en/actions/reference/workflow-commands-for-github-actions#setting-an-error-message
+fn github_annotation(name: &str, outcome: &AssertionOutcome) -> String {
+    format!(
+        "::error title={}::{}",
+        escape_github_property(&format!("cw query {}", name)),
+        escape_github_data(&outcome.details())
+    )
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // NOTE: XML 1.0 has no way to write most control characters, not even escaped.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_github_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_github_property(value: &str) -> String {
+    escape_github_data(value)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+
+    use crate::assertion::{
+        escape_xml, github_annotation, junit_xml, Report, RowAssertion, MAX_FAILURE_ROWS,
+    };
+
+    fn row(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn junit_report_of_a_passed_assertion() {
+        let mut outcome = RowAssertion::max(1).start();
+        outcome.record(&row(json!({"@message": "ok"})));
+
+        assert_eq!(
+            junit_xml("errors.cwql", &outcome),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<testsuite name=\"cw query\" tests=\"1\" failures=\"0\">\n",
+                "  <testcase classname=\"cw.query\" name=\"errors.cwql\"/>\n",
+                "</testsuite>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn junit_report_of_a_failed_assertion_escapes_the_rows() {
+        let mut outcome = RowAssertion::max(0).start();
+        outcome.record(&row(json!({"@message": "<a href='x'>&</a>\u{7}"})));
+
+        assert_eq!(
+            junit_xml("\"errors\" & <more>", &outcome),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<testsuite name=\"cw query\" tests=\"1\" failures=\"1\">\n",
+                "  <testcase classname=\"cw.query\" name=\"&quot;errors&quot; &amp; &lt;more&gt;\">\n",
+                "    <failure message=\"expected no rows, got 1\">expected no rows, got 1\n",
+                "{&quot;@message&quot;:&quot;&lt;a href=&apos;x&apos;&gt;&amp;&lt;/a&gt;\\u0007&quot;}",
+                "</failure>\n",
+                "  </testcase>\n",
+                "</testsuite>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn github_annotation_of_a_failed_assertion_escapes_newlines_and_properties() {
+        let mut outcome = RowAssertion::max(1).start();
+        for i in 0..8 {
+            outcome.record(&row(json!({ "n": i, "text": "50%" })));
+        }
+
+        assert_eq!(
+            github_annotation("a:b,c.cwql", &outcome),
+            concat!(
+                "::error title=cw query a%3Ab%2Cc.cwql::expected at most 1 rows, got 8",
+                "%0A{\"n\":1,\"text\":\"50%25\"}",
+                "%0A{\"n\":2,\"text\":\"50%25\"}",
+                "%0A{\"n\":3,\"text\":\"50%25\"}",
+                "%0A{\"n\":4,\"text\":\"50%25\"}",
+                "%0A{\"n\":5,\"text\":\"50%25\"}",
+                "%0A... and 2 more",
+            )
+        );
+    }
+
+    #[test]
+    fn only_the_first_offending_rows_are_kept_however_wide_the_result() {
+        const WIDTH: usize = 16 * 1024;
+        let mut outcome = RowAssertion::max(10).start();
+        // NOTE: Every row is built and dropped in turn, like the rows streamed out of a query.
+        for i in 0..10_000 {
+            let message = format!("{:0>width$}", i, width = WIDTH);
+            outcome.record(&row(json!({ "@message": message })));
+        }
+
+        let held: usize = outcome
+            .offending
+            .iter()
+            .map(|row| row["@message"].as_str().map_or(0, str::len))
+            .sum();
+        assert_eq!(outcome.rows, 10_000);
+        assert_eq!(outcome.offending.len(), MAX_FAILURE_ROWS);
+        assert_eq!(held, MAX_FAILURE_ROWS * WIDTH);
+        assert!(outcome.offending[0]["@message"]
+            .as_str()
+            .is_some_and(|m| m.ends_with("10")));
+    }
+
+    #[test]
+    fn escape_xml_drops_control_characters_xml_cannot_hold() {
+        assert_eq!(escape_xml("a\u{7}\u{1b}[0m\tb\r\n"), "a[0m\tb\r\n");
+    }
+
+    #[test]
+    fn passed_assertion_message() {
+        let outcome = RowAssertion::max(3).start();
+
+        assert!(outcome.passed());
+        assert_eq!(outcome.message(), "0 rows, at most 3 allowed");
+    }
+
+    #[test]
+    fn report_parses_junit_and_github() {
+        assert_eq!(
+            "junit:out/report.xml".parse::<Report>().unwrap(),
+            Report::Junit("out/report.xml".into())
+        );
+        assert_eq!("github".parse::<Report>().unwrap(), Report::Github);
+        assert!("junit:".parse::<Report>().is_err());
+        assert!("github:x".parse::<Report>().is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use aws_sdk_cloudwatchlogs::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_cloudwatchlogs::operation::delete_retention_policy::{
+    DeleteRetentionPolicyError, DeleteRetentionPolicyOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::describe_log_groups::{
+    DescribeLogGroupsError, DescribeLogGroupsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::describe_log_streams::{
+    DescribeLogStreamsError, DescribeLogStreamsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::filter_log_events::{
+    FilterLogEventsError, FilterLogEventsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::get_log_group_fields::{
+    GetLogGroupFieldsError, GetLogGroupFieldsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::get_query_results::{
+    GetQueryResultsError, GetQueryResultsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::put_retention_policy::{
+    PutRetentionPolicyError, PutRetentionPolicyOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::start_query::{StartQueryError, StartQueryOutput};
+use aws_sdk_cloudwatchlogs::operation::stop_query::{StopQueryError, StopQueryOutput};
+use aws_sdk_cloudwatchlogs::operation::RequestId;
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use crate::db::{AuditEntry, Database};
+use crate::logs_api::{
+    DescribeLogGroupsRequest, DescribeLogStreamsRequest, FilterLogEventsRequest, LogsApi,
+    StartQueryRequest,
+};
+
+/// Collects the calls made through an [`AuditedClient`] while a command runs, they are written
+/// to the database in one go once it is done.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLog {
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, entry: AuditEntry) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push(entry);
+        }
+    }
+
+    /// Writes the collected calls to `db` and removes the ones older than `retention`.
+    pub async fn persist(&self, db: &impl Database, retention: Duration) -> eyre::Result<()> {
+        let entries = match self.entries.lock() {
+            Ok(mut entries) => std::mem::take(&mut *entries),
+            Err(_) => return Err(eyre::eyre!("The audit log was poisoned")),
+        };
+
+        db.save_audit(&entries).await?;
+        let pruned = db
+            .prune_audit(Utc::now() - chrono::TimeDelta::from_std(retention)?)
+            .await?;
+        tracing::debug!(
+            target: "cw",
+            "audit: recorded {} calls, pruned {}",
+            entries.len(),
+            pruned
+        );
+        Ok(())
+    }
+}
+
+/// A [`LogsApi`] that records every call into an [`AuditLog`], when one is set, before handing
+/// back the result of `inner`.
+#[derive(Debug, Clone)]
+pub struct AuditedClient<C> {
+    inner: C,
+    audit_log: Option<AuditLog>,
+}
+
+impl<C: LogsApi> AuditedClient<C> {
+    pub fn new(inner: C, audit_log: Option<AuditLog>) -> Self {
+        Self { inner, audit_log }
+    }
+
+    fn audited<T, E>(
+        &self,
+        operation: &'static str,
+        parameters: Value,
+        call: impl Future<Output = Result<T, SdkError<E>>> + Send,
+    ) -> impl Future<Output = Result<T, SdkError<E>>> + Send
+    where
+        T: RequestId + Send,
+        E: ProvideErrorMetadata + Send,
+    {
+        let audit_log = self.audit_log.clone();
+        async move {
+            let Some(audit_log) = audit_log else {
+                return call.await;
+            };
+
+            let created_at = Utc::now();
+            let started = Instant::now();
+            let result = call.await;
+
+            let (request_id, outcome) = match &result {
+                Ok(output) => (output.request_id(), "ok".to_string()),
+                Err(err) => (err.request_id(), err.code().unwrap_or("error").to_string()),
+            };
+            audit_log.record(AuditEntry {
+                operation: operation.to_string(),
+                parameters: parameters.to_string(),
+                request_id: request_id.map(str::to_string),
+                duration_ms: i64::try_from(started.elapsed().as_millis()).unwrap_or(i64::MAX),
+                outcome,
+                created_at,
+            });
+
+            result
+        }
+    }
+}
+
+// NOTE: Filter patterns and query strings are left out of the parameters on purpose, they can
+// hold the very values someone was looking for.
+impl<C: LogsApi> LogsApi for AuditedClient<C> {
+    fn region(&self) -> Option<String> {
+        self.inner.region()
+    }
+
+    fn filter_log_events(
+        &self,
+        request: FilterLogEventsRequest,
+    ) -> impl Future<Output = Result<FilterLogEventsOutput, SdkError<FilterLogEventsError>>> + Send
+    {
+        let parameters = json!({
+            "log_group_name": request.group_name,
+            "log_stream_name_prefix": request.stream_name_prefix,
+            "start_time": request.start_time,
+            "end_time": request.end_time,
+        });
+        self.audited(
+            "FilterLogEvents",
+            parameters,
+            self.inner.filter_log_events(request),
+        )
+    }
+
+    fn describe_log_groups(
+        &self,
+        request: DescribeLogGroupsRequest,
+    ) -> impl Future<Output = Result<DescribeLogGroupsOutput, SdkError<DescribeLogGroupsError>>> + Send
+    {
+        let parameters = json!({
+            "log_group_name_prefix": request.name_prefix,
+            "log_group_name_pattern": request.name_pattern,
+            "include_linked_accounts": request.include_linked_accounts,
+        });
+        self.audited(
+            "DescribeLogGroups",
+            parameters,
+            self.inner.describe_log_groups(request),
+        )
+    }
+
+    fn describe_log_streams(
+        &self,
+        request: DescribeLogStreamsRequest,
+    ) -> impl Future<Output = Result<DescribeLogStreamsOutput, SdkError<DescribeLogStreamsError>>> + Send
+    {
+        let parameters = json!({ "log_group_identifier": request.group_identifier });
+        self.audited(
+            "DescribeLogStreams",
+            parameters,
+            self.inner.describe_log_streams(request),
+        )
+    }
+
+    fn start_query(
+        &self,
+        request: StartQueryRequest,
+    ) -> impl Future<Output = Result<StartQueryOutput, SdkError<StartQueryError>>> + Send {
+        let parameters = json!({
+            "log_group_names": request.group_names,
+            "start_time": request.start_time,
+            "end_time": request.end_time,
+        });
+        self.audited("StartQuery", parameters, self.inner.start_query(request))
+    }
+
+    fn get_query_results(
+        &self,
+        query_id: &str,
+    ) -> impl Future<Output = Result<GetQueryResultsOutput, SdkError<GetQueryResultsError>>> + Send
+    {
+        let parameters = json!({ "query_id": query_id });
+        self.audited(
+            "GetQueryResults",
+            parameters,
+            self.inner.get_query_results(query_id),
+        )
+    }
+
+    fn stop_query(
+        &self,
+        query_id: &str,
+    ) -> impl Future<Output = Result<StopQueryOutput, SdkError<StopQueryError>>> + Send {
+        let parameters = json!({ "query_id": query_id });
+        self.audited("StopQuery", parameters, self.inner.stop_query(query_id))
+    }
+
+    fn get_log_group_fields(
+        &self,
+        group_identifier: &str,
+    ) -> impl Future<Output = Result<GetLogGroupFieldsOutput, SdkError<GetLogGroupFieldsError>>> + Send
+    {
+        let parameters = json!({ "log_group_identifier": group_identifier });
+        self.audited(
+            "GetLogGroupFields",
+            parameters,
+            self.inner.get_log_group_fields(group_identifier),
+        )
+    }
+
+    fn put_retention_policy(
+        &self,
+        group_name: &str,
+        days: i32,
+    ) -> impl Future<Output = Result<PutRetentionPolicyOutput, SdkError<PutRetentionPolicyError>>> + Send
+    {
+        let parameters = json!({ "log_group_name": group_name, "retention_in_days": days });
+        self.audited(
+            "PutRetentionPolicy",
+            parameters,
+            self.inner.put_retention_policy(group_name, days),
+        )
+    }
+
+    fn delete_retention_policy(
+        &self,
+        group_name: &str,
+    ) -> impl Future<
+        Output = Result<DeleteRetentionPolicyOutput, SdkError<DeleteRetentionPolicyError>>,
+    > + Send {
+        let parameters = json!({ "log_group_name": group_name });
+        self.audited(
+            "DeleteRetentionPolicy",
+            parameters,
+            self.inner.delete_retention_policy(group_name),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aws_sdk_cloudwatchlogs::operation::filter_log_events::{
+        FilterLogEventsError, FilterLogEventsOutput,
+    };
+    use chrono::{TimeDelta, Utc};
+
+    use crate::audit::{AuditLog, AuditedClient};
+    use crate::db::{Database, Sqlite};
+    use crate::fake_logs::{service_error, FakeLogs};
+    use crate::logs_api::{FilterLogEventsRequest, LogsApi};
+
+    fn request() -> FilterLogEventsRequest {
+        FilterLogEventsRequest {
+            group_name: "/ecs/api".to_string(),
+            filter_pattern: Some("password=hunter2".to_string()),
+            start_time: Some(1_000),
+            end_time: Some(2_000),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_are_recorded_with_their_outcome_but_without_the_filter() {
+        let logs = FakeLogs::new();
+        logs.push_filter_log_events(Ok(FilterLogEventsOutput::builder().build()))
+            .push_filter_log_events(Err(service_error::<FilterLogEventsError>(
+                "AccessDeniedException",
+                "Not allowed.",
+            )));
+        let audit_log = AuditLog::new();
+        let client = AuditedClient::new(logs.clone(), Some(audit_log.clone()));
+
+        client.filter_log_events(request()).await.unwrap();
+        client.filter_log_events(request()).await.unwrap_err();
+
+        let db = Sqlite::in_memory().await.unwrap();
+        audit_log
+            .persist(&db, Duration::from_secs(3600))
+            .await
+            .unwrap();
+        let entries = db
+            .list_audit(Utc::now() - TimeDelta::minutes(1))
+            .await
+            .unwrap();
+        let outcomes: Vec<_> = entries.iter().map(|e| e.outcome.as_str()).collect();
+        assert_eq!(outcomes, ["ok", "AccessDeniedException"]);
+        assert!(entries.iter().all(|e| e.operation == "FilterLogEvents"));
+        assert_eq!(
+            entries[0].parameters,
+            r#"{"end_time":2000,"log_group_name":"/ecs/api","log_stream_name_prefix":null,"start_time":1000}"#
+        );
+        assert!(!entries[1].parameters.contains("hunter2"));
+        assert_eq!(logs.filter_log_events_requests(), [request(), request()]);
+    }
+
+    #[tokio::test]
+    async fn without_an_audit_log_calls_go_straight_through() {
+        let logs = FakeLogs::new();
+        let client = AuditedClient::new(logs.clone(), None);
+
+        client.filter_log_events(request()).await.unwrap();
+
+        assert_eq!(logs.filter_log_events_requests().len(), 1);
+    }
+}
@@ -1,137 +1,92 @@
 use std::fs;
-use std::sync::OnceLock;
+use std::sync::Arc;
 
 use aws_config::{retry::RetryConfig, Region};
 use aws_config::{AppName, BehaviorVersion, SdkConfig};
 use aws_sdk_cloudwatchlogs as cloudwatchlogs;
+use aws_sdk_cloudwatchlogs::types::LogGroup;
 use aws_sdk_sts as sts;
 use aws_smithy_http_client::proxy::ProxyConfig;
 use aws_smithy_http_client::tls::{self, TlsContext, TrustStore};
-use aws_smithy_http_client::{Builder, ConnectorBuilder};
+use aws_smithy_http_client::{Builder, Connector, ConnectorBuilder};
 use eyre::Context;
+use tokio::sync::OnceCell;
 
-trait AwsClient {
-    fn cw(&self) -> &cloudwatchlogs::Client;
-    fn sts(&self) -> &sts::Client;
-}
+use crate::audit::{AuditLog, AuditedClient};
+use crate::clock_skew::{ClockSkew, ClockSkewInterceptor};
+use crate::connection_stats::{ConnectionReuseInterceptor, ConnectionStats};
+use crate::logs_api::{DescribeLogGroupsRequest, LogsApi};
+use crate::metrics::{Metrics, MetricsInterceptor};
+use crate::progress_reporter::{NoopReporter, ProgressReporter};
+use crate::rate_limit::RateLimiter;
 
-pub struct DefaultAwsClient {
-    config: SdkConfig,
-    cw_client: OnceLock<cloudwatchlogs::Client>,
-    sts_client: OnceLock<sts::Client>,
-}
+/// The CloudWatch Logs client commands run against.
+pub type LogsClient = AuditedClient<cloudwatchlogs::Client>;
 
-impl DefaultAwsClient {
-    pub fn new(config: SdkConfig) -> Self {
-        Self {
-            config,
-            cw_client: OnceLock::new(),
-            sts_client: OnceLock::new(),
-        }
-    }
-}
-
-impl AwsClient for DefaultAwsClient {
-    fn cw(&self) -> &aws_sdk_cloudwatchlogs::Client {
-        self.cw_client
-            .get_or_init(|| cloudwatchlogs::Client::new(&self.config))
-    }
-
-    fn sts(&self) -> &sts::Client {
-        self.sts_client
-            .get_or_init(|| sts::Client::new(&self.config))
-    }
-}
-
-pub struct AwsClientBuilder {
+pub struct LogClientBuilder {
     profile_name: Option<String>,
     region: Option<String>,
     retry_config: RetryConfig,
+    connection_stats: ConnectionStats,
+    clock_skew: ClockSkew,
+    metrics: Metrics,
+    audit_log: Option<AuditLog>,
+    rate_limiter: Arc<RateLimiter>,
+    reporter: Arc<dyn ProgressReporter>,
+    sdk_config: OnceCell<SdkConfig>,
 }
 
-impl AwsClientBuilder {
+impl LogClientBuilder {
     pub fn new() -> Self {
-        AwsClientBuilder {
+        LogClientBuilder {
             profile_name: None,
             region: None,
             retry_config: RetryConfig::standard(),
+            connection_stats: ConnectionStats::new(),
+            clock_skew: ClockSkew::new(),
+            metrics: Metrics::new(),
+            audit_log: None,
+            rate_limiter: Arc::new(RateLimiter::default()),
+            reporter: Arc::new(NoopReporter),
+            sdk_config: OnceCell::new(),
         }
     }
 
-    pub fn use_profile_name(mut self, profile_name: Option<String>) -> Self {
-        self.profile_name = profile_name;
+    pub fn use_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = rate_limiter;
         self
     }
 
-    pub fn use_region(mut self, region: Option<String>) -> Self {
-        self.region = region;
-        self
+    /// The limiter every CloudWatch Logs call made through this builder's clients should await.
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.rate_limiter.clone()
     }
 
-    pub async fn build(&self) -> eyre::Result<DefaultAwsClient> {
-        let mut config_builder = aws_config::from_env()
-            .retry_config(self.retry_config.clone())
-            .behavior_version(BehaviorVersion::latest());
-
-        if let Some(profile_name) = &self.profile_name {
-            config_builder = config_builder.profile_name(profile_name);
-        }
-
-        if let Some(region) = &self.region {
-            config_builder = config_builder.region(Region::new(region.clone()));
-        }
-
-        let mut store = TrustStore::empty().with_native_roots(true);
-        if let Some(cert_bytes) = std::env::var("AWS_CA_BUNDLE")
-            .ok()
-            .map(|a| fs::read(&a).context(format!("Failed reading AWS_CA_BUNDLE: {}", &a)))
-            .transpose()?
-        {
-            store = store.with_pem_certificate(cert_bytes);
-        }
-        let context = TlsContext::builder().with_trust_store(store).build()?;
-
-        let http_client =
-            Builder::new().build_with_connector_fn(move |settings, runtime_components| {
-                let mut conn_builder = ConnectorBuilder::default()
-                    .tls_provider(tls::Provider::Rustls(
-                        tls::rustls_provider::CryptoMode::AwsLc,
-                    ))
-                    .tls_context(context.clone());
-
-                conn_builder.set_connector_settings(settings.cloned());
-                if let Some(components) = runtime_components {
-                    conn_builder.set_sleep_impl(components.sleep_impl());
-                }
-
-                conn_builder.set_proxy_config(Some(ProxyConfig::from_env()));
-                conn_builder.build()
-            });
+    /// The clock skew measured from the first response of this builder's clients.
+    pub(crate) fn clock_skew(&self) -> ClockSkew {
+        self.clock_skew.clone()
+    }
 
-        let config = config_builder
-            .app_name(AppName::new("cw").unwrap())
-            .http_client(http_client)
-            .load()
-            .await;
+    /// The API call counters of this builder's clients, see [`Metrics`].
+    pub(crate) fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
 
-        let client = DefaultAwsClient::new(config);
-        Ok(client)
+    /// Reports the retries and throttled responses of this builder's clients.
+    pub(crate) fn use_progress_reporter(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.reporter = reporter;
+        self
     }
-}
 
-pub struct LogClientBuilder {
-    profile_name: Option<String>,
-    region: Option<String>,
-    retry_config: RetryConfig,
-}
+    /// Records every call made through this builder's clients, see [`AuditedClient`].
+    pub(crate) fn use_audit_log(mut self, audit_log: Option<AuditLog>) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
 
-impl LogClientBuilder {
-    pub fn new() -> Self {
-        LogClientBuilder {
-            profile_name: None,
-            region: None,
-            retry_config: RetryConfig::standard(),
-        }
+    pub(crate) fn use_connection_stats(mut self, connection_stats: ConnectionStats) -> Self {
+        self.connection_stats = connection_stats;
+        self
     }
 
     pub fn use_profile_name(mut self, profile_name: Option<String>) -> Self {
@@ -144,7 +99,48 @@ impl LogClientBuilder {
         self
     }
 
-    pub async fn build(&self) -> eyre::Result<cloudwatchlogs::Client> {
+    /// The profile used to load credentials, either passed explicitly or taken from AWS_PROFILE.
+    pub fn profile_name(&self) -> Option<String> {
+        self.profile_name
+            .clone()
+            .or_else(|| std::env::var("AWS_PROFILE").ok())
+    }
+
+    pub async fn build(&self) -> eyre::Result<LogsClient> {
+        let config = self.build_sdk_config().await?;
+        Ok(self.client(&config))
+    }
+
+    /// Creates a logs client for `config` that records connection reuse, clock skew and call
+    /// counts into the shared stats, and its calls into the audit log when there is one.
+    pub(crate) fn client(&self, config: &SdkConfig) -> LogsClient {
+        let config = cloudwatchlogs::config::Builder::from(config)
+            .interceptor(ConnectionReuseInterceptor::new(
+                self.connection_stats.clone(),
+            ))
+            .interceptor(ClockSkewInterceptor::new(self.clock_skew.clone()))
+            .interceptor(MetricsInterceptor::new(
+                self.metrics.clone(),
+                self.reporter.clone(),
+            ))
+            .build();
+        AuditedClient::new(
+            cloudwatchlogs::Client::from_conf(config),
+            self.audit_log.clone(),
+        )
+    }
+
+    /// The SDK config for this builder's profile and region. It is loaded on the first call and
+    /// cloned after that, so every client built in one run shares a single credentials cache.
+    pub(crate) async fn build_sdk_config(&self) -> eyre::Result<SdkConfig> {
+        self.sdk_config
+            .get_or_try_init(|| self.load_sdk_config())
+            .await
+            .cloned()
+    }
+
+    async fn load_sdk_config(&self) -> eyre::Result<SdkConfig> {
+        tracing::debug!(target: "cw", "loading the AWS SDK config");
         let mut config_builder = aws_config::from_env()
             .retry_config(self.retry_config.clone())
             .behavior_version(BehaviorVersion::latest());
@@ -157,15 +153,7 @@ impl LogClientBuilder {
             config_builder = config_builder.region(Region::new(region.clone()));
         }
 
-        let mut store = TrustStore::empty().with_native_roots(true);
-        if let Some(cert_bytes) = std::env::var("AWS_CA_BUNDLE")
-            .ok()
-            .map(|a| fs::read(&a).context(format!("Failed reading AWS_CA_BUNDLE: {}", &a)))
-            .transpose()?
-        {
-            store = store.with_pem_certificate(cert_bytes);
-        }
-        let context = TlsContext::builder().with_trust_store(store).build()?;
+        let context = tls_context()?;
 
         let http_client =
             Builder::new().build_with_connector_fn(move |settings, runtime_components| {
@@ -185,12 +173,144 @@ impl LogClientBuilder {
             });
 
         let config = config_builder
-            .app_name(AppName::new("cw").unwrap())
+            .app_name(AppName::new("cw")?)
             .http_client(http_client)
             .load()
             .await;
 
-        let client = cloudwatchlogs::Client::new(&config);
-        Ok(client)
+        Ok(config)
+    }
+}
+
+/// Trusts the native roots, plus the certificate in AWS_CA_BUNDLE when it is set.
+fn tls_context() -> eyre::Result<TlsContext> {
+    let mut store = TrustStore::empty().with_native_roots(true);
+    if let Some(cert_bytes) = std::env::var("AWS_CA_BUNDLE")
+        .ok()
+        .map(|a| fs::read(&a).context(format!("Failed reading AWS_CA_BUNDLE: {}", &a)))
+        .transpose()?
+    {
+        store = store.with_pem_certificate(cert_bytes);
+    }
+    Ok(TlsContext::builder().with_trust_store(store).build()?)
+}
+
+/// A connector for requests outside the AWS SDK, with the TLS and proxy settings the SDK uses.
+pub(crate) fn https_connector() -> eyre::Result<Connector> {
+    Ok(ConnectorBuilder::default()
+        .tls_provider(tls::Provider::Rustls(
+            tls::rustls_provider::CryptoMode::AwsLc,
+        ))
+        .tls_context(tls_context()?)
+        .proxy_config(ProxyConfig::from_env())
+        .build())
+}
+
+/// Looks up a single log group by its exact name or ARN, including the groups of linked source
+/// accounts. A name several accounts share has to be narrowed down with `account_id`, or be
+/// passed as the ARN of one of them.
+pub(crate) async fn resolve_log_group(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    identifier: &str,
+    account_id: Option<&str>,
+) -> eyre::Result<LogGroup> {
+    let arn = identifier
+        .starts_with("arn:")
+        .then(|| identifier.trim_end_matches(":*"));
+    let group_name = match arn {
+        Some(arn) => arn
+            .split_once(":log-group:")
+            .map(|(_, name)| name)
+            .ok_or_else(|| eyre::eyre!("{} is not a log group ARN", identifier))?,
+        None => identifier,
+    };
+
+    limiter.acquire().await;
+    let response = client
+        .describe_log_groups(DescribeLogGroupsRequest {
+            name_prefix: Some(group_name.to_string()),
+            include_linked_accounts: Some(true),
+            ..Default::default()
+        })
+        .await
+        .wrap_err_with(|| format!("Failed describing log group {}.", identifier))?;
+
+    let candidates = response
+        .log_groups
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|group| group.log_group_name() == Some(group_name))
+        .filter(|group| arn.is_none_or(|arn| group.log_group_arn() == Some(arn)))
+        .filter(|group| account_id.is_none_or(|id| group_account_id(group) == Some(id)))
+        .collect();
+
+    pick_log_group(identifier, candidates)
+}
+
+/// The only group in `candidates`, an error listing every account when there are several.
+fn pick_log_group(identifier: &str, mut candidates: Vec<LogGroup>) -> eyre::Result<LogGroup> {
+    match candidates.len() {
+        0 => Err(eyre::eyre!("Can't find log group with name {}", identifier)),
+        1 => Ok(candidates.remove(0)),
+        _ => {
+            let matches = candidates
+                .iter()
+                .map(|group| {
+                    format!(
+                        "  {}\t{}",
+                        group_account_id(group).unwrap_or("unknown"),
+                        group.log_group_arn().unwrap_or_default()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Err(eyre::eyre!(
+                "{} exists in {} accounts, pass --account-id or the ARN of one of them:\n{}",
+                identifier,
+                candidates.len(),
+                matches
+            ))
+        }
     }
 }
+
+/// The account id of a log group, the fifth field of its ARN.
+fn group_account_id(group: &LogGroup) -> Option<&str> {
+    group.log_group_arn()?.split(':').nth(4)
+}
+
+/// Looks up a single log group by its exact name, `None` when there is no such group.
+pub(crate) async fn find_log_group(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    group_name: &str,
+) -> eyre::Result<Option<LogGroup>> {
+    limiter.acquire().await;
+    let response = client
+        .describe_log_groups(DescribeLogGroupsRequest {
+            name_prefix: Some(group_name.to_string()),
+            ..Default::default()
+        })
+        .await
+        .wrap_err_with(|| format!("Failed describing log group {}.", group_name))?;
+
+    Ok(response
+        .log_groups
+        .unwrap_or_default()
+        .into_iter()
+        .find(|l| l.log_group_name() == Some(group_name)))
+}
+
+/// Resolves the account id of the caller with a single STS GetCallerIdentity call.
+pub(crate) async fn caller_account(config: &SdkConfig) -> eyre::Result<String> {
+    let identity = sts::Client::new(config)
+        .get_caller_identity()
+        .send()
+        .await
+        .context("Failed resolving the caller identity.")?;
+
+    identity
+        .account
+        .ok_or_else(|| eyre::eyre!("GetCallerIdentity did not return an account id."))
+}
@@ -0,0 +1,56 @@
+// NOTE: build.rs captures these to tell nightly builds of the same version apart. A value it
+// couldn't determine is `unknown`, e.g. the git commit of a build from a source tarball.
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// `git describe --always --dirty --tags` of the checkout that was built.
+pub const GIT_DESCRIBE: &str = env!("CW_GIT_DESCRIBE");
+/// When the build ran in UTC, or SOURCE_DATE_EPOCH when that was set.
+pub const BUILD_DATE: &str = env!("CW_BUILD_DATE");
+pub const TARGET: &str = env!("CW_TARGET");
+pub const RUSTC_VERSION: &str = env!("CW_RUSTC_VERSION");
+
+/// What `cw --version` prints, `cw -V` only prints the version.
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "\ncommit: ",
+    env!("CW_GIT_DESCRIBE"),
+    "\nbuilt:  ",
+    env!("CW_BUILD_DATE"),
+    "\ntarget: ",
+    env!("CW_TARGET"),
+    "\nrustc:  ",
+    env!("CW_RUSTC_VERSION"),
+);
+
+/// Everything on one line, for log lines and crash reports.
+pub fn summary() -> String {
+    format!(
+        "cw {} ({}, built {}, {}, {})",
+        VERSION, GIT_DESCRIBE, BUILD_DATE, TARGET, RUSTC_VERSION
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_info::{
+        summary, BUILD_DATE, GIT_DESCRIBE, LONG_VERSION, RUSTC_VERSION, TARGET, VERSION,
+    };
+
+    #[test]
+    fn build_metadata_is_filled_in_and_printed() {
+        for value in [GIT_DESCRIBE, TARGET, RUSTC_VERSION] {
+            assert!(!value.is_empty());
+        }
+        assert!(
+            BUILD_DATE == "unknown" || chrono::DateTime::parse_from_rfc3339(BUILD_DATE).is_ok(),
+            "{}",
+            BUILD_DATE
+        );
+
+        let lines: Vec<_> = LONG_VERSION.lines().collect();
+        assert_eq!(lines[0], VERSION);
+        assert_eq!(lines[2], format!("built:  {}", BUILD_DATE));
+        assert_eq!(lines.len(), 5);
+        assert!(summary().starts_with(&format!("cw {} ({}, ", VERSION, GIT_DESCRIBE)));
+    }
+}
@@ -0,0 +1,318 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use eyre::Context;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// When `cw query --output-dir` starts the next part file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkLimit {
+    /// At most this many rows per part.
+    Rows(u64),
+    /// At most this many bytes per part, a single row larger than that gets a part of its own.
+    Bytes(u64),
+}
+
+impl ChunkLimit {
+    fn is_full(&self, part: &Part, next_len: u64) -> bool {
+        if part.rows == 0 {
+            return false;
+        }
+        match *self {
+            Self::Rows(max) => part.rows >= max,
+            Self::Bytes(max) => part.bytes + next_len > max,
+        }
+    }
+}
+
+/// Parses a size like `512`, `64K`, `100MiB` or `1GB`, units are binary.
+pub fn parse_byte_size(value: &str) -> eyre::Result<u64> {
+    let invalid = || {
+        eyre::eyre!(
+            "Invalid size '{}', expected a number of bytes with an optional K, M or G unit",
+            value
+        )
+    };
+    let value = value.trim();
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    match number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+    {
+        Some(bytes) if bytes > 0 => Ok(bytes),
+        _ => Err(invalid()),
+    }
+}
+
+/// The part file rows are being written to.
+struct Part {
+    file_name: String,
+    writer: BufWriter<File>,
+    hasher: Sha256,
+    rows: u64,
+    bytes: u64,
+}
+
+/// Writes result rows to `part-00000.jsonl`, `part-00001.jsonl`, ... in a directory, starting
+/// the next part once the current one reaches its [`ChunkLimit`]. A part is closed as soon as it
+/// is full, only the open one is buffered. The `manifest.json` listing every part is written
+/// last, a directory without one holds an export that didn't finish.
+pub struct ChunkedExport {
+    dir: PathBuf,
+    limit: ChunkLimit,
+    query: String,
+    window: Value,
+    current: Option<Part>,
+    parts: Vec<Value>,
+}
+
+impl ChunkedExport {
+    /// Starts an export of the rows `query` found between `start_time` and `end_time`, in epoch
+    /// milliseconds, into `dir`. The directory is created when missing and has to be empty, so
+    /// parts of an earlier export never end up next to the new ones.
+    pub fn create(
+        dir: &Path,
+        limit: ChunkLimit,
+        query: &str,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    ) -> eyre::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed creating {}", dir.display()))?;
+        let mut entries =
+            std::fs::read_dir(dir).with_context(|| format!("Failed reading {}", dir.display()))?;
+        if entries.next().is_some() {
+            return Err(eyre::eyre!(
+                "--output-dir {} isn't empty, pick a new directory for every export",
+                dir.display()
+            ));
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            limit,
+            query: query.to_string(),
+            window: json!({ "start_time": start_time, "end_time": end_time }),
+            current: None,
+            parts: Vec::new(),
+        })
+    }
+
+    pub fn write_row(&mut self, row: &Map<String, Value>) -> eyre::Result<()> {
+        let mut line = serde_json::to_vec(row)?;
+        line.push(b'\n');
+        let len = line.len() as u64;
+
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|part| self.limit.is_full(part, len))
+        {
+            self.close_part()?;
+        }
+        if self.current.is_none() {
+            self.current = Some(self.open_part()?);
+        }
+        let Some(part) = self.current.as_mut() else {
+            return Ok(());
+        };
+
+        part.writer.write_all(&line).with_context(|| {
+            format!(
+                "Failed writing to {}",
+                self.dir.join(&part.file_name).display()
+            )
+        })?;
+        part.hasher.update(&line);
+        part.rows += 1;
+        part.bytes += len;
+        Ok(())
+    }
+
+    /// Closes the last part and writes the manifest, returning its path.
+    pub fn finish(mut self) -> eyre::Result<PathBuf> {
+        self.close_part()?;
+
+        let rows: u64 = self
+            .parts
+            .iter()
+            .filter_map(|part| part["rows"].as_u64())
+            .sum();
+        let manifest = json!({
+            "query": self.query,
+            "window": self.window,
+            "rows": rows,
+            "parts": self.parts,
+        });
+
+        // NOTE: Written next to it first, a half written manifest would claim a finished export.
+        let path = self.dir.join(MANIFEST_FILE);
+        let temp_path = self.dir.join(format!(".{}.tmp", MANIFEST_FILE));
+        std::fs::write(&temp_path, serde_json::to_vec_pretty(&manifest)?)
+            .with_context(|| format!("Failed writing {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, &path).with_context(|| {
+            format!(
+                "Failed moving {} to {}",
+                temp_path.display(),
+                path.display()
+            )
+        })?;
+        Ok(path)
+    }
+
+    fn open_part(&self) -> eyre::Result<Part> {
+        let file_name = format!("part-{:05}.jsonl", self.parts.len());
+        let path = self.dir.join(&file_name);
+        let file =
+            File::create(&path).with_context(|| format!("Failed creating {}", path.display()))?;
+        Ok(Part {
+            file_name,
+            writer: BufWriter::new(file),
+            hasher: Sha256::new(),
+            rows: 0,
+            bytes: 0,
+        })
+    }
+
+    fn close_part(&mut self) -> eyre::Result<()> {
+        let Some(part) = self.current.take() else {
+            return Ok(());
+        };
+        let path = self.dir.join(&part.file_name);
+        part.writer
+            .into_inner()
+            .map_err(|err| err.into_error())
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Failed writing to {}", path.display()))?;
+
+        self.parts.push(json!({
+            "file": part.file_name,
+            "rows": part.rows,
+            "bytes": part.bytes,
+            "sha256": format!("{:x}", part.hasher.finalize()),
+            "window": self.window,
+        }));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::{json, Map, Value};
+
+    use crate::chunked_export::{parse_byte_size, ChunkLimit, ChunkedExport};
+
+    fn export_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cw-export-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn row(message: &str) -> Map<String, Value> {
+        json!({ "@message": message }).as_object().unwrap().clone()
+    }
+
+    fn export(name: &str, limit: ChunkLimit, messages: &[&str]) -> Value {
+        let dir = export_dir(name);
+        let mut export =
+            ChunkedExport::create(&dir, limit, "fields @message", Some(0), None).unwrap();
+        for message in messages {
+            export.write_row(&row(message)).unwrap();
+        }
+        let manifest_path = export.finish().unwrap();
+        let manifest = serde_json::from_slice(&std::fs::read(manifest_path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        manifest
+    }
+
+    fn part_rows(manifest: &Value) -> Vec<u64> {
+        manifest["parts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|part| part["rows"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_byte_size_reads_binary_units() {
+        assert_eq!(parse_byte_size("512").unwrap(), 512);
+        assert_eq!(parse_byte_size("512B").unwrap(), 512);
+        assert_eq!(parse_byte_size("64K").unwrap(), 64 * 1024);
+        assert_eq!(parse_byte_size("100MiB").unwrap(), 100 * 1024 * 1024);
+        assert_eq!(parse_byte_size(" 1 gb ").unwrap(), 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn parse_byte_size_refuses_invalid_sizes() {
+        for value in [
+            "",
+            "0",
+            "0K",
+            "K",
+            "1.5M",
+            "-1",
+            "12T",
+            "99999999999999999999G",
+        ] {
+            assert!(parse_byte_size(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn row_limit_starts_a_part_every_n_rows() {
+        let manifest = export("rows", ChunkLimit::Rows(2), &["a", "b", "c", "d", "e"]);
+        assert_eq!(part_rows(&manifest), vec![2, 2, 1]);
+        assert_eq!(manifest["rows"], 5);
+        assert_eq!(manifest["parts"][2]["file"], "part-00002.jsonl");
+    }
+
+    #[test]
+    fn byte_limit_keeps_parts_under_the_size() {
+        // NOTE: Every row is `{"@message":"x"}` and a newline, 17 bytes.
+        let manifest = export("bytes", ChunkLimit::Bytes(40), &["a", "b", "c", "d", "e"]);
+        assert_eq!(part_rows(&manifest), vec![2, 2, 1]);
+        assert!(manifest["parts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|part| part["bytes"].as_u64().unwrap() <= 40));
+    }
+
+    #[test]
+    fn byte_limit_gives_a_large_row_a_part_of_its_own() {
+        let large = "x".repeat(100);
+        let manifest = export("large", ChunkLimit::Bytes(40), &["a", &large, "b"]);
+        assert_eq!(part_rows(&manifest), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn create_refuses_a_directory_with_files() {
+        let dir = export_dir("taken");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("part-00000.jsonl"), "").unwrap();
+
+        let created =
+            ChunkedExport::create(&dir, ChunkLimit::Rows(1), "fields @message", None, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(created.is_err());
+    }
+}
@@ -0,0 +1,42 @@
+//! The `cw` command line tool, what the binary runs.
+
+use std::process::ExitCode;
+
+use clap::error::ErrorKind;
+use clap::Parser;
+
+use crate::build_info::summary as build_summary;
+use crate::commands::Cw;
+use crate::deadline::exit_code;
+use crate::ui::{render_error, supports_ansi};
+use crate::update_check::version_notice;
+
+/// Parses the command line, runs the command and prints what went wrong, if anything.
+pub fn main() -> ExitCode {
+    // NOTE: The build a crash came from is the first thing a bug report needs.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        eprintln!("{}", build_summary());
+        default_hook(info);
+    }));
+
+    let cw = match Cw::try_parse() {
+        Ok(cw) => cw,
+        Err(err) if err.kind() == ErrorKind::DisplayVersion => {
+            let _ = err.print();
+            if let Some(notice) = version_notice() {
+                eprintln!("{}", notice);
+            }
+            return ExitCode::from(0);
+        }
+        Err(err) => err.exit(),
+    };
+
+    match cw.run() {
+        Err(err) => {
+            eprint!("{}", render_error(&err, supports_ansi(&std::io::stderr())));
+            ExitCode::from(exit_code(&err))
+        }
+        Ok(_) => ExitCode::from(0),
+    }
+}
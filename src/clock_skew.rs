@@ -0,0 +1,145 @@
+use std::sync::{Arc, Mutex};
+
+use aws_sdk_cloudwatchlogs::config::{
+    interceptors::BeforeDeserializationInterceptorContextRef, ConfigBag, Intercept,
+    RuntimeComponents,
+};
+use aws_smithy_runtime_api::box_error::BoxError;
+use chrono::{DateTime, Utc};
+
+// NOTE: The Date header only has second precision and the default start time already reaches
+// 30 seconds into the past, so smaller differences are not worth correcting.
+const CLOCK_SKEW_THRESHOLD_MS: i64 = 15_000;
+
+/// How far the local clock runs ahead of CloudWatch, in milliseconds, measured from the Date
+/// header of the first response. Negative when the local clock is behind.
+#[derive(Debug, Clone, Default)]
+pub struct ClockSkew {
+    skew_ms: Arc<Mutex<Option<i64>>>,
+}
+
+impl ClockSkew {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The measured skew, `None` until a response with a Date header came in.
+    pub fn get(&self) -> Option<i64> {
+        self.skew_ms.lock().ok().and_then(|skew| *skew)
+    }
+
+    fn record_once(&self, skew_ms: i64) {
+        if let Ok(mut skew) = self.skew_ms.lock() {
+            skew.get_or_insert(skew_ms);
+        }
+    }
+}
+
+/// Estimates the skew between the local clock and the server's, positive when the local clock
+/// is ahead.
+pub fn estimate_skew(server_time: DateTime<Utc>, local_time: DateTime<Utc>) -> i64 {
+    (local_time - server_time).num_milliseconds()
+}
+
+/// Shifts a start time derived from the local clock onto the server's clock. Skews that don't
+/// pass [`exceeds_threshold`] are ignored and the start time is returned as is.
+pub fn apply_skew(start_time: i64, skew_ms: i64) -> i64 {
+    if exceeds_threshold(skew_ms) {
+        start_time - skew_ms
+    } else {
+        start_time
+    }
+}
+
+/// Whether a skew is worth correcting. Only a local clock running ahead is, one that is behind
+/// just makes the default start time reach a little further back.
+pub fn exceeds_threshold(skew_ms: i64) -> bool {
+    skew_ms > CLOCK_SKEW_THRESHOLD_MS
+}
+
+/// Records the skew between the local clock and the Date header of the first response into a
+/// shared [`ClockSkew`].
+#[derive(Debug)]
+pub struct ClockSkewInterceptor {
+    skew: ClockSkew,
+}
+
+impl ClockSkewInterceptor {
+    pub fn new(skew: ClockSkew) -> Self {
+        Self { skew }
+    }
+}
+
+impl Intercept for ClockSkewInterceptor {
+    fn name(&self) -> &'static str {
+        "ClockSkewInterceptor"
+    }
+
+    fn read_before_deserialization(
+        &self,
+        context: &BeforeDeserializationInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let local_time = Utc::now();
+        let server_time = context
+            .response()
+            .headers()
+            .get("date")
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok());
+
+        if let Some(server_time) = server_time {
+            self.skew
+                .record_once(estimate_skew(server_time.with_timezone(&Utc), local_time));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, TimeZone, Utc};
+
+    use crate::clock_skew::{apply_skew, estimate_skew, exceeds_threshold, ClockSkew};
+
+    #[test]
+    fn a_local_clock_ahead_of_the_server_gives_a_positive_skew() {
+        let server = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            estimate_skew(server, server + TimeDelta::minutes(4)),
+            240_000
+        );
+        assert_eq!(
+            estimate_skew(server, server - TimeDelta::seconds(3)),
+            -3_000
+        );
+        assert_eq!(estimate_skew(server, server), 0);
+    }
+
+    #[test]
+    fn only_a_clock_running_ahead_past_the_threshold_is_corrected() {
+        assert!(!exceeds_threshold(15_000));
+        assert!(exceeds_threshold(15_001));
+        assert!(!exceeds_threshold(-600_000));
+        assert!(!exceeds_threshold(0));
+    }
+
+    #[test]
+    fn applying_a_skew_moves_the_start_time_back_by_it() {
+        assert_eq!(apply_skew(1_000_000, 240_000), 760_000);
+        assert_eq!(apply_skew(1_000_000, 15_000), 1_000_000);
+        assert_eq!(apply_skew(1_000_000, -240_000), 1_000_000);
+    }
+
+    #[test]
+    fn only_the_first_measurement_is_kept() {
+        let skew = ClockSkew::new();
+        assert_eq!(skew.get(), None);
+
+        skew.clone().record_once(240_000);
+        skew.record_once(1_000);
+
+        assert_eq!(skew.get(), Some(240_000));
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::Display;
+
+use clap::Subcommand;
+use eyre::Context;
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use regex::Regex;
+use tokio::task::JoinHandle;
+
+use crate::db::{ArchiveQuery, Database};
+use crate::pipeline::{Origin, OutputArgs};
+use crate::table::{Cell, Table};
+use crate::ui::Ui;
+use crate::utils::{group_thousands, humanize_bytes, parse_human_time, parse_timestamp};
+
+#[derive(Subcommand, Debug)]
+pub enum Cmd {
+    /// Print the events stored in an archive with `cw tail --archive <NAME>`.
+    Search {
+        #[arg(index = 1, value_name = "NAME", help = "The archive to search.")]
+        name: String,
+
+        #[arg(
+            long,
+            help = "Only print events whose message matches this regular expression."
+        )]
+        grep: Option<String>,
+
+        #[arg(
+            long = "match",
+            value_name = "QUERY",
+            help = "Only print events matching this full text query, e.g. 'timeout OR \"connection reset\"'. Uses the archive's index, so it is much faster than --grep on large archives."
+        )]
+        text: Option<String>,
+
+        #[arg(
+            long,
+            value_parser = parse_human_time,
+            help = "Only print events after this time. Passed as either date/time or human-friendly format."
+        )]
+        since: Option<i64>,
+
+        #[arg(
+            long,
+            value_parser = parse_human_time,
+            help = "Only print events before this time. Passed as either date/time or human-friendly format."
+        )]
+        until: Option<i64>,
+
+        #[arg(long, help = "Print at most this many events.")]
+        limit: Option<u32>,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// List the archives with their number of events and time range.
+    Ls {
+        #[arg(short, long, help = "Print times in the local timezone.")]
+        local: bool,
+    },
+
+    /// Remove an archive and all of its events.
+    Rm {
+        #[arg(index = 1, value_name = "NAME", help = "The archive to remove.")]
+        name: String,
+    },
+}
+
+impl Display for Cmd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cmd::Search { .. } => write!(f, "search"),
+            Cmd::Ls { .. } => write!(f, "ls"),
+            Cmd::Rm { .. } => write!(f, "rm"),
+        }
+    }
+}
+
+impl Cmd {
+    pub async fn run(&self, db: impl Database, ui: Ui) -> eyre::Result<()> {
+        match self {
+            Self::Search {
+                name,
+                grep,
+                text,
+                since,
+                until,
+                limit,
+                output,
+            } => {
+                let grep = grep
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .context("Invalid --grep pattern")?;
+                let query = ArchiveQuery {
+                    since: *since,
+                    until: *until,
+                    text: text.clone(),
+                    grep,
+                    limit: *limit,
+                };
+
+                let events = db.search_archive(name, &query).await?;
+                if events.is_empty()
+                    && !db.list_archives().await?.iter().any(|a| &a.archive == name)
+                {
+                    return Err(eyre::eyre!("There is no archive named '{}'", name));
+                }
+
+                let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+                let mut tasks = FuturesUnordered::<JoinHandle<eyre::Result<()>>>::new();
+                tasks.push(
+                    output
+                        .spawn_writer(receiver, Origin::default(), false, None, ui)
+                        .await?,
+                );
+
+                for event in events {
+                    // NOTE: The writer only goes away when it failed, its error is returned below.
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+                drop(sender);
+
+                while let Some(res) = tasks.next().await {
+                    res??;
+                }
+                Ok(())
+            }
+            Self::Ls { local } => {
+                let archives = db.list_archives().await?;
+                if archives.is_empty() {
+                    ui.note(
+                        "There are no archives yet, create one with `cw tail --archive <NAME>`.",
+                    );
+                    return Ok(());
+                }
+
+                let mut table =
+                    Table::new(&["NAME", "EVENTS", "BYTES", "FIRST EVENT", "LAST EVENT"])
+                        .align_right(1)
+                        .align_right(2);
+                for archive in archives {
+                    let time = |timestamp: Option<i64>| {
+                        timestamp
+                            .and_then(|t| parse_timestamp(t, *local))
+                            .unwrap_or_else(|| "-".to_string())
+                    };
+                    table.push(vec![
+                        archive.archive.into(),
+                        Cell::new(group_thousands(archive.events.max(0) as u64)),
+                        Cell::new(humanize_bytes(archive.message_bytes.max(0) as u64)),
+                        time(archive.first_timestamp).into(),
+                        time(archive.last_timestamp).into(),
+                    ]);
+                }
+                table.print()?;
+                Ok(())
+            }
+            Self::Rm { name } => {
+                let removed = db.remove_archive(name).await?;
+                if removed == 0 {
+                    return Err(eyre::eyre!("There is no archive named '{}'", name));
+                }
+                ui.note(format!(
+                    "Removed archive '{}' with {} events.",
+                    name, removed
+                ));
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt::Display;
+
+use chrono::DateTime;
+use clap::Subcommand;
+
+use crate::config::AuditConfig;
+use crate::db::Database;
+use crate::table::Table;
+use crate::ui::Ui;
+use crate::utils::{format_datetime, parse_human_time};
+
+#[derive(Subcommand, Debug)]
+pub enum Cmd {
+    /// Print the CloudWatch Logs calls recorded while audit.enabled was set.
+    List {
+        #[arg(
+            long,
+            value_parser = parse_human_time,
+            default_value = "7d",
+            help = "Only show calls made after this time. Passed as either date/time or human-friendly format."
+        )]
+        since: i64,
+
+        #[arg(short, long, help = "Print times in the local timezone.")]
+        local: bool,
+    },
+}
+
+impl Display for Cmd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cmd::List { .. } => write!(f, "list"),
+        }
+    }
+}
+
+impl Cmd {
+    pub async fn run(&self, settings: &AuditConfig, db: impl Database, ui: Ui) -> eyre::Result<()> {
+        match self {
+            Self::List { since, local } => {
+                let since = DateTime::from_timestamp_millis(*since)
+                    .ok_or_else(|| eyre::eyre!("{} is not a valid timestamp", since))?;
+                let entries = db.list_audit(since).await?;
+
+                if entries.is_empty() && !settings.is_enabled() {
+                    ui.note("Auditing is off, turn it on with `cw config set audit.enabled true`.");
+                    return Ok(());
+                }
+
+                let mut table = Table::new(&[
+                    "TIME",
+                    "OPERATION",
+                    "OUTCOME",
+                    "DURATION",
+                    "REQUEST ID",
+                    "PARAMETERS",
+                ])
+                .align_right(3);
+                for entry in entries {
+                    table.push(vec![
+                        format_datetime(entry.created_at, *local).into(),
+                        entry.operation.into(),
+                        entry.outcome.into(),
+                        format!("{}ms", entry.duration_ms).into(),
+                        entry.request_id.as_deref().unwrap_or("-").into(),
+                        entry.parameters.into(),
+                    ]);
+                }
+                table.print()?;
+                Ok(())
+            }
+        }
+    }
+}
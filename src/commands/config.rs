@@ -0,0 +1,311 @@
+use std::{fmt::Display, io::Write, path::Path};
+
+use clap::Subcommand;
+use eyre::Context;
+use tabwriter::TabWriter;
+use toml_edit::DocumentMut;
+
+use crate::config::{
+    read_document, read_or_create_document, Config, ConfigKey, ConfigManager, KEYS,
+};
+use crate::editor::open_in_editor;
+
+#[derive(Subcommand, Debug)]
+pub enum Cmd {
+    /// Print the path of the config file.
+    Path,
+
+    /// Print the effective value of a key.
+    Get { key: String },
+
+    /// Write a value to the config file.
+    Set { key: String, value: String },
+
+    /// Remove a key from the config file, so its default applies again.
+    Unset { key: String },
+
+    /// Open the config file in $EDITOR.
+    Edit,
+
+    /// Print every key with its effective value and where that value comes from.
+    List,
+}
+
+impl Display for Cmd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cmd::Path => write!(f, "path"),
+            Cmd::Get { key } => write!(f, "get <{}>", key),
+            Cmd::Set { key, value: _ } => write!(f, "set <{}>", key),
+            Cmd::Unset { key } => write!(f, "unset <{}>", key),
+            Cmd::Edit => write!(f, "edit"),
+            Cmd::List => write!(f, "list"),
+        }
+    }
+}
+
+impl Cmd {
+    pub async fn run(&self, config: &impl ConfigManager) -> eyre::Result<()> {
+        let path = config.get_config_path()?;
+
+        match self {
+            Self::Path => {
+                println!("{}", path.display());
+                Ok(())
+            }
+            Self::Get { key } => {
+                let key = ConfigKey::find(key)?;
+                let document = Self::read_if_exists(&path)?;
+                if let Some((value, _)) = Self::resolve(key, &document) {
+                    println!("{}", value);
+                }
+                Ok(())
+            }
+            Self::Set { key, value } => {
+                let key = ConfigKey::find(key)?;
+                let value = key.parse_value(value)?;
+                let mut document = read_or_create_document(&path)?;
+
+                key.set(&mut document, value)?;
+                Config::from_document(&document).wrap_err_with(|| {
+                    format!("Refusing to write an invalid value for {}", key.name)
+                })?;
+
+                std::fs::write(&path, document.to_string())
+                    .wrap_err_with(|| format!("Failed writing {}", path.display()))
+            }
+            Self::Unset { key } => {
+                let key = ConfigKey::find(key)?;
+                if !path.exists() {
+                    return Ok(());
+                }
+                let mut document = read_document(&path)?;
+                if !key.unset(&mut document) {
+                    return Ok(());
+                }
+
+                std::fs::write(&path, document.to_string())
+                    .wrap_err_with(|| format!("Failed writing {}", path.display()))
+            }
+            Self::Edit => {
+                let document = read_or_create_document(&path)?;
+                let edited = open_in_editor(&document.to_string(), None)?;
+                let parsed = edited
+                    .parse::<DocumentMut>()
+                    .context("The edited config is not valid TOML, nothing was saved")?;
+                Config::from_document(&parsed)
+                    .context("The edited config is invalid, nothing was saved")?;
+
+                std::fs::write(&path, edited)
+                    .wrap_err_with(|| format!("Failed writing {}", path.display()))
+            }
+            Self::List => {
+                let document = Self::read_if_exists(&path)?;
+                let mut tw = TabWriter::new(std::io::stdout()).padding(2).minwidth(0);
+
+                for key in KEYS {
+                    match Self::resolve(key, &document) {
+                        Some((value, source)) => {
+                            writeln!(tw, "{}\t{}\t({})", key.name, value, source)?
+                        }
+                        None => writeln!(tw, "{}\t\t(unset)", key.name)?,
+                    }
+                }
+
+                tw.flush().context("failed to write to stdout")
+            }
+        }
+    }
+
+    fn read_if_exists(path: &Path) -> eyre::Result<DocumentMut> {
+        if path.exists() {
+            read_document(path)
+        } else {
+            Ok(DocumentMut::new())
+        }
+    }
+
+    /// Resolves the effective value of `key`: environment first, then the config file, then the
+    /// built-in default.
+    fn resolve(key: &ConfigKey, document: &DocumentMut) -> Option<(String, String)> {
+        if let Some((name, value)) = key
+            .env
+            .and_then(|name| std::env::var(name).ok().map(|value| (name, value)))
+        {
+            return Some((value, format!("env {}", name)));
+        }
+
+        if let Some(value) = key.get(document) {
+            return Some((value, "config file".to_string()));
+        }
+
+        key.default
+            .map(|value| (value.to_string(), "default".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::commands::config::Cmd;
+    use crate::config::{read_document, ConfigKey, ConfigManager};
+
+    /// Keeps the config file in a directory of its own.
+    #[derive(Clone)]
+    struct TempConfig(PathBuf);
+
+    impl TempConfig {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("cw-config-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+
+        async fn run(&self, cmd: Cmd) -> eyre::Result<()> {
+            cmd.run(self).await
+        }
+
+        fn resolve(&self, key: &str) -> Option<(String, String)> {
+            let document = Cmd::read_if_exists(&self.get_config_path().unwrap()).unwrap();
+            Cmd::resolve(ConfigKey::find(key).unwrap(), &document)
+        }
+
+        fn contents(&self) -> String {
+            std::fs::read_to_string(self.get_config_path().unwrap()).unwrap()
+        }
+    }
+
+    impl ConfigManager for TempConfig {
+        fn get_db_path(&self) -> eyre::Result<PathBuf> {
+            Ok(self.0.join("db.sqlite3"))
+        }
+
+        fn get_log_path(&self) -> eyre::Result<PathBuf> {
+            Ok(self.0.join("cw.log"))
+        }
+
+        fn get_config_path(&self) -> eyre::Result<PathBuf> {
+            Ok(self.0.join("config.toml"))
+        }
+
+        fn get_state_path(&self) -> eyre::Result<PathBuf> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn set(key: &str, value: &str) -> Cmd {
+        Cmd::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn unset(key: &str) -> Cmd {
+        Cmd::Unset {
+            key: key.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn set_then_unset_goes_back_to_the_default() {
+        let config = TempConfig::new("round-trip");
+        assert_eq!(
+            config.resolve("tail.timestamp"),
+            Some(("false".to_string(), "default".to_string()))
+        );
+
+        config.run(set("tail.timestamp", "true")).await.unwrap();
+        config
+            .run(set("tail.time_style", "relative"))
+            .await
+            .unwrap();
+        assert_eq!(
+            config.resolve("tail.timestamp"),
+            Some(("true".to_string(), "config file".to_string()))
+        );
+
+        config.run(unset("tail.timestamp")).await.unwrap();
+        assert_eq!(
+            config.resolve("tail.timestamp"),
+            Some(("false".to_string(), "default".to_string()))
+        );
+        assert_eq!(
+            config.resolve("tail.time_style"),
+            Some(("relative".to_string(), "config file".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn set_keeps_comments_and_unknown_keys() {
+        let config = TempConfig::new("keep");
+        std::fs::create_dir_all(&config.0).unwrap();
+        std::fs::write(
+            config.get_config_path().unwrap(),
+            "# mine\n[tail]\nlocal = true # keep\n[extra]\nkey = 1\n",
+        )
+        .unwrap();
+
+        config.run(set("tail.timestamp", "true")).await.unwrap();
+
+        assert_eq!(
+            config.contents(),
+            "# mine\n[tail]\nlocal = true # keep\ntimestamp = true\n[extra]\nkey = 1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn unset_without_a_config_file_creates_none() {
+        let config = TempConfig::new("unset-missing");
+
+        config.run(unset("tail.local")).await.unwrap();
+
+        assert!(!config.get_config_path().unwrap().exists());
+    }
+
+    #[tokio::test]
+    async fn unknown_keys_are_rejected() {
+        let config = TempConfig::new("unknown");
+
+        for cmd in [
+            set("tail.colour", "true"),
+            unset("tail.colour"),
+            Cmd::Get {
+                key: "tail.colour".to_string(),
+            },
+        ] {
+            let err = config.run(cmd).await.unwrap_err();
+            assert!(
+                err.to_string()
+                    .starts_with("Unknown config key 'tail.colour', available keys: "),
+                "{err}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_values_are_not_written() {
+        let config = TempConfig::new("invalid");
+        config.run(set("tail.timestamp", "true")).await.unwrap();
+        let before = config.contents();
+
+        let err = config.run(set("tail.timestamp", "yes")).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tail.timestamp expects true or false, got 'yes'"
+        );
+
+        let err = config
+            .run(set("tail.time_style", "sundial"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Refusing to write an invalid value for tail.time_style"
+        );
+
+        assert_eq!(config.contents(), before);
+        assert!(read_document(&config.get_config_path().unwrap()).is_ok());
+    }
+}
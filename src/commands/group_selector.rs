@@ -0,0 +1,562 @@
+use eyre::Context;
+
+use crate::lambda::LAMBDA_GROUP_PREFIX;
+
+// NOTE: The limits CreateLogGroup puts on names, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_CreateLogGroup.html#CWL-CreateLogGroup-request-logGroupName
+const MAX_GROUP_NAME_LEN: usize = 512;
+
+// NOTE: `arn:<partition>:logs:<region>:<account>:log-group:<name>`, the colons up to the name
+// belong to the ARN and don't start a stream prefix.
+const ARN_COLONS: usize = 6;
+
+/// How a [`GroupSelector`] names its log group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectorKind {
+    Name,
+    Arn,
+}
+
+/// A log group picked on the command line or in a groups file, by name or ARN, with an optional
+/// stream prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupSelector {
+    kind: SelectorKind,
+    identifier: String,
+    stream_prefix: Option<String>,
+}
+
+/// What a command accepts from the selectors it resolves, see [`GroupSelector::resolve`].
+#[derive(Debug, Clone, Copy)]
+pub struct GroupRules {
+    pub max: Option<usize>,
+    pub stream_prefixes: bool,
+}
+
+impl GroupSelector {
+    pub fn new(group: &str, stream_prefix: &str) -> eyre::Result<Self> {
+        let group = group.trim();
+        let stream_prefix = stream_prefix.trim();
+
+        if group.is_empty() {
+            return Err(eyre::eyre!("Group name cannot be empty"));
+        }
+
+        let kind = if group.starts_with("arn:") {
+            SelectorKind::Arn
+        } else {
+            SelectorKind::Name
+        };
+        let name = match kind {
+            SelectorKind::Arn => group
+                .split_once(":log-group:")
+                .map(|(_, name)| name)
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| {
+                    eyre::eyre!(
+                        "not a log group ARN, expected arn:<partition>:logs:<region>:<account>:log-group:<name>"
+                    )
+                })?,
+            SelectorKind::Name => group,
+        };
+        check_group_name(name)?;
+
+        // NOTE: The ARN CloudWatch shows for a group ends in `:*`, that is no stream prefix.
+        let stream_prefix = match (kind, stream_prefix) {
+            (_, "") | (SelectorKind::Arn, "*") => None,
+            (_, prefix) => Some(prefix.to_string()),
+        };
+        Ok(Self {
+            kind,
+            identifier: group.to_string(),
+            stream_prefix,
+        })
+    }
+
+    /// The group as it was given, its name or ARN.
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    /// The name of the group, also for a selector given as an ARN.
+    pub fn group_name(&self) -> &str {
+        match self.kind {
+            SelectorKind::Name => &self.identifier,
+            SelectorKind::Arn => self
+                .identifier
+                .split_once(":log-group:")
+                .map_or(self.identifier.as_str(), |(_, name)| name),
+        }
+    }
+
+    pub fn stream_prefix(&self) -> Option<&str> {
+        self.stream_prefix.as_deref()
+    }
+
+    /// Parses `group[:streamPrefix][,...]`, where a group is a name or an ARN. Separators can be
+    /// escaped with a backslash or by quoting (single or double quotes), so `a\:b` and `"a:b"`
+    /// both keep the colon. Everything after the first unescaped colon of a segment, past the
+    /// ARN when there is one, is the stream prefix. Surrounding whitespace is trimmed and empty
+    /// segments are skipped.
+    pub fn parse(groups_with_stream_prefix: &str) -> eyre::Result<Vec<Self>> {
+        #[derive(Clone, Copy)]
+        enum State {
+            Normal,
+            Escaped,
+            Quoted(char),
+            QuotedEscaped(char),
+        }
+
+        fn push(group: &mut String, stream: &mut Option<String>, c: char) {
+            stream.as_mut().unwrap_or(group).push(c);
+        }
+
+        fn within_arn(group: &str) -> bool {
+            let group = group.trim_start();
+            group == "arn" || (group.starts_with("arn:") && group.matches(':').count() < ARN_COLONS)
+        }
+
+        let mut selectors = Vec::new();
+        let mut group = String::new();
+        let mut stream: Option<String> = None;
+        let mut segment = String::new();
+        let mut state = State::Normal;
+
+        let mut finish = |group: &mut String,
+                          stream: &mut Option<String>,
+                          segment: &mut String|
+         -> eyre::Result<()> {
+            let stream = stream.take();
+            if group.trim().is_empty() && stream.is_none() {
+                group.clear();
+                segment.clear();
+                return Ok(());
+            }
+
+            let parsed = Self::new(group, stream.as_deref().unwrap_or(""))
+                .map_err(|e| eyre::eyre!("Invalid group '{}': {}", segment.trim(), e))?;
+            selectors.push(parsed);
+            group.clear();
+            segment.clear();
+            Ok(())
+        };
+
+        for c in groups_with_stream_prefix.chars() {
+            if !matches!((state, c), (State::Normal, ',')) {
+                segment.push(c);
+            }
+            state = match (state, c) {
+                (State::Escaped, c) => {
+                    push(&mut group, &mut stream, c);
+                    State::Normal
+                }
+                (State::QuotedEscaped(quote), c) => {
+                    push(&mut group, &mut stream, c);
+                    State::Quoted(quote)
+                }
+                (State::Quoted(quote), '\\') => State::QuotedEscaped(quote),
+                (State::Quoted(quote), c) if c == quote => State::Normal,
+                (State::Quoted(quote), c) => {
+                    push(&mut group, &mut stream, c);
+                    State::Quoted(quote)
+                }
+                (State::Normal, '\\') => State::Escaped,
+                (State::Normal, '"' | '\'') => State::Quoted(c),
+                (State::Normal, ',') => {
+                    finish(&mut group, &mut stream, &mut segment)?;
+                    State::Normal
+                }
+                (State::Normal, ':') if stream.is_none() && !within_arn(&group) => {
+                    stream = Some(String::new());
+                    State::Normal
+                }
+                (State::Normal, c) => {
+                    push(&mut group, &mut stream, c);
+                    State::Normal
+                }
+            };
+        }
+
+        match state {
+            State::Normal => finish(&mut group, &mut stream, &mut segment)?,
+            State::Escaped | State::QuotedEscaped(_) => {
+                return Err(eyre::eyre!(
+                    "Invalid group '{}': trailing backslash",
+                    segment.trim()
+                ))
+            }
+            State::Quoted(quote) => {
+                return Err(eyre::eyre!(
+                    "Invalid group '{}': missing closing {}",
+                    segment.trim(),
+                    quote
+                ))
+            }
+        }
+
+        Ok(selectors)
+    }
+
+    /// Parses a groups file, `group[:streamPrefix][,...]` per line like [`GroupSelector::parse`].
+    /// Blank lines and lines starting with `#` are skipped. Errors point at the line in
+    /// `source`.
+    pub fn parse_lines(contents: &str, source: &str) -> eyre::Result<Vec<Self>> {
+        let mut selectors = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            // NOTE: Only whole lines are comments, `#` is a valid log group name character.
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            selectors
+                .extend(Self::parse(line).wrap_err_with(|| format!("{}:{}", source, number + 1))?);
+        }
+        Ok(selectors)
+    }
+
+    /// Checks `selectors` against what a command accepts and drops the repeated ones, keeping
+    /// the order they were given in. Every command goes through here, so they agree on what a
+    /// valid selection is and say the same when it isn't.
+    pub fn resolve(selectors: Vec<Self>, rules: GroupRules) -> eyre::Result<Vec<Self>> {
+        let mut unique: Vec<Self> = Vec::with_capacity(selectors.len());
+        for selector in selectors {
+            if !unique.contains(&selector) {
+                unique.push(selector);
+            }
+        }
+
+        if unique.is_empty() {
+            return Err(eyre::eyre!("At least one log group is required!"));
+        }
+        if let Some(max) = rules.max.filter(|max| unique.len() > *max) {
+            return Err(eyre::eyre!(
+                "At most {} log group{} can be used at once, got {}.",
+                max,
+                if max == 1 { "" } else { "s" },
+                unique.len()
+            ));
+        }
+        if !rules.stream_prefixes {
+            if let Some(selector) = unique.iter().find(|s| s.stream_prefix.is_some()) {
+                return Err(eyre::eyre!(
+                    "Invalid group '{}:{}': only tail takes a stream prefix",
+                    selector.identifier,
+                    selector.stream_prefix().unwrap_or_default()
+                ));
+            }
+        }
+        Ok(unique)
+    }
+
+    /// Parses and resolves a selection of exactly one group without a stream prefix.
+    pub fn resolve_one(value: &str) -> eyre::Result<Self> {
+        let rules = GroupRules {
+            max: Some(1),
+            stream_prefixes: false,
+        };
+        Self::resolve(Self::parse(value)?, rules)?
+            .pop()
+            .ok_or_else(|| eyre::eyre!("At least one log group is required!"))
+    }
+
+    /// Uses `stream_prefix` verbatim for every group, refusing selectors that already carry one.
+    pub fn with_stream_prefix(
+        selectors: Vec<Self>,
+        stream_prefix: &str,
+    ) -> eyre::Result<Vec<Self>> {
+        selectors
+            .into_iter()
+            .map(|selector| match selector.stream_prefix {
+                Some(stream) => Err(eyre::eyre!(
+                    "Group '{}' already has stream prefix '{}', it can't be combined with --stream.",
+                    selector.identifier,
+                    stream
+                )),
+                None => Self::new(&selector.identifier, stream_prefix),
+            })
+            .collect()
+    }
+
+    /// Turns every Lambda group into one selector per stream prefix in `prefixes`.
+    pub fn with_stream_prefixes(
+        selectors: Vec<Self>,
+        prefixes: &[String],
+    ) -> eyre::Result<Vec<Self>> {
+        let mut expanded = Vec::with_capacity(selectors.len() * prefixes.len());
+        for selector in selectors {
+            if !selector.group_name().starts_with(LAMBDA_GROUP_PREFIX) {
+                return Err(eyre::eyre!(
+                    "Group '{}' is not a Lambda group ({}<function>), --lambda-version only works for those. Use --stream to pick streams of other groups.",
+                    selector.identifier,
+                    LAMBDA_GROUP_PREFIX
+                ));
+            }
+            if let Some(stream) = &selector.stream_prefix {
+                return Err(eyre::eyre!(
+                    "Group '{}' already has stream prefix '{}', it can't be combined with --lambda-version.",
+                    selector.identifier,
+                    stream
+                ));
+            }
+
+            for prefix in prefixes {
+                expanded.push(Self::new(&selector.identifier, prefix)?);
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+fn check_group_name(name: &str) -> eyre::Result<()> {
+    if name.chars().count() > MAX_GROUP_NAME_LEN {
+        return Err(eyre::eyre!(
+            "log group names are at most {} characters",
+            MAX_GROUP_NAME_LEN
+        ));
+    }
+    match name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '/' | '#')))
+    {
+        Some(c) => Err(eyre::eyre!("'{}' isn't allowed in log group names", c)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::group_selector::{GroupRules, GroupSelector};
+
+    fn parse(value: &str) -> Vec<(String, Option<String>)> {
+        GroupSelector::parse(value)
+            .unwrap()
+            .into_iter()
+            .map(|s| {
+                (
+                    s.identifier().to_string(),
+                    s.stream_prefix().map(str::to_string),
+                )
+            })
+            .collect()
+    }
+
+    fn selector(group: &str, stream: Option<&str>) -> (String, Option<String>) {
+        (group.to_string(), stream.map(str::to_string))
+    }
+
+    #[test]
+    fn parse_splits_groups_and_stream_prefixes() {
+        assert_eq!(
+            parse(" /ecs/api:web/ , /ecs/worker ,,"),
+            vec![
+                selector("/ecs/api", Some("web/")),
+                selector("/ecs/worker", None)
+            ]
+        );
+        assert_eq!(
+            parse("/ecs/api:web:1"),
+            vec![selector("/ecs/api", Some("web:1"))]
+        );
+        assert!(parse(" , ").is_empty());
+    }
+
+    #[test]
+    fn parse_reads_arns() {
+        let arn = "arn:aws:logs:eu-west-1:123456789012:log-group:/ecs/api";
+        let selectors = GroupSelector::parse(&format!("{}:*,{}:web/", arn, arn)).unwrap();
+        assert_eq!(selectors[0].identifier(), arn);
+        assert_eq!(selectors[0].group_name(), "/ecs/api");
+        assert_eq!(selectors[0].stream_prefix(), None);
+        assert_eq!(selectors[1].stream_prefix(), Some("web/"));
+    }
+
+    #[test]
+    fn parse_points_at_the_invalid_group() {
+        let err = GroupSelector::parse("/ecs/api,/ecs/a pi").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid group '/ecs/a pi': ' ' isn't allowed in log group names"
+        );
+        assert!(GroupSelector::parse(":web/").is_err());
+        assert!(GroupSelector::parse("arn:aws:logs:eu-west-1:123456789012:stream").is_err());
+        assert!(GroupSelector::parse(&"a".repeat(513)).is_err());
+    }
+
+    #[test]
+    fn parse_keeps_escaped_and_quoted_separators() {
+        assert_eq!(
+            parse(r#"/ecs/api:"a,b:c",/ecs/worker:d\,e"#),
+            vec![
+                selector("/ecs/api", Some("a,b:c")),
+                selector("/ecs/worker", Some("d,e"))
+            ]
+        );
+        assert_eq!(
+            parse(r"/ecs/api:'it\'s'"),
+            vec![selector("/ecs/api", Some("it's"))]
+        );
+        assert_eq!(
+            parse(r#"/ecs/api:web-"1,2"-\\,/ecs/worker"#),
+            vec![
+                selector("/ecs/api", Some(r"web-1,2-\")),
+                selector("/ecs/worker", None)
+            ]
+        );
+        assert_eq!(
+            parse(r#"/ecs/api:"it's 'quoted'""#),
+            vec![selector("/ecs/api", Some("it's 'quoted'"))]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_an_unfinished_escape_or_quote() {
+        assert_eq!(
+            GroupSelector::parse(r"/ecs/worker,/ecs/api:web\")
+                .unwrap_err()
+                .to_string(),
+            r"Invalid group '/ecs/api:web\': trailing backslash"
+        );
+        assert_eq!(
+            GroupSelector::parse(r#"/ecs/api:"web\"#)
+                .unwrap_err()
+                .to_string(),
+            r#"Invalid group '/ecs/api:"web\': trailing backslash"#
+        );
+        assert_eq!(
+            GroupSelector::parse("/ecs/api:'web,/ecs/worker")
+                .unwrap_err()
+                .to_string(),
+            "Invalid group '/ecs/api:'web,/ecs/worker': missing closing '"
+        );
+    }
+
+    #[test]
+    fn parse_lines_skips_blank_lines_and_comments() {
+        let contents = concat!(
+            "# services\n",
+            "\n",
+            "/ecs/api:web/, /ecs/worker\n",
+            "   \n",
+            "  # indented comment\n",
+            "/ecs/#payments\n",
+            "\t\n",
+        );
+        let selectors: Vec<_> = GroupSelector::parse_lines(contents, "groups.txt")
+            .unwrap()
+            .into_iter()
+            .map(|s| {
+                (
+                    s.identifier().to_string(),
+                    s.stream_prefix().map(str::to_string),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            selectors,
+            vec![
+                selector("/ecs/api", Some("web/")),
+                selector("/ecs/worker", None),
+                selector("/ecs/#payments", None),
+            ]
+        );
+        assert!(GroupSelector::parse_lines("# nothing\n\n", "groups.txt")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn parse_lines_counts_skipped_lines_in_the_error() {
+        let err = GroupSelector::parse_lines("# services\n\n/ecs/api\n/ecs/api:\"web\n", "-")
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "-:4");
+    }
+
+    #[test]
+    fn with_stream_prefix_takes_the_prefix_literally() {
+        let selectors = GroupSelector::parse("/ecs/api,/ecs/worker").unwrap();
+
+        let selectors = GroupSelector::with_stream_prefix(selectors, "web:1,2").unwrap();
+
+        assert_eq!(
+            selectors
+                .iter()
+                .map(|s| (s.identifier(), s.stream_prefix()))
+                .collect::<Vec<_>>(),
+            [
+                ("/ecs/api", Some("web:1,2")),
+                ("/ecs/worker", Some("web:1,2"))
+            ]
+        );
+    }
+
+    #[test]
+    fn with_stream_prefix_refuses_a_group_with_a_prefix() {
+        let selectors = GroupSelector::parse("/ecs/api,/ecs/worker:jobs/").unwrap();
+
+        let err = GroupSelector::with_stream_prefix(selectors, "web/").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Group '/ecs/worker' already has stream prefix 'jobs/', it can't be combined with --stream."
+        );
+    }
+
+    #[test]
+    fn resolve_drops_repeated_selectors() {
+        let rules = GroupRules {
+            max: None,
+            stream_prefixes: true,
+        };
+        let selectors =
+            GroupSelector::parse("/ecs/api,/ecs/worker,/ecs/api,/ecs/api:web/").unwrap();
+        let resolved = GroupSelector::resolve(selectors, rules).unwrap();
+        let identifiers: Vec<_> = resolved
+            .iter()
+            .map(|s| (s.identifier(), s.stream_prefix()))
+            .collect();
+        assert_eq!(
+            identifiers,
+            vec![
+                ("/ecs/api", None),
+                ("/ecs/worker", None),
+                ("/ecs/api", Some("web/"))
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_checks_the_rules() {
+        let rules = GroupRules {
+            max: Some(1),
+            stream_prefixes: false,
+        };
+        let err = GroupSelector::resolve(Vec::new(), rules).unwrap_err();
+        assert_eq!(err.to_string(), "At least one log group is required!");
+
+        let two = GroupSelector::parse("/ecs/api,/ecs/worker").unwrap();
+        let err = GroupSelector::resolve(two, rules).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "At most 1 log group can be used at once, got 2."
+        );
+
+        let prefixed = GroupSelector::parse("/ecs/api:web/").unwrap();
+        let err = GroupSelector::resolve(prefixed, rules).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid group '/ecs/api:web/': only tail takes a stream prefix"
+        );
+    }
+
+    #[test]
+    fn resolve_one_takes_a_single_group() {
+        assert_eq!(
+            GroupSelector::resolve_one("/ecs/api").unwrap().identifier(),
+            "/ecs/api"
+        );
+        assert!(GroupSelector::resolve_one("/ecs/api,/ecs/worker").is_err());
+        assert!(GroupSelector::resolve_one("").is_err());
+    }
+}
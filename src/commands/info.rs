@@ -1,13 +1,24 @@
 use clap::{Args, CommandFactory};
 
-use crate::{commands::Cw, config::ConfigManager, db::Database};
+use crate::{
+    build_info,
+    commands::Cw,
+    config::{cache_dir, data_dir, ConfigManager},
+    db::Database,
+    instance_lock::InstanceLock,
+};
 
 #[derive(Args, Debug)]
 #[command(args_conflicts_with_subcommands = true)]
 pub struct Cmd {}
 
 impl Cmd {
-    pub async fn run(&self, config: &impl ConfigManager, db: impl Database) -> eyre::Result<()> {
+    pub async fn run(
+        &self,
+        config: &impl ConfigManager,
+        db: impl Database,
+        instance_lock: Option<&InstanceLock>,
+    ) -> eyre::Result<()> {
         let version = db.version().await?;
         let engine = db.engine();
 
@@ -15,15 +26,47 @@ impl Cmd {
             "Version:        {}",
             Cw::command().get_version().unwrap_or("")
         );
+        println!("Commit:         {}", build_info::GIT_DESCRIBE);
+        println!("Built:          {}", build_info::BUILD_DATE);
+        println!("Target:         {}", build_info::TARGET);
+        println!("Rustc:          {}", build_info::RUSTC_VERSION);
         println!("Database:       {}-{}", engine, version);
         println!(
             "Database Path:  {}",
-            config.get_db_path().unwrap_or("".to_string())
+            config
+                .get_db_path()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default()
         );
         println!(
             "Logs:           {}",
-            config.get_log_path().unwrap_or("".to_string())
+            config
+                .get_log_path()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default()
         );
+        println!(
+            "Config:         {}",
+            config
+                .get_config_path()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default()
+        );
+        println!("Data Dir:       {}", data_dir().display());
+        println!(
+            "Instance Lock:  {}",
+            instance_lock
+                .map(InstanceLock::status)
+                .unwrap_or_else(|| "unavailable".to_string())
+        );
+        println!(
+            "State Dir:      {}",
+            config
+                .get_state_path()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default()
+        );
+        println!("Cache Dir:      {}", cache_dir().display());
         Ok(())
     }
 }
@@ -1,115 +1,470 @@
-use std::fmt::Display;
+use std::{collections::HashSet, fmt::Display, io::Write, pin::pin, sync::Arc};
 
 use aws_sdk_cloudwatchlogs as cloudwatchlogs;
+use aws_sdk_cloudwatchlogs::types::{LogGroup, LogStream};
 use chrono::{DateTime, Days, Months, Utc};
 use clap::{command, Subcommand};
 use eyre::Context;
+use futures_util::TryStreamExt;
+use tokio::sync::mpsc::Sender;
 
-use super::LogClientBuilder;
+use crate::aws::resolve_log_group;
+use crate::commands::group_selector::GroupSelector;
+use crate::commands::LogClientBuilder;
+use crate::db::Database;
+use crate::group_cache::GroupCache;
+use crate::logs_api::{
+    describe_log_groups_pages, describe_log_streams_pages, DescribeLogGroupsRequest,
+    DescribeLogStreamsRequest, LogsApi,
+};
+use crate::progress::Progress;
+use crate::rate_limit::RateLimiter;
+use crate::ui::Ui;
+
+// NOTE: 50 is the maximum page size for both DescribeLogGroups and DescribeLogStreams, and
+// the most pattern matches DescribeLogGroups returns, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_DescribeLogGroups.html#CWL-DescribeLogGroups-request-limit
+// https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_DescribeLogStreams.html#CWL-DescribeLogStreams-request-limit
+const DESCRIBE_PAGE_LIMIT: i32 = 50;
+
+// Number of pages fetched ahead of the page currently being printed.
+const PREFETCH_DEPTH: usize = 2;
+
+/// How `ls groups` narrows down the listing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupFilter {
+    All,
+    Prefix(String),
+    Contains(String),
+}
+
+impl GroupFilter {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            GroupFilter::All => true,
+            GroupFilter::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            GroupFilter::Contains(contains) => name.contains(contains.as_str()),
+        }
+    }
+
+    fn request(&self) -> DescribeLogGroupsRequest {
+        match self {
+            GroupFilter::All => DescribeLogGroupsRequest::default(),
+            GroupFilter::Prefix(prefix) => DescribeLogGroupsRequest {
+                name_prefix: Some(prefix.clone()),
+                ..Default::default()
+            },
+            GroupFilter::Contains(contains) => DescribeLogGroupsRequest {
+                name_pattern: Some(contains.clone()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Every group matching `filter`, for commands that act on the groups instead of printing them.
+/// Like `ls groups`, a pattern that hits the server side cap is matched against a full listing.
+pub(crate) async fn describe_matching_groups(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    filter: &GroupFilter,
+) -> eyre::Result<Vec<LogGroup>> {
+    let groups = describe_all_groups(client, limiter, filter.request()).await?;
+    if matches!(filter, GroupFilter::Contains(_)) && groups.len() == DESCRIBE_PAGE_LIMIT as usize {
+        let all = describe_all_groups(client, limiter, DescribeLogGroupsRequest::default()).await?;
+        return Ok(all
+            .into_iter()
+            .filter(|group| {
+                group
+                    .log_group_name()
+                    .is_some_and(|name| filter.matches(name))
+            })
+            .collect());
+    }
+    Ok(groups)
+}
+
+async fn describe_all_groups(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    request: DescribeLogGroupsRequest,
+) -> eyre::Result<Vec<LogGroup>> {
+    let request = DescribeLogGroupsRequest {
+        limit: Some(DESCRIBE_PAGE_LIMIT),
+        ..request
+    };
+    let mut pages = pin!(describe_log_groups_pages(client, limiter, request));
+    let mut groups = Vec::new();
+    while let Some(page) = pages
+        .try_next()
+        .await
+        .wrap_err("Failed describing log groups.")?
+    {
+        groups.extend(page.log_groups.unwrap_or_default());
+    }
+    Ok(groups)
+}
+
+/// What ends every name `ls` prints. NUL can't be part of a name, so `-0` output can be split
+/// safely by `xargs -0`, whatever characters the names contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    Newline,
+    Nul,
+}
+
+impl Terminator {
+    fn new(print0: bool) -> Self {
+        if print0 {
+            Terminator::Nul
+        } else {
+            Terminator::Newline
+        }
+    }
+
+    fn as_char(self) -> char {
+        match self {
+            Terminator::Newline => '\n',
+            Terminator::Nul => '\0',
+        }
+    }
+}
+
+/// Prints every group name once, whether it comes from the group cache or a page of the
+/// listing.
+pub struct GroupPrinter<W> {
+    out: W,
+    printed: HashSet<String>,
+    long: bool,
+    terminator: Terminator,
+}
+
+impl<W: Write> GroupPrinter<W> {
+    fn new(out: W, long: bool, terminator: Terminator) -> Self {
+        Self {
+            out,
+            printed: HashSet::new(),
+            long,
+            terminator,
+        }
+    }
+
+    /// Prints a cached name, the cache doesn't know the group class.
+    fn print_name(&mut self, name: &str) -> std::io::Result<()> {
+        if self.printed.insert(name.to_string()) {
+            write!(self.out, "{}{}", name, self.terminator.as_char())?;
+        }
+        Ok(())
+    }
+
+    fn print_group(&mut self, name: &str, group: &LogGroup) -> std::io::Result<()> {
+        if !self.printed.insert(name.to_string()) {
+            return Ok(());
+        }
+
+        let end = self.terminator.as_char();
+        match group.log_group_class().filter(|_| self.long) {
+            Some(class) => write!(self.out, "{}\t{}{}", name, class.as_str(), end),
+            None if self.long => write!(self.out, "{}\tUNKNOWN{}", name, end),
+            None => write!(self.out, "{}{}", name, end),
+        }
+    }
+}
 
 #[derive(Subcommand, Debug)]
 #[command(infer_subcommands = false)]
 pub enum Cmd {
     Groups {
+        #[arg(
+            conflicts_with_all = ["prefix", "contains"],
+            help = "Same as --contains."
+        )]
         filter: Option<String>,
+
+        #[arg(
+            long,
+            conflicts_with = "contains",
+            help = "Only list groups whose name starts with this prefix."
+        )]
+        prefix: Option<String>,
+
+        #[arg(
+            long,
+            help = "Only list groups whose name contains this case-sensitive string. CloudWatch returns at most 50 matches, beyond that cw filters a full listing itself."
+        )]
+        contains: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "Print the log group class (STANDARD or INFREQUENT_ACCESS) next to each group, separated by a tab."
+        )]
+        long: bool,
+
+        #[arg(
+            short = '0',
+            long,
+            conflicts_with = "long",
+            help = "End every name with a NUL byte instead of a newline, for use with `xargs -0`."
+        )]
+        print0: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "long",
+            help = "Print the groups from the local cache right away, then list the groups to add the ones the cache missed. The listing is skipped when the cache is less than 10 minutes old."
+        )]
+        cached: bool,
+
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(i32).range(1..=50),
+            default_value_t = DESCRIBE_PAGE_LIMIT,
+            help = "How many groups to request per DescribeLogGroups call, at most 50."
+        )]
+        page_size: i32,
     },
     Streams {
+        #[arg(help = "The name or ARN of the log group.")]
         group_name: String,
 
+        #[arg(
+            long,
+            help = "The account of the log group, when a monitoring account sees the same name in several linked source accounts."
+        )]
+        account_id: Option<String>,
+
         #[arg(
             short,
             long,
             help = "Log streams that have exceeded the log group's retention period are considered expired and are filtered. Add this flag to show all streams."
         )]
         show_expired: bool,
+
+        #[arg(
+            short = '0',
+            long,
+            help = "End every name with a NUL byte instead of a newline, for use with `xargs -0`."
+        )]
+        print0: bool,
+
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(i32).range(1..=50),
+            default_value_t = DESCRIBE_PAGE_LIMIT,
+            help = "How many streams to request per DescribeLogStreams call, at most 50."
+        )]
+        page_size: i32,
     },
 }
 
 impl Display for Cmd {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Cmd::Groups { filter: _ } => write!(f, "groups"),
-            Cmd::Streams {
-                group_name,
-                show_expired: _,
-            } => write!(f, "streams <{}>", group_name),
+            Cmd::Groups { .. } => write!(f, "groups"),
+            Cmd::Streams { group_name, .. } => write!(f, "streams <{}>", group_name),
         }
     }
 }
 
 impl Cmd {
-    pub async fn run(&self, builder: &LogClientBuilder) -> eyre::Result<()> {
+    pub async fn run(
+        &self,
+        builder: &LogClientBuilder,
+        db: impl Database,
+        ui: Ui,
+    ) -> eyre::Result<()> {
         let client = builder.build().await?;
+        let limiter = builder.rate_limiter();
         match self {
-            Self::Groups { filter } => self.list_groups(&client, filter).await,
+            Self::Groups {
+                filter,
+                prefix,
+                contains,
+                long,
+                print0,
+                cached,
+                page_size,
+            } => {
+                let filter = match (prefix, contains.as_ref().or(filter.as_ref())) {
+                    (Some(prefix), _) => GroupFilter::Prefix(prefix.clone()),
+                    (None, Some(contains)) => GroupFilter::Contains(contains.clone()),
+                    (None, None) => GroupFilter::All,
+                };
+                let mut printer =
+                    GroupPrinter::new(std::io::stdout(), *long, Terminator::new(*print0));
+                let cache = GroupCache::new(db, builder, &client);
+                self.list_groups(
+                    &client,
+                    &limiter,
+                    ui,
+                    &filter,
+                    &mut printer,
+                    &cache,
+                    *cached,
+                    *page_size,
+                )
+                .await
+            }
             Self::Streams {
                 group_name,
+                account_id,
                 show_expired: _,
-            } => self.list_streams(&client, group_name).await,
+                print0,
+                page_size,
+            } => {
+                let terminator = Terminator::new(*print0);
+                let selector = GroupSelector::resolve_one(group_name)?;
+                self.list_streams(
+                    &client,
+                    limiter,
+                    ui,
+                    selector.identifier(),
+                    account_id.as_deref(),
+                    terminator,
+                    *page_size,
+                )
+                .await
+            }
         }
     }
 
     pub async fn list_groups(
         &self,
-        client: &cloudwatchlogs::Client,
-        filter: &Option<String>,
+        client: &impl LogsApi,
+        limiter: &RateLimiter,
+        ui: Ui,
+        filter: &GroupFilter,
+        printer: &mut GroupPrinter<impl Write>,
+        cache: &GroupCache<impl Database>,
+        cached: bool,
+        page_size: i32,
     ) -> eyre::Result<()> {
-        let mut next_token: Option<String> = None;
+        let mut progress = ui.progress();
+        let started_at = Utc::now();
 
-        loop {
-            let mut request_builder = client
-                .describe_log_groups()
-                .set_log_group_name_pattern(filter.clone())
-                // NOTE: 50 is the maximum, ref: https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_DescribeLogGroups.html#CWL-DescribeLogGroups-request-limit
-                .limit(50);
-
-            if let Some(ref token) = next_token {
-                request_builder = request_builder.next_token(token);
+        // NOTE: Cached names go out first, the listing below only adds the ones the cache
+        // missed as its pages come in, the printer keeps a name from showing up twice.
+        if cached {
+            for name in cache.cached().await {
+                if filter.matches(&name) {
+                    printer.print_name(&name)?;
+                }
+            }
+            if cache.is_fresh().await {
+                printer.out.flush()?;
+                return Ok(());
             }
+        }
 
-            let response = request_builder
-                .send()
-                .await
-                .wrap_err("Failed creating AWS Client.")?;
-            let groups = response.log_groups();
+        let all = DescribeLogGroupsRequest {
+            limit: Some(page_size),
+            ..Default::default()
+        };
+        let request = DescribeLogGroupsRequest {
+            limit: Some(page_size),
+            ..filter.request()
+        };
+        let capped = Self::print_group_pages(
+            client,
+            limiter,
+            &mut progress,
+            request,
+            |_| true,
+            printer,
+            cache,
+        )
+        .await?;
+        if *filter == GroupFilter::All {
+            cache.finish_refresh(started_at).await;
+        }
 
-            for group in groups {
-                println!("{}", group.log_group_name().unwrap_or_default());
-            }
+        if let (GroupFilter::Contains(contains), true) = (filter, capped) {
+            tracing::info!(
+                target: "cw",
+                "pattern {} hit the server side match cap, filtering a full listing instead",
+                contains
+            );
+            Self::print_group_pages(
+                client,
+                limiter,
+                &mut progress,
+                all,
+                |name| name.contains(contains.as_str()),
+                printer,
+                cache,
+            )
+            .await?;
+        }
 
-            next_token = response.next_token().map(|t| t.to_string());
+        progress.clear();
+        printer.out.flush()?;
+        Ok(())
+    }
 
-            if next_token == None {
-                break;
+    /// Prints every group matching `request` and `include` that wasn't printed yet. Returns
+    /// whether the listing looks cut off by the server side cap on pattern matches, exactly as
+    /// many groups as the cap.
+    async fn print_group_pages(
+        client: &impl LogsApi,
+        limiter: &RateLimiter,
+        progress: &mut Progress,
+        request: DescribeLogGroupsRequest,
+        include: impl Fn(&str) -> bool,
+        printer: &mut GroupPrinter<impl Write>,
+        cache: &GroupCache<impl Database>,
+    ) -> eyre::Result<bool> {
+        let mut responses = pin!(describe_log_groups_pages(client, limiter, request));
+        let mut fetched = 0;
+        let mut pages = 0;
+
+        while let Some(response) = responses
+            .try_next()
+            .await
+            .wrap_err("Failed describing log groups.")?
+        {
+            let groups = response.log_groups();
+            fetched += groups.len();
+            pages += 1;
+
+            let names: Vec<String> = groups
+                .iter()
+                .filter_map(|group| group.log_group_name().map(str::to_string))
+                .collect();
+            cache.record(&names).await;
+
+            progress.clear();
+            for group in groups {
+                let Some(name) = group.log_group_name() else {
+                    continue;
+                };
+                if include(name) {
+                    printer.print_group(name, group)?;
+                }
             }
+            progress.update(format!("fetched {} groups ({} pages)…", fetched, pages));
         }
-        Ok(())
+
+        Ok(fetched == DESCRIBE_PAGE_LIMIT as usize)
     }
 
     pub async fn list_streams(
         &self,
-        client: &cloudwatchlogs::Client,
+        client: &impl LogsApi,
+        limiter: Arc<RateLimiter>,
+        ui: Ui,
         group_name: impl Into<String>,
+        account_id: Option<&str>,
+        terminator: Terminator,
+        page_size: i32,
     ) -> eyre::Result<()> {
-        let mut next_token: Option<String> = None;
         let group_name = group_name.into();
-
-        let log_groups = client
-            .describe_log_groups()
-            .log_group_name_prefix(&group_name)
-            .send()
-            .await?;
-
-        let log_group = if let Some(g) = log_groups
-            .log_groups()
-            .iter()
-            .filter(|l| l.log_group_name() == Some(&group_name))
-            .next()
-        {
-            g
-        } else {
-            return Err(eyre::eyre!("Can't find log group with name {}", group_name));
-        };
+        let log_group = resolve_log_group(client, &limiter, &group_name, account_id).await?;
+        // NOTE: The streams of a linked source account can only be described by the group's ARN.
+        let group_identifier = log_group
+            .log_group_arn()
+            .unwrap_or(group_name.as_str())
+            .to_string();
 
         let retention = if let Some(days) = log_group.retention_in_days() {
             tracing::info!(target: "cw", "The retention for {} is set to {}.", group_name, days);
@@ -119,39 +474,343 @@ impl Cmd {
             Utc::now().checked_sub_months(Months::new(6))
         };
 
-        loop {
-            let mut request_builder = client
-                .describe_log_streams()
-                .log_group_identifier(&group_name)
-                .order_by(cloudwatchlogs::types::OrderBy::LastEventTime)
-                .descending(true)
-                // NOTE: 50 is the maximum, ref:
-                .limit(50);
-
-            if let Some(ref token) = next_token {
-                request_builder = request_builder.next_token(token);
-            }
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(PREFETCH_DEPTH);
+        let fetcher = tokio::spawn(Self::fetch_stream_pages(
+            client.clone(),
+            limiter,
+            group_identifier,
+            page_size,
+            sender,
+        ));
 
-            let response = request_builder
-                .send()
-                .await
-                .wrap_err("Failed creating AWS Client.")?;
+        let mut progress = ui.progress();
+        let mut fetched = 0;
+        let mut pages = 0;
+
+        while let Some(page) = receiver.recv().await {
+            fetched += page.len();
+            pages += 1;
 
-            let streams = response.log_streams().iter().filter(|s| {
+            let streams = page.iter().filter(|s| {
                 s.last_event_timestamp()
-                    .map_or(false, |t| DateTime::from_timestamp_millis(t) > retention)
+                    .is_some_and(|t| DateTime::from_timestamp_millis(t) > retention)
             });
 
+            progress.clear();
             for stream in streams {
-                println!("{}", stream.log_stream_name().unwrap_or_default());
+                print!(
+                    "{}{}",
+                    stream.log_stream_name().unwrap_or_default(),
+                    terminator.as_char()
+                );
             }
+            progress.update(format!("fetched {} streams ({} pages)…", fetched, pages));
+        }
+        progress.clear();
+        std::io::stdout().flush()?;
+
+        fetcher.await??;
+        Ok(())
+    }
 
-            next_token = response.next_token().map(|t| t.to_string());
+    /// Fetches log stream pages and hands them over to the printer, so the next page is already
+    /// being requested while the current one is written to stdout.
+    async fn fetch_stream_pages(
+        client: impl LogsApi,
+        limiter: Arc<RateLimiter>,
+        group_name: String,
+        page_size: i32,
+        sender: Sender<Vec<LogStream>>,
+    ) -> eyre::Result<()> {
+        let mut responses = pin!(describe_log_streams_pages(
+            &client,
+            &limiter,
+            DescribeLogStreamsRequest {
+                group_identifier: group_name.clone(),
+                order_by: Some(cloudwatchlogs::types::OrderBy::LastEventTime),
+                descending: Some(true),
+                limit: Some(page_size),
+                next_token: None,
+            },
+        ));
 
-            if next_token == None {
+        while let Some(response) = responses
+            .try_next()
+            .await
+            .wrap_err_with(|| format!("Failed describing log streams for {}.", group_name))?
+        {
+            // NOTE: The receiver only goes away when printing failed, no point in fetching more.
+            if sender
+                .send(response.log_streams.unwrap_or_default())
+                .await
+                .is_err()
+            {
                 break;
             }
         }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_cloudwatchlogs::operation::describe_log_groups::DescribeLogGroupsOutput;
+    use aws_sdk_cloudwatchlogs::operation::describe_log_streams::DescribeLogStreamsOutput;
+    use aws_sdk_cloudwatchlogs::types::{LogGroup, LogGroupClass, LogStream};
+    use chrono::Utc;
+    use clap::Parser;
+
+    use crate::aws::LogClientBuilder;
+    use crate::commands::list::{
+        describe_matching_groups, Cmd, GroupFilter, GroupPrinter, Terminator,
+    };
+    use crate::fake_logs::FakeLogs;
+    use crate::group_cache::GroupCache;
+    use crate::memory_db::InMemoryDb;
+    use crate::rate_limit::RateLimiter;
+    use crate::ui::Ui;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(subcommand)]
+        cmd: Cmd,
+    }
+
+    fn to_strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Runs `ls groups --cached` against `logs` and returns what it printed.
+    async fn list_cached(logs: &FakeLogs, cache: &GroupCache<InMemoryDb>) -> String {
+        let cmd = Cli::parse_from(["ls", "groups", "--cached"]).cmd;
+        let mut printer = GroupPrinter::new(Vec::new(), false, Terminator::Newline);
+
+        cmd.list_groups(
+            logs,
+            &RateLimiter::new(100),
+            Ui::new(true),
+            &GroupFilter::All,
+            &mut printer,
+            cache,
+            true,
+            50,
+        )
+        .await
+        .unwrap();
+
+        String::from_utf8(printer.out).unwrap()
+    }
+
+    fn streams(names: &[&str], next_token: Option<&str>) -> DescribeLogStreamsOutput {
+        DescribeLogStreamsOutput::builder()
+            .set_log_streams(Some(
+                names
+                    .iter()
+                    .map(|name| LogStream::builder().log_stream_name(*name).build())
+                    .collect(),
+            ))
+            .set_next_token(next_token.map(str::to_string))
+            .build()
+    }
+
+    fn groups(names: &[String], next_token: Option<&str>) -> DescribeLogGroupsOutput {
+        DescribeLogGroupsOutput::builder()
+            .set_log_groups(Some(
+                names
+                    .iter()
+                    .map(|name| LogGroup::builder().log_group_name(name).build())
+                    .collect(),
+            ))
+            .set_next_token(next_token.map(str::to_string))
+            .build()
+    }
+
+    fn group_names(range: std::ops::Range<usize>, service: &str) -> Vec<String> {
+        range
+            .map(|i| format!("/ecs/{}-{:02}", service, i))
+            .collect()
+    }
+
+    fn names(page: &[LogStream]) -> Vec<&str> {
+        page.iter()
+            .filter_map(|stream| stream.log_stream_name())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn stream_pages_are_handed_over_in_order_across_empty_pages() {
+        let logs = FakeLogs::new();
+        logs.push_describe_log_streams(Ok(streams(&["web-2", "web-1"], Some("t1"))))
+            .push_describe_log_streams(Ok(streams(&[], Some("t2"))))
+            .push_describe_log_streams(Ok(streams(&["web-0"], None)));
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(8);
+
+        Cmd::fetch_stream_pages(
+            logs.clone(),
+            RateLimiter::new(100).into(),
+            "/ecs/api".to_string(),
+            20,
+            sender,
+        )
+        .await
+        .unwrap();
+
+        let mut pages = Vec::new();
+        while let Some(page) = receiver.recv().await {
+            pages.push(page);
+        }
+        let pages: Vec<_> = pages.iter().map(|page| names(page)).collect();
+        assert_eq!(pages, [vec!["web-2", "web-1"], vec![], vec!["web-0"]]);
+        let requests = logs.describe_log_streams_requests();
+        let tokens: Vec<_> = requests.iter().map(|r| r.next_token.as_deref()).collect();
+        assert_eq!(tokens, [None, Some("t1"), Some("t2")]);
+        assert!(requests.iter().all(|r| r.limit == Some(20)));
+    }
+
+    #[tokio::test]
+    async fn stream_pages_stop_once_the_printer_is_gone() {
+        let logs = FakeLogs::new();
+        logs.push_describe_log_streams(Ok(streams(&["web-1"], Some("t1"))))
+            .push_describe_log_streams(Ok(streams(&["web-0"], None)));
+        let (sender, receiver) = tokio::sync::mpsc::channel(8);
+        drop(receiver);
+
+        Cmd::fetch_stream_pages(
+            logs.clone(),
+            RateLimiter::new(100).into(),
+            "/ecs/api".to_string(),
+            50,
+            sender,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(logs.describe_log_streams_requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn an_empty_stream_listing_is_a_single_empty_page() {
+        let logs = FakeLogs::new();
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(8);
+
+        Cmd::fetch_stream_pages(
+            logs.clone(),
+            RateLimiter::new(100).into(),
+            "/ecs/api".to_string(),
+            50,
+            sender,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(receiver.recv().await, Some(vec![]));
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn a_pattern_hitting_the_cap_is_matched_against_a_full_listing() {
+        let logs = FakeLogs::new();
+        let matches = group_names(0..60, "api");
+        logs.push_describe_log_groups(Ok(groups(&matches[..50], None)))
+            .push_describe_log_groups(Ok(groups(&matches[..40], Some("t1"))))
+            .push_describe_log_groups(Ok(groups(&group_names(0..10, "web"), Some("t2"))))
+            .push_describe_log_groups(Ok(groups(&matches[40..], None)));
+        let filter = GroupFilter::Contains("api".to_string());
+
+        let found = describe_matching_groups(&logs, &RateLimiter::new(100), &filter)
+            .await
+            .unwrap();
+
+        let found: Vec<_> = found
+            .iter()
+            .filter_map(|group| group.log_group_name())
+            .collect();
+        assert_eq!(found, matches);
+        let requests = logs.describe_log_groups_requests();
+        let patterns: Vec<_> = requests.iter().map(|r| r.name_pattern.as_deref()).collect();
+        assert_eq!(patterns, [Some("api"), None, None, None]);
+    }
+
+    #[tokio::test]
+    async fn a_pattern_under_the_cap_keeps_the_server_side_matches() {
+        let logs = FakeLogs::new();
+        let matches = group_names(0..49, "api");
+        logs.push_describe_log_groups(Ok(groups(&matches, None)));
+        let filter = GroupFilter::Contains("api".to_string());
+
+        let found = describe_matching_groups(&logs, &RateLimiter::new(100), &filter)
+            .await
+            .unwrap();
+
+        assert_eq!(found.len(), 49);
+        assert_eq!(logs.describe_log_groups_requests().len(), 1);
+    }
+
+    #[test]
+    fn the_printer_prints_every_name_once() {
+        let mut printer = GroupPrinter::new(Vec::new(), true, Terminator::Nul);
+        let standard = LogGroup::builder()
+            .log_group_name("/a")
+            .log_group_class(LogGroupClass::Standard)
+            .build();
+        let unknown = LogGroup::builder().log_group_name("/b").build();
+
+        printer.print_group("/a", &standard).unwrap();
+        printer.print_name("/a").unwrap();
+        printer.print_group("/b", &unknown).unwrap();
+        printer.print_group("/a", &standard).unwrap();
+
+        assert_eq!(
+            String::from_utf8(printer.out).unwrap(),
+            "/a\tSTANDARD\0/b\tUNKNOWN\0"
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_names_go_out_first_and_the_listing_only_adds_new_ones() {
+        let logs = FakeLogs::new();
+        let cache = GroupCache::new(InMemoryDb::new(), &LogClientBuilder::new(), &logs);
+        cache.record(&to_strings(&["/c", "/gone", "/a"])).await;
+        logs.push_describe_log_groups(Ok(groups(&to_strings(&["/b", "/c"]), Some("t1"))))
+            .push_describe_log_groups(Ok(groups(&to_strings(&["/a", "/d", "/b"]), None)));
+        // NOTE: The refresh drops names cached before it started, which needs the clock to move.
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        let printed = list_cached(&logs, &cache).await;
+
+        assert_eq!(printed, "/a\n/c\n/gone\n/b\n/d\n");
+        assert_eq!(cache.cached().await, ["/a", "/b", "/c", "/d"]);
+        assert!(cache.is_fresh().await);
+    }
+
+    #[tokio::test]
+    async fn a_fresh_cache_skips_the_listing() {
+        let logs = FakeLogs::new();
+        let cache = GroupCache::new(InMemoryDb::new(), &LogClientBuilder::new(), &logs);
+        let started_at = Utc::now();
+        cache.record(&to_strings(&["/b", "/a"])).await;
+        cache.finish_refresh(started_at).await;
+
+        let printed = list_cached(&logs, &cache).await;
+
+        assert_eq!(printed, "/a\n/b\n");
+        assert!(logs.describe_log_groups_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_prefix_is_never_capped() {
+        let logs = FakeLogs::new();
+        logs.push_describe_log_groups(Ok(groups(&group_names(0..50, "api"), None)));
+        let filter = GroupFilter::Prefix("/ecs/api".to_string());
+
+        let found = describe_matching_groups(&logs, &RateLimiter::new(100), &filter)
+            .await
+            .unwrap();
+
+        assert_eq!(found.len(), 50);
+        let requests = logs.describe_log_groups_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].name_prefix.as_deref(), Some("/ecs/api"));
+    }
+}
@@ -1,22 +1,41 @@
-use std::{fmt::Display, u8};
+use std::io::Write;
+use std::{fmt::Display, path::PathBuf, str::FromStr, sync::Arc, time::Duration, u8};
 
 use clap::{command, Parser, Subcommand};
 use eyre::Context;
-use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::filter::{LevelFilter, Targets};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{fmt, Layer};
 
 use crate::{
+    audit::AuditLog,
     aws::LogClientBuilder,
-    config::{ConfigManager, LocalConfigManager},
-    db::{Database, Sqlite},
+    build_info,
+    config::{Config, ConfigManager, LocalConfigManager},
+    connection_stats::ConnectionStats,
+    db::{is_read_only, Database, QueryStatus, Sqlite, SqliteSettings},
+    deadline::{is_deadline_exceeded, DeadlineExceeded},
+    instance_lock::InstanceLock,
+    progress_reporter::{JsonReporter, NoopReporter, ProgressReporter},
+    rate_limit::{RateLimiter, DEFAULT_TPS},
+    ui::Ui,
+    update_check::UpdateCheck,
 };
 
+mod archive;
+mod audit;
+mod config;
+mod group_selector;
 mod info;
 mod list;
 mod query;
-mod tail;
+mod render;
+mod retention;
+pub(crate) mod tail;
+
+// How long a run cut short by --deadline tries to stop the queries it was waiting on.
+const STOP_QUERIES_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Subcommand, Debug)]
 pub enum CwCmd {
@@ -28,6 +47,19 @@ pub enum CwCmd {
     Query(query::Cmd),
 
     Info(info::Cmd),
+
+    Render(render::Cmd),
+
+    #[command(subcommand)]
+    Config(config::Cmd),
+
+    #[command(subcommand)]
+    Audit(audit::Cmd),
+
+    #[command(subcommand)]
+    Archive(archive::Cmd),
+
+    Retention(retention::Cmd),
 }
 
 impl Display for CwCmd {
@@ -44,12 +76,17 @@ impl Display for CwCmd {
                     .unwrap_or_else(|| "".to_string())
             ),
             CwCmd::Info(_cmd) => write!(f, "info"),
+            CwCmd::Render(_cmd) => write!(f, "render"),
+            CwCmd::Config(cmd) => write!(f, "config {}", cmd),
+            CwCmd::Audit(cmd) => write!(f, "audit {}", cmd),
+            CwCmd::Archive(cmd) => write!(f, "archive {}", cmd),
+            CwCmd::Retention(_cmd) => write!(f, "retention"),
         }
     }
 }
 
 #[derive(Parser)]
-#[command(version)]
+#[command(version, long_version = build_info::LONG_VERSION)]
 #[command(about = "Swiss army knife to query CloudWatch logs form the CLI.", long_about = None, disable_help_subcommand = true)]
 pub struct Cw {
     #[arg(
@@ -81,6 +118,61 @@ pub struct Cw {
     )]
     pub verbose: u8,
 
+    #[arg(
+        long,
+        global = true,
+        help = "Log the AWS SDK and HTTP client at the --verbose level too, instead of warnings only.",
+        display_order = 999
+    )]
+    pub verbose_sdk: bool,
+
+    #[arg(
+        long,
+        short = 'q',
+        global = true,
+        help = "Don't print progress, notes and warnings to stderr. Errors are still printed.",
+        display_order = 999
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        global = true,
+        long,
+        value_name = "FD",
+        help = "Write newline delimited JSON progress events to this file descriptor.",
+        display_order = 998
+    )]
+    pub progress_fd: Option<i32>,
+
+    #[arg(
+        global = true,
+        long,
+        value_name = "PATH",
+        conflicts_with = "progress_fd",
+        help = "Append newline delimited JSON progress events to this file.",
+        display_order = 998
+    )]
+    pub progress_file: Option<PathBuf>,
+
+    #[arg(
+        global = true,
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Maximum number of CloudWatch Logs requests per second, shared by all log groups. Defaults to 5.",
+        display_order = 0
+    )]
+    pub tps: Option<u32>,
+
+    #[arg(
+        global = true,
+        long,
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        help = "Stop the command once it ran this long, e.g. 5m, and exit with code 124.",
+        display_order = 0
+    )]
+    pub deadline: Option<Duration>,
+
     #[command(subcommand)]
     pub cmd: CwCmd,
 }
@@ -98,22 +190,53 @@ impl Cw {
         }
     }
 
+    /// The filter for the log file, taken from CW_LOG (e.g. `cw=debug,aws_smithy_runtime=trace`)
+    /// when set and derived from the verbosity otherwise.
+    fn log_targets(&self) -> eyre::Result<Targets> {
+        match std::env::var("CW_LOG") {
+            Ok(directives) if !directives.trim().is_empty() => Targets::from_str(&directives)
+                .wrap_err_with(|| format!("Invalid CW_LOG filter '{}'", directives)),
+            _ => Ok(log_targets(self.log_filter(), self.verbose_sdk)),
+        }
+    }
+
+    fn progress_reporter(&self) -> eyre::Result<Arc<dyn ProgressReporter>> {
+        match (self.progress_fd, &self.progress_file) {
+            (Some(fd), _) => Ok(Arc::new(JsonReporter::from_fd(fd)?)),
+            (None, Some(path)) => Ok(Arc::new(JsonReporter::from_path(path)?)),
+            (None, None) => Ok(Arc::new(NoopReporter)),
+        }
+    }
+
     fn setup_logging(&self, config: &LocalConfigManager) -> eyre::Result<()> {
         let log_path = config
             .get_log_path()
-            .context("Failed constructing file sink log path")?;
+            .context("Failed constructing file sink log path");
 
-        let file = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(log_path)
-            .context("Failed to open log file")?;
+        let file = log_path.and_then(|log_path| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_path)
+                .context("Failed to open log file")
+        });
+        let file = match file {
+            Ok(file) => file,
+            Err(err) if is_read_only(&err) => {
+                Ui::new(self.quiet).warn(format!(
+                    "Can't write to the log file ({:#}), logging is off this run.",
+                    err
+                ));
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
 
         let file_layer = fmt::Layer::default()
             .with_writer(file)
             .with_ansi(true)
             .with_target(true)
-            .with_filter(self.log_filter());
+            .with_filter(self.log_targets()?);
 
         tracing_subscriber::registry()
             .with(file_layer)
@@ -125,15 +248,21 @@ impl Cw {
         let config = LocalConfigManager::new();
         self.setup_logging(&config)?;
 
-        tracing::info!(target: "cw", "🐾 cw starting up!");
+        tracing::info!(target: "cw", "🐾 cw starting up! {}", build_info::summary());
         let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()?;
 
         tracing::info!(target: "cw", "running command {}", &self.cmd);
-        tracing::trace!(target: "cw", "log level: {}", self.log_filter());
+        tracing::trace!(target: "cw", "log level: {}, sdk: {}", self.log_filter(), self.verbose_sdk);
 
         let result = runtime.block_on(self.invoke_sub_command(config));
+        if result.as_ref().is_err_and(is_deadline_exceeded) {
+            // NOTE: Dropping the runtime waits for blocking tasks, like a read from a stdin that
+            // never ends. Past the deadline those are abandoned instead.
+            runtime.shutdown_background();
+            let _ = std::io::stdout().flush();
+        }
 
         if let Err(msg) = &result {
             tracing::error!(target: "cw", "failed running command {}, error={} cause={}", &self.cmd, msg, msg.root_cause());
@@ -148,23 +277,348 @@ impl Cw {
         T: ConfigManager,
     {
         let filter = self.log_filter();
+        let ui = Ui::new(self.quiet);
+        // NOTE: `cw config` is how a broken config file gets fixed, so it still runs on the
+        // defaults when the file doesn't load.
+        let settings = match (Config::load(&config.get_config_path()?), &self.cmd) {
+            (Err(err), CwCmd::Config(_)) => {
+                ui.warn(format!(
+                    "Can't load the config file ({:#}), using the defaults.",
+                    err
+                ));
+                Config::default()
+            }
+            (settings, _) => settings?,
+        };
+        let connection_stats = ConnectionStats::new();
+        let audit_log = settings.audit.is_enabled().then(AuditLog::new);
+        let rate_limiter = Arc::new(RateLimiter::new(
+            self.tps.or(settings.defaults.tps).unwrap_or(DEFAULT_TPS),
+        ));
+        let reporter = self.progress_reporter()?;
         let client_builder = LogClientBuilder::new()
-            .use_profile_name(self.profile.clone())
-            .use_region(self.region.clone());
+            .use_profile_name(self.profile.clone().or_else(|| settings.profile()))
+            .use_region(self.region.clone().or_else(|| settings.region()))
+            .use_connection_stats(connection_stats.clone())
+            .use_audit_log(audit_log.clone())
+            .use_rate_limiter(rate_limiter.clone())
+            .use_progress_reporter(reporter.clone());
 
-        let path = config.get_db_path()?;
-        let db = Sqlite::new(&path).await?;
+        let instance_lock = acquire_instance_lock(&config);
+        let contended = instance_lock
+            .as_ref()
+            .is_some_and(InstanceLock::is_contended);
+        if let (Some(lock), CwCmd::Query(_)) = (&instance_lock, &self.cmd) {
+            if lock.is_contended() {
+                ui.warn(format!(
+                    "Another cw process ({}) is using the data directory, query history writes wait for it.",
+                    lock.holder()
+                ));
+            }
+        }
+        let db = open_database(&config, &settings, contended, ui).await?;
+
+        // NOTE: The check runs next to the command and never holds it up, a check that didn't
+        // finish by the end of the command is dropped and tried again next run.
+        let update_check = UpdateCheck::from_config(&config, &settings)
+            .filter(UpdateCheck::is_due)
+            .map(|check| {
+                let refresh = check.spawn_refresh();
+                (check, refresh)
+            });
 
         if filter == LevelFilter::TRACE {
             let version = db.sqlite_version().await?;
             tracing::trace!(target: "cw", "SQLite Version: {}", version);
         }
 
-        match &self.cmd {
-            CwCmd::Ls(list) => list.run(&client_builder).await,
-            CwCmd::Tail(tail) => tail.run(&client_builder).await,
-            CwCmd::Query(query) => query.run(&client_builder, db).await,
-            CwCmd::Info(info) => info.run(&config, db).await,
+        if let (Some(deadline), CwCmd::Tail(tail)) = (self.deadline, &self.cmd) {
+            if tail.follow {
+                ui.warn(format!(
+                    "The follow stops once the --deadline of {} passes.",
+                    humantime::format_duration(deadline)
+                ));
+            }
         }
+
+        let started = query::StartedQueries::new();
+        let command = async {
+            match &self.cmd {
+                CwCmd::Ls(list) => list.run(&client_builder, db.clone(), ui).await,
+                CwCmd::Tail(tail) => {
+                    tail.with_defaults(&settings)?
+                        .run(&client_builder, db.clone(), &settings.archive, reporter, ui)
+                        .await
+                }
+                CwCmd::Query(query) => {
+                    query
+                        .with_defaults(&settings)?
+                        .run(&client_builder, db.clone(), reporter.as_ref(), &started, ui)
+                        .await
+                }
+                CwCmd::Info(info) => info.run(&config, db.clone(), instance_lock.as_ref()).await,
+                CwCmd::Audit(cmd) => cmd.run(&settings.audit, db.clone(), ui).await,
+                CwCmd::Archive(cmd) => cmd.run(db.clone(), ui).await,
+                CwCmd::Render(render) => render.run(ui).await,
+                CwCmd::Retention(cmd) => cmd.run(&client_builder, ui).await,
+                CwCmd::Config(cmd) => cmd.run(&config).await,
+            }
+        };
+        let result = match self.deadline {
+            Some(deadline) => match tokio::time::timeout(deadline, command).await {
+                Ok(result) => result,
+                Err(_) => Err(self
+                    .deadline_exceeded(deadline, &client_builder, &db, &started, ui)
+                    .await),
+            },
+            None => command.await,
+        };
+
+        connection_stats.report(&ui);
+        if let Some((check, refresh)) = update_check {
+            if let Some(notice) = check.finish(refresh) {
+                ui.note(notice);
+            }
+        }
+        rate_limiter.report();
+        client_builder.metrics().report();
+        if let Some(audit_log) = audit_log {
+            let retention = settings.audit.retention()?;
+            if let Err(err) = audit_log.persist(&db, retention).await {
+                ui.warn(format!("Failed writing the audit log: {}", err));
+            }
+        }
+        result
+    }
+
+    /// The error for a command cut short by `deadline`. The queries it was waiting on are stopped
+    /// and marked Interrupted, like after a Ctrl-C.
+    async fn deadline_exceeded(
+        &self,
+        deadline: Duration,
+        client_builder: &LogClientBuilder,
+        db: &impl Database,
+        started: &query::StartedQueries,
+        ui: Ui,
+    ) -> eyre::Report {
+        let in_progress = match interrupt_queries(db, started).await {
+            Ok(query_ids) if !query_ids.is_empty() => {
+                // NOTE: Past the deadline cw must not hang on an unreachable endpoint, the
+                // queries are stopped on a best-effort basis.
+                let stopped = stop_queries(client_builder, &query_ids, ui);
+                if tokio::time::timeout(STOP_QUERIES_TIMEOUT, stopped)
+                    .await
+                    .is_err()
+                {
+                    tracing::warn!(target: "cw", "gave up stopping queries {}", query_ids.join(", "));
+                }
+                format!(
+                    "cw {} was waiting for query {}",
+                    self.cmd,
+                    query_ids.join(", ")
+                )
+            }
+            Ok(_) => format!("cw {} was still running", self.cmd),
+            Err(err) => {
+                tracing::warn!(target: "cw", "failed marking queries as interrupted: {:?}", err);
+                format!("cw {} was still running", self.cmd)
+            }
+        };
+        DeadlineExceeded {
+            deadline,
+            in_progress,
+        }
+        .into()
+    }
+}
+
+/// Stops the running `query_ids`, see [`query::stop_query`].
+async fn stop_queries(client_builder: &LogClientBuilder, query_ids: &[String], ui: Ui) {
+    let client = match client_builder.build().await {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(target: "cw", "failed stopping the queries: {:?}", err);
+            return;
+        }
+    };
+    let limiter = client_builder.rate_limiter();
+    for query_id in query_ids {
+        query::stop_query(&client, &limiter, query_id, ui).await;
+    }
+}
+
+/// Marks the history rows of the `started` queries that haven't reached a final status as
+/// Interrupted, returning the ids of the queries that were started.
+async fn interrupt_queries(
+    db: &impl Database,
+    started: &query::StartedQueries,
+) -> eyre::Result<Vec<String>> {
+    let mut query_ids = Vec::new();
+    for id in started.ids() {
+        let Some(mut history) = db.get(&id).await? else {
+            continue;
+        };
+        if !history.status.is_in_flight() && history.status != QueryStatus::Queued {
+            continue;
+        }
+        history.set_status(QueryStatus::Interrupted);
+        db.update(&history).await?;
+        if !history.query_id.is_empty() {
+            query_ids.push(history.query_id);
+        }
+    }
+    db.flush().await?;
+    Ok(query_ids)
+}
+
+/// Locks the data directory for this run. When the lock file can't be created, e.g. in a
+/// read-only data directory, cw goes on as if no other process were running.
+fn acquire_instance_lock(config: &impl ConfigManager) -> Option<InstanceLock> {
+    let db_path = config.get_db_path().ok()?;
+    match InstanceLock::acquire(db_path.parent()?) {
+        Ok(lock) => Some(lock),
+        Err(err) => {
+            tracing::debug!(target: "cw", "running without the instance lock: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Opens the database in the data directory. When that directory can't be written to, e.g. a
+/// read-only home directory, this run goes on with an in-memory database instead. With
+/// `contended` set another cw process holds the instance lock, and history writes are retried
+/// while it has the database busy.
+async fn open_database(
+    config: &impl ConfigManager,
+    settings: &Config,
+    contended: bool,
+    ui: Ui,
+) -> eyre::Result<Sqlite> {
+    let opened = match config.get_db_path() {
+        Ok(path) => {
+            Sqlite::new(&SqliteSettings {
+                path,
+                journal_mode: settings.database.journal_mode,
+                retry_writes: contended,
+            })
+            .await
+        }
+        Err(err) => Err(err),
+    };
+
+    match opened {
+        Ok(db) => Ok(db),
+        Err(err) if is_read_only(&err) => {
+            tracing::warn!(target: "cw", "falling back to an in-memory database: {:?}", err);
+            ui.warn(format!(
+                "Can't write to the data directory ({:#}), query history and archives are not saved this run.",
+                err
+            ));
+            Sqlite::in_memory().await
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Logs cw's own targets at `level`. Everything else, which is mostly the AWS SDK, hyper and
+/// rustls, is capped at warnings unless `verbose_sdk` is set, they drown out cw's lines otherwise.
+fn log_targets(level: LevelFilter, verbose_sdk: bool) -> Targets {
+    let others = if verbose_sdk {
+        level
+    } else {
+        level.min(LevelFilter::WARN)
+    };
+
+    Targets::new().with_target("cw", level).with_default(others)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use crate::commands::query::StartedQueries;
+    use crate::commands::{interrupt_queries, open_database};
+    use crate::config::{Config, ConfigManager};
+    use crate::db::{Database, QueryHistory, QueryStatus};
+    use crate::memory_db::InMemoryDb;
+    use crate::ui::Ui;
+
+    /// A data directory cw isn't allowed to write to.
+    #[derive(Clone)]
+    struct DeniedDataDir;
+
+    impl ConfigManager for DeniedDataDir {
+        fn get_db_path(&self) -> eyre::Result<PathBuf> {
+            Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into())
+        }
+
+        fn get_log_path(&self) -> eyre::Result<PathBuf> {
+            self.get_db_path()
+        }
+
+        fn get_config_path(&self) -> eyre::Result<PathBuf> {
+            self.get_db_path()
+        }
+
+        fn get_state_path(&self) -> eyre::Result<PathBuf> {
+            self.get_db_path()
+        }
+    }
+
+    #[tokio::test]
+    async fn a_read_only_data_directory_falls_back_to_an_in_memory_database() {
+        let db = open_database(&DeniedDataDir, &Config::default(), false, Ui::new(true))
+            .await
+            .unwrap();
+
+        let history = QueryHistory::new("q-1".to_string(), "fields @message".to_string());
+        db.save(&history).await.unwrap();
+        assert_eq!(db.list().await.unwrap().len(), 1);
+    }
+
+    async fn saved(db: &InMemoryDb, query_id: &str, status: QueryStatus) -> QueryHistory {
+        let mut history = QueryHistory::new(query_id.to_string(), "fields @message".to_string());
+        history.set_status(status);
+        db.save(&history).await.unwrap();
+        history
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_deadline_only_interrupts_the_queries_of_this_run() {
+        let db = InMemoryDb::new();
+        let started = StartedQueries::new();
+        // NOTE: Another cw process, started after this one, is waiting on a query of its own.
+        saved(&db, "q-other", QueryStatus::Running).await;
+        for (query_id, status) in [
+            ("q-1", QueryStatus::Running),
+            ("", QueryStatus::Queued),
+            ("q-2", QueryStatus::Complete),
+        ] {
+            started.record(saved(&db, query_id, status).await.id());
+        }
+
+        let hung = std::future::pending::<eyre::Result<()>>();
+        let result = tokio::time::timeout(Duration::from_secs(30), hung).await;
+        assert!(result.is_err());
+        let query_ids = interrupt_queries(&db, &started).await.unwrap();
+
+        assert_eq!(query_ids, ["q-1"]);
+        let statuses: Vec<_> = db
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|h| (h.query_id, h.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("q-other".to_string(), QueryStatus::Running),
+                ("q-1".to_string(), QueryStatus::Interrupted),
+                (String::new(), QueryStatus::Interrupted),
+                ("q-2".to_string(), QueryStatus::Complete),
+            ]
+        );
     }
 }
@@ -1,60 +1,375 @@
 use std::fmt::Display;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use aws_sdk_cloudwatchlogs::types::QueryStatus;
+use aws_sdk_cloudwatchlogs::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_cloudwatchlogs::operation::start_query::{StartQueryError, StartQueryOutput};
 use chrono::Utc;
-use clap::{Args, Subcommand};
+use clap::{Args, Subcommand, ValueEnum};
 use eyre::Context;
-use serde_json::{Map, Value};
+use serde_json::{json, Map, Value};
 use tabwriter::TabWriter;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use tokio::time::sleep;
+use uuid::Uuid;
+use yansi::Style;
 
+use crate::assertion::{AssertionOutcome, Report, RowAssertion};
+use crate::aws::find_log_group;
+use crate::chunked_export::{parse_byte_size, ChunkLimit, ChunkedExport};
+use crate::commands::group_selector::{GroupRules, GroupSelector};
 use crate::commands::LogClientBuilder;
+use crate::compat::{classify_error, result_fields, ApiErrorKind, QueryState, QueryStats};
+use crate::config::Config;
 use crate::db::{Database, QueryHistory};
 use crate::editor::open_in_editor;
-use crate::utils::parse_human_time;
+use crate::group_cache::GroupCache;
+use crate::group_check::ensure_groups_exist;
+use crate::insights;
+use crate::logs_api::{DescribeLogGroupsRequest, LogsApi, StartQueryRequest};
+use crate::progress_reporter::{ProgressEvent, ProgressReporter};
+use crate::rate_limit::RateLimiter;
+use crate::row_filter::RowFilter;
+use crate::stable_rows::{row_hash, RowSort, ROW_HASH_FIELD};
+use crate::summary::{FieldSummary, Summarizer};
+use crate::table::{Cell, Table};
+use crate::ui::Ui;
+use crate::utils::{
+    check_retention_all, format_datetime, format_duration_ms, group_thousands, humanize_bytes,
+    parse_human_time, parse_insights_timestamp, parse_timestamp, truncate_text, RangeRules,
+    TimeRange, TimeStyle, MIN_RETENTION_MS,
+};
 
-#[derive(Args, Debug)]
+// NOTE: StartQuery accepts at most 50 log groups, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_StartQuery.html#CWL-StartQuery-request-logGroupNames
+const MAX_QUERY_GROUPS: usize = 50;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// NOTE: Field hints are a nicety, the editor opens without them rather than keep anyone waiting.
+const FIELD_HINTS_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_FIELD_HINT_GROUPS: usize = 10;
+
+// NOTE: A missing or unknown status is most likely a hiccup on the AWS side, so poll a few more
+// times before giving up on the query.
+const MAX_UNEXPECTED_STATUS_POLLS: u32 = 5;
+
+const QUEUE_BACKOFF_INITIAL: Duration = Duration::from_secs(2);
+const QUEUE_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+// NOTE: Insights queries time out after 60 minutes, a row still in flight after that was
+// abandoned by a previous run, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/CWL_QuerySyntax.html
+const MAX_QUERY_RUNTIME_MINUTES: i64 = 60;
+
+// NOTE: Insights keeps the results and status of a query for 7 days, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/AnalyzingLogData.html
+const QUERY_RESULTS_RETENTION_DAYS: i64 = 7;
+
+const DEFAULT_CHUNK_ROWS: u64 = 100_000;
+
+/// The history rows of the queries this process started, so a run cut short only marks its own
+/// rows and leaves those of other cw processes alone.
+#[derive(Debug, Clone, Default)]
+pub struct StartedQueries {
+    ids: Arc<Mutex<Vec<String>>>,
+}
+
+impl StartedQueries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, id: &str) {
+        if let Ok(mut ids) = self.ids.lock() {
+            ids.push(id.to_string());
+        }
+    }
+
+    /// The ids of the history rows, in the order the queries were started.
+    pub fn ids(&self) -> Vec<String> {
+        self.ids.lock().map(|ids| ids.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(Args, Clone, Debug)]
 #[command(args_conflicts_with_subcommands = true)]
 pub struct Cmd {
     #[arg(index = 1, value_name = "file_or_query_name")]
     pub file_or_query_name: Option<String>,
 
-    #[arg(short, long, required = true)]
+    #[arg(
+        short,
+        long,
+        help = "Log groups to query. Repeat the flag or pass a comma separated list. Defaults to the '#! groups:' directive of the query."
+    )]
     pub group_names: Vec<String>,
 
-    #[arg(short, long, value_parser = parse_human_time)]
+    #[arg(
+        short,
+        long,
+        value_parser = parse_human_time,
+        help = "The UTC start time, as a date/time, a duration back from now or 'now'. Defaults to the query's directives, then 30 seconds ago, or now with --no-lookback."
+    )]
     pub start_time: Option<i64>,
 
+    #[arg(
+        long,
+        conflicts_with = "start_time",
+        help = "Without a start time from a flag or directive, start at launch instead of 30 seconds back. Set defaults.lookback = false to make it the default."
+    )]
+    pub no_lookback: bool,
+
     #[arg(short, long, value_parser = parse_human_time)]
     pub end_time: Option<i64>,
 
+    #[arg(
+        short = 'w',
+        long,
+        value_name = "NAME",
+        help = "Use a time window from the config file. --start-time and --end-time take precedence."
+    )]
+    pub window: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "Render @timestamp and @ingestionTime as RFC3339 in the local timezone."
+    )]
+    pub local: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["local", "epoch_timestamps"],
+        help = "Render @timestamp and @ingestionTime as RFC3339 in UTC."
+    )]
+    pub utc: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "local",
+        help = "Render @timestamp and @ingestionTime as epoch milliseconds."
+    )]
+    pub epoch_timestamps: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with = "epoch_timestamps",
+        help = "How to render @timestamp and @ingestionTime, by default they are left as Insights returns them."
+    )]
+    pub time_style: Option<TimeStyle>,
+
+    #[arg(
+        long,
+        help = "Submit lines starting with '#' as part of the query instead of stripping them."
+    )]
+    pub no_strip_comments: bool,
+
+    #[arg(
+        long,
+        help = "Print the resolved groups, time window and query without starting it."
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        default_value = "5m",
+        help = "How long to wait for a free slot when the account is at its concurrent Insights query limit."
+    )]
+    pub max_queue_wait: Duration,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..=insights::MAX_RESULT_ROWS as i64),
+        help = "Append `| limit N` to the query when it doesn't have a limit. Refused for stats queries."
+    )]
+    pub ensure_limit: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Print a per-field summary (counts, distinct values, numeric range and top values) instead of the rows."
+    )]
+    pub summarize: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "summarize",
+        help = "Print the per-field summary after the rows."
+    )]
+    pub summarize_append: bool,
+
+    #[arg(
+        long,
+        help = "While the query is open in the editor, connect to CloudWatch Logs with a single cheap request, so the query starts right away on save."
+    )]
+    pub warm_up: bool,
+
+    #[arg(
+        long,
+        help = "Open the editor without the comment listing the fields discovered in the log groups."
+    )]
+    pub no_field_hints: bool,
+
+    #[arg(
+        long = "where",
+        value_name = "EXPR",
+        value_parser = RowFilter::parse,
+        help = "Only print the result rows matching this expression, e.g. 'status >= 500 && path != \"/health\"'. Supports ==, !=, <, <=, >, >=, contains, &&, || and !."
+    )]
+    pub where_filter: Option<RowFilter>,
+
+    #[arg(
+        long,
+        value_name = "FIELDS",
+        help = "Sort the result rows by these comma separated fields before printing, numbers numerically. Ties are ordered by the row contents, so two runs print their rows in the same order."
+    )]
+    pub sort_output: Option<RowSort>,
+
+    #[arg(
+        long,
+        help = "Add a _hash field to every row, the SHA-256 of its fields and values in key order, to diff the rows of two runs."
+    )]
+    pub row_hash: bool,
+
+    #[arg(
+        long,
+        help = "Skip checking that the log groups exist before starting the query."
+    )]
+    pub no_validate: bool,
+
+    #[arg(
+        long,
+        help = "Move the start time up to the earliest event any of the log groups still retains, when it predates all of them."
+    )]
+    pub clamp_to_retention: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Also write the result rows to this file as JSON lines, even with --summarize. The file only appears once every row is written."
+    )]
+    pub tee: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "assert_max",
+        help = "Fail when the query returns any rows, after printing them. For CI checks like no errors in the canary window."
+    )]
+    pub assert_empty: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Fail when the query returns more than N rows, after printing them."
+    )]
+    pub assert_max: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "REPORT",
+        help = "Also report the --assert-empty or --assert-max outcome as junit:<path>, a JUnit XML file, or github, an ::error:: annotation for GitHub Actions. Repeat the flag for both."
+    )]
+    pub report: Vec<Report>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write the result rows to JSON lines part files in this directory instead of stdout, with a manifest.json listing them. The directory has to be new or empty."
+    )]
+    pub output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "output_dir",
+        conflicts_with = "chunk_bytes",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Rows per part file with --output-dir, defaults to 100000."
+    )]
+    pub chunk_rows: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        requires = "output_dir",
+        value_parser = parse_byte_size,
+        help = "Start the next part file with --output-dir before one grows past this size, e.g. 64MiB."
+    )]
+    pub chunk_bytes: Option<u64>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Clone, Debug)]
 pub enum Commands {
-    History,
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommands>,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum HistoryCommands {
+    /// Print the cw invocation that reproduces a query run.
+    Command {
+        id: String,
+
+        #[arg(long, value_enum, default_value_t = ExportFormat::Shell)]
+        format: ExportFormat,
+    },
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum ExportFormat {
+    Shell,
+    Json,
 }
 
 impl Display for Commands {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Commands::History => write!(f, "history"),
+            Commands::History { command: None } => write!(f, "history"),
+            Commands::History {
+                command: Some(HistoryCommands::Command { id, .. }),
+            } => write!(f, "history command <{}>", id),
         }
     }
 }
 
 impl Cmd {
-    pub async fn run(&self, builder: &LogClientBuilder, db: impl Database) -> eyre::Result<()> {
+    /// Resolves the --window from the config file into the start and end time, as if they were
+    /// passed as flags.
+    pub fn with_defaults(&self, config: &Config) -> eyre::Result<Self> {
+        let mut cmd = self.clone();
+        cmd.no_lookback |= !config.defaults.lookback();
+
+        if let Some(name) = &self.window {
+            let range = TimeRange::new(self.start_time, self.end_time).or(config.window(name)?);
+            cmd.start_time = range.start;
+            cmd.end_time = range.end;
+        }
+        Ok(cmd)
+    }
+
+    pub async fn run(
+        &self,
+        builder: &LogClientBuilder,
+        db: impl Database,
+        reporter: &dyn ProgressReporter,
+        started: &StartedQueries,
+        ui: Ui,
+    ) -> eyre::Result<()> {
         match &self.command {
-            None => self.run_query(builder, db).await,
+            None => self.run_query(builder, db, reporter, started, ui).await,
             Some(cmd) => self.run_command(cmd, db).await,
         }
     }
@@ -63,6 +378,12 @@ impl Cmd {
         &self,
         file_or_query_name: &str,
     ) -> eyre::Result<String> {
+        if file_or_query_name == "-" {
+            let mut query = String::new();
+            tokio::io::stdin().read_to_string(&mut query).await?;
+            return Ok(query);
+        }
+
         // FIX: for now fail until stored queries are implemented.
         let path = PathBuf::from_str(file_or_query_name)?;
 
@@ -80,70 +401,341 @@ impl Cmd {
         &self,
         builder: &LogClientBuilder,
         db: impl Database,
+        reporter: &dyn ProgressReporter,
+        started: &StartedQueries,
+        ui: Ui,
     ) -> eyre::Result<()> {
+        if !self.report.is_empty() && self.assertion().is_none() {
+            return Err(eyre::eyre!(
+                "--report needs --assert-empty or --assert-max to report on."
+            ));
+        }
+
         let client = builder.build().await?;
+        let mut warm_up = None;
         let query = if let Some(file_or_query_name) = &self.file_or_query_name {
             self.get_query_from_file_or_query_name(file_or_query_name)
                 .await?
         } else {
-            let sample = "# vim: ft=lq\n";
-            let query = open_in_editor(sample, None)?;
+            if self.warm_up {
+                warm_up = Some(tokio::spawn(warm_up_connection(
+                    client.clone(),
+                    builder.rate_limiter(),
+                )));
+            }
+
+            let hints = if self.no_field_hints || self.group_names.is_empty() {
+                String::new()
+            } else {
+                let group_names = self.group_names(&[])?;
+                let fields = discover_fields(&client, &builder.rate_limiter(), &group_names).await;
+                insights::field_hints(&fields)
+            };
+            let sample = format!("# vim: ft=lq\n{}", hints);
+            let contents = if hints.is_empty() {
+                sample.clone()
+            } else {
+                format!("{}{}", sample, insights::STARTER_QUERY)
+            };
+
+            // NOTE: The editor blocks until it exits, on a blocking thread the runtime is free to
+            // run the warm-up in the meantime.
+            let query =
+                tokio::task::spawn_blocking(move || open_in_editor(&contents, None)).await??;
 
             query
-                .strip_prefix(sample)
+                .strip_prefix(sample.as_str())
                 .unwrap_or(query.as_str())
                 .to_string()
         };
+        // NOTE: A failed warm-up only matters when the query itself fails to start as well.
+        let warm_up_error = match warm_up {
+            Some(handle) => match handle.await {
+                Ok(result) => result.err(),
+                Err(err) => Some(eyre::eyre!(err)),
+            },
+            None => None,
+        };
+        let contents = query;
+        let (front_matter, body) = insights::parse_front_matter(&contents)?;
+        for directive in &front_matter.unknown {
+            ui.warn(format!("ignoring unknown directive '#! {}'.", directive));
+        }
+        let mut query = insights::sanitize(&body, self.no_strip_comments)?;
+        if let Some(limit) = self.ensure_limit {
+            query = insights::ensure_limit(&query, limit)?;
+        }
+        let mut shape = insights::query_shape(&query);
+        shape.has_limit |= front_matter.limit.is_some();
+        let group_names = self.group_names(&front_matter.groups)?;
 
-        let query_result = client
-            .start_query()
-            .set_log_group_names(Some(self.group_names.clone()))
-            .query_string(&query)
-            .start_time(
-                self.start_time
-                    // TODO: set start to 1h ago by default
-                    .unwrap_or_else(|| (Utc::now().timestamp() - 30) * 1000),
-            )
-            .end_time(
-                self.end_time
-                    .unwrap_or_else(|| Utc::now().timestamp() * 1000),
-            )
-            .send()
+        // NOTE: Flags win over the file's directives, which win over the defaults.
+        let now = Utc::now();
+        let front_matter_start = front_matter.start.or_else(|| {
+            front_matter
+                .last
+                .and_then(|last| chrono::TimeDelta::from_std(last).ok())
+                .map(|last| (now - last).timestamp_millis())
+        });
+        // TODO: set start to 1h ago by default
+        let range = TimeRange::new(self.start_time, self.end_time)
+            .or(TimeRange::new(front_matter_start, front_matter.end))
+            .resolve(RangeRules {
+                now,
+                lookback: !self.no_lookback,
+                follow: false,
+                future_end: false,
+                local: self.local,
+            })?;
+        let mut start_time = range.start;
+        let end_time = range.end_or(now.timestamp_millis());
+
+        if self.dry_run {
+            let plan = self.render_plan(
+                &group_names,
+                &query,
+                start_time,
+                end_time,
+                builder.profile_name(),
+                client.region(),
+            )?;
+            print!("{}", plan);
+            return Ok(());
+        }
+
+        let limiter = builder.rate_limiter();
+        if !self.no_validate {
+            let cache = GroupCache::new(db.clone(), builder, &client);
+            ensure_groups_exist(&client, &limiter, &cache, &group_names).await?;
+        }
+        start_time = self
+            .retention_start_time(&client, &limiter, &group_names, start_time, ui)
+            .await?;
+
+        let profile = builder.profile_name();
+        let region = client.region();
+        if let Err(err) = reconcile_stale_history(
+            &client,
+            &limiter,
+            &db,
+            profile.as_deref(),
+            region.as_deref(),
+        )
+        .await
+        {
+            tracing::warn!("failed reconciling stale query history: {:?}", err);
+        }
+
+        // NOTE: History keeps the query as written, comments included, so no context is lost.
+        let mut history = QueryHistory::new(String::new(), contents);
+        history.set_context(&group_names, start_time, end_time, profile, region)?;
+        started.record(history.id());
+
+        let request = StartQueryRequest {
+            group_names: group_names.clone(),
+            query: query.clone(),
+            start_time,
+            end_time,
+            limit: front_matter.limit,
+        };
+        let query_result = match self
+            .start_query(&client, request, &limiter, reporter, ui, &db, &mut history)
             .await
-            .context("Failed to fetch CloudWatch logs.")?;
+        {
+            Ok(result) => result,
+            Err(err) => {
+                if history.status == crate::db::QueryStatus::Queued {
+                    history.set_status(crate::db::QueryStatus::Failed);
+                    db.update(&history).await?;
+                }
+                if let Some(warm_up_error) = &warm_up_error {
+                    ui.note(format!(
+                        "The connection warm-up while editing failed as well: {:#}",
+                        warm_up_error
+                    ));
+                }
+                return Err(explain_start_query_error(
+                    &client,
+                    &limiter,
+                    &group_names,
+                    &query,
+                    err,
+                )
+                .await);
+            }
+        };
 
         let Some(query_id) = query_result.query_id() else {
-            return Err(eyre::eyre!("File provided via -file does not exist!"));
+            return Err(eyre::eyre!("StartQuery did not return a query id."));
         };
 
         tracing::info!("Collecting events for query with id {}", query_id);
-        let mut history = QueryHistory::new(query_id.to_string(), query);
-        db.save(&history).await?;
+        let queued = history.status == crate::db::QueryStatus::Queued;
+        history.query_id = query_id.to_string();
+        history.set_status(crate::db::QueryStatus::Scheduled);
+        if queued {
+            db.update(&history).await?;
+        } else {
+            db.save(&history).await?;
+        }
+        // NOTE: Make sure the row survives a crash while polling, so it can be reconciled later.
+        db.flush().await?;
 
+        let outcome = tokio::select! {
+            outcome = self.poll_query(&client, &limiter, reporter, ui, &db, &mut history) => outcome,
+            _ = tokio::signal::ctrl_c() => {
+                stop_query(&client, &limiter, &history.query_id, ui).await;
+                Err(eyre::eyre!("Interrupted while waiting for query {}", history.query_id))
+            }
+        };
+
+        if outcome.is_err() && history.status.is_in_flight() {
+            history.set_status(crate::db::QueryStatus::Interrupted);
+            db.update(&history).await?;
+        }
+
+        if outcome.is_ok() {
+            if let Some(warning) =
+                insights::truncation_warning(shape, history.records_total, history.records_matched)
+            {
+                ui.warn(warning);
+            }
+        }
+
+        outcome
+    }
+
+    /// Sends `request`, waiting with backoff while the account is at its concurrent query limit,
+    /// for at most --max-queue-wait. While waiting the query is kept in history as Queued, so it
+    /// is not lost when cw dies before a slot frees up.
+    async fn start_query(
+        &self,
+        client: &impl LogsApi,
+        request: StartQueryRequest,
+        limiter: &RateLimiter,
+        reporter: &dyn ProgressReporter,
+        ui: Ui,
+        db: &impl Database,
+        history: &mut QueryHistory,
+    ) -> Result<StartQueryOutput, SdkError<StartQueryError>> {
+        // NOTE: Measured on the clock `sleep` uses, so the wait is the same one the backoff adds up.
+        let started = tokio::time::Instant::now();
+        let mut backoff = QUEUE_BACKOFF_INITIAL;
+        let mut attempt = 1;
         loop {
-            let output = client.get_query_results().query_id(query_id).send().await?;
+            limiter.acquire().await;
+            let err = match client.start_query(request.clone()).await {
+                Ok(output) => return Ok(output),
+                Err(err) => err,
+            };
+
+            let limited = classify_error(&err) == ApiErrorKind::LimitExceeded;
+            if !limited || started.elapsed() + backoff > self.max_queue_wait {
+                return Err(err);
+            }
 
-            match output.status {
-                Some(QueryStatus::Scheduled) => {
-                    tokio::time::sleep(Duration::from_secs(2)).await;
+            if history.status != crate::db::QueryStatus::Queued {
+                ui.note(format!(
+                    "Concurrent query limit reached, waiting up to {} for a free slot.",
+                    humantime::format_duration(self.max_queue_wait)
+                ));
+                history.set_status(crate::db::QueryStatus::Queued);
+                let saved = match db.save(history).await {
+                    Ok(()) => db.flush().await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = saved {
+                    tracing::warn!("failed saving queued query to history: {:?}", err);
+                }
+            }
+
+            tracing::debug!("query limit reached, retrying in {:?}", backoff);
+            attempt += 1;
+            reporter.report(ProgressEvent::Retrying {
+                operation: "StartQuery",
+                attempt,
+                delay_ms: Some(backoff.as_millis() as u64),
+            });
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(QUEUE_BACKOFF_MAX);
+        }
+    }
+
+    async fn poll_query(
+        &self,
+        client: &impl LogsApi,
+        limiter: &RateLimiter,
+        reporter: &dyn ProgressReporter,
+        ui: Ui,
+        db: &impl Database,
+        history: &mut QueryHistory,
+    ) -> eyre::Result<()> {
+        let query_id = history.query_id.clone();
+        let query_id = query_id.as_str();
+        let started = Instant::now();
+        let mut progress = ui.progress();
+        let mut last_status = None;
+        let mut unexpected_polls = 0;
+        loop {
+            limiter.acquire().await;
+            let mut output = client.get_query_results(query_id).await?;
+
+            let state = QueryState::of(output.status.as_ref());
+            let statistics = output.statistics().map(QueryStats::from);
+            if last_status.as_ref() != Some(&state) {
+                reporter.report(ProgressEvent::QueryStatus {
+                    query_id,
+                    status: state.as_str(),
+                    records_matched: statistics.map(|s| s.records_matched),
+                    records_scanned: statistics.map(|s| s.records_scanned),
+                    bytes_scanned: statistics.map(|s| s.bytes_scanned),
+                });
+                last_status = Some(state.clone());
+            }
+
+            match state {
+                QueryState::Scheduled => {
+                    progress.update(format!(
+                        "query {} scheduled… {}",
+                        query_id,
+                        format_duration_ms(elapsed_ms(started))
+                    ));
+                    tokio::time::sleep(POLL_INTERVAL).await;
                     continue;
                 }
-                Some(QueryStatus::Running) => {
+                QueryState::Running => {
+                    progress.update(format!(
+                        "query {} running… {}",
+                        query_id,
+                        format_duration_ms(elapsed_ms(started))
+                    ));
                     history.set_status(crate::db::QueryStatus::Running);
                     db.update(&history).await?;
-                    sleep(Duration::from_secs(2)).await;
+                    sleep(POLL_INTERVAL).await;
                     continue;
                 }
-                Some(QueryStatus::Complete) => {
-                    let statistics = output.statistics().unwrap();
-                    let results = output.results();
+                QueryState::Complete => {
+                    progress.clear();
+                    // NOTE: Taken out of the response so every row is dropped once it is written,
+                    // instead of being kept around next to its JSON copy.
+                    let results = output.results.take().unwrap_or_default();
+                    let (records_matched, records_scanned, bytes_scanned) = match statistics {
+                        Some(s) => (s.records_matched, s.records_scanned, s.bytes_scanned),
+                        None => {
+                            ui.warn(format!(
+                                "query {} completed without statistics, reporting them as 0.",
+                                query_id
+                            ));
+                            (0.0, 0.0, 0.0)
+                        }
+                    };
 
                     history.set_status(crate::db::QueryStatus::Complete);
                     history.set_statistics(
                         results.len() as i64,
-                        statistics.records_matched,
-                        statistics.records_scanned,
-                        statistics.bytes_scanned,
+                        records_matched,
+                        records_scanned,
+                        bytes_scanned,
                     );
                     db.update(&history).await?;
 
@@ -151,59 +743,135 @@ impl Cmd {
                     tracing::info!(
                         "[{}] showing: {} of {} records matched.",
                         query_id,
-                        history.records_total,
-                        history.records_matched
+                        group_thousands(history.records_total.max(0) as u64),
+                        group_thousands(history.records_matched as u64)
                     );
 
-                    let duration = history.modified_at - history.created_at;
+                    let duration_ms = elapsed_ms(started);
+                    reporter.report(ProgressEvent::QuerySummary {
+                        query_id,
+                        records_returned: results.len(),
+                        duration_ms,
+                    });
                     tracing::info!(
-                        "[{}] {} records ({} bytes) scanned in {},{}s.",
+                        "[{}] {} records ({}) scanned in {}.",
                         query_id,
-                        history.records_scanned,
-                        history.bytes_scanned,
-                        duration.num_seconds(),
-                        duration.num_milliseconds() - (duration.num_seconds() * 1000)
+                        group_thousands(history.records_scanned as u64),
+                        humanize_bytes(history.bytes_scanned as u64),
+                        format_duration_ms(duration_ms)
                     );
 
-                    for line in results {
-                        let mut json = Map::new();
-                        for record in line {
-                            if let Some(field) = record.field() {
-                                // NOTE: Expose a flag wether to log the ptr or not.
-                                if field == "@ptr" {
-                                    continue;
-                                }
-
-                                json.insert(
-                                    field.to_string(),
-                                    Value::String(record.value().unwrap_or("").to_string()),
-                                );
+                    // NOTE: Rows are written one at a time, the summary and assertion only keep
+                    // what they report on, so a wide result isn't held in memory twice. Only
+                    // --sort-output has to see every row before writing the first.
+                    let rows = results
+                        .into_iter()
+                        .map(|line| self.result_row(result_fields(&line)))
+                        .filter(|row| {
+                            self.where_filter
+                                .as_ref()
+                                .is_none_or(|filter| filter.matches(row))
+                        });
+                    let rows: Box<dyn Iterator<Item = Map<String, Value>>> = match &self.sort_output
+                    {
+                        Some(sort) => Box::new(sort.sort(rows.collect()).into_iter()),
+                        None => Box::new(rows),
+                    };
+
+                    let mut tee = self.tee.as_deref().map(TeeFile::create).transpose()?;
+                    let mut export = self
+                        .output_dir
+                        .as_deref()
+                        .map(|dir| {
+                            ChunkedExport::create(
+                                dir,
+                                self.chunk_limit(),
+                                &history.contents,
+                                history.start_time,
+                                history.end_time,
+                            )
+                        })
+                        .transpose()?;
+                    let mut summarizer =
+                        (self.summarize || self.summarize_append).then(Summarizer::new);
+                    let mut outcome = self.assertion().map(|assertion| assertion.start());
+                    let mut stdout = std::io::stdout().lock();
+                    for mut row in rows {
+                        // NOTE: The summary leaves the hash out, every row has a different one.
+                        if let Some(summarizer) = summarizer.as_mut() {
+                            summarizer.add(&row);
+                        }
+                        if self.row_hash {
+                            row.insert(ROW_HASH_FIELD.to_string(), Value::String(row_hash(&row)));
+                        }
+
+                        match export.as_mut() {
+                            Some(export) => export.write_row(&row)?,
+                            None if !self.summarize => {
+                                serde_json::to_writer(&mut stdout, &row)?;
+                                writeln!(stdout)?;
                             }
+                            None => {}
                         }
-                        println!("{}", serde_json::to_string(&json)?);
+                        if let Some(tee) = tee.as_mut() {
+                            tee.write_row(&row)?;
+                        }
+                        if let Some(outcome) = outcome.as_mut() {
+                            outcome.record(&row);
+                        }
+                    }
+                    stdout.flush()?;
+                    drop(stdout);
+
+                    if let Some(tee) = tee {
+                        tee.commit()?;
+                    }
+                    if let Some(export) = export {
+                        let manifest = export.finish()?;
+                        ui.note(format!("Wrote the result rows, see {}", manifest.display()));
+                    }
+                    if let Some(summarizer) = summarizer {
+                        print_summary(&summarizer.finish())?;
+                    }
+                    if let Some(outcome) = outcome {
+                        self.check_assertion(&outcome)?;
                     }
                     break;
                 }
-                Some(QueryStatus::Failed) => {
+                QueryState::Failed => {
                     history.set_status(crate::db::QueryStatus::Failed);
                     db.update(&history).await?;
                     return Err(eyre::eyre!("Query failed: {}", history.query_id));
                 }
-                Some(QueryStatus::Timeout) => {
+                QueryState::Timeout => {
                     history.set_status(crate::db::QueryStatus::Timeout);
                     db.update(&history).await?;
                     return Err(eyre::eyre!("Query timed out: {}", history.query_id));
                 }
-                None => {
-                    tracing::info!(
-                        "[{}] No status returned, unsure if I should proceed, exiting for now",
-                        query_id
-                    );
-                    break;
+                QueryState::Cancelled => {
+                    history.set_status(crate::db::QueryStatus::Cancelled);
+                    db.update(&history).await?;
+                    return Err(eyre::eyre!("Query was cancelled: {}", history.query_id));
                 }
-                _ => {
-                    tracing::error!("[{}] UNHANDLED status: {:?}", query_id, output.status);
-                    break;
+                status @ QueryState::Unknown(_) => {
+                    unexpected_polls += 1;
+                    if unexpected_polls > MAX_UNEXPECTED_STATUS_POLLS {
+                        return Err(eyre::eyre!(
+                            "Query {} kept reporting an unexpected status ({}), giving up.",
+                            history.query_id,
+                            status
+                        ));
+                    }
+
+                    tracing::warn!(
+                        "[{}] unexpected status {}, polling again ({}/{})",
+                        query_id,
+                        status,
+                        unexpected_polls,
+                        MAX_UNEXPECTED_STATUS_POLLS
+                    );
+                    sleep(POLL_INTERVAL).await;
+                    continue;
                 }
             }
         }
@@ -211,19 +879,239 @@ impl Cmd {
         Ok(())
     }
 
-    pub async fn run_command(&self, cmd: &Commands, db: impl Database) -> eyre::Result<()> {
-        match cmd {
-            Commands::History => self.run_history(db).await,
+    fn chunk_limit(&self) -> ChunkLimit {
+        match (self.chunk_rows, self.chunk_bytes) {
+            (_, Some(bytes)) => ChunkLimit::Bytes(bytes),
+            (rows, None) => ChunkLimit::Rows(rows.unwrap_or(DEFAULT_CHUNK_ROWS)),
         }
     }
 
-    pub async fn run_history(&self, db: impl Database) -> eyre::Result<()> {
-        let mut tw = TabWriter::new(std::io::stdout()).padding(2).minwidth(0);
+    fn assertion(&self) -> Option<RowAssertion> {
+        match (self.assert_empty, self.assert_max) {
+            (true, _) => Some(RowAssertion::max(0)),
+            (false, Some(max)) => Some(RowAssertion::max(max)),
+            (false, None) => None,
+        }
+    }
+
+    /// Checks the outcome of --assert-empty or --assert-max, writing every --report before
+    /// failing.
+    fn check_assertion(&self, outcome: &AssertionOutcome) -> eyre::Result<()> {
+        let name = self.file_or_query_name.as_deref().unwrap_or("query");
+        for report in &self.report {
+            report.write(name, outcome)?;
+        }
+
+        if outcome.passed() {
+            Ok(())
+        } else {
+            Err(eyre::eyre!("Assertion failed: {}", outcome.message()))
+        }
+    }
+
+    /// Warns when `start_time` predates the retention of every group, the query would scan
+    /// nothing there, returning the start time to use.
+    async fn retention_start_time(
+        &self,
+        client: &impl LogsApi,
+        limiter: &RateLimiter,
+        group_names: &[String],
+        start_time: i64,
+        ui: Ui,
+    ) -> eyre::Result<i64> {
+        let now = Utc::now().timestamp_millis();
+        // NOTE: Every group keeps at least a day, no need to look up the retention before that.
+        if start_time >= now - MIN_RETENTION_MS {
+            return Ok(start_time);
+        }
 
+        let mut retention_days = Vec::with_capacity(group_names.len());
+        for name in group_names {
+            // NOTE: Missing groups are reported by the validation, or by StartQuery with
+            // --no-validate, they don't retain anything.
+            if let Some(group) = find_log_group(client, limiter, name).await? {
+                retention_days.push(group.retention_in_days());
+            }
+        }
+
+        let check = check_retention_all(start_time, &retention_days, now);
+        let groups = match group_names {
+            [name] => name.clone(),
+            _ => "the selected log groups".to_string(),
+        };
+        if let Some(warning) = check.warning(&groups, self.clamp_to_retention, self.local) {
+            ui.warn(warning);
+        }
+
+        Ok(check.start_time(start_time, self.clamp_to_retention))
+    }
+
+    /// The query `--dry-run` would start and where.
+    fn render_plan(
+        &self,
+        group_names: &[String],
+        query: &str,
+        start_time: i64,
+        end_time: i64,
+        profile: Option<String>,
+        region: Option<String>,
+    ) -> eyre::Result<String> {
+        let mut tw = TabWriter::new(Vec::new()).padding(2).minwidth(0);
+        for group_name in group_names {
+            writeln!(tw, "group:\t{}", group_name)?;
+        }
+        writeln!(
+            tw,
+            "start:\t{} ({})",
+            parse_timestamp(start_time, self.local).unwrap_or_default(),
+            start_time
+        )?;
         writeln!(
-            &mut tw,
-            "ID\tACCOUNT\tQUERY\tSTATUS\tTOTAL\tMATCHED\tSCANNED"
+            tw,
+            "end:\t{} ({})",
+            parse_timestamp(end_time, self.local).unwrap_or_default(),
+            end_time
         )?;
+        writeln!(tw, "profile:\t{}", profile.as_deref().unwrap_or("default"))?;
+        writeln!(tw, "region:\t{}", region.as_deref().unwrap_or("unknown"))?;
+        writeln!(tw, "poll interval:\t{}s", POLL_INTERVAL.as_secs())?;
+
+        // NOTE: The query goes after the table, a tab in it must not be taken for a column.
+        let plan = tw.into_inner().context("failed to render the plan")?;
+        Ok(format!("{}\n{}\n", String::from_utf8(plan)?, query))
+    }
+
+    /// The names of the groups from every -g flag, parsed and resolved like the groups of
+    /// `tail`, in the order they were passed in. `defaults` are used when no -g flag was passed.
+    fn group_names(&self, defaults: &[String]) -> eyre::Result<Vec<String>> {
+        let names = if self.group_names.is_empty() {
+            defaults
+        } else {
+            &self.group_names
+        };
+
+        let mut selectors = Vec::new();
+        for names in names {
+            selectors.extend(GroupSelector::parse(names)?);
+        }
+        let rules = GroupRules {
+            max: Some(MAX_QUERY_GROUPS),
+            stream_prefixes: false,
+        };
+
+        let mut group_names: Vec<String> = Vec::new();
+        for selector in GroupSelector::resolve(selectors, rules)? {
+            let name = selector.group_name();
+            if !group_names.iter().any(|g| g == name) {
+                group_names.push(name.to_string());
+            }
+        }
+        Ok(group_names)
+    }
+
+    /// A result row by field name, with the time fields rendered by [`Cmd::format_field`].
+    fn result_row<'a>(
+        &self,
+        fields: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> Map<String, Value> {
+        fields
+            .map(|(field, value)| (field.to_string(), self.format_field(field, value)))
+            .collect()
+    }
+
+    /// Renders a result field, reformatting the Insights time fields according to the time
+    /// options. Values that can't be parsed are passed through untouched.
+    fn format_field(&self, field: &str, value: &str) -> Value {
+        let is_time_field = field == "@timestamp" || field == "@ingestionTime";
+        let reformat = self.local || self.utc || self.epoch_timestamps || self.time_style.is_some();
+        if !is_time_field || !reformat {
+            return Value::String(value.to_string());
+        }
+
+        match parse_insights_timestamp(value) {
+            Some(time) if self.epoch_timestamps => Value::from(time.timestamp_millis()),
+            Some(time) => Value::String(match self.time_style {
+                Some(style) => style.render(time, self.local, Utc::now()),
+                None => format_datetime(time, self.local),
+            }),
+            None => Value::String(value.to_string()),
+        }
+    }
+
+    pub async fn run_command(&self, cmd: &Commands, db: impl Database) -> eyre::Result<()> {
+        match cmd {
+            Commands::History { command: None } => self.run_history(db).await,
+            Commands::History {
+                command: Some(HistoryCommands::Command { id, format }),
+            } => self.run_history_command(db, id, format).await,
+        }
+    }
+
+    pub async fn run_history_command(
+        &self,
+        db: impl Database,
+        id: &str,
+        format: &ExportFormat,
+    ) -> eyre::Result<()> {
+        let Some(item) = db.get(id).await? else {
+            return Err(eyre::eyre!("No query found in history with id {}", id));
+        };
+
+        let group_names = item.group_names()?;
+        let (Some(start_time), Some(end_time)) = (item.start_time, item.end_time) else {
+            return Err(eyre::eyre!(
+                "Query {} was recorded without its groups and time window, it can't be reproduced.",
+                id
+            ));
+        };
+
+        let start_time = parse_timestamp(start_time, false).unwrap_or_default();
+        let end_time = parse_timestamp(end_time, false).unwrap_or_default();
+
+        match format {
+            ExportFormat::Shell => {
+                let mut command = vec!["cw".to_string(), "query".to_string()];
+                if let Some(profile) = &item.profile {
+                    command.push(format!("--profile {}", shell_quote(profile)));
+                }
+                if let Some(region) = &item.region {
+                    command.push(format!("--region {}", shell_quote(region)));
+                }
+                for group_name in &group_names {
+                    command.push(format!("-g {}", shell_quote(group_name)));
+                }
+                command.push(format!("--start-time {}", shell_quote(&start_time)));
+                command.push(format!("--end-time {}", shell_quote(&end_time)));
+
+                let delimiter = heredoc_delimiter(&item.contents);
+                println!("{} - <<'{}'", command.join(" "), delimiter);
+                println!("{}", item.contents.trim_end_matches('\n'));
+                println!("{}", delimiter);
+            }
+            ExportFormat::Json => {
+                let json = json!({
+                    "query_id": item.query_id,
+                    "query": item.contents,
+                    "group_names": group_names,
+                    "start_time": start_time,
+                    "end_time": end_time,
+                    "profile": item.profile,
+                    "region": item.region,
+                });
+                println!("{}", serde_json::to_string_pretty(&json)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn run_history(&self, db: impl Database) -> eyre::Result<()> {
+        let mut table = Table::new(&[
+            "ID", "ACCOUNT", "QUERY", "STATUS", "TOTAL", "MATCHED", "SCANNED",
+        ])
+        .align_right(4)
+        .align_right(5)
+        .align_right(6);
 
         let size = terminal_size::terminal_size();
         for item in db.list().await? {
@@ -238,30 +1126,909 @@ impl Cmd {
                     truncate_text(&oneline, size)
                 })
                 .unwrap_or(oneline);
-            writeln!(
-                &mut tw,
-                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
-                item.query_id,
-                "",
-                contents,
-                item.status,
-                item.records_total,
-                item.records_matched,
-                item.records_scanned
-            )?;
+            table.push(vec![
+                Cell::new(&item.query_id).style(Style::new().dim()),
+                "".into(),
+                contents.into(),
+                Cell::new(&item.status).style(status_style(&item.status)),
+                Cell::new(group_thousands(item.records_total.max(0) as u64)),
+                Cell::new(group_thousands(item.records_matched as u64)),
+                Cell::new(group_thousands(item.records_scanned as u64)),
+            ]);
         }
 
-        tw.flush().context("failed to write to stdout")?;
+        table.print()
+    }
+}
 
-        Ok(())
+fn status_style(status: &crate::db::QueryStatus) -> Style {
+    match status {
+        crate::db::QueryStatus::Complete => Style::new().green(),
+        crate::db::QueryStatus::Failed => Style::new().red(),
+        crate::db::QueryStatus::Queued
+        | crate::db::QueryStatus::Scheduled
+        | crate::db::QueryStatus::Running => Style::new().yellow(),
+        crate::db::QueryStatus::Timeout
+        | crate::db::QueryStatus::Cancelled
+        | crate::db::QueryStatus::Interrupted
+        | crate::db::QueryStatus::Expired => Style::new().dim(),
+    }
+}
+
+/// The --tee file. Rows go to a temporary file next to it, which replaces the destination only
+/// once every row is written, so a failed run never leaves a truncated file behind.
+struct TeeFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    writer: Option<BufWriter<std::fs::File>>,
+}
+
+impl TeeFile {
+    fn create(path: &Path) -> eyre::Result<Self> {
+        let Some(file_name) = path.file_name() else {
+            return Err(eyre::eyre!("--tee needs a file, got {}", path.display()));
+        };
+        let temp_path = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            file_name.to_string_lossy(),
+            Uuid::new_v4()
+        ));
+        let file = std::fs::File::create(&temp_path)
+            .with_context(|| format!("Failed creating {}", temp_path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            temp_path,
+            writer: Some(BufWriter::new(file)),
+        })
+    }
+
+    fn write_row(&mut self, row: &Map<String, Value>) -> eyre::Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        serde_json::to_writer(&mut *writer, row)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"))
+            .with_context(|| format!("Failed writing to {}", self.temp_path.display()))
+    }
+
+    /// Moves the rows written so far into place.
+    fn commit(mut self) -> eyre::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer
+                .into_inner()
+                .map_err(|err| err.into_error())
+                .and_then(|file| file.sync_all())
+                .with_context(|| format!("Failed writing to {}", self.temp_path.display()))?;
+        }
+
+        std::fs::rename(&self.temp_path, &self.path).with_context(|| {
+            format!(
+                "Failed moving {} to {}",
+                self.temp_path.display(),
+                self.path.display()
+            )
+        })
+    }
+}
+
+impl Drop for TeeFile {
+    fn drop(&mut self) {
+        if !self.temp_path.exists() {
+            return;
+        }
+        if let Err(err) = std::fs::remove_file(&self.temp_path) {
+            tracing::debug!(target: "cw", "failed removing {}: {}", self.temp_path.display(), err);
+        }
+    }
+}
+
+/// Resolves the real status of rows a previous run left Scheduled, Running or Interrupted, once
+/// they are older than the maximum Insights runtime. Only rows recorded with the same profile and
+/// region are checked, the query ids are meaningless to any other account. Rows still Queued by
+/// then never got a query id, those are marked Interrupted. Rows CloudWatch no longer keeps the
+/// results of are marked Expired without asking, a failed lookup is retried on a later run.
+async fn reconcile_stale_history(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    db: &impl Database,
+    profile: Option<&str>,
+    region: Option<&str>,
+) -> eyre::Result<()> {
+    let cutoff = Utc::now() - chrono::TimeDelta::minutes(MAX_QUERY_RUNTIME_MINUTES);
+    let expiry = Utc::now() - chrono::TimeDelta::days(QUERY_RESULTS_RETENTION_DAYS);
+    let history = db.list().await?;
+
+    let abandoned = history.iter().filter(|h| {
+        h.status == crate::db::QueryStatus::Queued && h.query_id.is_empty() && h.created_at < cutoff
+    });
+    for history in abandoned {
+        let mut history = history.clone();
+        tracing::info!(
+            "reconciled stale Queued history entry from {} as Interrupted",
+            history.created_at
+        );
+        history.set_status(crate::db::QueryStatus::Interrupted);
+        db.update(&history).await?;
+    }
+
+    let stale = history.into_iter().filter(|h| {
+        (h.status.is_in_flight() || h.status == crate::db::QueryStatus::Interrupted)
+            && !h.query_id.is_empty()
+            && h.created_at < cutoff
+            && h.profile.as_deref() == profile
+            && h.region.as_deref() == region
+    });
+
+    for mut history in stale {
+        if history.created_at < expiry {
+            tracing::info!(
+                "[{}] stale {} history entry expired",
+                history.query_id,
+                history.status
+            );
+            history.set_status(crate::db::QueryStatus::Expired);
+            db.update(&history).await?;
+            continue;
+        }
+
+        limiter.acquire().await;
+        let output = match client.get_query_results(&history.query_id).await {
+            Ok(output) => output,
+            Err(err) => {
+                tracing::debug!("[{}] can't reconcile: {:?}", history.query_id, err);
+                continue;
+            }
+        };
+
+        let status = match QueryState::of(output.status.as_ref()) {
+            QueryState::Complete => crate::db::QueryStatus::Complete,
+            QueryState::Failed => crate::db::QueryStatus::Failed,
+            QueryState::Cancelled => crate::db::QueryStatus::Cancelled,
+            QueryState::Timeout => crate::db::QueryStatus::Timeout,
+            _ => continue,
+        };
+
+        if let Some(statistics) = output.statistics().map(QueryStats::from) {
+            history.set_statistics(
+                output.results().len() as i64,
+                statistics.records_matched,
+                statistics.records_scanned,
+                statistics.bytes_scanned,
+            );
+        }
+
+        tracing::info!(
+            "[{}] reconciled stale {} history entry as {}",
+            history.query_id,
+            history.status,
+            status
+        );
+        history.set_status(status);
+        db.update(&history).await?;
+    }
+
+    Ok(())
+}
+
+/// Stops `query_id` after an interrupt, so it doesn't keep scanning, and counting against the
+/// concurrent query limit, with nobody waiting for it.
+pub(crate) async fn stop_query(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    query_id: &str,
+    ui: Ui,
+) {
+    limiter.acquire().await;
+    match client.stop_query(query_id).await {
+        Ok(output) if output.success() => ui.note(format!("Stopped query {}.", query_id)),
+        // NOTE: StopQuery only fails to stop a query that already finished.
+        Ok(_) => tracing::debug!("[{}] query finished before it was stopped", query_id),
+        Err(err) => ui.warn(format!(
+            "Failed stopping query {}, it runs until it finishes: {}",
+            query_id,
+            err.message().unwrap_or("no details provided")
+        )),
+    }
+}
+
+async fn explain_start_query_error(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    group_names: &[String],
+    query: &str,
+    err: SdkError<StartQueryError>,
+) -> eyre::Report {
+    let Some(service_err) = err.as_service_error() else {
+        return eyre::Report::new(err).wrap_err("Failed to start CloudWatch Logs Insights query.");
+    };
+
+    let message = match service_err {
+        StartQueryError::MalformedQueryException(e) => {
+            let detail = e.query_compile_error().and_then(|c| c.message()).or(e.message());
+            let offset = e
+                .query_compile_error()
+                .and_then(|c| c.location())
+                .and_then(|l| l.start_char_offset());
+
+            match offset.and_then(|o| usize::try_from(o).ok()) {
+                Some(offset) => {
+                    let (line, column) = line_and_column(query, offset);
+                    format!(
+                        "Query is malformed at line {}, char {}: {}",
+                        line,
+                        column,
+                        detail.unwrap_or("no details provided")
+                    )
+                }
+                None => format!(
+                    "Query is malformed: {}",
+                    detail.unwrap_or("no details provided")
+                ),
+            }
+        }
+        StartQueryError::ResourceNotFoundException(_) => {
+            match find_missing_groups(client, limiter, group_names).await {
+                Ok(missing) if !missing.is_empty() => {
+                    format!("Log group(s) not found: {}", missing.join(", "))
+                }
+                _ => format!(
+                    "One or more log groups could not be found: {}",
+                    group_names.join(", ")
+                ),
+            }
+        }
+        StartQueryError::LimitExceededException(e) => format!(
+            "Query limit exceeded ({}). Try querying fewer log groups (requested {}) or wait for running queries to finish.",
+            e.message().unwrap_or("no details provided"),
+            group_names.len()
+        ),
+        e if classify_error(e) == ApiErrorKind::AccessDenied => format!(
+            "Access denied, the caller is missing the logs:StartQuery IAM permission: {}",
+            e.message().unwrap_or("no details provided")
+        ),
+        e => format!(
+            "Failed to start CloudWatch Logs Insights query: {}",
+            e.message().unwrap_or("no details provided")
+        ),
+    };
+
+    eyre::Report::new(err).wrap_err(message)
+}
+
+async fn find_missing_groups(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    group_names: &[String],
+) -> eyre::Result<Vec<String>> {
+    let mut missing = Vec::new();
+
+    for group_name in group_names {
+        limiter.acquire().await;
+        let response = client
+            .describe_log_groups(DescribeLogGroupsRequest {
+                name_prefix: Some(group_name.clone()),
+                ..Default::default()
+            })
+            .await
+            .context("Failed describing log groups.")?;
+
+        let exists = response
+            .log_groups()
+            .iter()
+            .any(|g| g.log_group_name() == Some(group_name.as_str()));
+
+        if !exists {
+            missing.push(group_name.clone());
+        }
     }
+
+    Ok(missing)
+}
+
+/// Makes the cheapest call there is, listing a single log group, to get credentials resolved and
+/// a connection pooled before the real request needs them.
+async fn warm_up_connection(client: impl LogsApi, limiter: Arc<RateLimiter>) -> eyre::Result<()> {
+    let started = Instant::now();
+    limiter.acquire().await;
+    client
+        .describe_log_groups(DescribeLogGroupsRequest {
+            limit: Some(1),
+            ..Default::default()
+        })
+        .await
+        .wrap_err("Failed warming up the connection")?;
+    tracing::debug!(target: "cw", "connection warm-up took {}ms", elapsed_ms(started));
+    Ok(())
 }
 
-fn truncate_text(s: &str, width: usize) -> String {
-    let mut clean = String::from_str(s).unwrap();
-    if clean.len() > width {
-        clean.truncate(width.saturating_sub(1));
-        clean.push('…');
+/// The fields Logs Insights discovered in `group_names` with the percentage of events that have
+/// them, the highest across the groups. Gives up after [`FIELD_HINTS_TIMEOUT`], the editor
+/// shouldn't wait on hints, and any failure just means no hints.
+async fn discover_fields(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    group_names: &[String],
+) -> Vec<(String, i32)> {
+    let lookups = group_names
+        .iter()
+        .take(MAX_FIELD_HINT_GROUPS)
+        .map(|name| async move {
+            limiter.acquire().await;
+            client.get_log_group_fields(name.trim()).await
+        });
+    let Ok(responses) =
+        tokio::time::timeout(FIELD_HINTS_TIMEOUT, futures_util::future::join_all(lookups)).await
+    else {
+        tracing::debug!(target: "cw", "gave up discovering fields after {:?}", FIELD_HINTS_TIMEOUT);
+        return Vec::new();
+    };
+
+    let mut fields: Vec<(String, i32)> = Vec::new();
+    for response in responses {
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                tracing::debug!(target: "cw", "failed discovering fields: {:?}", err);
+                continue;
+            }
+        };
+        for field in response.log_group_fields() {
+            let Some(name) = field.name() else {
+                continue;
+            };
+            match fields.iter_mut().find(|(known, _)| known == name) {
+                Some((_, percent)) => *percent = (*percent).max(field.percent()),
+                None => fields.push((name.to_string(), field.percent())),
+            }
+        }
+    }
+    fields
+}
+
+fn elapsed_ms(started: Instant) -> i64 {
+    i64::try_from(started.elapsed().as_millis()).unwrap_or(i64::MAX)
+}
+
+/// Translates a character offset into a 1-based line and column within `query`.
+fn line_and_column(query: &str, offset: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut column = 1;
+    for c in query.chars().take(offset) {
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    (line, column)
+}
+
+/// Quotes `value` for POSIX shells, only when it contains characters the shell would interpret.
+fn shell_quote(value: &str) -> String {
+    let is_safe = !value.is_empty()
+        && value.chars().all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.' | ':' | '+' | '=')
+        });
+
+    if is_safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Picks a heredoc delimiter that doesn't occur as a line in `contents`.
+fn heredoc_delimiter(contents: &str) -> String {
+    let mut delimiter = "EOF".to_string();
+    while contents.lines().any(|line| line == delimiter) {
+        delimiter.push('_');
+    }
+    delimiter
+}
+
+// NOTE: Long values such as @message would push the other columns off screen.
+const SUMMARY_VALUE_WIDTH: usize = 40;
+
+/// Prints one line per field, followed by its most frequent values.
+fn print_summary(summaries: &[FieldSummary]) -> eyre::Result<()> {
+    let mut tw = TabWriter::new(std::io::stdout()).padding(2).minwidth(0);
+    writeln!(tw, "FIELD\tNON-EMPTY\tDISTINCT\tMIN\tMAX\tTOP VALUES")?;
+    for summary in summaries {
+        let (min, max) = summary
+            .range
+            .map(|(min, max)| (min.to_string(), max.to_string()))
+            .unwrap_or_else(|| ("-".to_string(), "-".to_string()));
+        let top = summary
+            .top
+            .iter()
+            .map(|(value, count)| {
+                let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                format!("{} ({})", truncate_text(&value, SUMMARY_VALUE_WIDTH), count)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            summary.field, summary.non_empty, summary.distinct, min, max, top
+        )?;
+    }
+    tw.flush().context("failed to write to stdout")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aws_sdk_cloudwatchlogs::operation::describe_log_groups::DescribeLogGroupsOutput;
+    use aws_sdk_cloudwatchlogs::operation::get_query_results::{
+        GetQueryResultsError, GetQueryResultsOutput,
+    };
+    use aws_sdk_cloudwatchlogs::operation::start_query::{StartQueryError, StartQueryOutput};
+    use aws_sdk_cloudwatchlogs::types::{LogGroup, QueryStatistics, QueryStatus as InsightsStatus};
+    use chrono::{TimeDelta, Utc};
+    use clap::Parser;
+    use serde_json::{json, Value};
+
+    use crate::commands::query::{reconcile_stale_history, Cmd, TeeFile};
+    use crate::compat::{classify_error, ApiErrorKind};
+    use crate::db::{Database, QueryHistory, QueryStatus};
+    use crate::fake_logs::{service_error, FakeLogs};
+    use crate::logs_api::StartQueryRequest;
+    use crate::memory_db::InMemoryDb;
+    use crate::progress_reporter::NoopReporter;
+    use crate::rate_limit::RateLimiter;
+    use crate::ui::Ui;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        query: Cmd,
+    }
+
+    fn query(args: &[&str]) -> Cmd {
+        Cli::parse_from(std::iter::once("cw").chain(args.iter().copied())).query
+    }
+
+    fn limit_exceeded() -> aws_sdk_cloudwatchlogs::error::SdkError<StartQueryError> {
+        service_error("LimitExceededException", "Too many concurrent queries.")
+    }
+
+    async fn start(
+        logs: &FakeLogs,
+        cmd: &Cmd,
+        db: &InMemoryDb,
+        history: &mut QueryHistory,
+    ) -> bool {
+        cmd.start_query(
+            logs,
+            StartQueryRequest::default(),
+            &RateLimiter::new(100),
+            &NoopReporter,
+            Ui::new(true),
+            db,
+            history,
+        )
+        .await
+        .is_ok_and(|output| output.query_id() == Some("q-1"))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_query_waits_for_a_free_slot_and_is_kept_as_queued() {
+        let logs = FakeLogs::new();
+        logs.push_start_query(Err(limit_exceeded()))
+            .push_start_query(Err(limit_exceeded()))
+            .push_start_query(Ok(StartQueryOutput::builder().query_id("q-1").build()));
+        let db = InMemoryDb::new();
+        let mut history = QueryHistory::new(String::new(), "fields @message".to_string());
+
+        let started = start(&logs, &query(&[]), &db, &mut history).await;
+
+        assert!(started);
+        assert_eq!(logs.start_query_requests().len(), 3);
+        let saved = db.list().await.unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].status, QueryStatus::Queued);
+        assert_eq!(saved[0].contents, "fields @message");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_query_gives_up_once_the_queue_wait_runs_out() {
+        let logs = FakeLogs::new();
+        for _ in 0..10 {
+            logs.push_start_query(Err(limit_exceeded()));
+        }
+        let db = InMemoryDb::new();
+        let mut history = QueryHistory::new(String::new(), "fields @message".to_string());
+        let started_at = tokio::time::Instant::now();
+
+        let err = query(&["--max-queue-wait", "10s"])
+            .start_query(
+                &logs,
+                StartQueryRequest::default(),
+                &RateLimiter::new(100),
+                &NoopReporter,
+                Ui::new(true),
+                &db,
+                &mut history,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(classify_error(&err), ApiErrorKind::LimitExceeded);
+        // NOTE: Backs off 2s, 4s and would then pass the 10s with another 8s.
+        assert_eq!(logs.start_query_requests().len(), 3);
+        assert_eq!(started_at.elapsed(), Duration::from_secs(6));
+        assert_eq!(history.status, QueryStatus::Queued);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_waited_out() {
+        let logs = FakeLogs::new();
+        logs.push_start_query(Err(service_error::<StartQueryError>(
+            "MalformedQueryException",
+            "Unexpected symbol.",
+        )));
+        let db = InMemoryDb::new();
+        let mut history = QueryHistory::new(String::new(), "fields @message |".to_string());
+
+        let started = start(&logs, &query(&[]), &db, &mut history).await;
+
+        assert!(!started);
+        assert_eq!(logs.start_query_requests().len(), 1);
+        assert!(db.list().await.unwrap().is_empty());
+    }
+
+    fn stale(query_id: &str, status: QueryStatus, age: TimeDelta) -> QueryHistory {
+        let mut history = QueryHistory::new(query_id.to_string(), "fields @message".to_string());
+        history.status = status;
+        history.profile = Some("dev".to_string());
+        history.region = Some("eu-west-1".to_string());
+        history.created_at = Utc::now() - age;
+        history
+    }
+
+    fn results(status: InsightsStatus) -> GetQueryResultsOutput {
+        GetQueryResultsOutput::builder()
+            .status(status)
+            .statistics(
+                QueryStatistics::builder()
+                    .records_matched(12.0)
+                    .records_scanned(300.0)
+                    .bytes_scanned(4096.0)
+                    .build(),
+            )
+            .build()
+    }
+
+    async fn reconcile(logs: &FakeLogs, db: &InMemoryDb) -> Vec<(String, QueryStatus)> {
+        reconcile_stale_history(
+            logs,
+            &RateLimiter::new(100),
+            db,
+            Some("dev"),
+            Some("eu-west-1"),
+        )
+        .await
+        .unwrap();
+        db.list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|h| (h.query_id, h.status))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn stale_rows_take_the_status_cloudwatch_reports() {
+        let logs = FakeLogs::new();
+        logs.push_get_query_results(Ok(results(InsightsStatus::Complete)))
+            .push_get_query_results(Ok(results(InsightsStatus::Cancelled)))
+            .push_get_query_results(Ok(results(InsightsStatus::Running)));
+        let db = InMemoryDb::new();
+        let two_hours = TimeDelta::hours(2);
+        db.save(&stale("q-1", QueryStatus::Running, two_hours))
+            .await
+            .unwrap();
+        db.save(&stale("q-2", QueryStatus::Interrupted, two_hours))
+            .await
+            .unwrap();
+        db.save(&stale("q-3", QueryStatus::Scheduled, two_hours))
+            .await
+            .unwrap();
+
+        let statuses = reconcile(&logs, &db).await;
+
+        assert_eq!(
+            statuses,
+            [
+                ("q-1".to_string(), QueryStatus::Complete),
+                ("q-2".to_string(), QueryStatus::Cancelled),
+                ("q-3".to_string(), QueryStatus::Scheduled),
+            ]
+        );
+        let complete = db.get("q-1").await.unwrap().unwrap();
+        assert_eq!(complete.records_matched, 12.0);
+        assert_eq!(complete.bytes_scanned, 4096.0);
+    }
+
+    #[tokio::test]
+    async fn a_failed_lookup_is_retried_until_the_results_expire() {
+        let logs = FakeLogs::new();
+        logs.push_get_query_results(Err(service_error::<GetQueryResultsError>(
+            "ResourceNotFoundException",
+            "Query does not exist.",
+        )));
+        let db = InMemoryDb::new();
+        db.save(&stale("q-1", QueryStatus::Running, TimeDelta::hours(2)))
+            .await
+            .unwrap();
+        db.save(&stale("q-2", QueryStatus::Running, TimeDelta::days(8)))
+            .await
+            .unwrap();
+
+        let statuses = reconcile(&logs, &db).await;
+
+        assert_eq!(
+            statuses,
+            [
+                ("q-1".to_string(), QueryStatus::Running),
+                ("q-2".to_string(), QueryStatus::Expired),
+            ]
+        );
+        assert_eq!(logs.get_query_results_requests(), ["q-1"]);
+    }
+
+    #[tokio::test]
+    async fn only_stale_rows_of_this_account_are_looked_up() {
+        let logs = FakeLogs::new();
+        let db = InMemoryDb::new();
+        let mut other = stale("q-1", QueryStatus::Running, TimeDelta::hours(2));
+        other.region = Some("us-east-1".to_string());
+        db.save(&other).await.unwrap();
+        db.save(&stale("q-2", QueryStatus::Running, TimeDelta::minutes(5)))
+            .await
+            .unwrap();
+        db.save(&stale("", QueryStatus::Queued, TimeDelta::hours(2)))
+            .await
+            .unwrap();
+
+        let statuses = reconcile(&logs, &db).await;
+
+        assert_eq!(
+            statuses,
+            [
+                ("q-1".to_string(), QueryStatus::Running),
+                ("q-2".to_string(), QueryStatus::Running),
+                (String::new(), QueryStatus::Interrupted),
+            ]
+        );
+        assert!(logs.get_query_results_requests().is_empty());
+    }
+
+    #[test]
+    fn dry_run_plan() {
+        let plan = query(&[])
+            .render_plan(
+                &["/aws/lambda/api".to_string(), "/ecs/web".to_string()],
+                "fields @message\t| limit 5",
+                1_717_250_000_000,
+                1_717_253_600_000,
+                Some("prod".to_string()),
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            plan,
+            concat!(
+                "group:          /aws/lambda/api\n",
+                "group:          /ecs/web\n",
+                "start:          2024-06-01T13:53:20Z (1717250000000)\n",
+                "end:            2024-06-01T14:53:20Z (1717253600000)\n",
+                "profile:        prod\n",
+                "region:         unknown\n",
+                "poll interval:  2s\n",
+                "\n",
+                "fields @message\t| limit 5\n",
+            )
+        );
+    }
+
+    fn tee_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("cw-tee-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(dir: &std::path::Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn row(message: &str) -> serde_json::Map<String, serde_json::Value> {
+        let mut row = serde_json::Map::new();
+        row.insert("@message".to_string(), message.into());
+        row
+    }
+
+    #[test]
+    fn time_fields_are_rendered_as_asked() {
+        let render =
+            |args: &[&str], field: &str| query(args).format_field(field, "2024-06-01 14:03:11.123");
+
+        assert_eq!(render(&[], "@timestamp"), json!("2024-06-01 14:03:11.123"));
+        assert_eq!(
+            render(&["--utc"], "@timestamp"),
+            json!("2024-06-01T14:03:11.123Z")
+        );
+        assert_eq!(
+            render(&["--utc"], "@ingestionTime"),
+            json!("2024-06-01T14:03:11.123Z")
+        );
+        assert_eq!(
+            render(&["--utc", "--time-style", "iso"], "@timestamp"),
+            json!("2024-06-01T14:03:11Z")
+        );
+        assert_eq!(
+            render(&["--epoch-timestamps"], "@timestamp"),
+            json!(1_717_250_591_123_i64)
+        );
+        assert_eq!(
+            render(&["--utc"], "@message"),
+            json!("2024-06-01 14:03:11.123")
+        );
+        assert_eq!(
+            query(&["--utc"]).format_field("@timestamp", "yesterday"),
+            Value::from("yesterday")
+        );
+    }
+
+    #[test]
+    fn tee_rows_only_show_up_once_committed() {
+        let dir = tee_dir("commit");
+        let path = dir.join("rows.jsonl");
+        std::fs::write(&path, "previous run\n").unwrap();
+
+        let mut tee = TeeFile::create(&path).unwrap();
+        tee.write_row(&row("first")).unwrap();
+        tee.write_row(&row("second")).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous run\n");
+        assert_eq!(file_names(&dir).len(), 2);
+
+        tee.commit().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"@message\":\"first\"}\n{\"@message\":\"second\"}\n"
+        );
+        assert_eq!(file_names(&dir), ["rows.jsonl"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_run_leaves_the_destination_alone() {
+        let dir = tee_dir("failed");
+        let path = dir.join("rows.jsonl");
+        std::fs::write(&path, "previous run\n").unwrap();
+
+        let mut tee = TeeFile::create(&path).unwrap();
+        tee.write_row(&row("first")).unwrap();
+        drop(tee);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous run\n");
+        assert_eq!(file_names(&dir), ["rows.jsonl"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_rename_is_reported_and_cleans_up() {
+        let dir = tee_dir("rename");
+        // NOTE: A file can't replace a non-empty directory, so the rename fails.
+        let path = dir.join("rows.jsonl");
+        std::fs::create_dir_all(path.join("taken")).unwrap();
+
+        let mut tee = TeeFile::create(&path).unwrap();
+        tee.write_row(&row("first")).unwrap();
+        let err = tee.commit().unwrap_err();
+
+        assert!(err.to_string().starts_with("Failed moving "), "{}", err);
+        assert!(err.to_string().ends_with("rows.jsonl"), "{}", err);
+        assert_eq!(file_names(&dir), ["rows.jsonl"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tee_needs_a_file_name() {
+        let err = TeeFile::create(std::path::Path::new("/")).err().unwrap();
+
+        assert_eq!(err.to_string(), "--tee needs a file, got /");
+    }
+
+    fn group(name: &str, retention_in_days: Option<i32>) -> DescribeLogGroupsOutput {
+        DescribeLogGroupsOutput::builder()
+            .set_log_groups(Some(vec![LogGroup::builder()
+                .log_group_name(name)
+                .set_retention_in_days(retention_in_days)
+                .build()]))
+            .build()
+    }
+
+    async fn retention_start_time(cmd: &Cmd, logs: &FakeLogs, start_time: i64) -> i64 {
+        let names = ["/a".to_string(), "/b".to_string()];
+        cmd.retention_start_time(
+            logs,
+            &RateLimiter::new(100),
+            &names,
+            start_time,
+            Ui::new(true),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_start_before_every_retention_is_clamped_to_the_longest_one() {
+        let logs = FakeLogs::new();
+        logs.push_describe_log_groups(Ok(group("/a", Some(30))))
+            .push_describe_log_groups(Ok(group("/b", Some(7))));
+        let before = Utc::now();
+        let start_time = (before - TimeDelta::days(40)).timestamp_millis();
+
+        let clamped =
+            retention_start_time(&query(&["--clamp-to-retention"]), &logs, start_time).await;
+
+        let earliest = (before - TimeDelta::days(30)).timestamp_millis();
+        assert!(
+            (earliest..earliest + 1_000).contains(&clamped),
+            "{}",
+            clamped
+        );
+        assert_eq!(logs.describe_log_groups_requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_start_before_every_retention_is_kept_without_clamping() {
+        let logs = FakeLogs::new();
+        logs.push_describe_log_groups(Ok(group("/a", Some(30))))
+            .push_describe_log_groups(Ok(group("/b", Some(7))));
+        let start_time = (Utc::now() - TimeDelta::days(40)).timestamp_millis();
+
+        assert_eq!(
+            retention_start_time(&query(&[]), &logs, start_time).await,
+            start_time
+        );
+    }
+
+    #[tokio::test]
+    async fn a_group_without_retention_keeps_the_start() {
+        let logs = FakeLogs::new();
+        logs.push_describe_log_groups(Ok(group("/a", Some(30))))
+            .push_describe_log_groups(Ok(group("/b", None)));
+        let start_time = (Utc::now() - TimeDelta::days(400)).timestamp_millis();
+
+        let kept = retention_start_time(&query(&["--clamp-to-retention"]), &logs, start_time).await;
+
+        assert_eq!(kept, start_time);
+    }
+
+    #[tokio::test]
+    async fn a_start_within_a_day_skips_the_lookup() {
+        let logs = FakeLogs::new();
+        let start_time = (Utc::now() - TimeDelta::hours(23)).timestamp_millis();
+
+        let kept = retention_start_time(&query(&["--clamp-to-retention"]), &logs, start_time).await;
+
+        assert_eq!(kept, start_time);
+        assert!(logs.describe_log_groups_requests().is_empty());
     }
-    clean
 }
@@ -0,0 +1,107 @@
+use clap::Args;
+use eyre::Context;
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use serde_json::Value;
+use tokio::{
+    fs::File,
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+    sync::mpsc::UnboundedSender,
+    task::JoinHandle,
+};
+
+use crate::event::LogEvent;
+use crate::pipeline::{Origin, OutputArgs};
+use crate::ui::Ui;
+
+#[derive(Args, Debug)]
+pub struct Cmd {
+    #[arg(
+        index = 1,
+        value_name = "file.jsonl|-",
+        help = "JSON lines captured with `cw tail --output json`, use - to read from stdin."
+    )]
+    pub input: String,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+impl Cmd {
+    pub async fn run(&self, ui: Ui) -> eyre::Result<()> {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut tasks = FuturesUnordered::<JoinHandle<eyre::Result<()>>>::new();
+
+        let reader: Box<dyn AsyncBufRead + Unpin + Send> = if self.input == "-" {
+            Box::new(BufReader::new(tokio::io::stdin()))
+        } else {
+            let file = File::open(&self.input)
+                .await
+                .with_context(|| format!("Failed opening {}", self.input))?;
+            Box::new(BufReader::new(file))
+        };
+
+        tasks.push(tokio::spawn(Self::read_log_events(reader, sender)));
+        tasks.push(
+            self.output
+                .spawn_writer(receiver, Origin::default(), false, None, ui)
+                .await?,
+        );
+
+        while let Some(res) = tasks.next().await {
+            match res {
+                Ok(Ok(())) => continue,
+                Ok(Err(e)) => {
+                    for handle in tasks.into_iter() {
+                        handle.abort();
+                    }
+                    return Err(e);
+                }
+                Err(e) => {
+                    for handle in tasks.into_iter() {
+                        handle.abort();
+                    }
+                    return Err(eyre::eyre!(e));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn read_log_events(
+        mut reader: impl AsyncBufRead + Unpin,
+        sender: UnboundedSender<LogEvent>,
+    ) -> eyre::Result<()> {
+        let mut buffer = Vec::new();
+        let mut line_number = 0;
+
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer).await? == 0 {
+                break;
+            }
+            line_number += 1;
+
+            // NOTE: Captures can hold raw bytes from services logging binary data. Render those
+            // with replacement characters instead of failing on the whole file.
+            let line = String::from_utf8_lossy(&buffer);
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let value: Value = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid JSON on line {}", line_number))?;
+            let Some(event) = LogEvent::from_json(&value) else {
+                return Err(eyre::eyre!(
+                    "Expected a JSON object on line {}",
+                    line_number
+                ));
+            };
+
+            // NOTE: This only errors if the writer is gone, nothing left to render to.
+            sender.send(event)?;
+        }
+
+        Ok(())
+    }
+}
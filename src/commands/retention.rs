@@ -0,0 +1,354 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use aws_sdk_cloudwatchlogs::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_cloudwatchlogs::types::LogGroup;
+use clap::Parser;
+use eyre::Context;
+use yansi::Style;
+
+use crate::aws::find_log_group;
+use crate::commands::list::{describe_matching_groups, GroupFilter};
+use crate::commands::LogClientBuilder;
+use crate::compat::{classify_error, ApiErrorKind};
+use crate::logs_api::LogsApi;
+use crate::rate_limit::RateLimiter;
+use crate::table::{Cell, Table};
+use crate::ui::Ui;
+use crate::utils::humanize_bytes;
+
+// NOTE: PutRetentionPolicy only accepts these, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_PutRetentionPolicy.html#CWL-PutRetentionPolicy-request-retentionInDays
+const RETENTION_DAYS: &[i32] = &[
+    1, 3, 5, 7, 14, 30, 60, 90, 120, 150, 180, 365, 400, 545, 731, 1096, 1827, 2192, 2557, 2922,
+    3288, 3653,
+];
+
+/// A retention setting, `None` keeps events forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Retention(Option<i32>);
+
+impl Retention {
+    fn of(group: &LogGroup) -> Self {
+        Self(group.retention_in_days())
+    }
+}
+
+impl std::fmt::Display for Retention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(1) => write!(f, "1 day"),
+            Some(days) => write!(f, "{} days", days),
+            None => write!(f, "never expire"),
+        }
+    }
+}
+
+fn parse_retention_days(value: &str) -> eyre::Result<i32> {
+    let days: i32 = value
+        .parse()
+        .map_err(|_| eyre::eyre!("'{}' isn't a number of days", value))?;
+    if !RETENTION_DAYS.contains(&days) {
+        let allowed = RETENTION_DAYS
+            .iter()
+            .map(i32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(eyre::eyre!(
+            "CloudWatch doesn't accept {} days, pick one of {}",
+            days,
+            allowed
+        ));
+    }
+    Ok(days)
+}
+
+/// Print or change how long log groups keep their events.
+#[derive(Parser, Debug)]
+pub struct Cmd {
+    #[arg(
+        required_unless_present_any = ["prefix", "contains"],
+        conflicts_with_all = ["prefix", "contains"],
+        help = "The name of the log group."
+    )]
+    pub group_name: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "contains",
+        help = "Every group whose name starts with this prefix."
+    )]
+    pub prefix: Option<String>,
+
+    #[arg(
+        long,
+        help = "Every group whose name contains this case-sensitive string."
+    )]
+    pub contains: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DAYS",
+        value_parser = parse_retention_days,
+        conflicts_with = "never",
+        help = "Keep events for this many days, one of the values CloudWatch accepts (1, 3, 5, 7, 14, 30, 60, 90, ...)."
+    )]
+    pub set: Option<i32>,
+
+    #[arg(long, help = "Remove the retention policy, so events never expire.")]
+    pub never: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Change the retention without asking for confirmation."
+    )]
+    pub yes: bool,
+}
+
+/// The outcome of changing a single group.
+struct Change {
+    group_name: String,
+    before: Retention,
+    after: Retention,
+    error: Option<String>,
+}
+
+impl Cmd {
+    pub async fn run(&self, builder: &LogClientBuilder, ui: Ui) -> eyre::Result<()> {
+        let client = builder.build().await?;
+        let limiter = builder.rate_limiter();
+        let groups = self.groups(&client, &limiter).await?;
+
+        let target = match (self.set, self.never) {
+            (Some(days), _) => Retention(Some(days)),
+            (None, true) => Retention(None),
+            (None, false) => return Self::print_retention(&groups),
+        };
+
+        let changes: Vec<&LogGroup> = groups
+            .iter()
+            .filter(|group| Retention::of(group) != target)
+            .collect();
+        if changes.is_empty() {
+            ui.note(match target.0 {
+                Some(_) => format!("Every group already keeps its events for {}.", target),
+                None => "Every group already keeps its events forever.".to_string(),
+            });
+            return Ok(());
+        }
+
+        Self::print_plan(&changes, target)?;
+        if !self.confirmed(changes.len())? {
+            return Err(eyre::eyre!("Aborted, no retention was changed."));
+        }
+
+        let mut applied = Vec::with_capacity(changes.len());
+        for group in changes {
+            let group_name = group.log_group_name().unwrap_or_default().to_string();
+            limiter.acquire().await;
+            let error = apply(&client, &group_name, target)
+                .await
+                .err()
+                .map(|err| err.to_string());
+            applied.push(Change {
+                group_name,
+                before: Retention::of(group),
+                after: target,
+                error,
+            });
+        }
+
+        Self::print_summary(&applied)?;
+        let failed = applied.iter().filter(|c| c.error.is_some()).count();
+        if failed > 0 {
+            return Err(eyre::eyre!(
+                "Failed changing the retention of {} of {} groups.",
+                failed,
+                applied.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether to go ahead with changing `count` groups, asking on the terminal unless --yes was
+    /// passed.
+    fn confirmed(&self, count: usize) -> eyre::Result<bool> {
+        if self.yes {
+            return Ok(true);
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(eyre::eyre!(
+                "Pass --yes to change the retention without a terminal to confirm on."
+            ));
+        }
+        confirm(count, &mut std::io::stdin().lock(), &mut std::io::stderr())
+    }
+
+    async fn groups(
+        &self,
+        client: &impl LogsApi,
+        limiter: &RateLimiter,
+    ) -> eyre::Result<Vec<LogGroup>> {
+        let filter = match (&self.group_name, &self.prefix, &self.contains) {
+            (Some(group_name), _, _) => {
+                return match find_log_group(client, limiter, group_name).await? {
+                    Some(group) => Ok(vec![group]),
+                    None => Err(eyre::eyre!("Can't find log group with name {}", group_name)),
+                };
+            }
+            (None, Some(prefix), _) => GroupFilter::Prefix(prefix.clone()),
+            (None, None, Some(contains)) => GroupFilter::Contains(contains.clone()),
+            (None, None, None) => return Err(eyre::eyre!("At least one log group is required!")),
+        };
+
+        let groups = describe_matching_groups(client, limiter, &filter).await?;
+        if groups.is_empty() {
+            return Err(eyre::eyre!("No log groups match."));
+        }
+        Ok(groups)
+    }
+
+    fn print_retention(groups: &[LogGroup]) -> eyre::Result<()> {
+        let mut table = Table::new(&["GROUP", "RETENTION", "STORED"]).align_right(2);
+        for group in groups {
+            table.push(vec![
+                group.log_group_name().unwrap_or_default().into(),
+                Retention::of(group).to_string().into(),
+                stored_bytes(group).into(),
+            ]);
+        }
+        table.print()
+    }
+
+    fn print_plan(groups: &[&LogGroup], target: Retention) -> eyre::Result<()> {
+        let mut table = Table::new(&["GROUP", "BEFORE", "AFTER", "STORED"]).align_right(3);
+        for group in groups {
+            table.push(vec![
+                group.log_group_name().unwrap_or_default().into(),
+                Retention::of(group).to_string().into(),
+                Cell::new(target).style(Style::new().bold()),
+                stored_bytes(group).into(),
+            ]);
+        }
+        table.print()
+    }
+
+    fn print_summary(changes: &[Change]) -> eyre::Result<()> {
+        let mut table = Table::new(&["GROUP", "BEFORE", "AFTER", "RESULT"]);
+        for change in changes {
+            let result = match &change.error {
+                None => Cell::new("changed").style(Style::new().green()),
+                Some(error) => Cell::new(error).style(Style::new().red()),
+            };
+            table.push(vec![
+                change.group_name.as_str().into(),
+                change.before.to_string().into(),
+                change.after.to_string().into(),
+                result,
+            ]);
+        }
+        table.print()
+    }
+}
+
+/// The bytes a group stores, CloudWatch updates the number about once a day.
+fn stored_bytes(group: &LogGroup) -> String {
+    group
+        .stored_bytes()
+        .map(|bytes| humanize_bytes(u64::try_from(bytes).unwrap_or_default()))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Asks on `output` whether to go ahead with changing `count` groups, anything but a yes read
+/// from `input` is a no.
+fn confirm(count: usize, input: &mut impl BufRead, output: &mut impl Write) -> eyre::Result<bool> {
+    write!(
+        output,
+        "Change the retention of {} group{}? [y/N] ",
+        count,
+        if count == 1 { "" } else { "s" }
+    )?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input
+        .read_line(&mut answer)
+        .wrap_err("Failed reading the confirmation")?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "Yes"))
+}
+
+async fn apply(client: &impl LogsApi, group_name: &str, target: Retention) -> eyre::Result<()> {
+    match target.0 {
+        Some(days) => client
+            .put_retention_policy(group_name, days)
+            .await
+            .map(|_| ())
+            .map_err(|err| retention_error(err, "logs:PutRetentionPolicy")),
+        None => client
+            .delete_retention_policy(group_name)
+            .await
+            .map(|_| ())
+            .map_err(|err| retention_error(err, "logs:DeleteRetentionPolicy")),
+    }
+}
+
+fn retention_error<E>(err: SdkError<E>, permission: &str) -> eyre::Report
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    let message = match classify_error(&err) {
+        ApiErrorKind::AccessDenied => {
+            format!(
+                "access denied, this needs the {} IAM permission",
+                permission
+            )
+        }
+        ApiErrorKind::ResourceNotFound => "the log group no longer exists".to_string(),
+        _ => err.message().unwrap_or("no details provided").to_string(),
+    };
+    eyre::Report::new(err).wrap_err(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::commands::retention::{confirm, Cmd};
+
+    fn answer(input: &str, count: usize) -> (bool, String) {
+        let mut output = Vec::new();
+        let confirmed = confirm(count, &mut input.as_bytes(), &mut output).unwrap();
+        (confirmed, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn confirm_goes_ahead_on_yes() {
+        assert_eq!(
+            answer("y\n", 2),
+            (true, "Change the retention of 2 groups? [y/N] ".to_string())
+        );
+        assert_eq!(
+            answer("Yes\n", 1),
+            (true, "Change the retention of 1 group? [y/N] ".to_string())
+        );
+    }
+
+    #[test]
+    fn confirm_stops_on_no() {
+        assert!(!answer("n\n", 2).0);
+        assert!(!answer("yep\n", 2).0);
+    }
+
+    #[test]
+    fn confirm_stops_on_an_empty_answer() {
+        assert!(!answer("\n", 2).0);
+        assert!(!answer("", 2).0);
+    }
+
+    #[test]
+    fn yes_goes_ahead_without_asking() {
+        let cmd = Cmd::parse_from(["retention", "/aws/app", "--set", "7", "--yes"]);
+
+        assert!(cmd.confirmed(2).unwrap());
+    }
+}
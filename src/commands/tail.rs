@@ -1,458 +1,552 @@
-use std::{fmt::Write, future::Future, io::IsTerminal, time::Duration};
+use std::{
+    collections::HashMap, io::Write, path::PathBuf, str::FromStr, sync::Arc, time::Duration,
+};
 
-use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
-use aws_sdk_cloudwatchlogs::Client;
+use aws_config::SdkConfig;
+use aws_sdk_cloudwatchlogs::error::SdkError;
+use aws_sdk_cloudwatchlogs::operation::describe_log_groups::DescribeLogGroupsError;
+use aws_sdk_cloudwatchlogs::types::{LogGroup, LogGroupClass};
 use chrono::Utc;
-use clap::{Parser, ValueEnum};
+use clap::Parser;
 use eyre::Context;
 use futures_util::{stream::FuturesUnordered, StreamExt};
-use serde_json::{json, Value};
+use regex::Regex;
+use serde_json::json;
+use tabwriter::TabWriter;
 use tokio::{
-    io::{AsyncWrite, AsyncWriteExt},
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    sync::{mpsc::UnboundedSender, watch},
     task::JoinHandle,
 };
-use yansi::Paint;
-
-use crate::utils::{parse_human_time, parse_timestamp};
-
-use super::LogClientBuilder;
-
-#[derive(Debug, Clone)]
-pub struct LogGroupRef(String, Option<String>);
-
-impl LogGroupRef {
-    pub fn new(group_name: &str, stream_name: &str) -> eyre::Result<Self> {
-        let group_name = group_name.trim();
-        let stream_name = stream_name.trim();
 
-        if group_name.is_empty() {
-            return Err(eyre::eyre!("Group name cannot be empty"));
-        }
-
-        Ok(Self(
-            group_name.to_string(),
-            if stream_name.is_empty() {
-                None
-            } else {
-                Some(stream_name.to_string())
-            },
-        ))
-    }
+use crate::archive::ArchiveSink;
+use crate::aws::{caller_account, find_log_group};
+use crate::clock_skew::{apply_skew, exceeds_threshold};
+use crate::commands::group_selector::{GroupRules, GroupSelector};
+use crate::commands::LogClientBuilder;
+use crate::compat::{classify_error, newest_timestamp, ApiErrorKind};
+use crate::config::{ArchiveConfig, Config};
+use crate::db::Database;
+use crate::event::LogEvent;
+use crate::filter_pattern;
+use crate::filter_presets;
+use crate::group_cache::GroupCache;
+use crate::group_check::missing_groups_error;
+use crate::lag::{LagAction, LagTracker};
+use crate::lambda::{version_stream_prefixes, LambdaVersion};
+use crate::logs_api::{DescribeLogGroupsRequest, FilterLogEventsRequest, LogsApi};
+use crate::notify::NotifySink;
+use crate::pipeline::{Origin, OutputArgs, OutputType};
+use crate::progress_reporter::{ProgressEvent, ProgressReporter};
+use crate::rate_limit::RateLimiter;
+use crate::suspend::{watch_resume, Resumed};
+use crate::ui::Ui;
+use crate::utils::{
+    check_retention, format_duration_ms, parse_human_time, parse_timestamp, RangeRules,
+    ResolvedRange, TimeRange,
+};
 
-    pub fn parse(groups_with_stream_prefix: &str) -> eyre::Result<Vec<Self>> {
-        groups_with_stream_prefix
-            .split(',')
-            .map(str::trim)
-            .filter(|s| !s.is_empty())
-            .map(|s| {
-                let (group, stream) = s.split_once(':').unwrap_or((s, ""));
-                Self::new(group, stream).map_err(|e| eyre::eyre!("Invalid group '{}': {}", s, e))
-            })
-            .collect()
-    }
-}
+// Bounds of the backoff used while following a group that has no new events.
+const MIN_POLL_INTERVAL_SECS: u64 = 1;
+const MAX_POLL_INTERVAL_SECS: u64 = 10;
 
-#[derive(Clone, PartialEq, Debug)]
-struct LogEvent {
-    pub group_name: String,
-    pub log_stream_name: Option<String>,
-    pub timestamp: Option<i64>,
-    pub message: Option<String>,
-    pub ingestion_time: Option<i64>,
-    pub event_id: Option<String>,
+fn skew_warning(skew_ms: i64) -> String {
+    format!(
+        "The local clock is {}s ahead of CloudWatch, moving the default start time back.",
+        skew_ms / 1000
+    )
 }
 
-impl From<(&str, &FilteredLogEvent)> for LogEvent {
-    fn from((group_name, event): (&str, &FilteredLogEvent)) -> Self {
-        Self {
-            group_name: group_name.to_owned(),
-            log_stream_name: event.log_stream_name.clone(),
-            timestamp: event.timestamp,
-            message: event.message.clone(),
-            ingestion_time: event.ingestion_time,
-            event_id: event.event_id.clone(),
-        }
+/// The warning for a failed lookup of `group_name` before tailing it, the tail goes on with the
+/// requested start time and without the retention and class checks.
+fn lookup_warning(group_name: &str, err: &eyre::Report) -> String {
+    let denied = err
+        .downcast_ref::<SdkError<DescribeLogGroupsError>>()
+        .is_some_and(|err| classify_error(err) == ApiErrorKind::AccessDenied);
+    if denied {
+        format!(
+            "Can't look up {}, logs:DescribeLogGroups is denied. Tailing it without checking its retention and class.",
+            group_name
+        )
+    } else {
+        format!(
+            "Failed looking up {}, tailing it without checking its retention and class: {}",
+            group_name,
+            err.root_cause()
+        )
     }
 }
 
-#[derive(ValueEnum, Clone, Debug)]
-pub enum OutputType {
-    Text,
-    Json,
-}
-
-#[derive(Debug, Default)]
-struct JsonHighlighter;
-
-impl JsonHighlighter {
-    fn format_json(value: &Value, output: &mut String) {
-        match value {
-            Value::Object(map) => {
-                let _ = write!(output, "{}", Paint::new("{").dim());
-                let mut first = true;
-                for (key, val) in map {
-                    if !first {
-                        let _ = write!(output, "{}", Paint::new(",").dim());
-                    }
-                    first = false;
-
-                    let _ = write!(output, " ");
-                    let _ = write!(output, "{}", "\"".yellow());
-                    let _ = write!(output, "{}", key.yellow());
-                    let _ = write!(output, "{}", "\"".yellow());
-                    let _ = write!(output, "{} ", Paint::new(":").dim());
+/// A followed log group was deleted, ends its producer without ending the tail.
+#[derive(Debug)]
+pub(crate) struct GroupDeleted(String);
 
-                    Self::format_json(val, output);
-                }
-                let _ = write!(output, " {}", Paint::new("}").dim());
-            }
-            Value::Array(array) => {
-                let _ = write!(output, "{}", Paint::new("[").dim());
-                let mut first = true;
-                for item in array {
-                    if !first {
-                        let _ = write!(output, "{} ", Paint::new(",").dim());
-                    }
-                    first = false;
-
-                    Self::format_json(item, output);
-                }
-                let _ = write!(output, "{}", Paint::new("]").dim());
-            }
-            Value::String(value) => {
-                let _ = write!(output, "{}", "\"".green());
-                let _ = write!(output, "{}", value.green());
-                let _ = write!(output, "{}", "\"".green());
-            }
-            Value::Number(value) => {
-                let _ = write!(output, "{}", value.to_string().cyan());
-            }
-            Value::Bool(value) => {
-                let _ = write!(output, "{}", value.to_string().blue());
-            }
-            Value::Null => {
-                let _ = write!(output, "{}", "null".blue());
-            }
-        }
+impl std::fmt::Display for GroupDeleted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} was deleted, no longer following it.", self.0)
     }
 }
 
-fn highlight_json_if_applicable(message: &str) -> Option<String> {
-    let trimmed = message.trim_start();
-    if !(trimmed.starts_with('{') || trimmed.starts_with('[')) {
-        return None;
-    }
+impl std::error::Error for GroupDeleted {}
 
-    let value: Value = serde_json::from_str(trimmed).ok()?;
-    let mut output = String::new();
-    let leading_len = message.len().saturating_sub(trimmed.len());
-    if leading_len > 0 {
-        output.push_str(&message[..leading_len]);
-    }
-
-    JsonHighlighter::format_json(&value, &mut output);
-    Some(output)
-}
-
-trait LogEventWriter {
-    fn write<'a>(
-        &'a mut self,
-        event: &'a LogEvent,
-    ) -> impl Future<Output = eyre::Result<()>> + Send + 'a;
-}
+#[derive(Parser, Clone, Debug)]
+pub struct Cmd {
+    #[arg(
+        index = 1,
+        value_name = "groupName[:logStreamPrefix][,...]",
+        required_unless_present_any = ["list_filter_presets", "groups_file"]
+    )]
+    pub groups_and_stream_prefix: Option<String>,
 
-struct TextWriter<W>
-where
-    W: AsyncWrite + Unpin + Send,
-{
-    use_local_time: bool,
-    with_timestamp: bool,
-    with_group_name: bool,
-    with_stream_name: bool,
-    with_event_id: bool,
-    use_color: bool,
-
-    sink: W,
-}
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read more groups from this file, or stdin for -, one groupName[:logStreamPrefix] per line. Blank lines and lines starting with # are skipped."
+    )]
+    pub groups_file: Option<PathBuf>,
 
-impl<W> TextWriter<W>
-where
-    W: AsyncWrite + Unpin + Send,
-{
-    pub fn new(
-        use_local_time: bool,
-        with_timestamp: bool,
-        with_group_name: bool,
-        with_stream_name: bool,
-        with_event_id: bool,
-        use_color: bool,
-        sink: W,
-    ) -> Self {
-        Self {
-            use_local_time,
-            with_timestamp,
-            with_group_name,
-            with_stream_name,
-            with_event_id,
-            use_color,
-            sink,
-        }
-    }
-}
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        help = "Stream name prefix for every group. Taken literally, so colons and commas need no escaping."
+    )]
+    pub stream: Option<String>,
 
-impl<W> LogEventWriter for TextWriter<W>
-where
-    W: AsyncWrite + Unpin + Send,
-{
-    async fn write(&mut self, event: &LogEvent) -> eyre::Result<()> {
-        let mut line = String::new();
-
-        if self.with_timestamp {
-            if let Some(time) = event
-                .timestamp
-                .and_then(|ts| parse_timestamp(ts, self.use_local_time))
-            {
-                write!(&mut line, "{} - ", time.green())?;
-            }
-        }
+    #[arg(
+        long,
+        value_name = "VERSION",
+        value_parser = LambdaVersion::from_str,
+        conflicts_with = "stream",
+        help = "Only tail the streams of this Lambda function version ($LATEST or a number). Lambda names streams after the UTC day they started, a follow picks up new streams until midnight UTC of the next day."
+    )]
+    pub lambda_version: Option<LambdaVersion>,
 
-        if self.with_group_name {
-            write!(&mut line, "{} - ", event.group_name.blue())?;
-        }
+    #[arg(
+        short,
+        long,
+        value_parser = parse_human_time,
+        help="The UTC start time. Passed as either date/time or human-friendly format. Use 'in <duration>' or '+<duration>' to start in the future, or 'now' to start at launch. Defaults to 30 seconds ago, or now with --no-lookback.",
+    )]
+    pub start_time: Option<i64>,
 
-        if self.with_stream_name {
-            if let Some(stream_name) = event.log_stream_name.as_deref() {
-                write!(&mut line, "{} - ", stream_name.cyan())?;
-            }
-        }
+    #[arg(
+        long,
+        conflicts_with = "start_time",
+        help = "Without --start-time, start at launch instead of 30 seconds back. Set defaults.lookback = false to make it the default."
+    )]
+    pub no_lookback: bool,
 
-        if self.with_event_id {
-            if let Some(event_id) = event.event_id.as_deref() {
-                write!(&mut line, "{} - ", event_id.yellow())?;
-            }
-        }
+    #[arg(
+        short,
+        long,
+        value_parser = parse_human_time,
+        help="The UTC end time. Passed as either date/time or human-friendly format.",
+    )]
+    pub end_time: Option<i64>,
 
-        if let Some(msg) = &event.message {
-            if self.use_color {
-                if let Some(highlighted) = highlight_json_if_applicable(msg) {
-                    line.push_str(&highlighted);
-                } else {
-                    line.push_str(msg);
-                }
-            } else {
-                line.push_str(msg);
-            }
-        }
+    #[arg(
+        short = 'w',
+        long,
+        value_name = "NAME",
+        help = "Use a time window from the config file. --start-time and --end-time take precedence."
+    )]
+    pub window: Option<String>,
 
-        line.push('\n');
-        self.sink
-            .write_all(line.as_bytes())
-            .await
-            .context("failed to write to sink")
-    }
-}
+    #[arg(short, long, help = "Tail or continue following the logs.")]
+    pub follow: bool,
 
-struct JsonWriter<W>
-where
-    W: AsyncWrite + Unpin + Send,
-{
-    use_local_time: bool,
-    with_timestamp: bool,
-    with_group_name: bool,
-    with_stream_name: bool,
-    with_event_id: bool,
-
-    sink: W,
-}
+    #[arg(
+        short = 'g',
+        long,
+        alias = "grep",
+        help = "Pattern to filter logs by. Repeat to match events matching any of the patterns. See http://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/FilterAndPatternSyntax.html for syntax."
+    )]
+    pub filter: Vec<String>,
 
-impl<W> JsonWriter<W>
-where
-    W: AsyncWrite + Unpin + Send,
-{
-    pub fn new(
-        use_local_time: bool,
-        with_timestamp: bool,
-        with_group_name: bool,
-        with_stream_name: bool,
-        with_event_id: bool,
-        sink: W,
-    ) -> Self {
-        Self {
-            use_local_time,
-            with_timestamp,
-            with_group_name,
-            with_stream_name,
-            with_event_id,
-            sink,
-        }
-    }
-}
+    #[arg(
+        long,
+        value_name = "NAME[:ARG]",
+        conflicts_with = "filter",
+        help = "Filter logs with a named pattern preset, see --list-filter-presets."
+    )]
+    pub filter_preset: Option<String>,
 
-impl<W> LogEventWriter for JsonWriter<W>
-where
-    W: AsyncWrite + Unpin + Send,
-{
-    async fn write(&mut self, event: &LogEvent) -> eyre::Result<()> {
-        let mut json = json!({ "message": event.message });
-
-        if self.with_timestamp {
-            if let Some(time) = event
-                .timestamp
-                .and_then(|ts| parse_timestamp(ts, self.use_local_time))
-            {
-                json["timestamp"] = time.into();
-            }
-        }
+    #[arg(long, help = "List the available filter presets and exit.")]
+    pub list_filter_presets: bool,
 
-        if self.with_event_id {
-            if let Some(id) = &event.event_id {
-                json["id"] = id.clone().into();
-            }
-        }
+    #[command(flatten)]
+    pub output: OutputArgs,
 
-        if self.with_group_name {
-            json["group"] = event.group_name.clone().into();
-        }
+    #[arg(long, help = "Print the AWS region the events were read from.")]
+    pub print_region: bool,
 
-        if self.with_stream_name {
-            if let Some(stream) = &event.log_stream_name {
-                json["stream"] = stream.clone().into();
-            }
-        }
+    #[arg(long, help = "Print the AWS account id the events were read from.")]
+    pub print_account: bool,
 
-        let mut line = json.to_string();
-        line.push('\n');
-        self.sink
-            .write_all(line.as_bytes())
-            .await
-            .context("failed to write to sink")
-    }
-}
+    #[arg(
+        long,
+        help = "Move the start time up to the earliest event still within each log group's retention period."
+    )]
+    pub clamp_to_retention: bool,
 
-#[derive(Parser, Clone, Debug)]
-pub struct Cmd {
-    #[arg(index = 1, value_name = "groupName[:logStreamPrefix][,...]")]
-    pub groups_and_stream_prefix: String,
+    #[arg(
+        long,
+        help = "Print the resolved groups, filter and time window without fetching any events."
+    )]
+    pub dry_run: bool,
 
     #[arg(
-        short,
         long,
-        value_parser = parse_human_time,
-        help="The UTC start time. Passed as either date/time or human-friendly format.",
+        value_name = "NAME",
+        help = "Also store the events in a local archive, search it later with `cw archive search <NAME>`."
     )]
-    pub start_time: Option<i64>,
+    pub archive: Option<String>,
 
     #[arg(
-        short,
         long,
-        value_parser = parse_human_time,
-        help="The UTC end time. Passed as either date/time or human-friendly format.",
+        help = "Don't look the log groups up before tailing, which skips the retention and class checks and the search for similar names when a group doesn't exist."
     )]
-    pub end_time: Option<i64>,
+    pub no_validate: bool,
 
-    #[arg(short, long, help = "Tail or continue following the logs.")]
-    pub follow: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Keep a JSON document with the uptime, event and API call counters of this tail in this file, rewritten every --metrics-interval."
+    )]
+    pub metrics_file: Option<PathBuf>,
 
     #[arg(
-        short = 'g',
         long,
-        alias = "grep",
-        help = "Pattern to filter logs by. See http://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/FilterAndPatternSyntax.html for syntax."
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        default_value = "10s",
+        requires = "metrics_file",
+        help = "How often --metrics-file is rewritten."
     )]
-    pub filter: Option<String>,
+    pub metrics_interval: Duration,
 
-    #[arg(short, long = "timestamp", help = "Print the event timestamp.")]
-    pub print_timestamp: bool,
+    #[arg(
+        long,
+        requires = "follow",
+        help = "After being suspended (Ctrl-Z) and resumed, continue from now instead of catching up on the events written in between."
+    )]
+    pub skip_while_suspended: bool,
 
-    #[arg(short = 'i', long = "event-id", help = "Print the event id.")]
-    pub print_event_id: bool,
+    #[arg(
+        long,
+        requires = "follow",
+        help = "When a followed log group is deleted, wait for it to be created again instead of no longer following it."
+    )]
+    pub wait_for_group: bool,
 
     #[arg(
-        long = "stream-name",
-        help = "Print the log stream name that this event belongs to."
+        long,
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        requires = "follow",
+        help = "When a follow falls further behind the newest events than this, skip ahead to now instead of catching up. cw warns how much it skipped."
     )]
-    pub print_stream_name: bool,
+    pub max_lag: Option<Duration>,
 
     #[arg(
-        long = "group-name",
-        help = "Print the log group name that this event belongs to."
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        requires = "follow",
+        help = "Send a desktop notification when an event message matches this regex, ringing the terminal bell where that isn't possible."
     )]
-    pub print_group_name: bool,
+    pub notify: Option<Regex>,
 
-    #[arg(long, short, value_enum, default_value_t=OutputType::Text)]
-    pub output: OutputType,
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        default_value = "1m",
+        requires = "notify",
+        help = "Notify at most once per this interval, the next notification counts the matches in between."
+    )]
+    pub notify_interval: Duration,
+}
 
-    #[arg(short, long, help = "Treat date and time in local timezone.")]
-    pub local: bool,
+/// What `tail` would request for a single group, see --dry-run.
+#[derive(Debug)]
+struct GroupPlan {
+    group_name: String,
+    stream_prefix: Option<String>,
+    start_time: i64,
 }
 
 impl Cmd {
-    pub async fn run(&self, builder: &LogClientBuilder) -> eyre::Result<()> {
-        let log_group_refs = LogGroupRef::parse(&self.groups_and_stream_prefix)?;
-        let client = builder.build().await?;
-        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
-        let mut tasks = FuturesUnordered::<JoinHandle<eyre::Result<()>>>::new();
+    /// Applies the `[tail]` defaults and the --window from the config file.
+    pub fn with_defaults(&self, config: &Config) -> eyre::Result<Self> {
+        let mut cmd = self.clone();
+        cmd.no_lookback |= !config.defaults.lookback();
+        cmd.output = self.output.with_defaults(&config.tail);
+
+        if let Some(name) = &self.window {
+            let range = TimeRange::new(self.start_time, self.end_time).or(config.window(name)?);
+            cmd.start_time = range.start;
+            cmd.end_time = range.end;
+        }
+        Ok(cmd)
+    }
 
-        let start_time = self
-            .start_time
-            // NOTE: Moving `now` slightly into the past. That way it's more
-            // likely that an empty start time atleast returns something.
-            .unwrap_or_else(|| (Utc::now().timestamp() - 30) * 1000);
+    /// The groups from the positional argument followed by those in `--groups-file`, each pair
+    /// only once.
+    fn group_selectors(&self) -> eyre::Result<Vec<GroupSelector>> {
+        let mut selectors = match &self.groups_and_stream_prefix {
+            Some(groups_and_stream_prefix) => GroupSelector::parse(groups_and_stream_prefix)?,
+            None => Vec::new(),
+        };
+        if let Some(path) = &self.groups_file {
+            let (contents, source) = if path.as_os_str() == "-" {
+                let contents = std::io::read_to_string(std::io::stdin())
+                    .wrap_err("Failed reading the groups from stdin")?;
+                (contents, "stdin".to_string())
+            } else {
+                let contents = std::fs::read_to_string(path).wrap_err_with(|| {
+                    format!("Failed reading the groups file {}", path.display())
+                })?;
+                (contents, path.display().to_string())
+            };
+            selectors.extend(GroupSelector::parse_lines(&contents, &source)?);
+        }
 
-        if self.end_time.is_some() && self.follow {
+        let rules = GroupRules {
+            max: None,
+            stream_prefixes: true,
+        };
+        GroupSelector::resolve(selectors, rules)
+    }
+
+    pub async fn run(
+        &self,
+        builder: &LogClientBuilder,
+        db: impl Database,
+        archive_config: &ArchiveConfig,
+        reporter: Arc<dyn ProgressReporter>,
+        ui: Ui,
+    ) -> eyre::Result<()> {
+        if self.list_filter_presets {
+            return Self::list_filter_presets();
+        }
+
+        let mut selectors = self.group_selectors()?;
+        if let Some(stream_prefix) = &self.stream {
+            selectors = GroupSelector::with_stream_prefix(selectors, stream_prefix)?;
+        }
+        let filter = self.filter_pattern()?;
+        let config = builder.build_sdk_config().await?;
+        let client = builder.client(&config);
+        let limiter = builder.rate_limiter();
+
+        let range = TimeRange::new(self.start_time, self.end_time).resolve(RangeRules {
+            now: Utc::now(),
+            lookback: !self.no_lookback,
+            follow: self.follow,
+            // NOTE: A tail without --follow stops at the newest event, whatever the end time.
+            future_end: true,
+            local: self.output.local,
+        })?;
+        let start_time = range.start;
+
+        if self.output.order_by.is_some() && self.follow {
             return Err(eyre::eyre!(
-                "You can not use --end-time together with --follow!"
+                "You can not use --order-by together with --follow, events are sorted once all of them are in!"
+            ));
+        }
+
+        if let Some(version) = &self.lambda_version {
+            // NOTE: When following, include tomorrow's streams so a follow running past
+            // midnight UTC keeps picking up new execution environments for another day.
+            let now = Utc::now();
+            let end_time = match range.end {
+                Some(end_time) => end_time,
+                None if self.follow => (now + chrono::Days::new(1)).timestamp_millis(),
+                None => now.timestamp_millis(),
+            }
+            .max(start_time);
+            let prefixes = version_stream_prefixes(version, start_time, end_time)?;
+            selectors = GroupSelector::with_stream_prefixes(selectors, &prefixes)?;
+        }
+
+        let mut plans = Vec::with_capacity(selectors.len());
+        let mut missing = Vec::new();
+        // NOTE: --lambda-version turns a group into a selector per day, the group is only looked
+        // up for the first of them.
+        let mut start_times: HashMap<String, i64> = HashMap::new();
+        for selector in selectors {
+            let group_name = selector.group_name().to_string();
+            if missing.contains(&group_name) {
+                continue;
+            }
+            let start_time = match start_times.get(&group_name) {
+                Some(start_time) => *start_time,
+                None if self.no_validate => start_time,
+                None => {
+                    let checked = self
+                        .checked_start_time(&client, &limiter, &group_name, start_time, ui)
+                        .await;
+                    let Some(checked) = checked else {
+                        missing.push(group_name);
+                        continue;
+                    };
+                    start_times.insert(group_name.clone(), checked);
+                    checked
+                }
+            };
+
+            plans.push(GroupPlan {
+                group_name,
+                stream_prefix: selector.stream_prefix().map(str::to_string),
+                start_time,
+            });
+        }
+
+        if !missing.is_empty() {
+            let cache = GroupCache::new(db.clone(), builder, &client);
+            return Err(missing_groups_error(&client, &limiter, &cache, &missing).await);
+        }
+
+        if self.start_time.is_none() {
+            let skew = match builder.clock_skew().get() {
+                Some(skew) => Some(skew),
+                None => Self::measure_skew(&client, &limiter, &plans, builder).await,
+            };
+            if let Some(skew) = skew.filter(|skew| exceeds_threshold(*skew)) {
+                ui.warn(skew_warning(skew));
+                for plan in plans.iter_mut() {
+                    plan.start_time = apply_skew(plan.start_time, skew);
+                }
+            }
+        }
+
+        if self.dry_run {
+            let plan = self.render_plan(
+                &plans,
+                range,
+                filter.as_deref(),
+                builder.profile_name(),
+                config.region().map(|r| r.to_string()),
+                limiter.tps(),
+            )?;
+            print!("{}", plan);
+            return Ok(());
+        }
+
+        let origin = self.resolve_origin(&config).await?;
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut tasks = FuturesUnordered::<JoinHandle<eyre::Result<()>>>::new();
+
+        if start_time > Utc::now().timestamp_millis() {
+            ui.note(format!(
+                "Waiting until {} before tailing.",
+                parse_timestamp(start_time, self.output.local).unwrap_or_default()
             ));
         }
 
-        for LogGroupRef(group_name, stream_name) in &log_group_refs {
+        // NOTE: Like the metrics task, the watcher never ends on its own and is aborted once the
+        // tasks are done.
+        let (resumed, resume_watcher) = match self.follow {
+            true => watch_resume(ui, self.skip_while_suspended)?.unzip(),
+            false => (None, None),
+        };
+
+        for plan in plans {
             let log_producer = tokio::spawn(Self::tail_log_producer(
                 client.clone(),
                 sender.clone(),
-                start_time,
-                self.end_time,
-                self.filter.clone(),
+                reporter.clone(),
+                limiter.clone(),
+                plan.start_time,
+                range.end,
+                filter.clone(),
                 self.follow,
-                group_name.into(),
-                stream_name.clone(),
+                plan.group_name,
+                plan.stream_prefix,
+                resumed.clone(),
+                self.skip_while_suspended,
+                self.wait_for_group,
+                self.max_lag,
+                ui,
             ));
             tasks.push(log_producer);
         }
+        let producers = tasks.len();
         drop(sender); // NOTE: dropping here because each producers already has a clone
 
-        let sink = tokio::io::stdout();
-        let use_color = std::io::stdout().is_terminal();
-        let log_writer = match self.output {
-            OutputType::Text => {
-                let w = TextWriter::new(
-                    self.local,
-                    self.print_timestamp,
-                    self.print_group_name,
-                    self.print_stream_name,
-                    self.print_event_id,
-                    use_color,
-                    sink,
-                );
-                tokio::spawn(Self::write_log_event(receiver, w))
+        let receiver = match &self.archive {
+            Some(name) => {
+                let sink = ArchiveSink {
+                    name: name.clone(),
+                    max_events: archive_config.max_events(),
+                    retention: archive_config.retention()?,
+                };
+                let (forward, forwarded) = tokio::sync::mpsc::unbounded_channel();
+                tasks.push(tokio::spawn(sink.run(db, receiver, forward)));
+                forwarded
             }
-            OutputType::Json => {
-                let w = JsonWriter::new(
-                    self.local,
-                    self.print_timestamp,
-                    self.print_group_name,
-                    self.print_stream_name,
-                    self.print_event_id,
-                    sink,
-                );
-                tokio::spawn(Self::write_log_event(receiver, w))
+            None => receiver,
+        };
+
+        let receiver = match &self.notify {
+            Some(pattern) => {
+                let sink = NotifySink {
+                    pattern: pattern.clone(),
+                    interval: self.notify_interval,
+                };
+                let (forward, forwarded) = tokio::sync::mpsc::unbounded_channel();
+                tasks.push(tokio::spawn(sink.run(receiver, forward)));
+                forwarded
             }
+            None => receiver,
         };
+
+        let metrics = self.metrics_file.as_ref().map(|_| builder.metrics());
+        let log_writer = self
+            .output
+            .spawn_writer(receiver, origin, self.follow, metrics.clone(), ui)
+            .await?;
         tasks.push(log_writer);
 
+        // NOTE: The metrics task never ends on its own, it is left out of `tasks` and aborted
+        // once those are done.
+        let metrics_task = match (&metrics, &self.metrics_file) {
+            (Some(metrics), Some(path)) => Some(tokio::spawn(
+                metrics
+                    .clone()
+                    .write_every(path.clone(), self.metrics_interval),
+            )),
+            _ => None,
+        };
+        let result = Self::wait_for_tasks(tasks, producers, ui).await;
+
+        if let Some(task) = metrics_task {
+            task.abort();
+        }
+        if let Some(task) = resume_watcher {
+            task.abort();
+        }
+        if let (Some(metrics), Some(path)) = (&metrics, &self.metrics_file) {
+            metrics.write_file(path)?;
+        }
+        result
+    }
+
+    /// Waits for every task, aborting the others when one fails. A producer whose group was
+    /// deleted only fails the tail when every one of the `producers` ended that way.
+    async fn wait_for_tasks(
+        mut tasks: FuturesUnordered<JoinHandle<eyre::Result<()>>>,
+        producers: usize,
+        ui: Ui,
+    ) -> eyre::Result<()> {
+        let mut deleted = 0;
         while let Some(res) = tasks.next().await {
             match res {
                 Ok(Ok(())) => continue,
+                Ok(Err(e)) if e.downcast_ref::<GroupDeleted>().is_some() => {
+                    ui.warn(&e);
+                    deleted += 1;
+                }
                 Ok(Err(e)) => {
                     for handle in tasks.into_iter() {
                         handle.abort();
@@ -468,35 +562,251 @@ impl Cmd {
             }
         }
 
+        if deleted > 0 && deleted == producers {
+            return Err(eyre::eyre!("Every followed log group was deleted."));
+        }
         Ok(())
     }
 
-    async fn tail_log_producer(
-        client: Client,
+    fn filter_pattern(&self) -> eyre::Result<Option<String>> {
+        let Some(preset) = &self.filter_preset else {
+            return match self.filter.as_slice() {
+                [] => Ok(None),
+                patterns => {
+                    let pattern = filter_pattern::combine_or(patterns)?;
+                    if patterns.len() > 1 {
+                        tracing::debug!(target: "cw", "filter patterns combined into: {}", pattern);
+                    }
+                    Ok(Some(pattern))
+                }
+            };
+        };
+
+        let pattern = filter_presets::expand(preset)?;
+        tracing::debug!(target: "cw", "filter preset {} expanded to: {}", preset, pattern);
+        Ok(Some(pattern))
+    }
+
+    /// The requests `--dry-run` would send, as text or as JSON with `--output json`.
+    fn render_plan(
+        &self,
+        plans: &[GroupPlan],
+        range: ResolvedRange,
+        filter: Option<&str>,
+        profile: Option<String>,
+        region: Option<String>,
+        tps: u32,
+    ) -> eyre::Result<String> {
+        let render_time = |ms: i64| parse_timestamp(ms, self.output.local).unwrap_or_default();
+        // NOTE: Retention is only checked when a start time was passed, which costs one
+        // DescribeLogGroups call per group.
+        let describe_calls = if self.start_time.is_some() {
+            plans.len()
+        } else {
+            0
+        };
+
+        if matches!(self.output.output_type, OutputType::Json) {
+            let plan = json!({
+                "groups": plans.iter().map(|p| json!({
+                    "group_name": p.group_name,
+                    "stream_prefix": p.stream_prefix,
+                    "start_time": p.start_time,
+                    "start_time_human": render_time(p.start_time),
+                })).collect::<Vec<_>>(),
+                "filter_pattern": filter,
+                "filter_preset": self.filter_preset,
+                "end_time": range.end,
+                "end_time_human": range.end.map(render_time),
+                "follow": self.follow,
+                "profile": profile,
+                "region": region,
+                "poll_interval_secs": [MIN_POLL_INTERVAL_SECS, MAX_POLL_INTERVAL_SECS],
+                "tps": tps,
+                "describe_calls": describe_calls,
+            });
+            return Ok(format!("{}\n", serde_json::to_string_pretty(&plan)?));
+        }
+
+        let mut tw = TabWriter::new(Vec::new()).padding(2).minwidth(0);
+        for plan in plans {
+            writeln!(
+                tw,
+                "group:\t{}{}",
+                plan.group_name,
+                plan.stream_prefix
+                    .as_ref()
+                    .map(|s| format!(" (stream prefix {})", s))
+                    .unwrap_or_default()
+            )?;
+            writeln!(
+                tw,
+                "  start:\t{} ({})",
+                render_time(plan.start_time),
+                plan.start_time
+            )?;
+        }
+        match range.end {
+            Some(end_time) => writeln!(tw, "end:\t{} ({})", render_time(end_time), end_time)?,
+            None if self.follow => writeln!(tw, "end:\tnone, following")?,
+            None => writeln!(tw, "end:\tnone, stops after the last page")?,
+        }
+        match (filter, &self.filter_preset) {
+            (Some(filter), Some(preset)) => {
+                writeln!(tw, "filter:\t{} (preset {})", filter, preset)?
+            }
+            (Some(filter), None) => writeln!(tw, "filter:\t{}", filter)?,
+            (None, _) => writeln!(tw, "filter:\tnone")?,
+        }
+        writeln!(tw, "profile:\t{}", profile.as_deref().unwrap_or("default"))?;
+        writeln!(tw, "region:\t{}", region.as_deref().unwrap_or("unknown"))?;
+        if self.follow {
+            writeln!(
+                tw,
+                "poll interval:\t{}s to {}s backoff when idle",
+                MIN_POLL_INTERVAL_SECS, MAX_POLL_INTERVAL_SECS
+            )?;
+        }
+        writeln!(tw, "rate limit:\t{} requests/s", tps)?;
+        if describe_calls > 0 {
+            writeln!(
+                tw,
+                "note:\t{} DescribeLogGroups call(s) were made to check retention",
+                describe_calls
+            )?;
+        }
+
+        let plan = tw.into_inner().context("failed to render the plan")?;
+        Ok(String::from_utf8(plan)?)
+    }
+
+    fn list_filter_presets() -> eyre::Result<()> {
+        let mut tw = TabWriter::new(std::io::stdout()).padding(2).minwidth(0);
+        for preset in filter_presets::PRESETS {
+            writeln!(tw, "{}", preset)?;
+        }
+        tw.flush().context("failed to write to stdout")
+    }
+
+    async fn resolve_origin(&self, config: &SdkConfig) -> eyre::Result<Origin> {
+        let region = if self.print_region {
+            config.region().map(|r| r.to_string())
+        } else {
+            None
+        };
+
+        // NOTE: Only call STS when the account is actually printed, it's an extra round trip.
+        let account = if self.print_account {
+            Some(caller_account(config).await?)
+        } else {
+            None
+        };
+
+        Ok(Origin { region, account })
+    }
+
+    /// Looks `group_name` up for the class and retention checks, returning the start time to use
+    /// for it, or `None` when the group doesn't exist. Tailing only needs FilterLogEvents, so a
+    /// failed lookup only skips the checks.
+    async fn checked_start_time(
+        &self,
+        client: &impl LogsApi,
+        limiter: &RateLimiter,
+        group_name: &str,
+        start_time: i64,
+        ui: Ui,
+    ) -> Option<i64> {
+        match find_log_group(client, limiter, group_name).await {
+            Ok(Some(log_group)) => {
+                self.check_group_class(&log_group, ui);
+                Some(match self.start_time {
+                    Some(_) => self.retention_start_time(&log_group, ui, start_time),
+                    None => start_time,
+                })
+            }
+            Ok(None) => None,
+            Err(err) => {
+                ui.warn(lookup_warning(group_name, &err));
+                Some(start_time)
+            }
+        }
+    }
+
+    /// Logs the class of `log_group` and warns when following an Infrequent Access group, those
+    /// take longer to make events available to FilterLogEvents.
+    fn check_group_class(&self, log_group: &LogGroup, ui: Ui) {
+        let group_name = log_group.log_group_name().unwrap_or_default();
+        let class = log_group.log_group_class();
+        tracing::info!(
+            target: "cw",
+            "log group {} has class {}",
+            group_name,
+            class.map_or("unknown", |c| c.as_str())
+        );
+
+        if self.follow && class == Some(&LogGroupClass::InfrequentAccess) {
+            ui.warn(format!(
+                "{} is an Infrequent Access log group, it doesn't support live tail and new events can take longer to show up while following.",
+                group_name
+            ));
+        }
+    }
+
+    /// Warns when `start_time` predates the retention period of `log_group`, returning the start
+    /// time to use for that group.
+    fn retention_start_time(&self, log_group: &LogGroup, ui: Ui, start_time: i64) -> i64 {
+        let group_name = log_group.log_group_name().unwrap_or_default();
+        let check = check_retention(
+            start_time,
+            log_group.retention_in_days(),
+            Utc::now().timestamp_millis(),
+        );
+
+        if let Some(warning) = check.warning(group_name, self.clamp_to_retention, self.output.local)
+        {
+            ui.warn(warning);
+        }
+
+        check.start_time(start_time, self.clamp_to_retention)
+    }
+
+    pub(crate) async fn tail_log_producer(
+        client: impl LogsApi,
         sender: UnboundedSender<LogEvent>,
+        reporter: Arc<dyn ProgressReporter>,
+        limiter: Arc<RateLimiter>,
         start_time: i64,
         end_time: Option<i64>,
         filter: Option<String>,
         follow: bool,
         group_name: String,
         stream_name: Option<String>,
+        mut resumed: Option<watch::Receiver<Option<Resumed>>>,
+        skip_while_suspended: bool,
+        wait_for_group: bool,
+        max_lag: Option<Duration>,
+        ui: Ui,
     ) -> eyre::Result<()> {
         tracing::info!(target: "cw", "starting tail log producer");
-        let mut tail_sleep_sec = 1;
+        Self::wait_until(start_time, &group_name).await;
+        reporter.report(ProgressEvent::ProducerStarted { group: &group_name });
+
+        let mut tail_sleep_sec = MIN_POLL_INTERVAL_SECS;
         let mut start_time = start_time;
         let mut next_token: Option<String> = None;
-        let mut builder = client
-            .filter_log_events()
-            .log_group_name(&group_name)
-            .limit(10_000); // INFO: This is the default value.
-
-        if let Some(stream_name) = &stream_name {
-            builder = builder.log_stream_name_prefix(stream_name);
-        }
-
-        if let Some(filter_pattern) = &filter {
-            builder = builder.filter_pattern(filter_pattern);
-        }
+        let mut events_sent: u64 = 0;
+        // NOTE: A group that is missing from the start is an error like any other, only a
+        // group that disappears while following is waited for or left behind.
+        let mut established = false;
+        let mut missing = false;
+        let mut lag_tracker = LagTracker::new(max_lag);
+        let request = FilterLogEventsRequest {
+            group_name: group_name.clone(),
+            stream_name_prefix: stream_name,
+            filter_pattern: filter,
+            limit: Some(10_000), // INFO: This is the default value.
+            ..Default::default()
+        };
 
         loop {
             tracing::trace!(
@@ -506,20 +816,67 @@ impl Cmd {
                 end_time,
                 next_token
             );
-            let response = builder
-                .clone()
-                .start_time(start_time)
-                .set_end_time(end_time)
-                .set_next_token(next_token)
-                .send()
-                .await
-                .context("Failed to fetch CloudWatch logs.")?;
+            limiter.acquire().await;
+            let result = client
+                .filter_log_events(FilterLogEventsRequest {
+                    start_time: Some(start_time),
+                    end_time,
+                    next_token,
+                    ..request.clone()
+                })
+                .await;
+            let response = match result {
+                Ok(response) => response,
+                Err(err)
+                    if follow
+                        && established
+                        && classify_error(&err) == ApiErrorKind::ResourceNotFound =>
+                {
+                    if !wait_for_group {
+                        reporter.report(ProgressEvent::ProducerStopped {
+                            group: &group_name,
+                            events: events_sent,
+                        });
+                        return Err(GroupDeleted(group_name).into());
+                    }
+                    if !missing {
+                        ui.warn(format!(
+                            "{} was deleted, waiting for it to be created again.",
+                            group_name
+                        ));
+                        missing = true;
+                    }
+                    next_token = None;
+                    tokio::time::sleep(Duration::from_secs(MAX_POLL_INTERVAL_SECS)).await;
+                    continue;
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("Failed to fetch log events for {}.", group_name))
+                }
+            };
+            established = true;
+            if missing {
+                ui.note(format!("{} is back, following it again.", group_name));
+                missing = false;
+            }
 
             let events = response.events();
+            let now = Utc::now().timestamp_millis();
+            let newest = newest_timestamp(events);
+            reporter.report(ProgressEvent::PageFetched {
+                group: &group_name,
+                count: events.len(),
+                has_next_token: response.next_token().is_some(),
+                lag_ms: newest.map(|newest| now - newest),
+            });
+
             for event in events {
                 // NOTE: This only errors if the receiver is dropped or closed. If this happens
-                // there's no point in continuing to process anymore events.
+                // there's no point in continuing to process anymore events. The channel is
+                // unbounded, so the next page is requested while the writer is still busy.
                 sender.send((group_name.as_str(), event).into())?;
+                events_sent += 1;
             }
 
             next_token = response.next_token().map(|s| s.to_string());
@@ -533,6 +890,47 @@ impl Cmd {
                 start_time = timestamp + 1;
             }
 
+            let action = match newest {
+                Some(newest) if follow => lag_tracker.check(newest, now, next_token.is_some()),
+                _ => LagAction::None,
+            };
+            match action {
+                LagAction::None => {}
+                LagAction::Warn { lag } => ui.warn(format!(
+                    "{} is {} behind its newest events, cw can't keep up with how fast they are written. Narrow it down with --filter or a stream prefix, or pass --max-lag to skip ahead.",
+                    group_name,
+                    format_duration_ms(lag)
+                )),
+                LagAction::CaughtUp => {
+                    ui.note(format!("{} caught up with its newest events.", group_name))
+                }
+                LagAction::Skip { to, skipped } => {
+                    ui.warn(format!(
+                        "Skipped ~{} of {} to stay within --max-lag.",
+                        format_duration_ms(skipped),
+                        group_name
+                    ));
+                    start_time = start_time.max(to);
+                    next_token = None;
+                    continue;
+                }
+            }
+
+            // NOTE: Every producer sees the resume at once, starting over from the shortest poll
+            // interval keeps them from waiting out a backoff that was set before the suspension.
+            let resume = resumed
+                .as_mut()
+                .filter(|resumed| resumed.has_changed().unwrap_or(false))
+                .and_then(|resumed| *resumed.borrow_and_update());
+            if let Some(resume) = resume {
+                tail_sleep_sec = MIN_POLL_INTERVAL_SECS;
+                if skip_while_suspended {
+                    start_time = start_time.max(resume.at);
+                    next_token = None;
+                }
+                continue;
+            }
+
             if events.len() == 0 && follow {
                 tracing::debug!(
                     target: "cw",
@@ -540,24 +938,324 @@ impl Cmd {
                     tail_sleep_sec
                 );
                 tokio::time::sleep(Duration::from_secs(tail_sleep_sec)).await;
-                tail_sleep_sec = (tail_sleep_sec + 1).clamp(1, 10);
+                tail_sleep_sec =
+                    (tail_sleep_sec + 1).clamp(MIN_POLL_INTERVAL_SECS, MAX_POLL_INTERVAL_SECS);
             } else {
-                tail_sleep_sec = 1;
+                tail_sleep_sec = MIN_POLL_INTERVAL_SECS;
             }
         }
+
+        reporter.report(ProgressEvent::ProducerStopped {
+            group: &group_name,
+            events: events_sent,
+        });
         Ok(())
     }
 
-    async fn write_log_event(
-        mut receiver: UnboundedReceiver<LogEvent>,
-        mut writer: impl LogEventWriter,
+    /// Measures the clock skew with a single lookup when nothing was requested yet, which is the
+    /// case with --no-validate.
+    async fn measure_skew(
+        client: &impl LogsApi,
+        limiter: &RateLimiter,
+        plans: &[GroupPlan],
+        builder: &LogClientBuilder,
+    ) -> Option<i64> {
+        let plan = plans.first()?;
+        limiter.acquire().await;
+        // NOTE: An error response carries a Date header as well, so even a denied lookup measures
+        // the skew. The error itself shows up again once the producers start.
+        let _ = client
+            .describe_log_groups(DescribeLogGroupsRequest {
+                name_prefix: Some(plan.group_name.clone()),
+                limit: Some(1),
+                ..Default::default()
+            })
+            .await;
+        builder.clock_skew().get()
+    }
+
+    /// Sleeps until `start_time` when it lies in the future, so a scheduled tail doesn't keep
+    /// polling an empty window.
+    async fn wait_until(start_time: i64, group_name: &str) {
+        let now = Utc::now().timestamp_millis();
+        let Ok(delay) = u64::try_from(start_time - now) else {
+            return;
+        };
+
+        if delay > 0 {
+            tracing::info!(target: "cw", "waiting {}ms before tailing {}", delay, group_name);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use aws_sdk_cloudwatchlogs::operation::filter_log_events::{
+        FilterLogEventsError, FilterLogEventsOutput,
+    };
+    use aws_sdk_cloudwatchlogs::types::FilteredLogEvent;
+    use clap::Parser;
+    use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+    use crate::commands::tail::{Cmd, GroupDeleted, GroupPlan};
+    use crate::event::LogEvent;
+    use crate::fake_logs::{service_error, FakeLogs};
+    use crate::progress_reporter::NoopReporter;
+    use crate::rate_limit::RateLimiter;
+    use crate::ui::Ui;
+    use crate::utils::ResolvedRange;
+
+    fn page(events: &[(i64, &str)], next_token: Option<&str>) -> FilterLogEventsOutput {
+        FilterLogEventsOutput::builder()
+            .set_events(Some(
+                events
+                    .iter()
+                    .map(|(timestamp, message)| {
+                        FilteredLogEvent::builder()
+                            .log_stream_name("web-1")
+                            .timestamp(*timestamp)
+                            .message(*message)
+                            .build()
+                    })
+                    .collect(),
+            ))
+            .set_next_token(next_token.map(str::to_string))
+            .build()
+    }
+
+    async fn produce(
+        logs: &FakeLogs,
+        sender: UnboundedSender<LogEvent>,
+        end_time: Option<i64>,
+        follow: bool,
+        wait_for_group: bool,
     ) -> eyre::Result<()> {
-        tracing::info!(target: "cw", "starting tail log writer");
+        Cmd::tail_log_producer(
+            logs.clone(),
+            sender,
+            Arc::new(NoopReporter),
+            Arc::new(RateLimiter::new(100)),
+            0,
+            end_time,
+            None,
+            follow,
+            "/ecs/api".to_string(),
+            None,
+            None,
+            false,
+            wait_for_group,
+            None,
+            Ui::new(true),
+        )
+        .await
+    }
 
-        while let Some(event) = receiver.recv().await {
-            writer.write(&event).await?;
+    fn messages(receiver: &mut UnboundedReceiver<LogEvent>) -> Vec<String> {
+        let mut messages = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            messages.push(event.message.unwrap_or_default());
         }
+        messages
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn a_backfill_fetches_every_page_without_waiting_for_the_writer() {
+        let logs = FakeLogs::new();
+        logs.push_filter_log_events(Ok(page(&[(1_000, "first"), (2_000, "second")], Some("t1"))))
+            .push_filter_log_events(Ok(page(&[(3_000, "third")], Some("t2"))))
+            .push_filter_log_events(Ok(page(&[(4_000, "fourth")], None)));
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        // NOTE: Nothing reads the events until the producer is done.
+        produce(&logs, sender, Some(10_000), false, false)
+            .await
+            .unwrap();
+        let tokens: Vec<_> = logs
+            .filter_log_events_requests()
+            .into_iter()
+            .map(|request| request.next_token)
+            .collect();
+        assert_eq!(
+            tokens,
+            [None, Some("t1".to_string()), Some("t2".to_string())]
+        );
+        assert_eq!(
+            messages(&mut receiver),
+            ["first", "second", "third", "fourth"]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_group_deleted_while_following_ends_its_producer() {
+        let logs = FakeLogs::new();
+        logs.push_filter_log_events(Ok(page(&[(1_000, "before")], None)))
+            .push_filter_log_events(Err(service_error::<FilterLogEventsError>(
+                "ResourceNotFoundException",
+                "The specified log group does not exist.",
+            )));
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let err = produce(&logs, sender, None, true, false).await.unwrap_err();
+
+        assert!(err.downcast_ref::<GroupDeleted>().is_some());
+        assert_eq!(messages(&mut receiver), ["before"]);
+    }
+
+    #[tokio::test]
+    async fn a_group_missing_from_the_start_is_an_error_like_any_other() {
+        let logs = FakeLogs::new();
+        logs.push_filter_log_events(Err(service_error::<FilterLogEventsError>(
+            "ResourceNotFoundException",
+            "The specified log group does not exist.",
+        )));
+        let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let err = produce(&logs, sender, None, true, false).await.unwrap_err();
+
+        assert!(err.downcast_ref::<GroupDeleted>().is_none());
+        assert!(err.to_string().contains("/ecs/api"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiting_for_a_deleted_group_picks_it_up_again() {
+        let logs = FakeLogs::new();
+        logs.push_filter_log_events(Ok(page(&[(1_000, "before")], None)))
+            .push_filter_log_events(Err(service_error::<FilterLogEventsError>(
+                "ResourceNotFoundException",
+                "The specified log group does not exist.",
+            )))
+            .push_filter_log_events(Ok(page(&[(5_000, "back")], None)));
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        // NOTE: A follow never ends on its own, the paused clock runs out the timeout right away.
+        let followed = tokio::time::timeout(
+            Duration::from_secs(60),
+            produce(&logs, sender, None, true, true),
+        )
+        .await;
+
+        assert!(followed.is_err());
+        assert_eq!(messages(&mut receiver), ["before", "back"]);
+        let requests = logs.filter_log_events_requests();
+        assert_eq!(requests[2].start_time, Some(1_001));
+        assert_eq!(requests[2].next_token, None);
+    }
+
+    fn plans() -> Vec<GroupPlan> {
+        vec![
+            GroupPlan {
+                group_name: "/ecs/web".to_string(),
+                stream_prefix: Some("nginx/".to_string()),
+                start_time: 1_717_250_000_000,
+            },
+            GroupPlan {
+                group_name: "/aws/lambda/api".to_string(),
+                stream_prefix: None,
+                start_time: 1_717_250_030_000,
+            },
+        ]
+    }
+
+    #[test]
+    fn dry_run_plan_while_following() {
+        let cmd = Cmd::parse_from([
+            "tail",
+            "/ecs/web",
+            "--follow",
+            "--filter-preset",
+            "contains-error",
+        ]);
+        let range = ResolvedRange {
+            start: 1_717_250_000_000,
+            end: None,
+        };
+
+        let plan = cmd
+            .render_plan(
+                &plans(),
+                range,
+                Some("?ERROR ?Error ?error"),
+                None,
+                Some("eu-west-1".to_string()),
+                5,
+            )
+            .unwrap();
+
+        assert_eq!(
+            plan,
+            concat!(
+                "group:          /ecs/web (stream prefix nginx/)\n",
+                "  start:        2024-06-01T13:53:20Z (1717250000000)\n",
+                "group:          /aws/lambda/api\n",
+                "  start:        2024-06-01T13:53:50Z (1717250030000)\n",
+                "end:            none, following\n",
+                "filter:         ?ERROR ?Error ?error (preset contains-error)\n",
+                "profile:        default\n",
+                "region:         eu-west-1\n",
+                "poll interval:  1s to 10s backoff when idle\n",
+                "rate limit:     5 requests/s\n",
+            )
+        );
+    }
+
+    #[test]
+    fn dry_run_plan_as_json() {
+        let cmd = Cmd::parse_from([
+            "tail",
+            "/ecs/web",
+            "--start-time",
+            "2024-06-01T13:53:20Z",
+            "--output",
+            "json",
+        ]);
+        let range = ResolvedRange {
+            start: 1_717_250_000_000,
+            end: Some(1_717_253_600_000),
+        };
+
+        let plan = cmd
+            .render_plan(
+                &plans()[..1],
+                range,
+                None,
+                Some("prod".to_string()),
+                None,
+                5,
+            )
+            .unwrap();
+
+        assert_eq!(
+            plan,
+            concat!(
+                "{\n",
+                "  \"describe_calls\": 1,\n",
+                "  \"end_time\": 1717253600000,\n",
+                "  \"end_time_human\": \"2024-06-01T14:53:20Z\",\n",
+                "  \"filter_pattern\": null,\n",
+                "  \"filter_preset\": null,\n",
+                "  \"follow\": false,\n",
+                "  \"groups\": [\n",
+                "    {\n",
+                "      \"group_name\": \"/ecs/web\",\n",
+                "      \"start_time\": 1717250000000,\n",
+                "      \"start_time_human\": \"2024-06-01T13:53:20Z\",\n",
+                "      \"stream_prefix\": \"nginx/\"\n",
+                "    }\n",
+                "  ],\n",
+                "  \"poll_interval_secs\": [\n",
+                "    1,\n",
+                "    10\n",
+                "  ],\n",
+                "  \"profile\": \"prod\",\n",
+                "  \"region\": null,\n",
+                "  \"tps\": 5\n",
+                "}\n",
+            )
+        );
     }
 }
@@ -0,0 +1,294 @@
+//! Every conversion from AWS SDK types that cw relies on, so an SDK upgrade that renames a field
+//! or adds a status only touches this file. The rest of cw works with the types here, the SDK
+//! types only show up where clients are built and in the [`crate::logs_api::LogsApi`] calls.
+
+use std::fmt::Display;
+
+use aws_sdk_cloudwatchlogs::error::ProvideErrorMetadata;
+use aws_sdk_cloudwatchlogs::types::{FilteredLogEvent, QueryStatistics, QueryStatus, ResultField};
+
+use crate::event::LogEvent;
+
+impl From<(&str, &FilteredLogEvent)> for LogEvent {
+    fn from((group_name, event): (&str, &FilteredLogEvent)) -> Self {
+        Self {
+            group_name: group_name.to_owned(),
+            log_stream_name: event.log_stream_name.clone(),
+            timestamp: event.timestamp,
+            message: event.message.clone(),
+            ingestion_time: event.ingestion_time,
+            event_id: event.event_id.clone(),
+        }
+    }
+}
+
+/// The timestamp of the newest event in a page, pages are not always in timestamp order.
+pub(crate) fn newest_timestamp(events: &[FilteredLogEvent]) -> Option<i64> {
+    events.iter().filter_map(|event| event.timestamp()).max()
+}
+
+/// The fields of a Logs Insights result row as name and value, without `@ptr`. A field without
+/// a value is empty.
+pub(crate) fn result_fields(row: &[ResultField]) -> impl Iterator<Item = (&str, &str)> {
+    row.iter().filter_map(|field| {
+        let name = field.field()?;
+        (name != "@ptr").then(|| (name, field.value().unwrap_or_default()))
+    })
+}
+
+/// The status of a Logs Insights query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryState {
+    Scheduled,
+    Running,
+    Complete,
+    Failed,
+    Timeout,
+    Cancelled,
+    /// A status this version of cw doesn't know, or none at all.
+    Unknown(Option<String>),
+}
+
+impl QueryState {
+    pub fn of(status: Option<&QueryStatus>) -> Self {
+        match status {
+            Some(QueryStatus::Scheduled) => Self::Scheduled,
+            Some(QueryStatus::Running) => Self::Running,
+            Some(QueryStatus::Complete) => Self::Complete,
+            Some(QueryStatus::Failed) => Self::Failed,
+            Some(QueryStatus::Timeout) => Self::Timeout,
+            Some(QueryStatus::Cancelled) => Self::Cancelled,
+            Some(other) => Self::Unknown(Some(other.as_str().to_string())),
+            None => Self::Unknown(None),
+        }
+    }
+
+    /// Whether the query stopped, successfully or not, and polling again won't change anything.
+    pub fn is_done(&self) -> bool {
+        matches!(
+            self,
+            Self::Complete | Self::Failed | Self::Timeout | Self::Cancelled
+        )
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Scheduled => "Scheduled",
+            Self::Running => "Running",
+            Self::Complete => "Complete",
+            Self::Failed => "Failed",
+            Self::Timeout => "Timeout",
+            Self::Cancelled => "Cancelled",
+            Self::Unknown(Some(status)) => status,
+            Self::Unknown(None) => "Unknown",
+        }
+    }
+}
+
+impl Display for QueryState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What a Logs Insights query went through, as reported with its results.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueryStats {
+    pub records_matched: f64,
+    pub records_scanned: f64,
+    pub bytes_scanned: f64,
+}
+
+impl From<&QueryStatistics> for QueryStats {
+    fn from(statistics: &QueryStatistics) -> Self {
+        Self {
+            records_matched: statistics.records_matched,
+            records_scanned: statistics.records_scanned,
+            bytes_scanned: statistics.bytes_scanned,
+        }
+    }
+}
+
+/// The kinds of failed calls cw explains to the user, told apart by the error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorKind {
+    AccessDenied,
+    ResourceNotFound,
+    LimitExceeded,
+    Other,
+}
+
+/// Classifies a failed call on its error code, the same for every operation.
+pub fn classify_error(err: &impl ProvideErrorMetadata) -> ApiErrorKind {
+    match err.code() {
+        Some("AccessDeniedException") => ApiErrorKind::AccessDenied,
+        Some("ResourceNotFoundException") => ApiErrorKind::ResourceNotFound,
+        Some("LimitExceededException") => ApiErrorKind::LimitExceeded,
+        _ => ApiErrorKind::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_cloudwatchlogs::error::ErrorMetadata;
+    use aws_sdk_cloudwatchlogs::operation::start_query::StartQueryError;
+    use aws_sdk_cloudwatchlogs::types::{
+        FilteredLogEvent, QueryStatistics, QueryStatus, ResultField,
+    };
+
+    use crate::compat::{
+        classify_error, newest_timestamp, result_fields, ApiErrorKind, QueryState, QueryStats,
+    };
+    use crate::event::LogEvent;
+    use crate::fake_logs::service_error;
+
+    #[test]
+    fn filtered_log_events_become_log_events_of_the_group() {
+        let event = FilteredLogEvent::builder()
+            .log_stream_name("web-1")
+            .timestamp(1_000)
+            .message("started")
+            .ingestion_time(1_005)
+            .event_id("id-1")
+            .build();
+
+        assert_eq!(
+            LogEvent::from(("/ecs/api", &event)),
+            LogEvent {
+                group_name: "/ecs/api".to_string(),
+                log_stream_name: Some("web-1".to_string()),
+                timestamp: Some(1_000),
+                message: Some("started".to_string()),
+                ingestion_time: Some(1_005),
+                event_id: Some("id-1".to_string()),
+            }
+        );
+        assert_eq!(
+            LogEvent::from(("/ecs/api", &FilteredLogEvent::builder().build())),
+            LogEvent {
+                group_name: "/ecs/api".to_string(),
+                log_stream_name: None,
+                timestamp: None,
+                message: None,
+                ingestion_time: None,
+                event_id: None,
+            }
+        );
+    }
+
+    #[test]
+    fn newest_timestamp_is_the_latest_in_the_page() {
+        let at = |timestamp| FilteredLogEvent::builder().timestamp(timestamp).build();
+        let events = [at(20), FilteredLogEvent::builder().build(), at(30), at(10)];
+
+        assert_eq!(newest_timestamp(&events), Some(30));
+        assert_eq!(
+            newest_timestamp(&[FilteredLogEvent::builder().build()]),
+            None
+        );
+        assert_eq!(newest_timestamp(&[]), None);
+    }
+
+    #[test]
+    fn result_fields_skip_the_pointer_and_unnamed_fields() {
+        let row = [
+            ResultField::builder()
+                .field("@timestamp")
+                .value("2024-06-01 14:03:11.123")
+                .build(),
+            ResultField::builder().field("@ptr").value("CmAK").build(),
+            ResultField::builder().field("level").build(),
+            ResultField::builder().value("orphan").build(),
+            ResultField::builder()
+                .field("@message")
+                .value("started")
+                .build(),
+        ];
+
+        assert_eq!(
+            result_fields(&row).collect::<Vec<_>>(),
+            [
+                ("@timestamp", "2024-06-01 14:03:11.123"),
+                ("level", ""),
+                ("@message", "started"),
+            ]
+        );
+    }
+
+    #[test]
+    fn query_states_follow_the_sdk_status() {
+        let cases = [
+            (QueryStatus::Scheduled, QueryState::Scheduled, false),
+            (QueryStatus::Running, QueryState::Running, false),
+            (QueryStatus::Complete, QueryState::Complete, true),
+            (QueryStatus::Failed, QueryState::Failed, true),
+            (QueryStatus::Timeout, QueryState::Timeout, true),
+            (QueryStatus::Cancelled, QueryState::Cancelled, true),
+        ];
+
+        for (status, expected, done) in cases {
+            let state = QueryState::of(Some(&status));
+            assert_eq!(state, expected);
+            assert_eq!(state.is_done(), done, "{}", state);
+            assert_eq!(state.to_string(), status.as_str());
+        }
+    }
+
+    #[test]
+    fn an_unknown_query_status_keeps_its_name() {
+        let state = QueryState::of(Some(&QueryStatus::from("Paused")));
+
+        assert_eq!(state, QueryState::Unknown(Some("Paused".to_string())));
+        assert!(!state.is_done());
+        assert_eq!(state.to_string(), "Paused");
+
+        let state = QueryState::of(None);
+        assert_eq!(state, QueryState::Unknown(None));
+        assert!(!state.is_done());
+        assert_eq!(state.to_string(), "Unknown");
+    }
+
+    #[test]
+    fn query_stats_come_from_the_statistics() {
+        let statistics = QueryStatistics::builder()
+            .records_matched(12.0)
+            .records_scanned(3_400.0)
+            .bytes_scanned(56_000.0)
+            .build();
+
+        assert_eq!(
+            QueryStats::from(&statistics),
+            QueryStats {
+                records_matched: 12.0,
+                records_scanned: 3_400.0,
+                bytes_scanned: 56_000.0,
+            }
+        );
+    }
+
+    #[test]
+    fn classify_error_goes_by_the_error_code() {
+        let cases = [
+            ("AccessDeniedException", ApiErrorKind::AccessDenied),
+            ("ResourceNotFoundException", ApiErrorKind::ResourceNotFound),
+            ("LimitExceededException", ApiErrorKind::LimitExceeded),
+            ("ThrottlingException", ApiErrorKind::Other),
+            ("InvalidParameterException", ApiErrorKind::Other),
+            ("accessdeniedexception", ApiErrorKind::Other),
+            ("", ApiErrorKind::Other),
+        ];
+
+        for (code, expected) in cases {
+            let err = service_error::<StartQueryError>(code, "failed");
+            let service_err = err.as_service_error().unwrap();
+            assert_eq!(classify_error(service_err), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn classify_error_without_a_code_is_other() {
+        let meta = ErrorMetadata::builder().message("failed").build();
+
+        assert_eq!(classify_error(&meta), ApiErrorKind::Other);
+    }
+}
@@ -1,8 +1,21 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use eyre::Context;
+use serde::Deserialize;
+use toml_edit::DocumentMut;
+
+use crate::db::JournalMode;
+use crate::time_window::{resolve_window, TimeWindow, WindowConfig};
+use crate::utils::{PrefixField, TimeRange, TimeStyle};
 
 pub trait ConfigManager: Sized + Clone + Send + Sync {
-    fn get_db_path(&self) -> eyre::Result<String>;
-    fn get_log_path(&self) -> eyre::Result<String>;
+    fn get_db_path(&self) -> eyre::Result<PathBuf>;
+    fn get_log_path(&self) -> eyre::Result<PathBuf>;
+    fn get_config_path(&self) -> eyre::Result<PathBuf>;
+    fn get_state_path(&self) -> eyre::Result<PathBuf>;
 }
 
 #[derive(Default, Clone, Debug)]
@@ -30,44 +43,682 @@ pub fn home_dir() -> PathBuf {
     PathBuf::from(home)
 }
 
+/// The kinds of directories cw keeps files in. On Unix they follow the XDG base directory
+/// spec, on Windows they live under %LOCALAPPDATA% (config under %APPDATA%) unless the XDG
+/// variable is set explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AppDir {
+    Data,
+    Config,
+    State,
+    Cache,
+}
+
+impl AppDir {
+    fn xdg_var(self) -> &'static str {
+        match self {
+            AppDir::Data => "XDG_DATA_HOME",
+            AppDir::Config => "XDG_CONFIG_HOME",
+            AppDir::State => "XDG_STATE_HOME",
+            AppDir::Cache => "XDG_CACHE_HOME",
+        }
+    }
+
+    fn resolve(self) -> PathBuf {
+        self.resolve_from(std::env::var_os(self.xdg_var()))
+    }
+
+    // NOTE: Takes the value of the XDG variable so tests don't have to touch the environment,
+    // which is shared by every test running in parallel.
+    fn resolve_from(self, xdg_dir: Option<OsString>) -> PathBuf {
+        xdg_dir.filter(|dir| !dir.is_empty()).map_or_else(
+            || self.platform_default(),
+            |dir| PathBuf::from(dir).join("cw"),
+        )
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn platform_default(self) -> PathBuf {
+        let home = home_dir();
+        match self {
+            AppDir::Data => home.join(".local").join("share"),
+            AppDir::Config => home.join(".config"),
+            AppDir::State => home.join(".local").join("state"),
+            AppDir::Cache => home.join(".local").join("cache"),
+        }
+        .join("cw")
+    }
+
+    #[cfg(target_os = "windows")]
+    fn platform_default(self) -> PathBuf {
+        self.known_folder_default(
+            std::env::var_os("APPDATA"),
+            std::env::var_os("LOCALAPPDATA"),
+        )
+    }
+
+    // NOTE: Like `resolve_from`, takes %APPDATA% and %LOCALAPPDATA% so tests don't have to set
+    // them.
+    #[cfg(target_os = "windows")]
+    fn known_folder_default(
+        self,
+        app_data: Option<OsString>,
+        local_app_data: Option<OsString>,
+    ) -> PathBuf {
+        let known_folder = |dir: Option<OsString>, fallback: &[&str]| {
+            dir.filter(|dir| !dir.is_empty()).map_or_else(
+                || fallback.iter().fold(home_dir(), |dir, part| dir.join(part)),
+                PathBuf::from,
+            )
+        };
+
+        match self {
+            AppDir::Config => known_folder(app_data, &["AppData", "Roaming"]).join("cw"),
+            AppDir::Data => known_folder(local_app_data, &["AppData", "Local"])
+                .join("cw")
+                .join("data"),
+            AppDir::State => known_folder(local_app_data, &["AppData", "Local"])
+                .join("cw")
+                .join("state"),
+            AppDir::Cache => known_folder(local_app_data, &["AppData", "Local"])
+                .join("cw")
+                .join("cache"),
+        }
+    }
+}
+
 pub fn data_dir() -> PathBuf {
-    let data_dir = std::env::var("XDG_DATA_HOME")
-        .map_or_else(|_| home_dir().join(".local").join("share"), PathBuf::from);
+    AppDir::Data.resolve()
+}
 
-    data_dir.join("cw")
+pub fn config_dir() -> PathBuf {
+    AppDir::Config.resolve()
 }
 
-pub fn cache_dir() -> PathBuf {
-    let data_dir = std::env::var("XDG_CACHE_HOME")
-        .map_or_else(|_| home_dir().join(".local").join("cache"), PathBuf::from);
+/// Where cw keeps data that should outlive a session but isn't worth backing up, like its log.
+pub fn state_dir() -> PathBuf {
+    AppDir::State.resolve()
+}
 
-    data_dir.join("cw")
+/// Only for data cw can regenerate at any time, the directory may be wiped while cw runs.
+pub fn cache_dir() -> PathBuf {
+    AppDir::Cache.resolve()
 }
 
 impl ConfigManager for LocalConfigManager {
-    fn get_db_path(&self) -> eyre::Result<String> {
-        let mut cw_data_dir = data_dir();
-
+    fn get_db_path(&self) -> eyre::Result<PathBuf> {
+        let cw_data_dir = data_dir();
         std::fs::create_dir_all(&cw_data_dir)?;
+        Ok(cw_data_dir.join("db.sqlite3"))
+    }
 
-        cw_data_dir.push("db.sqlite3");
+    fn get_log_path(&self) -> eyre::Result<PathBuf> {
+        let log_path = self.get_state_path()?.join("cw.log");
+        migrate_legacy_log(&cache_dir().join("cw.log"), &log_path);
+        Ok(log_path)
+    }
+
+    fn get_config_path(&self) -> eyre::Result<PathBuf> {
+        Ok(config_dir().join("config.toml"))
+    }
+
+    fn get_state_path(&self) -> eyre::Result<PathBuf> {
+        let cw_state_dir = state_dir();
+        std::fs::create_dir_all(&cw_state_dir)?;
+        Ok(cw_state_dir)
+    }
+}
+
+// NOTE: Older versions wrote the log to the cache dir, carry it over once. Losing it is not worth
+// failing over, so a failed move just starts a fresh log.
+fn migrate_legacy_log(legacy_path: &Path, log_path: &Path) {
+    if legacy_path.exists() && !log_path.exists() {
+        let _ = std::fs::rename(legacy_path, log_path);
+    }
+}
 
-        match cw_data_dir.to_str() {
-            Some(data) => Ok(data.to_string()),
-            None => Err(eyre::eyre!("Can't construct db path in data dir!")),
+const DEFAULT_AUDIT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const DEFAULT_ARCHIVE_RETENTION: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+const DEFAULT_ARCHIVE_MAX_EVENTS: u64 = 1_000_000;
+
+pub const CONFIG_TEMPLATE: &str = r#"# cw configuration file.
+#
+# Run `cw config list` to see every available key and where its current value comes from.
+# Flags passed on the command line always take precedence over the values below.
+
+# [defaults]
+# profile = "default"
+# region = "eu-west-1"
+# tps = 5
+# lookback = true # start 30s back when tail or query get no --start-time
+
+# [tail]
+# timestamp = true
+# event_id = false
+# stream_name = false
+# group_name = false
+# local = false
+# time_style = "iso" # iso, iso-millis, compact, unix or relative
+# prefix_order = "stream,timestamp" # picks and orders timestamp, group, stream and id
+
+# Named time windows for `--window <name>` on tail and query.
+# [windows.business-hours]
+# start = "09:00"
+# end = "17:30"
+#
+# [windows.today]
+# last = "24h"
+#
+# [windows.since-deploy]
+# from_file = "/var/run/last-deploy"
+
+# Record every CloudWatch Logs call (operation, groups, time range, outcome) in the local
+# database, review them with `cw audit list`.
+# [audit]
+# enabled = false
+# retention = "30d"
+
+# Limits for the archives written by `cw tail --archive <name>`. Each archive keeps at most
+# max_events events, the oldest go first, and events archived longer than retention ago are
+# removed.
+# [archive]
+# max_events = 1000000
+# retention = "90d"
+
+# The database uses WAL and falls back to DELETE when the filesystem can't do WAL, which is
+# common on NFS. Set journal_mode to "wal" or "delete" to skip the detection.
+# [database]
+# journal_mode = "delete"
+
+# Look for newer cw releases in a JSON manifest like {"version": "0.3.0", "url": "..."}, once a
+# day and on `cw --version`. Nothing is fetched unless check_url is set.
+# [update]
+# check_url = "https://artifacts.example.com/cw/latest.json"
+"#;
+
+/// Settings read from the config file. Every value is optional, unset values fall back to the
+/// built-in defaults, and keys cw doesn't know about are ignored so newer files keep working.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub defaults: DefaultsConfig,
+    pub tail: TailConfig,
+    pub windows: BTreeMap<String, WindowConfig>,
+    pub audit: AuditConfig,
+    pub archive: ArchiveConfig,
+    pub database: DatabaseConfig,
+    pub update: UpdateConfig,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct UpdateConfig {
+    pub check_url: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub journal_mode: Option<JournalMode>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: Option<bool>,
+    pub retention: Option<String>,
+}
+
+impl AuditConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+
+    /// How long audit entries are kept, 30 days unless configured otherwise.
+    pub fn retention(&self) -> eyre::Result<Duration> {
+        match &self.retention {
+            Some(retention) => humantime::parse_duration(retention)
+                .wrap_err_with(|| format!("Invalid audit.retention '{}'", retention)),
+            None => Ok(DEFAULT_AUDIT_RETENTION),
         }
     }
+}
 
-    fn get_log_path(&self) -> eyre::Result<String> {
-        let mut cw_cache_dir = cache_dir();
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    pub max_events: Option<u64>,
+    pub retention: Option<String>,
+}
 
-        std::fs::create_dir_all(&cw_cache_dir)?;
+impl ArchiveConfig {
+    /// The most events a single archive keeps, a million unless configured otherwise.
+    pub fn max_events(&self) -> u64 {
+        self.max_events.unwrap_or(DEFAULT_ARCHIVE_MAX_EVENTS)
+    }
 
-        cw_cache_dir.push("cw.log");
+    /// How long archived events are kept, 90 days unless configured otherwise.
+    pub fn retention(&self) -> eyre::Result<Duration> {
+        match &self.retention {
+            Some(retention) => humantime::parse_duration(retention)
+                .wrap_err_with(|| format!("Invalid archive.retention '{}'", retention)),
+            None => Ok(DEFAULT_ARCHIVE_RETENTION),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct DefaultsConfig {
+    pub profile: Option<String>,
+    pub region: Option<String>,
+    pub tps: Option<u32>,
+    pub lookback: Option<bool>,
+}
+
+impl DefaultsConfig {
+    /// Whether tail and query start 30 seconds back when no start time is given, they do unless
+    /// configured otherwise.
+    pub fn lookback(&self) -> bool {
+        self.lookback.unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct TailConfig {
+    pub timestamp: Option<bool>,
+    pub event_id: Option<bool>,
+    pub stream_name: Option<bool>,
+    pub group_name: Option<bool>,
+    pub local: Option<bool>,
+    pub time_style: Option<TimeStyle>,
+    pub prefix_order: Option<String>,
+}
+
+impl TailConfig {
+    /// The `tail.prefix_order` list, e.g. `stream,timestamp`.
+    pub fn prefix_order(&self) -> eyre::Result<Option<Vec<PrefixField>>> {
+        self.prefix_order
+            .as_deref()
+            .map(PrefixField::parse_list)
+            .transpose()
+            .wrap_err("Invalid tail.prefix_order")
+    }
+}
 
-        match cw_cache_dir.to_str() {
-            Some(data) => Ok(data.to_string()),
-            None => Err(eyre::eyre!("Can't construct cw.log path in cache dir!")),
+impl Config {
+    /// Loads the config file at `path`, a missing file yields the defaults.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
         }
+
+        let document = read_document(path)?;
+        Self::from_document(&document)
+            .wrap_err_with(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn from_document(document: &DocumentMut) -> eyre::Result<Self> {
+        let config: Self = toml_edit::de::from_document(document.clone())?;
+
+        if config.defaults.tps == Some(0) {
+            return Err(eyre::eyre!("defaults.tps must be at least 1"));
+        }
+
+        config.tail.prefix_order()?;
+        config.audit.retention()?;
+        config.archive.retention()?;
+        if config.archive.max_events == Some(0) {
+            return Err(eyre::eyre!("archive.max_events must be at least 1"));
+        }
+
+        for (name, window) in &config.windows {
+            TimeWindow::try_from(window).wrap_err_with(|| format!("Invalid window '{}'", name))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Resolves the `--window` named `name` against the current time.
+    pub fn window(&self, name: &str) -> eyre::Result<TimeRange> {
+        resolve_window(&self.windows, name, chrono::Local::now())
+    }
+
+    /// The AWS profile to fall back to when neither --profile nor AWS_PROFILE is set.
+    pub fn profile(&self) -> Option<String> {
+        match std::env::var("AWS_PROFILE") {
+            Ok(_) => None,
+            Err(_) => self.defaults.profile.clone(),
+        }
+    }
+
+    /// The AWS region to fall back to when neither --region nor AWS_REGION is set.
+    pub fn region(&self) -> Option<String> {
+        match std::env::var("AWS_REGION") {
+            Ok(_) => None,
+            Err(_) => self.defaults.region.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    String,
+    Bool,
+    Integer,
+}
+
+/// A key that can be read and written with `cw config`.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigKey {
+    pub name: &'static str,
+    pub kind: ValueKind,
+    /// Environment variable that takes precedence over the config file.
+    pub env: Option<&'static str>,
+    pub default: Option<&'static str>,
+}
+
+pub const KEYS: &[ConfigKey] = &[
+    ConfigKey {
+        name: "defaults.profile",
+        kind: ValueKind::String,
+        env: Some("AWS_PROFILE"),
+        default: None,
+    },
+    ConfigKey {
+        name: "defaults.region",
+        kind: ValueKind::String,
+        env: Some("AWS_REGION"),
+        default: None,
+    },
+    ConfigKey {
+        name: "defaults.tps",
+        kind: ValueKind::Integer,
+        env: None,
+        default: Some("5"),
+    },
+    ConfigKey {
+        name: "defaults.lookback",
+        kind: ValueKind::Bool,
+        env: None,
+        default: Some("true"),
+    },
+    ConfigKey {
+        name: "tail.timestamp",
+        kind: ValueKind::Bool,
+        env: None,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "tail.event_id",
+        kind: ValueKind::Bool,
+        env: None,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "tail.stream_name",
+        kind: ValueKind::Bool,
+        env: None,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "tail.group_name",
+        kind: ValueKind::Bool,
+        env: None,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "tail.local",
+        kind: ValueKind::Bool,
+        env: None,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "tail.time_style",
+        kind: ValueKind::String,
+        env: None,
+        default: Some("iso"),
+    },
+    ConfigKey {
+        name: "tail.prefix_order",
+        kind: ValueKind::String,
+        env: None,
+        default: None,
+    },
+    ConfigKey {
+        name: "audit.enabled",
+        kind: ValueKind::Bool,
+        env: None,
+        default: Some("false"),
+    },
+    ConfigKey {
+        name: "audit.retention",
+        kind: ValueKind::String,
+        env: None,
+        default: Some("30d"),
+    },
+    ConfigKey {
+        name: "archive.max_events",
+        kind: ValueKind::Integer,
+        env: None,
+        default: Some("1000000"),
+    },
+    ConfigKey {
+        name: "archive.retention",
+        kind: ValueKind::String,
+        env: None,
+        default: Some("90d"),
+    },
+    ConfigKey {
+        name: "database.journal_mode",
+        kind: ValueKind::String,
+        env: None,
+        default: None,
+    },
+    ConfigKey {
+        name: "update.check_url",
+        kind: ValueKind::String,
+        env: None,
+        default: None,
+    },
+];
+
+impl ConfigKey {
+    pub fn find(name: &str) -> eyre::Result<&'static ConfigKey> {
+        KEYS.iter().find(|k| k.name == name).ok_or_else(|| {
+            eyre::eyre!(
+                "Unknown config key '{}', available keys: {}",
+                name,
+                KEYS.iter().map(|k| k.name).collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
+
+    fn path(&self) -> (&'static str, &'static str) {
+        self.name.split_once('.').unwrap_or(("", self.name))
+    }
+
+    pub fn parse_value(&self, raw: &str) -> eyre::Result<toml_edit::Value> {
+        match self.kind {
+            ValueKind::String => Ok(raw.into()),
+            ValueKind::Bool => raw
+                .parse::<bool>()
+                .map(Into::into)
+                .map_err(|_| eyre::eyre!("{} expects true or false, got '{}'", self.name, raw)),
+            ValueKind::Integer => raw
+                .parse::<i64>()
+                .map(Into::into)
+                .map_err(|_| eyre::eyre!("{} expects a number, got '{}'", self.name, raw)),
+        }
+    }
+
+    /// Reads the value of this key from `document`, rendered the way it would be passed to set.
+    pub fn get(&self, document: &DocumentMut) -> Option<String> {
+        let (section, key) = self.path();
+        let value = document.get(section)?.get(key)?.as_value()?;
+
+        Some(match value.as_str() {
+            Some(s) => s.to_string(),
+            None => value.to_string().trim().to_string(),
+        })
+    }
+
+    pub fn set(&self, document: &mut DocumentMut, value: toml_edit::Value) -> eyre::Result<()> {
+        let (section, key) = self.path();
+        let table = document
+            .entry(section)
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| eyre::eyre!("'{}' in the config file is not a table", section))?;
+
+        table[key] = toml_edit::value(value);
+        Ok(())
+    }
+
+    /// Removes this key from `document`, returns whether it was set.
+    pub fn unset(&self, document: &mut DocumentMut) -> bool {
+        let (section, key) = self.path();
+        document
+            .get_mut(section)
+            .and_then(|item| item.as_table_like_mut())
+            .and_then(|table| table.remove(key))
+            .is_some()
+    }
+}
+
+pub fn read_document(path: &Path) -> eyre::Result<DocumentMut> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed reading {}", path.display()))?;
+
+    contents
+        .parse::<DocumentMut>()
+        .wrap_err_with(|| format!("Failed parsing {}", path.display()))
+}
+
+/// Reads the config file at `path`, creating it from [`CONFIG_TEMPLATE`] when it doesn't exist.
+pub fn read_or_create_document(path: &Path) -> eyre::Result<DocumentMut> {
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed creating {}", parent.display()))?;
+        }
+        std::fs::write(path, CONFIG_TEMPLATE)
+            .wrap_err_with(|| format!("Failed creating {}", path.display()))?;
+    }
+
+    read_document(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use crate::config::{migrate_legacy_log, AppDir};
+
+    #[test]
+    fn an_xdg_variable_overrides_the_platform_default() {
+        for dir in [AppDir::Data, AppDir::Config, AppDir::State, AppDir::Cache] {
+            assert_eq!(
+                dir.resolve_from(Some(OsString::from("/tmp/xdg"))),
+                std::path::Path::new("/tmp/xdg/cw"),
+                "{:?}",
+                dir
+            );
+        }
+    }
+
+    #[test]
+    fn an_unset_or_empty_xdg_variable_falls_back_to_the_platform_default() {
+        for dir in [AppDir::Data, AppDir::Config, AppDir::State, AppDir::Cache] {
+            assert_eq!(dir.resolve_from(None), dir.platform_default(), "{:?}", dir);
+            assert_eq!(
+                dir.resolve_from(Some(OsString::new())),
+                dir.platform_default(),
+                "{:?}",
+                dir
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn state_and_cache_default_to_separate_dirs_under_home() {
+        let home = crate::config::home_dir();
+
+        assert_eq!(
+            AppDir::State.platform_default(),
+            home.join(".local/state/cw")
+        );
+        assert_eq!(
+            AppDir::Cache.platform_default(),
+            home.join(".local/cache/cw")
+        );
+        assert_eq!(
+            AppDir::Data.platform_default(),
+            home.join(".local/share/cw")
+        );
+        assert_eq!(AppDir::Config.platform_default(), home.join(".config/cw"));
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn config_goes_under_appdata_and_the_rest_under_localappdata() {
+        let app_data = || Some(OsString::from(r"C:\Users\me\AppData\Roaming"));
+        let local_app_data = || Some(OsString::from(r"C:\Users\me\AppData\Local"));
+        let resolve = |dir: AppDir| dir.known_folder_default(app_data(), local_app_data());
+
+        assert_eq!(
+            resolve(AppDir::Config),
+            std::path::Path::new(r"C:\Users\me\AppData\Roaming\cw")
+        );
+        assert_eq!(
+            resolve(AppDir::Data),
+            std::path::Path::new(r"C:\Users\me\AppData\Local\cw\data")
+        );
+        assert_eq!(
+            resolve(AppDir::State),
+            std::path::Path::new(r"C:\Users\me\AppData\Local\cw\state")
+        );
+        assert_eq!(
+            resolve(AppDir::Cache),
+            std::path::Path::new(r"C:\Users\me\AppData\Local\cw\cache")
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn missing_known_folders_fall_back_to_the_user_profile() {
+        let home = crate::config::home_dir();
+
+        assert_eq!(
+            AppDir::Config.known_folder_default(None, None),
+            home.join("AppData").join("Roaming").join("cw")
+        );
+        assert_eq!(
+            AppDir::State.known_folder_default(Some(OsString::new()), Some(OsString::new())),
+            home.join("AppData").join("Local").join("cw").join("state")
+        );
+    }
+
+    #[test]
+    fn the_legacy_log_moves_once_and_never_overwrites_the_new_one() {
+        let dir = std::env::temp_dir().join(format!("cw-log-migration-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let legacy = dir.join("legacy.log");
+        let current = dir.join("cw.log");
+
+        std::fs::write(&legacy, "old").unwrap();
+        migrate_legacy_log(&legacy, &current);
+        assert!(!legacy.exists());
+        assert_eq!(std::fs::read_to_string(&current).unwrap(), "old");
+
+        std::fs::write(&legacy, "older").unwrap();
+        std::fs::write(&current, "new").unwrap();
+        migrate_legacy_log(&legacy, &current);
+        assert_eq!(std::fs::read_to_string(&current).unwrap(), "new");
+        assert!(legacy.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
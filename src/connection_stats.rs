@@ -0,0 +1,171 @@
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use aws_sdk_cloudwatchlogs::config::{
+    interceptors::BeforeDeserializationInterceptorContextRef, ConfigBag, Intercept,
+    RuntimeComponents,
+};
+use aws_smithy_runtime_api::{box_error::BoxError, client::connection::CaptureSmithyConnection};
+
+use crate::ui::Ui;
+
+// NOTE: Below this many requests a cold pool dominates the numbers, so don't warn.
+const MIN_REQUESTS_FOR_WARNING: u64 = 5;
+
+#[derive(Debug, Default)]
+struct Counters {
+    seen: HashSet<SocketAddr>,
+    new: u64,
+    reused: u64,
+    unknown: u64,
+}
+
+/// Counts how many requests went out over a fresh connection versus one taken from the pool.
+/// A connection is identified by its local socket address, which stays the same for as long as
+/// the underlying TCP connection lives.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl ConnectionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, local_addr: Option<SocketAddr>) {
+        let Ok(mut counters) = self.counters.lock() else {
+            return;
+        };
+
+        match local_addr {
+            Some(addr) if counters.seen.insert(addr) => counters.new += 1,
+            Some(_) => counters.reused += 1,
+            None => counters.unknown += 1,
+        }
+    }
+
+    /// Logs the connection counts and warns on stderr when connections are hardly ever reused.
+    pub fn report(&self, ui: &Ui) {
+        let Ok(counters) = self.counters.lock() else {
+            return;
+        };
+
+        tracing::debug!(
+            target: "cw",
+            "connections: {} new, {} reused, {} unknown",
+            counters.new,
+            counters.reused,
+            counters.unknown
+        );
+
+        if let Some(warning) = counters.warning() {
+            ui.warn(warning);
+        }
+    }
+}
+
+impl Counters {
+    /// A warning when fewer than one in ten requests reused a connection.
+    fn warning(&self) -> Option<String> {
+        let total = self.new + self.reused;
+        if total < MIN_REQUESTS_FOR_WARNING || self.reused * 10 >= total {
+            return None;
+        }
+        Some(format!(
+            "{} of {} requests opened a new connection, check keep-alive settings or your proxy.",
+            self.new, total
+        ))
+    }
+}
+
+/// Records the connection used by every request into a shared [`ConnectionStats`].
+#[derive(Debug)]
+pub struct ConnectionReuseInterceptor {
+    stats: ConnectionStats,
+}
+
+impl ConnectionReuseInterceptor {
+    pub fn new(stats: ConnectionStats) -> Self {
+        Self { stats }
+    }
+}
+
+impl Intercept for ConnectionReuseInterceptor {
+    fn name(&self) -> &'static str {
+        "ConnectionReuseInterceptor"
+    }
+
+    fn read_after_transmit(
+        &self,
+        _context: &BeforeDeserializationInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        // NOTE: The capture is put in the config bag by the SDK's connection poisoning
+        // interceptor and filled in by the HTTP client once a connection was picked.
+        let connection = cfg
+            .load::<CaptureSmithyConnection>()
+            .and_then(|capture| capture.get());
+
+        self.stats
+            .record(connection.as_ref().and_then(|c| c.local_addr()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use crate::connection_stats::ConnectionStats;
+
+    fn addr(port: u16) -> Option<SocketAddr> {
+        Some(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    fn record(stats: &ConnectionStats, ports: &[u16]) {
+        for port in ports {
+            stats.record(addr(*port));
+        }
+    }
+
+    #[test]
+    fn a_local_address_seen_before_is_a_reused_connection() {
+        let stats = ConnectionStats::new();
+        record(&stats, &[50_000, 50_000, 50_001, 50_000]);
+        stats.record(None);
+
+        let counters = stats.counters.lock().unwrap();
+        assert_eq!((counters.new, counters.reused, counters.unknown), (2, 2, 1));
+    }
+
+    #[test]
+    fn warns_when_hardly_any_connection_is_reused() {
+        let stats = ConnectionStats::new();
+        record(&stats, &(50_000..50_009).collect::<Vec<_>>());
+        stats.record(addr(50_000));
+        assert_eq!(stats.counters.lock().unwrap().warning(), None);
+
+        record(&stats, &[50_009]);
+        assert_eq!(
+            stats.counters.lock().unwrap().warning().as_deref(),
+            Some(
+                "10 of 11 requests opened a new connection, check keep-alive settings or your \
+                 proxy."
+            )
+        );
+    }
+
+    #[test]
+    fn does_not_warn_about_a_handful_of_requests() {
+        let stats = ConnectionStats::new();
+        record(&stats, &[50_000, 50_001, 50_002, 50_003]);
+        stats.record(None);
+
+        assert_eq!(stats.counters.lock().unwrap().warning(), None);
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::event::LogEvent;
+
+// NOTE: A key that keeps getting events is written once it has this many, so a hot key can't
+// hold on to memory forever.
+const MAX_EVENTS_PER_KEY: usize = 1000;
+
+/// How `--join-on` finds the correlation key of an event. A value with a capture group is a
+/// regex and the key is the first capture, anything else is a JSON field, dotted for nested
+/// fields, e.g. `requestId` or `context.traceId`.
+#[derive(Debug, Clone)]
+pub enum JoinKey {
+    Field(Vec<String>),
+    Regex(Regex),
+}
+
+impl FromStr for JoinKey {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> eyre::Result<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(eyre::eyre!("--join-on needs a JSON field or a regex"));
+        }
+
+        if value.contains('(') {
+            let regex = Regex::new(value)?;
+            if regex.captures_len() < 2 {
+                return Err(eyre::eyre!(
+                    "The --join-on regex '{}' has no capture group to take the key from",
+                    value
+                ));
+            }
+            return Ok(Self::Regex(regex));
+        }
+
+        Ok(Self::Field(value.split('.').map(str::to_string).collect()))
+    }
+}
+
+impl JoinKey {
+    /// The key of `message`, `None` when it has none or it is empty.
+    pub fn extract(&self, message: &str) -> Option<String> {
+        let key = match self {
+            JoinKey::Regex(regex) => regex.captures(message)?.get(1)?.as_str().to_string(),
+            JoinKey::Field(path) => {
+                let value: Value = serde_json::from_str(message.trim()).ok()?;
+                let field = path
+                    .iter()
+                    .try_fold(&value, |value, name| value.get(name))?;
+                match field {
+                    Value::String(key) => key.clone(),
+                    Value::Number(key) => key.to_string(),
+                    _ => return None,
+                }
+            }
+        };
+        Some(key).filter(|key| !key.is_empty())
+    }
+}
+
+/// The events of all tailed groups that share a correlation key, in the order they arrived.
+#[derive(Debug)]
+pub struct Correlated {
+    pub key: String,
+    pub events: Vec<LogEvent>,
+}
+
+impl Correlated {
+    /// The groups the events came from, in the order they first showed up.
+    pub fn groups(&self) -> Vec<&str> {
+        let mut groups: Vec<&str> = Vec::new();
+        for event in &self.events {
+            if !groups.contains(&event.group_name.as_str()) {
+                groups.push(&event.group_name);
+            }
+        }
+        groups
+    }
+}
+
+#[derive(Debug)]
+struct PendingKey {
+    correlated: Correlated,
+    first_seen: Instant,
+    last_seen: Instant,
+}
+
+pub enum Joined {
+    Event(LogEvent),
+    Correlated(Correlated),
+}
+
+/// Buffers events by their `--join-on` key until no event with that key came in for `window`,
+/// so a request traced through several groups is written as one block. Events without a key
+/// pass through untouched. At most `max_keys` keys are held, the oldest is written early to
+/// make room for a new one.
+pub struct CorrelationGrouper {
+    key: JoinKey,
+    window: Duration,
+    max_keys: usize,
+    pending: HashMap<String, PendingKey>,
+}
+
+impl CorrelationGrouper {
+    pub fn new(key: JoinKey, window: Duration, max_keys: usize) -> Self {
+        Self {
+            key,
+            window,
+            max_keys: max_keys.max(1),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn process(&mut self, event: LogEvent, now: Instant) -> Vec<Joined> {
+        let Some(key) = event
+            .message
+            .as_deref()
+            .and_then(|message| self.key.extract(message))
+        else {
+            return vec![Joined::Event(event)];
+        };
+
+        let mut output = Vec::new();
+        if let Some(pending) = self.pending.get_mut(&key) {
+            pending.correlated.events.push(event);
+            pending.last_seen = now;
+            if pending.correlated.events.len() >= MAX_EVENTS_PER_KEY {
+                if let Some(pending) = self.pending.remove(&key) {
+                    output.push(Joined::Correlated(pending.correlated));
+                }
+            }
+            return output;
+        }
+
+        if self.pending.len() >= self.max_keys {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, pending)| pending.first_seen)
+                .map(|(key, _)| key.clone());
+            if let Some(pending) = oldest.and_then(|key| self.pending.remove(&key)) {
+                output.push(Joined::Correlated(pending.correlated));
+            }
+        }
+
+        self.pending.insert(
+            key.clone(),
+            PendingKey {
+                correlated: Correlated {
+                    key,
+                    events: vec![event],
+                },
+                first_seen: now,
+                last_seen: now,
+            },
+        );
+        output
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Returns the keys that saw no new event for the window, oldest first.
+    pub fn expired(&mut self, now: Instant) -> Vec<Correlated> {
+        let window = self.window;
+        let mut expired = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.last_seen) >= window)
+            .map(|(key, pending)| (pending.first_seen, key.clone()))
+            .collect::<Vec<_>>();
+        expired.sort();
+
+        expired
+            .into_iter()
+            .filter_map(|(_, key)| self.pending.remove(&key))
+            .map(|pending| pending.correlated)
+            .collect()
+    }
+
+    /// Returns every key still buffered, oldest first.
+    pub fn flush(&mut self) -> Vec<Correlated> {
+        let mut pending = self.pending.drain().map(|(_, p)| p).collect::<Vec<_>>();
+        pending.sort_by_key(|p| p.first_seen);
+        pending.into_iter().map(|p| p.correlated).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::correlation::{Correlated, CorrelationGrouper, JoinKey, Joined, MAX_EVENTS_PER_KEY};
+    use crate::event::LogEvent;
+
+    const WINDOW: Duration = Duration::from_secs(5);
+
+    fn event(group: &str, message: &str) -> LogEvent {
+        LogEvent {
+            group_name: group.to_string(),
+            log_stream_name: Some("web-1".to_string()),
+            timestamp: Some(0),
+            message: Some(message.to_string()),
+            ingestion_time: None,
+            event_id: None,
+        }
+    }
+
+    fn grouper(max_keys: usize) -> CorrelationGrouper {
+        CorrelationGrouper::new("requestId".parse().unwrap(), WINDOW, max_keys)
+    }
+
+    fn block(correlated: &Correlated) -> String {
+        let messages: Vec<_> = correlated
+            .events
+            .iter()
+            .map(|e| e.message.as_deref().unwrap_or_default())
+            .collect();
+        format!("{}: {}", correlated.key, messages.join(", "))
+    }
+
+    fn joined(output: Vec<Joined>) -> Vec<String> {
+        output
+            .iter()
+            .map(|joined| match joined {
+                Joined::Event(event) => event.message.clone().unwrap_or_default(),
+                Joined::Correlated(correlated) => block(correlated),
+            })
+            .collect()
+    }
+
+    fn blocks(output: Vec<Correlated>) -> Vec<String> {
+        output.iter().map(block).collect()
+    }
+
+    #[test]
+    fn a_field_key_is_read_from_json_messages() {
+        let key: JoinKey = "context.traceId".parse().unwrap();
+
+        assert_eq!(
+            key.extract(r#"{"context":{"traceId":"t-1"}}"#).as_deref(),
+            Some("t-1")
+        );
+        assert_eq!(
+            key.extract(r#" {"context":{"traceId":42}} "#).as_deref(),
+            Some("42")
+        );
+        assert_eq!(key.extract(r#"{"context":{"traceId":""}}"#), None);
+        assert_eq!(key.extract(r#"{"context":{"traceId":true}}"#), None);
+        assert_eq!(key.extract(r#"{"traceId":"t-1"}"#), None);
+        assert_eq!(key.extract("traceId=t-1"), None);
+    }
+
+    #[test]
+    fn a_regex_key_is_its_first_capture() {
+        let key: JoinKey = r"req=(\w+) user=(\w+)".parse().unwrap();
+
+        assert_eq!(key.extract("GET / req=r1 user=u1").as_deref(), Some("r1"));
+        assert_eq!(key.extract("GET /"), None);
+    }
+
+    #[test]
+    fn a_key_needs_a_field_or_a_capture_group() {
+        assert_eq!(
+            "  ".parse::<JoinKey>().unwrap_err().to_string(),
+            "--join-on needs a JSON field or a regex"
+        );
+        assert_eq!(
+            r"(?i)req=\w+".parse::<JoinKey>().unwrap_err().to_string(),
+            r"The --join-on regex '(?i)req=\w+' has no capture group to take the key from"
+        );
+    }
+
+    #[test]
+    fn interleaved_groups_are_joined_per_key_and_keyless_events_pass_through() {
+        let mut grouper = grouper(10);
+        let now = Instant::now();
+
+        let mut passed = Vec::new();
+        for (group, message) in [
+            ("/ecs/api", r#"{"requestId":"r-1","msg":"api 1"}"#),
+            ("/ecs/api", r#"{"requestId":"r-2","msg":"api 2"}"#),
+            ("/ecs/worker", "worker started"),
+            ("/ecs/worker", r#"{"requestId":"r-2","msg":"worker 2"}"#),
+            ("/ecs/db", r#"{"requestId":"r-1","msg":"db 1"}"#),
+        ] {
+            passed.extend(joined(grouper.process(event(group, message), now)));
+        }
+        let expired = grouper.expired(now + WINDOW);
+
+        assert_eq!(passed, ["worker started"]);
+        assert_eq!(expired[0].groups(), ["/ecs/api", "/ecs/db"]);
+        assert_eq!(expired[1].groups(), ["/ecs/api", "/ecs/worker"]);
+        assert_eq!(expired[0].events.len(), 2);
+        assert_eq!(expired[1].key, "r-2");
+        assert!(!grouper.has_pending());
+    }
+
+    #[test]
+    fn a_key_expires_a_window_after_its_last_event() {
+        let mut grouper = grouper(10);
+        let now = Instant::now();
+
+        grouper.process(event("/ecs/api", r#"{"requestId":"r-1"}"#), now);
+        grouper.process(
+            event("/ecs/db", r#"{"requestId":"r-1"}"#),
+            now + Duration::from_secs(4),
+        );
+
+        assert!(grouper.expired(now + Duration::from_secs(8)).is_empty());
+        assert_eq!(grouper.expired(now + Duration::from_secs(9)).len(), 1);
+    }
+
+    #[test]
+    fn the_oldest_key_is_written_early_to_make_room() {
+        let mut grouper = grouper(2);
+        let now = Instant::now();
+
+        grouper.process(event("/ecs/api", r#"{"requestId":"r-1"}"#), now);
+        grouper.process(
+            event("/ecs/api", r#"{"requestId":"r-2"}"#),
+            now + Duration::from_millis(1),
+        );
+        let evicted = grouper.process(
+            event("/ecs/api", r#"{"requestId":"r-3"}"#),
+            now + Duration::from_millis(2),
+        );
+
+        assert_eq!(joined(evicted), [r#"r-1: {"requestId":"r-1"}"#]);
+        assert_eq!(
+            blocks(grouper.flush()),
+            [r#"r-2: {"requestId":"r-2"}"#, r#"r-3: {"requestId":"r-3"}"#]
+        );
+    }
+
+    #[test]
+    fn a_hot_key_is_written_once_it_is_full() {
+        let mut grouper = grouper(10);
+        let now = Instant::now();
+
+        let mut written = Vec::new();
+        for _ in 0..MAX_EVENTS_PER_KEY + 1 {
+            written.extend(grouper.process(event("/ecs/api", r#"{"requestId":"r-1"}"#), now));
+        }
+
+        assert_eq!(written.len(), 1);
+        let Joined::Correlated(correlated) = &written[0] else {
+            panic!("expected a correlated block");
+        };
+        assert_eq!(correlated.events.len(), MAX_EVENTS_PER_KEY);
+        assert_eq!(grouper.flush()[0].events.len(), 1);
+    }
+}
@@ -1,18 +1,26 @@
 use std::{
     fmt::{Debug, Display},
     fs,
-    path::Path,
+    future::Future,
+    io::ErrorKind,
+    path::{Path, PathBuf},
     str::FromStr,
     time::Duration,
 };
 
 use chrono::{DateTime, Utc};
+use eyre::Context;
+use futures_util::TryStreamExt;
+use regex::Regex;
+use serde::Deserialize;
 use sqlx::{
     sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
     SqlitePool,
 };
 use uuid::Uuid;
 
+use crate::event::LogEvent;
+
 #[derive(Default, Debug, Clone, PartialEq, sqlx::FromRow)]
 pub struct QueryHistory {
     id: String,
@@ -25,6 +33,12 @@ pub struct QueryHistory {
     pub records_scanned: f64,
     pub bytes_scanned: f64,
 
+    pub group_names: Option<String>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub profile: Option<String>,
+    pub region: Option<String>,
+
     pub created_at: DateTime<Utc>,
     pub modified_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
@@ -45,6 +59,36 @@ impl QueryHistory {
         }
     }
 
+    /// The id cw gave this run, which unlike the query id is known before the query started.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Records the inputs of the run, so it can be reproduced later.
+    pub fn set_context(
+        &mut self,
+        group_names: &[String],
+        start_time: i64,
+        end_time: i64,
+        profile: Option<String>,
+        region: Option<String>,
+    ) -> eyre::Result<()> {
+        self.group_names = Some(serde_json::to_string(group_names)?);
+        self.start_time = Some(start_time);
+        self.end_time = Some(end_time);
+        self.profile = profile;
+        self.region = region;
+        self.modified_at = Utc::now();
+        Ok(())
+    }
+
+    pub fn group_names(&self) -> eyre::Result<Vec<String>> {
+        match &self.group_names {
+            Some(names) => Ok(serde_json::from_str(names)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
     pub fn set_status(&mut self, status: QueryStatus) {
         self.status = status;
         self.modified_at = Utc::now();
@@ -65,13 +109,97 @@ impl QueryHistory {
     }
 }
 
+/// A single CloudWatch Logs call in the audit trail. Only the operation and the parameters that
+/// say what was read are kept, never credentials, query strings or log events.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub operation: String,
+    /// The parameters of the call as a JSON object.
+    pub parameters: String,
+    pub request_id: Option<String>,
+    pub duration_ms: i64,
+    /// `ok`, or the error code when the call failed.
+    pub outcome: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The account and region cached log group names belong to. An unset profile or region is
+/// stored as an empty string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheScope {
+    pub profile: String,
+    pub region: String,
+}
+
+/// What `cw archive search` looks for in an archive. Every unset field matches all events.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveQuery {
+    /// Events at or after this epoch millisecond timestamp.
+    pub since: Option<i64>,
+    /// Events at or before this epoch millisecond timestamp.
+    pub until: Option<i64>,
+    /// An SQLite FTS5 query run against the messages, e.g. `timeout OR "connection reset"`.
+    pub text: Option<String>,
+    /// A pattern the messages have to match, checked on the events the rest of the query finds.
+    pub grep: Option<Regex>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ArchiveSummary {
+    pub archive: String,
+    pub events: i64,
+    /// The size of all messages in the archive, in bytes.
+    pub message_bytes: i64,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ArchivedEvent {
+    group_name: String,
+    stream_name: Option<String>,
+    timestamp: Option<i64>,
+    ingestion_time: Option<i64>,
+    message: Option<String>,
+    event_id: Option<String>,
+}
+
+impl From<ArchivedEvent> for LogEvent {
+    fn from(event: ArchivedEvent) -> Self {
+        Self {
+            group_name: event.group_name,
+            log_stream_name: event.stream_name,
+            timestamp: event.timestamp,
+            message: event.message,
+            ingestion_time: event.ingestion_time,
+            event_id: event.event_id,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, sqlx::Type)]
 pub enum QueryStatus {
+    /// Waiting for a free slot under the account's concurrent query limit, not started yet.
+    Queued,
     Scheduled,
     Running,
     Complete,
     Failed,
     Timeout,
+    /// Stopped with StopQuery before it completed.
+    Cancelled,
+    /// cw stopped waiting before the query reached a terminal status.
+    Interrupted,
+    /// Never reconciled while CloudWatch kept its results, its real status is unknown.
+    Expired,
+}
+
+impl QueryStatus {
+    /// Whether cw was still waiting on the query when the row was last written.
+    pub fn is_in_flight(&self) -> bool {
+        matches!(self, Self::Scheduled | Self::Running)
+    }
 }
 
 impl Default for QueryStatus {
@@ -83,15 +211,23 @@ impl Default for QueryStatus {
 impl Display for QueryStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            QueryStatus::Queued => write!(f, "Queued"),
             QueryStatus::Scheduled => write!(f, "Scheduled"),
             QueryStatus::Running => write!(f, "Running"),
             QueryStatus::Complete => write!(f, "Complete"),
             QueryStatus::Failed => write!(f, "Failed"),
             QueryStatus::Timeout => write!(f, "Timeout"),
+            QueryStatus::Cancelled => write!(f, "Cancelled"),
+            QueryStatus::Interrupted => write!(f, "Interrupted"),
+            QueryStatus::Expired => write!(f, "Expired"),
         }
     }
 }
 
+/// Where cw keeps query history, audit entries and archived events, a SQLite file by default.
+// NOTE: The commands run on a current thread runtime, so most futures don't need to be Send.
+// The ones awaited from spawned tasks spell it out instead.
+#[allow(async_fn_in_trait)]
 pub trait Database: Sized + Clone + Send + Sync + 'static {
     type Settings: Debug + Clone + Send + Sync + 'static;
     async fn new(settings: &Self::Settings) -> eyre::Result<Self>;
@@ -102,35 +238,177 @@ pub trait Database: Sized + Clone + Send + Sync + 'static {
     async fn save(&self, history: &QueryHistory) -> eyre::Result<()>;
     async fn update(&self, history: &QueryHistory) -> eyre::Result<()>;
     async fn list(&self) -> eyre::Result<Vec<QueryHistory>>;
+    async fn get(&self, id: &str) -> eyre::Result<Option<QueryHistory>>;
+
+    /// Makes everything written so far durable on disk.
+    async fn flush(&self) -> eyre::Result<()>;
+
+    async fn save_audit(&self, entries: &[AuditEntry]) -> eyre::Result<()>;
+    /// Audit entries created at or after `since`, oldest first.
+    async fn list_audit(&self, since: DateTime<Utc>) -> eyre::Result<Vec<AuditEntry>>;
+    /// Removes audit entries created before `before`, returning how many were removed.
+    async fn prune_audit(&self, before: DateTime<Utc>) -> eyre::Result<u64>;
+
+    // NOTE: Archiving runs in its own task while tailing, so these two spell out that their
+    // futures are Send.
+    /// Adds `events` to `archive`, events it already holds are skipped.
+    fn save_archive(
+        &self,
+        archive: &str,
+        events: &[LogEvent],
+    ) -> impl Future<Output = eyre::Result<()>> + Send;
+    /// Trims `archive` to its newest `max_events` events and removes the ones archived before
+    /// `before`, returning how many were removed.
+    fn prune_archive(
+        &self,
+        archive: &str,
+        max_events: u64,
+        before: DateTime<Utc>,
+    ) -> impl Future<Output = eyre::Result<u64>> + Send;
+    /// Events in `archive` matching `query`, oldest first.
+    async fn search_archive(
+        &self,
+        archive: &str,
+        query: &ArchiveQuery,
+    ) -> eyre::Result<Vec<LogEvent>>;
+    async fn list_archives(&self) -> eyre::Result<Vec<ArchiveSummary>>;
+    /// Removes `archive` and all its events, returning how many events were removed.
+    async fn remove_archive(&self, archive: &str) -> eyre::Result<u64>;
+
+    /// Records that the groups in `names` exist in `scope`.
+    async fn cache_groups(&self, scope: &CacheScope, names: &[String]) -> eyre::Result<()>;
+    /// The cached group names of `scope`, sorted by name.
+    async fn cached_groups(&self, scope: &CacheScope) -> eyre::Result<Vec<String>>;
+    /// When the last full listing of `scope` completed.
+    async fn groups_refreshed_at(&self, scope: &CacheScope) -> eyre::Result<Option<DateTime<Utc>>>;
+    /// Marks a full listing of `scope` that started at `started_at` as complete, dropping the
+    /// groups it didn't see as they were deleted since.
+    async fn finish_group_refresh(
+        &self,
+        scope: &CacheScope,
+        started_at: DateTime<Utc>,
+    ) -> eyre::Result<()>;
 }
 
 #[derive(Debug, Clone)]
 pub struct Sqlite {
     pool: SqlitePool,
+    retry_writes: bool,
 }
 
-impl Sqlite {
-    pub async fn sqlite_version(&self) -> eyre::Result<String> {
-        let result: String = sqlx::query_scalar("SELECT sqlite_version()")
-            .fetch_one(&self.pool)
-            .await?;
+// NOTE: Primary SQLite result codes, extended codes carry them in the lowest byte, ref:
+// https://www.sqlite.org/rescode.html
+const SQLITE_PERM: i32 = 3;
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+const SQLITE_READONLY: i32 = 8;
+const SQLITE_IOERR: i32 = 10;
+const SQLITE_CANTOPEN: i32 = 14;
+const SQLITE_PROTOCOL: i32 = 15;
 
-        Ok(result)
-    }
+// NOTE: Each attempt already waits out SQLite's own busy timeout, the backoff on top gives the
+// other process room to finish its transaction.
+const WRITE_RETRY_ATTEMPTS: u32 = 6;
+const WRITE_RETRY_INITIAL: Duration = Duration::from_millis(100);
+const WRITE_RETRY_MAX: Duration = Duration::from_secs(2);
 
-    async fn setup_db(pool: &SqlitePool) -> eyre::Result<()> {
-        sqlx::migrate!("./migrations").run(pool).await?;
+/// The journal mode to open the database with, see `database.journal_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Wal,
+    Delete,
+}
 
-        Ok(())
+impl From<JournalMode> for SqliteJournalMode {
+    fn from(mode: JournalMode) -> Self {
+        match mode {
+            JournalMode::Wal => SqliteJournalMode::Wal,
+            JournalMode::Delete => SqliteJournalMode::Delete,
+        }
     }
 }
 
-impl Database for Sqlite {
-    type Settings = String;
+#[derive(Debug, Clone)]
+pub struct SqliteSettings {
+    pub path: PathBuf,
+    /// Forces a journal mode. When unset WAL is used, falling back to DELETE when the filesystem
+    /// can't do WAL, which is common on network filesystems.
+    pub journal_mode: Option<JournalMode>,
+    /// Retries history writes with backoff while the database is busy, for when another cw
+    /// process holds the data directory lock.
+    pub retry_writes: bool,
+}
+
+fn sqlite_code(err: &eyre::Report) -> Option<i32> {
+    err.chain()
+        .find_map(|cause| match cause.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::Database(db_err)) => db_err
+                .code()
+                .and_then(|code| code.parse::<i32>().ok())
+                .map(|code| code & 0xff),
+            _ => None,
+        })
+}
+
+fn io_error_kind(err: &eyre::Report) -> Option<ErrorKind> {
+    err.chain().find_map(|cause| {
+        if let Some(io_err) = cause.downcast_ref::<std::io::Error>() {
+            return Some(io_err.kind());
+        }
+        match cause.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::Io(io_err)) => Some(io_err.kind()),
+            _ => None,
+        }
+    })
+}
+
+/// Whether opening the database failed because its directory or file can't be written to.
+pub fn is_read_only(err: &eyre::Report) -> bool {
+    matches!(
+        sqlite_code(err),
+        Some(SQLITE_READONLY | SQLITE_PERM | SQLITE_CANTOPEN)
+    ) || matches!(
+        io_error_kind(err),
+        Some(ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem)
+    )
+}
+
+/// Whether opening the database failed on the locking WAL relies on, which network
+/// filesystems like NFS often don't support.
+fn is_wal_failure(err: &eyre::Report) -> bool {
+    matches!(sqlite_code(err), Some(SQLITE_IOERR | SQLITE_PROTOCOL))
+}
+
+/// Whether another connection held the database for longer than the busy timeout.
+fn is_busy(err: &eyre::Report) -> bool {
+    matches!(sqlite_code(err), Some(SQLITE_BUSY | SQLITE_LOCKED))
+}
 
-    async fn new(path: &Self::Settings) -> eyre::Result<Self> {
-        let path = Path::new(path);
+impl Sqlite {
+    /// A database that lives only as long as this process, for when the data directory can't
+    /// be written to.
+    pub async fn in_memory() -> eyre::Result<Self> {
+        // NOTE: Every connection to :memory: gets a database of its own, so the pool has to hold
+        // on to exactly one connection for the whole run.
+        let opts = SqliteConnectOptions::from_str("sqlite::memory:")?;
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(opts)
+            .await?;
 
+        Self::setup_db(&pool).await?;
+
+        Ok(Self {
+            pool,
+            retry_writes: false,
+        })
+    }
+
+    async fn open(path: &Path, journal_mode: JournalMode) -> eyre::Result<Self> {
         let create = !path.exists();
         if create {
             if let Some(dir) = path.parent() {
@@ -138,8 +416,9 @@ impl Database for Sqlite {
             }
         }
 
-        let opts = SqliteConnectOptions::from_str(path.as_os_str().to_str().unwrap())?
-            .journal_mode(SqliteJournalMode::Wal)
+        let opts = SqliteConnectOptions::new()
+            .filename(path)
+            .journal_mode(journal_mode.into())
             .optimize_on_close(true, None)
             .synchronous(SqliteSynchronous::Normal)
             .create_if_missing(true);
@@ -151,26 +430,94 @@ impl Database for Sqlite {
 
         Self::setup_db(&pool).await?;
 
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            retry_writes: false,
+        })
     }
 
-    async fn version(&self) -> eyre::Result<String> {
-        self.sqlite_version().await
+    /// Opens with WAL, falling back to DELETE when the filesystem can't do the locking WAL
+    /// needs.
+    async fn open_with_fallback(path: &Path) -> eyre::Result<Self> {
+        match Self::open_when_free(path, JournalMode::Wal).await {
+            Err(err) if is_wal_failure(&err) => {
+                tracing::warn!(
+                    target: "cw",
+                    "opening {} with WAL failed, retrying with journal_mode=DELETE: {}",
+                    path.display(),
+                    err
+                );
+                Self::open_when_free(path, JournalMode::Delete).await
+            }
+            result => result,
+        }
     }
 
-    fn engine(&self) -> &str {
-        "sqlite"
+    /// Opens the database, retrying with backoff while another process keeps it busy, e.g.
+    /// while it runs the migrations.
+    async fn open_when_free(path: &Path, journal_mode: JournalMode) -> eyre::Result<Self> {
+        let mut delay = WRITE_RETRY_INITIAL;
+        let mut attempt = 1;
+        loop {
+            match Self::open(path, journal_mode).await {
+                Err(err) if attempt < WRITE_RETRY_ATTEMPTS && is_busy(&err) => {
+                    tracing::debug!(target: "cw", "database busy, retrying the open in {:?} ({}/{})", delay, attempt, WRITE_RETRY_ATTEMPTS);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(WRITE_RETRY_MAX);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     }
 
-    async fn save(&self, history: &QueryHistory) -> eyre::Result<()> {
+    pub async fn sqlite_version(&self) -> eyre::Result<String> {
+        let result: String = sqlx::query_scalar("SELECT sqlite_version()")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn setup_db(pool: &SqlitePool) -> eyre::Result<()> {
+        sqlx::migrate!("./migrations").run(pool).await?;
+
+        Ok(())
+    }
+
+    /// Runs `write`, retrying with backoff while the database is busy when `retry_writes` is set.
+    async fn write_with_retry<F, Fut>(&self, write: F) -> eyre::Result<()>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = eyre::Result<()>>,
+    {
+        let mut delay = WRITE_RETRY_INITIAL;
+        let mut attempt = 1;
+        loop {
+            match write().await {
+                Err(err)
+                    if self.retry_writes && attempt < WRITE_RETRY_ATTEMPTS && is_busy(&err) =>
+                {
+                    tracing::debug!(target: "cw", "database busy, retrying the write in {:?} ({}/{})", delay, attempt, WRITE_RETRY_ATTEMPTS);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(WRITE_RETRY_MAX);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn insert_history(&self, history: &QueryHistory) -> eyre::Result<()> {
         let mut tx = self.pool.begin().await?;
         sqlx::query(
             "insert or ignore into query_history(
                 id, query_id, contents, status,
                 records_total, records_matched, records_scanned, bytes_scanned,
+                group_names, start_time, end_time, profile, region,
                 created_at, modified_at, deleted_at
             )
-            values(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            values(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
         )
         .bind(&history.id)
         .bind(history.query_id.as_str())
@@ -180,6 +527,11 @@ impl Database for Sqlite {
         .bind(history.records_matched)
         .bind(history.records_scanned)
         .bind(history.bytes_scanned)
+        .bind(history.group_names.as_deref())
+        .bind(history.start_time)
+        .bind(history.end_time)
+        .bind(history.profile.as_deref())
+        .bind(history.region.as_deref())
         .bind(history.created_at)
         .bind(history.modified_at)
         .bind(history.deleted_at)
@@ -191,8 +543,7 @@ impl Database for Sqlite {
         Ok(())
     }
 
-    async fn update(&self, history: &QueryHistory) -> eyre::Result<()> {
-        // dbg!("updating query_history in sqlite");
+    async fn update_history(&self, history: &QueryHistory) -> eyre::Result<()> {
         let mut tx = self.pool.begin().await?;
         sqlx::query(
             "update query_history set
@@ -203,9 +554,14 @@ impl Database for Sqlite {
                     records_matched = ?6,
                     records_scanned = ?7,
                     bytes_scanned   = ?8,
-                    created_at      = ?9,
-                    modified_at     = ?10,
-                    deleted_at      = ?11
+                    group_names     = ?9,
+                    start_time      = ?10,
+                    end_time        = ?11,
+                    profile         = ?12,
+                    region          = ?13,
+                    created_at      = ?14,
+                    modified_at     = ?15,
+                    deleted_at      = ?16
                 where id = ?1",
         )
         .bind(&history.id)
@@ -216,6 +572,11 @@ impl Database for Sqlite {
         .bind(history.records_matched)
         .bind(history.records_scanned)
         .bind(history.bytes_scanned)
+        .bind(history.group_names.as_deref())
+        .bind(history.start_time)
+        .bind(history.end_time)
+        .bind(history.profile.as_deref())
+        .bind(history.region.as_deref())
         .bind(history.created_at)
         .bind(history.modified_at)
         .bind(history.deleted_at)
@@ -226,6 +587,37 @@ impl Database for Sqlite {
 
         Ok(())
     }
+}
+
+impl Database for Sqlite {
+    type Settings = SqliteSettings;
+
+    async fn new(settings: &Self::Settings) -> eyre::Result<Self> {
+        let db = match settings.journal_mode {
+            Some(journal_mode) => Self::open_when_free(&settings.path, journal_mode).await?,
+            None => Self::open_with_fallback(&settings.path).await?,
+        };
+        Ok(Self {
+            retry_writes: settings.retry_writes,
+            ..db
+        })
+    }
+
+    async fn version(&self) -> eyre::Result<String> {
+        self.sqlite_version().await
+    }
+
+    fn engine(&self) -> &str {
+        "sqlite"
+    }
+
+    async fn save(&self, history: &QueryHistory) -> eyre::Result<()> {
+        self.write_with_retry(|| self.insert_history(history)).await
+    }
+
+    async fn update(&self, history: &QueryHistory) -> eyre::Result<()> {
+        self.write_with_retry(|| self.update_history(history)).await
+    }
 
     async fn list(&self) -> eyre::Result<Vec<QueryHistory>> {
         let items = sqlx::query_as::<_, QueryHistory>("select * from query_history")
@@ -233,4 +625,766 @@ impl Database for Sqlite {
             .await?;
         Ok(items)
     }
+
+    async fn get(&self, id: &str) -> eyre::Result<Option<QueryHistory>> {
+        let item = sqlx::query_as::<_, QueryHistory>(
+            "select * from query_history where query_id = ?1 or id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(item)
+    }
+
+    async fn flush(&self) -> eyre::Result<()> {
+        // NOTE: With synchronous=NORMAL a WAL commit isn't fsynced, a full checkpoint copies the
+        // WAL into the database file and syncs it.
+        sqlx::query("pragma wal_checkpoint(FULL)")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_audit(&self, entries: &[AuditEntry]) -> eyre::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for entry in entries {
+            sqlx::query(
+                "insert into api_audit(
+                    operation, parameters, request_id, duration_ms, outcome, created_at
+                )
+                values(?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .bind(entry.operation.as_str())
+            .bind(entry.parameters.as_str())
+            .bind(entry.request_id.as_deref())
+            .bind(entry.duration_ms)
+            .bind(entry.outcome.as_str())
+            .bind(entry.created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn list_audit(&self, since: DateTime<Utc>) -> eyre::Result<Vec<AuditEntry>> {
+        let items = sqlx::query_as::<_, AuditEntry>(
+            "select operation, parameters, request_id, duration_ms, outcome, created_at
+                from api_audit where created_at >= ?1 order by created_at, id",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(items)
+    }
+
+    async fn prune_audit(&self, before: DateTime<Utc>) -> eyre::Result<u64> {
+        let result = sqlx::query("delete from api_audit where created_at < ?1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn save_archive(&self, archive: &str, events: &[LogEvent]) -> eyre::Result<()> {
+        let archived_at = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        for event in events {
+            sqlx::query(
+                "insert or ignore into archive_events(
+                    archive, group_name, stream_name, timestamp, ingestion_time, message,
+                    event_id, archived_at
+                )
+                values(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .bind(archive)
+            .bind(event.group_name.as_str())
+            .bind(event.log_stream_name.as_deref())
+            .bind(event.timestamp)
+            .bind(event.ingestion_time)
+            .bind(event.message.as_deref())
+            .bind(event.event_id.as_deref())
+            .bind(archived_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn prune_archive(
+        &self,
+        archive: &str,
+        max_events: u64,
+        before: DateTime<Utc>,
+    ) -> eyre::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let expired =
+            sqlx::query("delete from archive_events where archive = ?1 and archived_at < ?2")
+                .bind(archive)
+                .bind(before)
+                .execute(&mut *tx)
+                .await?;
+        let over_cap = sqlx::query(
+            "delete from archive_events where archive = ?1 and id <= (
+                select id from archive_events where archive = ?1 order by id desc limit 1 offset ?2
+            )",
+        )
+        .bind(archive)
+        .bind(i64::try_from(max_events).unwrap_or(i64::MAX))
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(expired.rows_affected() + over_cap.rows_affected())
+    }
+
+    async fn search_archive(
+        &self,
+        archive: &str,
+        query: &ArchiveQuery,
+    ) -> eyre::Result<Vec<LogEvent>> {
+        let failed = || match &query.text {
+            Some(text) => format!("Failed searching archive '{}' for '{}'", archive, text),
+            None => format!("Failed searching archive '{}'", archive),
+        };
+        // NOTE: With a grep pattern the limit counts the matching events, the rows are streamed
+        // until that many matched instead.
+        let sql_limit = if query.grep.is_some() {
+            None
+        } else {
+            query.limit
+        };
+        let mut rows = sqlx::query_as::<_, ArchivedEvent>(
+            "select group_name, stream_name, timestamp, ingestion_time, message, event_id
+                from archive_events
+                where archive = ?1
+                    and (?2 is null or timestamp >= ?2)
+                    and (?3 is null or timestamp <= ?3)
+                    and (?4 is null or id in (
+                        select rowid from archive_events_fts where archive_events_fts match ?4
+                    ))
+                order by timestamp, id
+                limit coalesce(?5, -1)",
+        )
+        .bind(archive)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(query.text.as_deref())
+        .bind(sql_limit)
+        .fetch(&self.pool);
+
+        let limit = query.limit.map_or(usize::MAX, |limit| limit as usize);
+        let mut events = Vec::new();
+        while events.len() < limit {
+            let Some(item) = rows.try_next().await.wrap_err_with(failed)? else {
+                break;
+            };
+            let matches = query.grep.as_ref().is_none_or(|grep| {
+                item.message
+                    .as_deref()
+                    .is_some_and(|message| grep.is_match(message))
+            });
+            if matches {
+                events.push(LogEvent::from(item));
+            }
+        }
+        Ok(events)
+    }
+
+    async fn list_archives(&self) -> eyre::Result<Vec<ArchiveSummary>> {
+        let items = sqlx::query_as::<_, ArchiveSummary>(
+            "select archive, count(*) as events,
+                    coalesce(sum(length(cast(message as blob))), 0) as message_bytes,
+                    min(timestamp) as first_timestamp, max(timestamp) as last_timestamp
+                from archive_events group by archive order by archive",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(items)
+    }
+
+    async fn remove_archive(&self, archive: &str) -> eyre::Result<u64> {
+        let result = sqlx::query("delete from archive_events where archive = ?1")
+            .bind(archive)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn cache_groups(&self, scope: &CacheScope, names: &[String]) -> eyre::Result<()> {
+        let seen_at = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        for name in names {
+            sqlx::query(
+                "insert into group_cache(profile, region, group_name, seen_at)
+                values(?1, ?2, ?3, ?4)
+                on conflict(profile, region, group_name) do update set seen_at = excluded.seen_at",
+            )
+            .bind(scope.profile.as_str())
+            .bind(scope.region.as_str())
+            .bind(name.as_str())
+            .bind(seen_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn cached_groups(&self, scope: &CacheScope) -> eyre::Result<Vec<String>> {
+        let items = sqlx::query_scalar::<_, String>(
+            "select group_name from group_cache
+                where profile = ?1 and region = ?2 order by group_name",
+        )
+        .bind(scope.profile.as_str())
+        .bind(scope.region.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(items)
+    }
+
+    async fn groups_refreshed_at(&self, scope: &CacheScope) -> eyre::Result<Option<DateTime<Utc>>> {
+        let refreshed_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+            "select refreshed_at from group_cache_refresh where profile = ?1 and region = ?2",
+        )
+        .bind(scope.profile.as_str())
+        .bind(scope.region.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(refreshed_at)
+    }
+
+    async fn finish_group_refresh(
+        &self,
+        scope: &CacheScope,
+        started_at: DateTime<Utc>,
+    ) -> eyre::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("delete from group_cache where profile = ?1 and region = ?2 and seen_at < ?3")
+            .bind(scope.profile.as_str())
+            .bind(scope.region.as_str())
+            .bind(started_at)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "insert into group_cache_refresh(profile, region, refreshed_at)
+            values(?1, ?2, ?3)
+            on conflict(profile, region) do update set refreshed_at = excluded.refreshed_at",
+        )
+        .bind(scope.profile.as_str())
+        .bind(scope.region.as_str())
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+    use regex::Regex;
+    use sqlx::{Connection, SqliteConnection};
+
+    use crate::db::{
+        is_read_only, is_wal_failure, ArchiveQuery, AuditEntry, CacheScope, Database, QueryHistory,
+        QueryStatus, Sqlite, SqliteSettings,
+    };
+    use crate::event::LogEvent;
+    use crate::memory_db::InMemoryDb;
+
+    async fn columns(conn: &mut SqliteConnection, table: &str) -> Vec<String> {
+        sqlx::query_scalar(&format!("select name from pragma_table_info('{}')", table))
+            .fetch_all(conn)
+            .await
+            .unwrap()
+    }
+
+    async fn tables(conn: &mut SqliteConnection) -> Vec<String> {
+        sqlx::query_scalar(
+            "select name from sqlite_master where type = 'table' and name not like 'sqlite_%'
+                and name not like '%_fts_%' order by name",
+        )
+        .fetch_all(conn)
+        .await
+        .unwrap()
+    }
+
+    fn event(id: &str, timestamp: i64, message: &str) -> LogEvent {
+        LogEvent {
+            group_name: "/aws/lambda/api".to_string(),
+            log_stream_name: Some("stream".to_string()),
+            timestamp: Some(timestamp),
+            message: Some(message.to_string()),
+            ingestion_time: Some(timestamp + 1),
+            event_id: Some(id.to_string()),
+        }
+    }
+
+    fn scope() -> CacheScope {
+        CacheScope {
+            profile: "dev".to_string(),
+            region: "eu-west-1".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn every_migration_leaves_the_expected_schema() {
+        let migrator = sqlx::migrate!("./migrations");
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+
+        let expected: [(&str, &[&str]); 5] = [
+            (
+                "query_history",
+                &[
+                    "id",
+                    "query_id",
+                    "account",
+                    "status",
+                    "contents",
+                    "records_total",
+                    "records_matched",
+                    "records_scanned",
+                    "bytes_scanned",
+                    "created_at",
+                    "modified_at",
+                    "deleted_at",
+                ],
+            ),
+            (
+                "query_history",
+                &["group_names", "start_time", "end_time", "profile", "region"],
+            ),
+            (
+                "api_audit",
+                &[
+                    "id",
+                    "operation",
+                    "parameters",
+                    "request_id",
+                    "duration_ms",
+                    "outcome",
+                    "created_at",
+                ],
+            ),
+            (
+                "archive_events",
+                &[
+                    "id",
+                    "archive",
+                    "group_name",
+                    "stream_name",
+                    "timestamp",
+                    "ingestion_time",
+                    "message",
+                    "event_id",
+                    "archived_at",
+                ],
+            ),
+            (
+                "group_cache",
+                &["profile", "region", "group_name", "seen_at"],
+            ),
+        ];
+        assert_eq!(migrator.iter().count(), expected.len());
+
+        for (migration, (table, added)) in migrator.iter().zip(expected) {
+            sqlx::raw_sql(&migration.sql)
+                .execute(&mut conn)
+                .await
+                .unwrap_or_else(|err| panic!("migration {} failed: {}", migration.version, err));
+
+            let columns = columns(&mut conn, table).await;
+            for column in added {
+                assert!(
+                    columns.iter().any(|c| c == column),
+                    "migration {} didn't leave {}.{}, got {:?}",
+                    migration.version,
+                    table,
+                    column,
+                    columns
+                );
+            }
+        }
+
+        assert_eq!(
+            tables(&mut conn).await,
+            [
+                "api_audit",
+                "archive_events",
+                "archive_events_fts",
+                "group_cache",
+                "group_cache_refresh",
+                "query_history",
+            ]
+        );
+        assert_eq!(
+            columns(&mut conn, "group_cache_refresh").await,
+            ["profile", "region", "refreshed_at"]
+        );
+    }
+
+    #[tokio::test]
+    async fn migrations_run_again_without_changes() {
+        let db = Sqlite::in_memory().await.unwrap();
+        sqlx::migrate!("./migrations").run(&db.pool).await.unwrap();
+
+        let applied: i64 = sqlx::query_scalar("select count(*) from _sqlx_migrations")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            applied,
+            sqlx::migrate!("./migrations").iter().count() as i64
+        );
+    }
+
+    #[tokio::test]
+    async fn history_round_trips() {
+        history_round_trips_in(Sqlite::in_memory().await.unwrap()).await;
+        history_round_trips_in(InMemoryDb::new()).await;
+    }
+
+    async fn history_round_trips_in(db: impl Database) {
+        let mut history = QueryHistory::new("q-1".to_string(), "fields @message".to_string());
+        history
+            .set_context(
+                &["/aws/lambda/api".to_string(), "/ecs/web".to_string()],
+                1_000,
+                2_000,
+                Some("dev".to_string()),
+                None,
+            )
+            .unwrap();
+        db.save(&history).await.unwrap();
+
+        let saved = db.get("q-1").await.unwrap().unwrap();
+        assert_eq!(saved.status, QueryStatus::Scheduled);
+        assert_eq!(saved.deleted_at, None);
+        assert_eq!(
+            saved.group_names().unwrap(),
+            ["/aws/lambda/api", "/ecs/web"]
+        );
+        assert_eq!(
+            (saved.start_time, saved.end_time),
+            (Some(1_000), Some(2_000))
+        );
+        assert_eq!(saved.profile.as_deref(), Some("dev"));
+        assert_eq!(saved.region, None);
+        assert_eq!(db.get(&saved.id).await.unwrap(), Some(saved));
+
+        history.set_statistics(3, 10.0, 250.0, 4096.0);
+        history.set_status(QueryStatus::Complete);
+        db.update(&history).await.unwrap();
+
+        let listed = db.list().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].status, QueryStatus::Complete);
+        assert_eq!(listed[0].records_total, 3);
+        assert_eq!(listed[0].records_matched, 10.0);
+        assert_eq!(listed[0].records_scanned, 250.0);
+        assert_eq!(listed[0].bytes_scanned, 4096.0);
+        assert_eq!(db.get("q-2").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn every_status_is_stored_by_name() {
+        let db = Sqlite::in_memory().await.unwrap();
+        let statuses = [
+            QueryStatus::Queued,
+            QueryStatus::Scheduled,
+            QueryStatus::Running,
+            QueryStatus::Complete,
+            QueryStatus::Failed,
+            QueryStatus::Timeout,
+            QueryStatus::Cancelled,
+            QueryStatus::Interrupted,
+            QueryStatus::Expired,
+        ];
+
+        for (i, status) in statuses.iter().enumerate() {
+            let mut history = QueryHistory::new(format!("q-{}", i), String::new());
+            history.set_status(status.clone());
+            db.save(&history).await.unwrap();
+
+            let stored: String =
+                sqlx::query_scalar("select status from query_history where query_id = ?1")
+                    .bind(&history.query_id)
+                    .fetch_one(&db.pool)
+                    .await
+                    .unwrap();
+            assert_eq!(stored, status.to_string());
+            assert_eq!(
+                db.get(&history.query_id).await.unwrap().unwrap().status,
+                *status
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn audit_entries_are_listed_and_pruned_by_age() {
+        audit_entries_are_listed_and_pruned_by_age_in(Sqlite::in_memory().await.unwrap()).await;
+        audit_entries_are_listed_and_pruned_by_age_in(InMemoryDb::new()).await;
+    }
+
+    async fn audit_entries_are_listed_and_pruned_by_age_in(db: impl Database) {
+        let now = Utc::now();
+        let entry = |operation: &str, age_days: i64| AuditEntry {
+            operation: operation.to_string(),
+            parameters: r#"{"log_group_name":"/ecs/web"}"#.to_string(),
+            request_id: None,
+            duration_ms: 12,
+            outcome: "ok".to_string(),
+            created_at: now - TimeDelta::days(age_days),
+        };
+        db.save_audit(&[entry("StartQuery", 40), entry("FilterLogEvents", 1)])
+            .await
+            .unwrap();
+
+        let recent = db.list_audit(now - TimeDelta::days(7)).await.unwrap();
+        assert_eq!(recent, [entry("FilterLogEvents", 1)]);
+
+        assert_eq!(db.prune_audit(now - TimeDelta::days(30)).await.unwrap(), 1);
+        assert_eq!(
+            db.list_audit(now - TimeDelta::days(365)).await.unwrap(),
+            [entry("FilterLogEvents", 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn archives_skip_events_they_already_hold() {
+        archives_skip_events_they_already_hold_in(Sqlite::in_memory().await.unwrap()).await;
+        archives_skip_events_they_already_hold_in(InMemoryDb::new()).await;
+    }
+
+    async fn archives_skip_events_they_already_hold_in(db: impl Database) {
+        db.save_archive(
+            "incident",
+            &[event("a", 1, "first"), event("b", 2, "second")],
+        )
+        .await
+        .unwrap();
+        db.save_archive(
+            "incident",
+            &[event("b", 2, "second"), event("c", 3, "third")],
+        )
+        .await
+        .unwrap();
+
+        let events = db
+            .search_archive("incident", &ArchiveQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            [
+                event("a", 1, "first"),
+                event("b", 2, "second"),
+                event("c", 3, "third")
+            ]
+        );
+
+        let summaries = db.list_archives().await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].events, 3);
+        assert_eq!(summaries[0].message_bytes, 16);
+        assert_eq!(
+            (summaries[0].first_timestamp, summaries[0].last_timestamp),
+            (Some(1), Some(3))
+        );
+
+        assert_eq!(db.remove_archive("incident").await.unwrap(), 3);
+        assert!(db.list_archives().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn archive_search_filters_by_time_text_and_pattern() {
+        archive_search_filters_by_time_text_and_pattern_in(Sqlite::in_memory().await.unwrap())
+            .await;
+        archive_search_filters_by_time_text_and_pattern_in(InMemoryDb::new()).await;
+    }
+
+    async fn archive_search_filters_by_time_text_and_pattern_in(db: impl Database) {
+        let events: Vec<_> = (0..10)
+            .map(|i| {
+                let message = if i % 2 == 0 {
+                    "timeout talking to db"
+                } else {
+                    "ok"
+                };
+                event(&i.to_string(), i, message)
+            })
+            .collect();
+        db.save_archive("incident", &events).await.unwrap();
+
+        let search = |query: ArchiveQuery| {
+            let db = db.clone();
+            async move {
+                db.search_archive("incident", &query)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .filter_map(|event| event.timestamp)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let window = ArchiveQuery {
+            since: Some(3),
+            until: Some(6),
+            ..Default::default()
+        };
+        assert_eq!(search(window).await, [3, 4, 5, 6]);
+
+        let text = ArchiveQuery {
+            text: Some("timeout".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(search(text).await, [0, 2]);
+
+        // NOTE: The limit counts the events matching the pattern, not the rows read.
+        let grep = ArchiveQuery {
+            grep: Some(Regex::new("^ok$").unwrap()),
+            limit: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(search(grep).await, [1, 3, 5]);
+
+        let none = ArchiveQuery {
+            grep: Some(Regex::new("refused").unwrap()),
+            ..Default::default()
+        };
+        assert!(search(none).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn archive_prune_keeps_the_newest_events() {
+        archive_prune_keeps_the_newest_events_in(Sqlite::in_memory().await.unwrap()).await;
+        archive_prune_keeps_the_newest_events_in(InMemoryDb::new()).await;
+    }
+
+    async fn archive_prune_keeps_the_newest_events_in(db: impl Database) {
+        let events: Vec<_> = (0..5).map(|i| event(&i.to_string(), i, "m")).collect();
+        db.save_archive("incident", &events).await.unwrap();
+        db.save_archive("other", &events).await.unwrap();
+
+        let hour_ago = Utc::now() - TimeDelta::hours(1);
+        assert_eq!(db.prune_archive("incident", 2, hour_ago).await.unwrap(), 3);
+        let kept = db
+            .search_archive("incident", &ArchiveQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(kept, [event("3", 3, "m"), event("4", 4, "m")]);
+
+        let later = Utc::now() + TimeDelta::hours(1);
+        assert_eq!(db.prune_archive("other", 10, later).await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn group_refresh_drops_the_groups_it_did_not_see() {
+        group_refresh_drops_the_groups_it_did_not_see_in(Sqlite::in_memory().await.unwrap()).await;
+        group_refresh_drops_the_groups_it_did_not_see_in(InMemoryDb::new()).await;
+    }
+
+    async fn group_refresh_drops_the_groups_it_did_not_see_in(db: impl Database) {
+        let other = CacheScope {
+            region: "us-east-1".to_string(),
+            ..scope()
+        };
+        db.cache_groups(&scope(), &["/b".to_string(), "/a".to_string()])
+            .await
+            .unwrap();
+        db.cache_groups(&other, &["/c".to_string()]).await.unwrap();
+        assert_eq!(db.groups_refreshed_at(&scope()).await.unwrap(), None);
+
+        let started_at = Utc::now();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        db.cache_groups(&scope(), &["/b".to_string()])
+            .await
+            .unwrap();
+        db.finish_group_refresh(&scope(), started_at).await.unwrap();
+
+        assert_eq!(db.cached_groups(&scope()).await.unwrap(), ["/b"]);
+        assert_eq!(db.cached_groups(&other).await.unwrap(), ["/c"]);
+        assert!(db
+            .groups_refreshed_at(&scope())
+            .await
+            .unwrap()
+            .is_some_and(|refreshed_at| refreshed_at >= started_at));
+        assert_eq!(db.groups_refreshed_at(&other).await.unwrap(), None);
+    }
+
+    /// A directory made read-only (0o555), or None when the permissions don't stop this process
+    /// from writing, e.g. when the tests run as root.
+    #[cfg(unix)]
+    fn read_only_dir(name: &str) -> Option<std::path::PathBuf> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("cw-read-only-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+        if std::fs::write(dir.join("probe"), "").is_ok() {
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+            std::fs::remove_dir_all(&dir).unwrap();
+            return None;
+        }
+        Some(dir)
+    }
+
+    #[cfg(unix)]
+    fn remove_read_only_dir(dir: &std::path::Path) {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn opening_in_a_read_only_directory_is_a_read_only_error() {
+        let Some(dir) = read_only_dir("db") else {
+            return;
+        };
+
+        for path in [dir.join("db.sqlite3"), dir.join("data").join("db.sqlite3")] {
+            let err = Sqlite::new(&SqliteSettings {
+                path: path.clone(),
+                journal_mode: None,
+                retry_writes: false,
+            })
+            .await
+            .err()
+            .unwrap();
+
+            assert!(is_read_only(&err), "{}: {:?}", path.display(), err);
+            assert!(!is_wal_failure(&err), "{}: {:?}", path.display(), err);
+        }
+
+        remove_read_only_dir(&dir);
+    }
+
+    #[test]
+    fn only_permission_errors_are_read_only() {
+        let denied = eyre::Report::new(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        let missing = eyre::Report::new(std::io::Error::from(std::io::ErrorKind::NotFound));
+
+        assert!(is_read_only(
+            &denied.wrap_err("Failed opening the database")
+        ));
+        assert!(!is_read_only(&missing));
+        assert!(!is_read_only(&eyre::eyre!("migration 3 failed")));
+    }
 }
@@ -0,0 +1,39 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+/// The exit code of a run cut short by `--deadline`, the same one `timeout(1)` exits with.
+pub const DEADLINE_EXIT_CODE: u8 = 124;
+
+/// A command didn't finish within `--deadline`.
+#[derive(Debug)]
+pub struct DeadlineExceeded {
+    pub deadline: Duration,
+    /// What the command was doing when the deadline passed.
+    pub in_progress: String,
+}
+
+impl Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The --deadline of {} passed, {}.",
+            humantime::format_duration(self.deadline),
+            self.in_progress
+        )
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+pub fn is_deadline_exceeded(err: &eyre::Report) -> bool {
+    err.chain().any(|cause| cause.is::<DeadlineExceeded>())
+}
+
+/// The exit code for a run that failed with `err`.
+pub fn exit_code(err: &eyre::Report) -> u8 {
+    if is_deadline_exceeded(err) {
+        DEADLINE_EXIT_CODE
+    } else {
+        1
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::f64::consts::LN_2;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use crate::event::LogEvent;
+
+// The window is split in this many partitions, the oldest is dropped as a whole once the events
+// move past it. One more is kept, so an event is always compared with at least the full window.
+const PARTITIONS: i64 = 4;
+
+// How many keys a single Bloom filter is sized for. A partition that sees more adds filters, so
+// memory follows the number of events in the window rather than a guess made up front.
+const FILTER_CAPACITY: usize = 64 * 1024;
+
+/// What makes two events the same for --dedupe-window.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DedupeKey {
+    /// The CloudWatch event id, events without one fall back to their message.
+    #[default]
+    Id,
+    /// The group, stream, timestamp and message, for pipelines that ship an event more than once
+    /// under new ids.
+    Message,
+}
+
+impl DedupeKey {
+    fn hashes(self, event: &LogEvent) -> (u64, u64) {
+        let mut hasher = DefaultHasher::new();
+        match (self, &event.event_id) {
+            (Self::Id, Some(id)) => id.hash(&mut hasher),
+            _ => (
+                &event.group_name,
+                &event.log_stream_name,
+                event.timestamp,
+                &event.message,
+            )
+                .hash(&mut hasher),
+        }
+        let first = hasher.finish();
+        // NOTE: Finishing doesn't reset the hasher, writing one more byte gives a second hash
+        // independent enough for double hashing.
+        hasher.write_u8(0xff);
+        (first, hasher.finish() | 1)
+    }
+}
+
+/// Remembers the events of the last `window`, by event time, to drop the ones seen before.
+/// Membership is a Bloom filter per partition of the window, so a small share of new events,
+/// at most the false positive rate, is dropped as a duplicate too.
+#[derive(Debug)]
+pub struct DedupeWindow {
+    key: DedupeKey,
+    partition_ms: i64,
+    false_positive_rate: f64,
+    partitions: VecDeque<Partition>,
+    dropped: u64,
+}
+
+impl DedupeWindow {
+    pub fn new(window: Duration, key: DedupeKey, false_positive_rate: f64) -> Self {
+        let window_ms = i64::try_from(window.as_millis()).unwrap_or(i64::MAX);
+        Self {
+            key,
+            partition_ms: (window_ms / PARTITIONS).max(1),
+            false_positive_rate,
+            partitions: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Whether `event` was seen within the window, remembering it when it wasn't.
+    pub fn is_duplicate(&mut self, event: &LogEvent) -> bool {
+        let hashes = self.key.hashes(event);
+        let index = match event.timestamp {
+            Some(timestamp) => timestamp.div_euclid(self.partition_ms),
+            None => self
+                .partitions
+                .back()
+                .map_or(0, |partition| partition.index),
+        };
+        self.rotate(index);
+
+        if self.partitions.iter().any(|p| p.contains(hashes)) {
+            self.dropped += 1;
+            return true;
+        }
+
+        // NOTE: Late events go into the newest partition, they are remembered a little longer
+        // than the window instead of being forgotten right away.
+        let rate = self.filter_rate();
+        if let Some(partition) = self.partitions.back_mut() {
+            partition.insert(hashes, rate);
+        }
+        false
+    }
+
+    /// How many events were dropped as duplicates.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Starts a partition for `index` when it is newer than the newest, and drops the partitions
+    /// that fell out of the window.
+    fn rotate(&mut self, index: i64) {
+        if self.partitions.back().is_some_and(|p| p.index >= index) {
+            return;
+        }
+        self.partitions.push_back(Partition {
+            index,
+            filters: Vec::new(),
+        });
+        while self
+            .partitions
+            .front()
+            .is_some_and(|p| p.index < index - PARTITIONS)
+        {
+            self.partitions.pop_front();
+        }
+    }
+
+    /// The false positive rate of the first filter of a partition. Every further filter halves
+    /// it, so all filters of all kept partitions together stay within the configured rate.
+    fn filter_rate(&self) -> f64 {
+        self.false_positive_rate / (2.0 * (PARTITIONS + 1) as f64)
+    }
+}
+
+#[derive(Debug)]
+struct Partition {
+    index: i64,
+    filters: Vec<BloomFilter>,
+}
+
+impl Partition {
+    fn contains(&self, hashes: (u64, u64)) -> bool {
+        self.filters.iter().any(|filter| filter.contains(hashes))
+    }
+
+    fn insert(&mut self, hashes: (u64, u64), rate: f64) {
+        if self.filters.last().is_none_or(BloomFilter::is_full) {
+            let rate = rate / 2f64.powi(i32::try_from(self.filters.len()).unwrap_or(i32::MAX));
+            self.filters.push(BloomFilter::new(FILTER_CAPACITY, rate));
+        }
+        if let Some(filter) = self.filters.last_mut() {
+            filter.insert(hashes);
+        }
+    }
+}
+
+/// A plain Bloom filter using double hashing, ref:
+/// https://www.eecs.harvard.edu/~michaelm/postscripts/rsa2008.pdf
+#[derive(Debug)]
+struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+    items: usize,
+    capacity: usize,
+}
+
+impl BloomFilter {
+    fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let bits = (-(capacity as f64) * false_positive_rate.ln() / (LN_2 * LN_2)).ceil();
+        let words = bits as usize / 64 + 1;
+        let hashes = ((words * 64) as f64 / capacity as f64 * LN_2)
+            .round()
+            .max(1.0);
+        Self {
+            bits: vec![0; words],
+            hashes: hashes as u32,
+            items: 0,
+            capacity,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.items >= self.capacity
+    }
+
+    fn contains(&self, hashes: (u64, u64)) -> bool {
+        self.positions(hashes)
+            .all(|(word, bit)| self.bits[word] & bit != 0)
+    }
+
+    fn insert(&mut self, hashes: (u64, u64)) {
+        for (word, bit) in self.positions(hashes) {
+            self.bits[word] |= bit;
+        }
+        self.items += 1;
+    }
+
+    fn positions(&self, (first, second): (u64, u64)) -> impl Iterator<Item = (usize, u64)> {
+        let len = (self.bits.len() * 64) as u64;
+        (0..u64::from(self.hashes)).map(move |i| {
+            let position = first.wrapping_add(i.wrapping_mul(second)) % len;
+            ((position / 64) as usize, 1u64 << (position % 64))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::dedupe::{DedupeKey, DedupeWindow};
+    use crate::event::LogEvent;
+
+    fn event(id: Option<&str>, timestamp: i64, message: &str) -> LogEvent {
+        LogEvent {
+            group_name: "/ecs/api".to_string(),
+            log_stream_name: Some("web/1".to_string()),
+            timestamp: Some(timestamp),
+            message: Some(message.to_string()),
+            ingestion_time: None,
+            event_id: id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn drops_events_with_an_id_seen_before() {
+        let mut window = DedupeWindow::new(Duration::from_secs(60), DedupeKey::Id, 0.001);
+        assert!(!window.is_duplicate(&event(Some("1"), 1_000, "a")));
+        assert!(!window.is_duplicate(&event(Some("2"), 1_000, "a")));
+        assert!(window.is_duplicate(&event(Some("1"), 2_000, "b")));
+        assert_eq!(window.dropped(), 1);
+    }
+
+    #[test]
+    fn events_without_an_id_fall_back_to_their_message() {
+        let mut window = DedupeWindow::new(Duration::from_secs(60), DedupeKey::Id, 0.001);
+        assert!(!window.is_duplicate(&event(None, 1_000, "a")));
+        assert!(window.is_duplicate(&event(None, 1_000, "a")));
+        assert!(!window.is_duplicate(&event(None, 1_000, "b")));
+        assert!(!window.is_duplicate(&event(None, 2_000, "a")));
+    }
+
+    #[test]
+    fn message_key_ignores_the_id() {
+        let mut window = DedupeWindow::new(Duration::from_secs(60), DedupeKey::Message, 0.001);
+        assert!(!window.is_duplicate(&event(Some("1"), 1_000, "a")));
+        assert!(window.is_duplicate(&event(Some("2"), 1_000, "a")));
+        assert!(!window.is_duplicate(&event(Some("1"), 1_000, "b")));
+    }
+
+    #[test]
+    fn remembers_events_for_the_whole_window() {
+        let mut window = DedupeWindow::new(Duration::from_secs(4), DedupeKey::Id, 0.001);
+        assert!(!window.is_duplicate(&event(Some("1"), 0, "a")));
+        assert!(!window.is_duplicate(&event(Some("2"), 4_999, "a")));
+        assert!(window.is_duplicate(&event(Some("1"), 4_999, "a")));
+    }
+
+    #[test]
+    fn forgets_events_once_the_window_moved_past_them() {
+        let mut window = DedupeWindow::new(Duration::from_secs(4), DedupeKey::Id, 0.001);
+        assert!(!window.is_duplicate(&event(Some("1"), 0, "a")));
+        assert!(!window.is_duplicate(&event(Some("2"), 5_000, "a")));
+        assert!(!window.is_duplicate(&event(Some("1"), 5_000, "a")));
+    }
+
+    #[test]
+    fn late_events_are_still_compared() {
+        let mut window = DedupeWindow::new(Duration::from_secs(4), DedupeKey::Id, 0.001);
+        assert!(!window.is_duplicate(&event(Some("1"), 3_000, "a")));
+        assert!(!window.is_duplicate(&event(Some("2"), 1_000, "a")));
+        assert!(window.is_duplicate(&event(Some("2"), 3_500, "a")));
+    }
+
+    #[test]
+    fn false_positives_stay_within_the_rate() {
+        let rate = 0.01;
+        let mut window = DedupeWindow::new(Duration::from_secs(60), DedupeKey::Id, rate);
+        // NOTE: More events than a single filter holds, so the partition adds a second one.
+        let seen = 100_000;
+        for id in 0..seen {
+            window.is_duplicate(&event(Some(&format!("seen-{}", id)), 1_000, "a"));
+        }
+
+        let checked = 100_000;
+        let false_positives = (0..checked)
+            .filter(|id| window.is_duplicate(&event(Some(&format!("new-{}", id)), 1_000, "a")))
+            .count();
+        assert!(
+            (false_positives as f64) / (checked as f64) <= rate,
+            "{} false positives out of {}",
+            false_positives,
+            checked
+        );
+    }
+}
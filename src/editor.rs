@@ -2,11 +2,43 @@ use std::{
     env,
     fs::File,
     io::{Read, Write},
-    process::Command,
+    path::PathBuf,
+    process::{Command, Stdio},
 };
 
+use eyre::Context;
 use uuid::Uuid;
 
+/// Removes the temporary query file once the editor session is over, including on early returns.
+struct TempFileGuard(PathBuf);
+
+impl Drop for TempFileGuard {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.0) {
+            tracing::debug!(target: "cw", "failed removing {}: {}", self.0.display(), err);
+        }
+    }
+}
+
+/// A handle to the terminal of the current session, so the editor can take over the terminal
+/// even when stdin or stdout is redirected.
+#[cfg(not(target_os = "windows"))]
+fn terminal_stdio() -> eyre::Result<Stdio> {
+    let tty = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("Failed to open /dev/tty")?;
+    Ok(Stdio::from(tty))
+}
+
+// NOTE: Windows has no /dev/tty. Its console devices (CONIN$, CONOUT$) can't be opened for
+// reading and writing through one handle, so let the editor inherit the console instead.
+#[cfg(target_os = "windows")]
+fn terminal_stdio() -> eyre::Result<Stdio> {
+    Ok(Stdio::inherit())
+}
+
 pub fn open_in_editor(contents: &str, use_editor: Option<String>) -> eyre::Result<String> {
     let editor = use_editor
         .or_else(|| env::var("EDITOR").ok())
@@ -20,26 +52,17 @@ pub fn open_in_editor(contents: &str, use_editor: Option<String>) -> eyre::Resul
         .create(true)
         .open(&tmp_filepath)
         .expect("File does not exist");
+    let _guard = TempFileGuard(tmp_filepath.clone());
 
     tmp_file.write_all(contents.as_bytes())?;
-    tmp_file.flush()?;
-
-    // Get handle to the TTY attached to current terminal session.
-    let tty = File::options()
-        .read(true)
-        .write(true)
-        .open("/dev/tty")
-        .expect("Failed to open /dev/tty");
+    tmp_file.sync_all()?;
 
     // TODO: add check to see if status code indicates success
     let _result = Command::new(editor)
         .arg(&tmp_filepath)
-        .stdin(tty.try_clone().expect("Failed to clone /dev/tty for stdin"))
-        .stdout(
-            tty.try_clone()
-                .expect("Failed to clone /dev/tty for stdout"),
-        )
-        .stderr(tty)
+        .stdin(terminal_stdio()?)
+        .stdout(terminal_stdio()?)
+        .stderr(terminal_stdio()?)
         .status()?;
 
     // NOTE: Reopening the file to ensure I pick up the changes written to disk by the EDITOR
@@ -0,0 +1,63 @@
+//! A log event as cw reads it, whichever API or file it came from.
+
+use chrono::DateTime;
+use serde_json::Value;
+
+/// An event of a log group.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LogEvent {
+    pub group_name: String,
+    pub log_stream_name: Option<String>,
+    pub timestamp: Option<i64>,
+    pub message: Option<String>,
+    pub ingestion_time: Option<i64>,
+    pub event_id: Option<String>,
+}
+
+// NOTE: A data protection policy replaces every character of a sensitive value with an asterisk,
+// ref: https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/mask-sensitive-log-data.html.
+// FilterLogEvents doesn't say which events were masked, so a run of asterisks is all there is.
+const MASK_MARKER: &str = "****";
+
+impl LogEvent {
+    /// Whether a data protection policy masked part of the message.
+    pub fn is_masked(&self) -> bool {
+        self.message
+            .as_deref()
+            .is_some_and(|message| message.contains(MASK_MARKER))
+    }
+
+    /// Reads an event from a JSON object as written by `--output json`. Every field is optional,
+    /// and both the output names (`group`, `stream`, `id`) and the field names of this struct are
+    /// accepted.
+    pub fn from_json(value: &Value) -> Option<Self> {
+        let object = value.as_object()?;
+        let field = |names: &[&str]| names.iter().find_map(|name| object.get(*name));
+        let string = |names: &[&str]| {
+            field(names).and_then(|v| match v {
+                Value::Null => None,
+                Value::String(s) => Some(s.clone()),
+                other => Some(other.to_string()),
+            })
+        };
+        let millis = |names: &[&str]| {
+            field(names).and_then(|v| match v {
+                Value::Number(n) => n.as_i64(),
+                Value::String(s) => DateTime::parse_from_rfc3339(s)
+                    .ok()
+                    .map(|t| t.timestamp_millis())
+                    .or_else(|| s.parse().ok()),
+                _ => None,
+            })
+        };
+
+        Some(Self {
+            group_name: string(&["group", "group_name"]).unwrap_or_default(),
+            log_stream_name: string(&["stream", "log_stream_name"]),
+            timestamp: millis(&["timestamp"]),
+            message: string(&["message"]),
+            ingestion_time: millis(&["ingestion_time", "ingestionTime"]),
+            event_id: string(&["id", "event_id"]),
+        })
+    }
+}
@@ -0,0 +1,504 @@
+//! A [`LogsApi`] answering from responses queued up front instead of AWS, so code written against
+//! the trait can be tested without an account. Every operation has its own queue, which is
+//! answered in order and remembers the requests it got.
+
+use std::collections::VecDeque;
+use std::future::{ready, Future};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use aws_sdk_cloudwatchlogs::config::http::HttpResponse;
+use aws_sdk_cloudwatchlogs::error::{ErrorMetadata, SdkError};
+use aws_sdk_cloudwatchlogs::operation::delete_retention_policy::{
+    DeleteRetentionPolicyError, DeleteRetentionPolicyOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::describe_log_groups::{
+    DescribeLogGroupsError, DescribeLogGroupsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::describe_log_streams::{
+    DescribeLogStreamsError, DescribeLogStreamsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::filter_log_events::{
+    FilterLogEventsError, FilterLogEventsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::get_log_group_fields::{
+    GetLogGroupFieldsError, GetLogGroupFieldsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::get_query_results::{
+    GetQueryResultsError, GetQueryResultsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::put_retention_policy::{
+    PutRetentionPolicyError, PutRetentionPolicyOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::start_query::{StartQueryError, StartQueryOutput};
+use aws_sdk_cloudwatchlogs::operation::stop_query::{StopQueryError, StopQueryOutput};
+use aws_smithy_runtime_api::client::result::CreateUnhandledError;
+use aws_smithy_runtime_api::http::StatusCode;
+use aws_smithy_types::body::SdkBody;
+
+use crate::logs_api::{
+    DescribeLogGroupsRequest, DescribeLogStreamsRequest, FilterLogEventsRequest, LogsApi,
+    StartQueryRequest,
+};
+
+/// The responses queued for one operation and the requests it got, in order.
+#[derive(Debug)]
+struct Script<Req, Out, Err> {
+    responses: VecDeque<Result<Out, SdkError<Err>>>,
+    requests: Vec<Req>,
+}
+
+impl<Req, Out: Default, Err> Script<Req, Out, Err> {
+    /// The next queued response, or an empty one once the queue ran out, so a follow loop keeps
+    /// finding nothing new instead of failing.
+    // NOTE: The SDK errors are large, this returns them the way the client does.
+    #[allow(clippy::result_large_err)]
+    fn answer(&mut self, request: Req) -> Result<Out, SdkError<Err>> {
+        self.requests.push(request);
+        self.responses
+            .pop_front()
+            .unwrap_or_else(|| Ok(Out::default()))
+    }
+}
+
+impl<Req, Out, Err> Default for Script<Req, Out, Err> {
+    fn default() -> Self {
+        Self {
+            responses: VecDeque::new(),
+            requests: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Scripts {
+    region: Option<String>,
+    filter_log_events: Script<FilterLogEventsRequest, FilterLogEventsOutput, FilterLogEventsError>,
+    describe_log_groups:
+        Script<DescribeLogGroupsRequest, DescribeLogGroupsOutput, DescribeLogGroupsError>,
+    describe_log_streams:
+        Script<DescribeLogStreamsRequest, DescribeLogStreamsOutput, DescribeLogStreamsError>,
+    start_query: Script<StartQueryRequest, StartQueryOutput, StartQueryError>,
+    get_query_results: Script<String, GetQueryResultsOutput, GetQueryResultsError>,
+    stop_query: Script<String, StopQueryOutput, StopQueryError>,
+    get_log_group_fields: Script<String, GetLogGroupFieldsOutput, GetLogGroupFieldsError>,
+    put_retention_policy: Script<(String, i32), PutRetentionPolicyOutput, PutRetentionPolicyError>,
+    delete_retention_policy:
+        Script<String, DeleteRetentionPolicyOutput, DeleteRetentionPolicyError>,
+}
+
+/// An in-memory [`LogsApi`]. Clones share their queues, so a test keeps one to queue responses
+/// and look at the requests while the code under test calls another.
+///
+/// ```
+/// use aws_sdk_cloudwatchlogs::operation::filter_log_events::FilterLogEventsError;
+/// use cw::fake_logs::{service_error, FakeLogs};
+///
+/// let logs = FakeLogs::new();
+/// logs.push_filter_log_events(Err(service_error::<FilterLogEventsError>(
+///     "ResourceNotFoundException",
+///     "The specified log group does not exist.",
+/// )));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FakeLogs {
+    scripts: Arc<Mutex<Scripts>>,
+}
+
+impl FakeLogs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The region [`LogsApi::region`] reports.
+    pub fn with_region(self, region: impl Into<String>) -> Self {
+        self.scripts().region = Some(region.into());
+        self
+    }
+
+    // NOTE: A test that panicked while holding the lock already failed, the queues it left
+    // behind are still good enough to look at.
+    fn scripts(&self) -> MutexGuard<'_, Scripts> {
+        self.scripts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn push_filter_log_events(
+        &self,
+        response: Result<FilterLogEventsOutput, SdkError<FilterLogEventsError>>,
+    ) -> &Self {
+        self.scripts()
+            .filter_log_events
+            .responses
+            .push_back(response);
+        self
+    }
+
+    pub fn push_describe_log_groups(
+        &self,
+        response: Result<DescribeLogGroupsOutput, SdkError<DescribeLogGroupsError>>,
+    ) -> &Self {
+        self.scripts()
+            .describe_log_groups
+            .responses
+            .push_back(response);
+        self
+    }
+
+    pub fn push_describe_log_streams(
+        &self,
+        response: Result<DescribeLogStreamsOutput, SdkError<DescribeLogStreamsError>>,
+    ) -> &Self {
+        self.scripts()
+            .describe_log_streams
+            .responses
+            .push_back(response);
+        self
+    }
+
+    pub fn push_start_query(
+        &self,
+        response: Result<StartQueryOutput, SdkError<StartQueryError>>,
+    ) -> &Self {
+        self.scripts().start_query.responses.push_back(response);
+        self
+    }
+
+    pub fn push_get_query_results(
+        &self,
+        response: Result<GetQueryResultsOutput, SdkError<GetQueryResultsError>>,
+    ) -> &Self {
+        self.scripts()
+            .get_query_results
+            .responses
+            .push_back(response);
+        self
+    }
+
+    pub fn push_stop_query(
+        &self,
+        response: Result<StopQueryOutput, SdkError<StopQueryError>>,
+    ) -> &Self {
+        self.scripts().stop_query.responses.push_back(response);
+        self
+    }
+
+    pub fn push_get_log_group_fields(
+        &self,
+        response: Result<GetLogGroupFieldsOutput, SdkError<GetLogGroupFieldsError>>,
+    ) -> &Self {
+        self.scripts()
+            .get_log_group_fields
+            .responses
+            .push_back(response);
+        self
+    }
+
+    pub fn push_put_retention_policy(
+        &self,
+        response: Result<PutRetentionPolicyOutput, SdkError<PutRetentionPolicyError>>,
+    ) -> &Self {
+        self.scripts()
+            .put_retention_policy
+            .responses
+            .push_back(response);
+        self
+    }
+
+    pub fn push_delete_retention_policy(
+        &self,
+        response: Result<DeleteRetentionPolicyOutput, SdkError<DeleteRetentionPolicyError>>,
+    ) -> &Self {
+        self.scripts()
+            .delete_retention_policy
+            .responses
+            .push_back(response);
+        self
+    }
+
+    pub fn filter_log_events_requests(&self) -> Vec<FilterLogEventsRequest> {
+        self.scripts().filter_log_events.requests.clone()
+    }
+
+    pub fn describe_log_groups_requests(&self) -> Vec<DescribeLogGroupsRequest> {
+        self.scripts().describe_log_groups.requests.clone()
+    }
+
+    pub fn describe_log_streams_requests(&self) -> Vec<DescribeLogStreamsRequest> {
+        self.scripts().describe_log_streams.requests.clone()
+    }
+
+    pub fn start_query_requests(&self) -> Vec<StartQueryRequest> {
+        self.scripts().start_query.requests.clone()
+    }
+
+    /// The query ids polled for results.
+    pub fn get_query_results_requests(&self) -> Vec<String> {
+        self.scripts().get_query_results.requests.clone()
+    }
+
+    /// The query ids stopped.
+    pub fn stop_query_requests(&self) -> Vec<String> {
+        self.scripts().stop_query.requests.clone()
+    }
+
+    pub fn get_log_group_fields_requests(&self) -> Vec<String> {
+        self.scripts().get_log_group_fields.requests.clone()
+    }
+
+    /// The groups and the days their retention was set to.
+    pub fn put_retention_policy_requests(&self) -> Vec<(String, i32)> {
+        self.scripts().put_retention_policy.requests.clone()
+    }
+
+    pub fn delete_retention_policy_requests(&self) -> Vec<String> {
+        self.scripts().delete_retention_policy.requests.clone()
+    }
+}
+
+impl LogsApi for FakeLogs {
+    fn region(&self) -> Option<String> {
+        self.scripts().region.clone()
+    }
+
+    fn filter_log_events(
+        &self,
+        request: FilterLogEventsRequest,
+    ) -> impl Future<Output = Result<FilterLogEventsOutput, SdkError<FilterLogEventsError>>> + Send
+    {
+        ready(self.scripts().filter_log_events.answer(request))
+    }
+
+    fn describe_log_groups(
+        &self,
+        request: DescribeLogGroupsRequest,
+    ) -> impl Future<Output = Result<DescribeLogGroupsOutput, SdkError<DescribeLogGroupsError>>> + Send
+    {
+        ready(self.scripts().describe_log_groups.answer(request))
+    }
+
+    fn describe_log_streams(
+        &self,
+        request: DescribeLogStreamsRequest,
+    ) -> impl Future<Output = Result<DescribeLogStreamsOutput, SdkError<DescribeLogStreamsError>>> + Send
+    {
+        ready(self.scripts().describe_log_streams.answer(request))
+    }
+
+    fn start_query(
+        &self,
+        request: StartQueryRequest,
+    ) -> impl Future<Output = Result<StartQueryOutput, SdkError<StartQueryError>>> + Send {
+        ready(self.scripts().start_query.answer(request))
+    }
+
+    fn get_query_results(
+        &self,
+        query_id: &str,
+    ) -> impl Future<Output = Result<GetQueryResultsOutput, SdkError<GetQueryResultsError>>> + Send
+    {
+        ready(
+            self.scripts()
+                .get_query_results
+                .answer(query_id.to_string()),
+        )
+    }
+
+    fn stop_query(
+        &self,
+        query_id: &str,
+    ) -> impl Future<Output = Result<StopQueryOutput, SdkError<StopQueryError>>> + Send {
+        ready(self.scripts().stop_query.answer(query_id.to_string()))
+    }
+
+    fn get_log_group_fields(
+        &self,
+        group_identifier: &str,
+    ) -> impl Future<Output = Result<GetLogGroupFieldsOutput, SdkError<GetLogGroupFieldsError>>> + Send
+    {
+        ready(
+            self.scripts()
+                .get_log_group_fields
+                .answer(group_identifier.to_string()),
+        )
+    }
+
+    fn put_retention_policy(
+        &self,
+        group_name: &str,
+        days: i32,
+    ) -> impl Future<Output = Result<PutRetentionPolicyOutput, SdkError<PutRetentionPolicyError>>> + Send
+    {
+        ready(
+            self.scripts()
+                .put_retention_policy
+                .answer((group_name.to_string(), days)),
+        )
+    }
+
+    fn delete_retention_policy(
+        &self,
+        group_name: &str,
+    ) -> impl Future<
+        Output = Result<DeleteRetentionPolicyOutput, SdkError<DeleteRetentionPolicyError>>,
+    > + Send {
+        ready(
+            self.scripts()
+                .delete_retention_policy
+                .answer(group_name.to_string()),
+        )
+    }
+}
+
+/// A failed call the way CloudWatch Logs answers one: an error with `code` and `message` in a
+/// 400 response. cw tells errors apart by their code, see [`crate::compat::classify_error`].
+pub fn service_error<E: CreateUnhandledError>(code: &str, message: &str) -> SdkError<E> {
+    let meta = ErrorMetadata::builder().code(code).message(message).build();
+    let error = E::create_unhandled_error(Box::new(meta.clone()), Some(meta));
+    match StatusCode::try_from(400) {
+        Ok(status) => SdkError::service_error(error, HttpResponse::new(status, SdkBody::empty())),
+        Err(err) => SdkError::construction_failure(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_cloudwatchlogs::operation::describe_log_groups::{
+        DescribeLogGroupsError, DescribeLogGroupsOutput,
+    };
+    use aws_sdk_cloudwatchlogs::operation::filter_log_events::FilterLogEventsError;
+    use aws_sdk_cloudwatchlogs::types::LogGroup;
+    use futures_util::{StreamExt, TryStreamExt};
+
+    use crate::compat::{classify_error, ApiErrorKind};
+    use crate::fake_logs::{service_error, FakeLogs};
+    use crate::logs_api::{
+        describe_log_groups_pages, DescribeLogGroupsRequest, FilterLogEventsRequest, LogsApi,
+    };
+    use crate::rate_limit::RateLimiter;
+
+    fn page(names: &[&str], next_token: Option<&str>) -> DescribeLogGroupsOutput {
+        DescribeLogGroupsOutput::builder()
+            .set_log_groups(Some(
+                names
+                    .iter()
+                    .map(|name| LogGroup::builder().log_group_name(*name).build())
+                    .collect(),
+            ))
+            .set_next_token(next_token.map(str::to_string))
+            .build()
+    }
+
+    fn names(pages: &[DescribeLogGroupsOutput]) -> Vec<&str> {
+        pages
+            .iter()
+            .flat_map(|page| page.log_groups())
+            .filter_map(|group| group.log_group_name())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn pages_follow_the_next_token_until_the_last_page() {
+        let logs = FakeLogs::new();
+        logs.push_describe_log_groups(Ok(page(&["/a", "/b"], Some("t1"))))
+            .push_describe_log_groups(Ok(page(&["/c"], Some("t2"))))
+            .push_describe_log_groups(Ok(page(&[], None)));
+        let limiter = RateLimiter::new(100);
+        let request = DescribeLogGroupsRequest {
+            name_prefix: Some("/".to_string()),
+            ..Default::default()
+        };
+
+        let pages: Vec<_> = describe_log_groups_pages(&logs, &limiter, request)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(names(&pages), ["/a", "/b", "/c"]);
+        let requests = logs.describe_log_groups_requests();
+        let tokens: Vec<_> = requests.iter().map(|r| r.next_token.as_deref()).collect();
+        assert_eq!(tokens, [None, Some("t1"), Some("t2")]);
+        assert!(requests
+            .iter()
+            .all(|r| r.name_prefix.as_deref() == Some("/")));
+        assert_eq!(limiter.acquired(), 3);
+    }
+
+    #[tokio::test]
+    async fn pages_stop_at_the_first_error() {
+        let logs = FakeLogs::new();
+        logs.push_describe_log_groups(Ok(page(&["/a"], Some("t1"))))
+            .push_describe_log_groups(Err(service_error::<DescribeLogGroupsError>(
+                "AccessDeniedException",
+                "not allowed",
+            )))
+            .push_describe_log_groups(Ok(page(&["/never"], None)));
+        let limiter = RateLimiter::new(100);
+
+        let pages: Vec<_> =
+            describe_log_groups_pages(&logs, &limiter, DescribeLogGroupsRequest::default())
+                .collect()
+                .await;
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(names(&[pages[0].as_ref().unwrap().clone()]), ["/a"]);
+        let err = pages[1].as_ref().unwrap_err();
+        assert_eq!(
+            classify_error(err.as_service_error().unwrap()),
+            ApiErrorKind::AccessDenied
+        );
+        assert_eq!(logs.describe_log_groups_requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn an_empty_queue_answers_with_an_empty_page() {
+        let logs = FakeLogs::new();
+        let request = FilterLogEventsRequest {
+            group_name: "/a".to_string(),
+            ..Default::default()
+        };
+
+        let output = logs.filter_log_events(request.clone()).await.unwrap();
+
+        assert!(output.events().is_empty());
+        assert_eq!(output.next_token(), None);
+        assert_eq!(logs.filter_log_events_requests(), [request]);
+    }
+
+    #[tokio::test]
+    async fn service_errors_carry_their_code_and_message() {
+        let logs = FakeLogs::new().with_region("eu-west-1");
+        logs.push_filter_log_events(Err(service_error::<FilterLogEventsError>(
+            "ResourceNotFoundException",
+            "The specified log group does not exist.",
+        )));
+
+        let err = logs
+            .filter_log_events(FilterLogEventsRequest::default())
+            .await
+            .unwrap_err();
+
+        let service_err = err.as_service_error().unwrap();
+        assert_eq!(classify_error(service_err), ApiErrorKind::ResourceNotFound);
+        assert_eq!(
+            aws_sdk_cloudwatchlogs::error::ProvideErrorMetadata::message(service_err),
+            Some("The specified log group does not exist.")
+        );
+        assert_eq!(logs.region().as_deref(), Some("eu-west-1"));
+    }
+
+    #[tokio::test]
+    async fn clones_share_their_queues() {
+        let logs = FakeLogs::new();
+        let clone = logs.clone();
+        logs.push_describe_log_groups(Ok(page(&["/a"], None)));
+
+        let output = clone
+            .describe_log_groups(DescribeLogGroupsRequest::default())
+            .await
+            .unwrap();
+
+        assert_eq!(names(&[output]), ["/a"]);
+        assert_eq!(logs.describe_log_groups_requests().len(), 1);
+    }
+}
@@ -0,0 +1,249 @@
+/// How a term of an unstructured filter pattern takes part in the match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TermKind {
+    /// `term`, the event must contain it.
+    Required,
+    /// `?term`, the event must contain at least one of these.
+    Optional,
+    /// `-term`, the event must not contain it.
+    Excluded,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Term {
+    kind: TermKind,
+    /// The term as written without its `?` or `-`, quotes included for phrases.
+    text: String,
+}
+
+/// A single `--filter` pattern reduced to what can be OR-ed with other patterns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Alternatives {
+    /// Terms or quoted phrases where any one of them matches.
+    Terms(Vec<String>),
+    /// The body of a `{ ... }` JSON pattern.
+    Json(String),
+}
+
+/// Combines several filter patterns into one that matches an event when any of them does, ref:
+/// https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/FilterAndPatternSyntax.html
+///
+/// Single terms, quoted phrases and `?term` lists become one `?term` list, JSON patterns are
+/// joined with `||`. Anything the syntax has no way to OR is rejected with the reason why.
+pub fn combine_or(patterns: &[String]) -> eyre::Result<String> {
+    if let [pattern] = patterns {
+        return Ok(pattern.clone());
+    }
+
+    let alternatives = patterns
+        .iter()
+        .map(|pattern| alternatives(pattern))
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    if alternatives
+        .iter()
+        .all(|a| matches!(a, Alternatives::Json(_)))
+    {
+        let bodies = alternatives
+            .iter()
+            .filter_map(|a| match a {
+                Alternatives::Json(body) => Some(format!("({})", body)),
+                Alternatives::Terms(_) => None,
+            })
+            .collect::<Vec<_>>();
+        return Ok(format!("{{ {} }}", bodies.join(" || ")));
+    }
+
+    let mut terms: Vec<String> = Vec::new();
+    for alternative in alternatives {
+        let Alternatives::Terms(alternative) = alternative else {
+            return Err(eyre::eyre!(
+                "Can't combine JSON and text filter patterns, CloudWatch only ORs patterns of the same kind. Use `cw query` to search for both at once."
+            ));
+        };
+        for term in alternative {
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+    }
+
+    Ok(terms
+        .iter()
+        .map(|term| format!("?{}", term))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+fn alternatives(pattern: &str) -> eyre::Result<Alternatives> {
+    let trimmed = pattern.trim();
+
+    if trimmed.is_empty() {
+        return Err(eyre::eyre!(
+            "An empty filter pattern matches every event, so OR-ing it with other patterns would too"
+        ));
+    }
+
+    if let Some(body) = trimmed.strip_prefix('{') {
+        let Some(body) = body.strip_suffix('}') else {
+            return Err(eyre::eyre!(
+                "Invalid JSON filter pattern '{}', expected it to end with }}",
+                pattern
+            ));
+        };
+        return Ok(Alternatives::Json(body.trim().to_string()));
+    }
+
+    if trimmed.starts_with('[') {
+        return Err(eyre::eyre!(
+            "Can't OR the space-delimited filter pattern '{}', its fields are matched by position and the syntax has no OR across patterns. Pass it as the only --filter.",
+            pattern
+        ));
+    }
+
+    if trimmed.starts_with('%') {
+        return Err(eyre::eyre!(
+            "Can't OR the regex filter pattern '{}', a filter pattern holds a single regex. Use one regex with | between the alternatives instead.",
+            pattern
+        ));
+    }
+
+    let terms = terms(trimmed)?;
+    if terms.iter().any(|t| t.kind == TermKind::Excluded) {
+        return Err(eyre::eyre!(
+            "Can't OR the filter pattern '{}', excluded terms (-term) only work together with the terms they narrow down. Pass it as the only --filter.",
+            pattern
+        ));
+    }
+
+    match terms.as_slice() {
+        [term] => Ok(Alternatives::Terms(vec![term.text.clone()])),
+        terms if terms.iter().all(|t| t.kind == TermKind::Optional) => Ok(Alternatives::Terms(
+            terms.iter().map(|t| t.text.clone()).collect(),
+        )),
+        _ => Err(eyre::eyre!(
+            "Can't OR the filter pattern '{}', it matches events containing all of its terms and the syntax can't group those. Pass a single term or \"quoted phrase\" per --filter.",
+            pattern
+        )),
+    }
+}
+
+/// Splits an unstructured pattern into its terms, keeping quoted phrases together.
+fn terms(pattern: &str) -> eyre::Result<Vec<Term>> {
+    let mut terms = Vec::new();
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let kind = match c {
+            '?' => TermKind::Optional,
+            '-' => TermKind::Excluded,
+            _ => TermKind::Required,
+        };
+        if kind != TermKind::Required {
+            chars.next();
+        }
+
+        let mut text = String::new();
+        if chars.peek() == Some(&'"') {
+            text.push('"');
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => {
+                        return Err(eyre::eyre!(
+                            "Unterminated quoted phrase in filter pattern '{}'",
+                            pattern
+                        ))
+                    }
+                    Some('\\') => {
+                        text.push('\\');
+                        if let Some(escaped) = chars.next() {
+                            text.push(escaped);
+                        }
+                    }
+                    Some('"') => {
+                        text.push('"');
+                        break;
+                    }
+                    Some(c) => text.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+        }
+
+        if text.is_empty() || text == "\"\"" {
+            return Err(eyre::eyre!("Empty term in filter pattern '{}'", pattern));
+        }
+        terms.push(Term { kind, text });
+    }
+
+    Ok(terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter_pattern::combine_or;
+
+    fn combine(patterns: &[&str]) -> eyre::Result<String> {
+        combine_or(&patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn a_single_pattern_is_kept_as_is() {
+        assert_eq!(
+            combine(&["ERROR -healthcheck"]).unwrap(),
+            "ERROR -healthcheck"
+        );
+        assert_eq!(combine(&["[ip, user]"]).unwrap(), "[ip, user]");
+    }
+
+    #[test]
+    fn terms_and_phrases_become_one_optional_list() {
+        assert_eq!(
+            combine(&["ERROR", "\"time out\"", "?WARN ?FATAL", "ERROR"]).unwrap(),
+            "?ERROR ?\"time out\" ?WARN ?FATAL"
+        );
+        assert_eq!(
+            combine(&["\"say \\\"hi\\\"\"", "bye"]).unwrap(),
+            "?\"say \\\"hi\\\"\" ?bye"
+        );
+    }
+
+    #[test]
+    fn json_patterns_are_joined_with_or() {
+        assert_eq!(
+            combine(&["{ $.level = \"error\" }", "{$.status >= 500}"]).unwrap(),
+            "{ ($.level = \"error\") || ($.status >= 500) }"
+        );
+    }
+
+    #[test]
+    fn patterns_without_an_or_are_refused() {
+        let refused = [
+            ["ERROR", "{ $.level = \"error\" }"],
+            ["ERROR", ""],
+            ["ERROR", "{ $.level = 1"],
+            ["ERROR", "[ip, user]"],
+            ["ERROR", "%err(or)?%"],
+            ["ERROR", "WARN -healthcheck"],
+            ["ERROR", "WARN timeout"],
+            ["ERROR", "\"time out"],
+            ["ERROR", "?\"\""],
+        ];
+        for patterns in refused {
+            assert!(combine(&patterns).is_err(), "{:?}", patterns);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt::Display;
+
+use crate::utils::parse_number;
+
+/// A named shortcut for a CloudWatch Logs filter pattern, ref:
+/// https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/FilterAndPatternSyntax.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterPreset {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    expansion: Expansion,
+}
+
+/// How a preset turns into a filter pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expansion {
+    /// A fixed pattern, the preset takes no argument.
+    Pattern(&'static str),
+    /// Built from a `<field>=<value>` argument.
+    JsonField,
+}
+
+pub const PRESETS: &[FilterPreset] = &[
+    FilterPreset {
+        name: "contains-error",
+        usage: "contains-error",
+        description: "Events containing ERROR, Error or error.",
+        expansion: Expansion::Pattern("?ERROR ?Error ?error"),
+    },
+    FilterPreset {
+        name: "contains-exception",
+        usage: "contains-exception",
+        description: "Events containing Exception or exception.",
+        expansion: Expansion::Pattern("?Exception ?exception"),
+    },
+    FilterPreset {
+        name: "json-level-error",
+        usage: "json-level-error",
+        description: "JSON events with a level field set to error or ERROR.",
+        expansion: Expansion::Pattern(r#"{ $.level = "error" || $.level = "ERROR" }"#),
+    },
+    FilterPreset {
+        name: "json-status-5xx",
+        usage: "json-status-5xx",
+        description: "JSON events with a numeric status field between 500 and 599.",
+        expansion: Expansion::Pattern("{ $.status >= 500 && $.status <= 599 }"),
+    },
+    FilterPreset {
+        name: "json-field",
+        usage: "json-field:<field>=<value>",
+        description:
+            "JSON events where the given field equals the value, numbers are compared numerically.",
+        expansion: Expansion::JsonField,
+    },
+    FilterPreset {
+        name: "lambda-timeout",
+        usage: "lambda-timeout",
+        description: "Lambda invocations that timed out.",
+        expansion: Expansion::Pattern(r#""Task timed out""#),
+    },
+    FilterPreset {
+        name: "lambda-report",
+        usage: "lambda-report",
+        description: "Lambda REPORT lines with duration and memory statistics.",
+        expansion: Expansion::Pattern(r#""REPORT RequestId""#),
+    },
+];
+
+impl Display for FilterPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{}", self.usage, self.description)
+    }
+}
+
+/// Expands a preset reference (`name` or `name:argument`) into a filter pattern.
+pub fn expand(preset: &str) -> eyre::Result<String> {
+    let (name, argument) = match preset.split_once(':') {
+        Some((name, argument)) => (name.trim(), Some(argument.trim())),
+        None => (preset.trim(), None),
+    };
+
+    let Some(found) = PRESETS.iter().find(|p| p.name == name) else {
+        return Err(eyre::eyre!(
+            "Unknown filter preset '{}', available presets: {}",
+            name,
+            PRESETS
+                .iter()
+                .map(|p| p.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    };
+
+    match (found.expansion, argument) {
+        (Expansion::Pattern(pattern), None) => Ok(pattern.to_string()),
+        (Expansion::Pattern(_), Some(_)) => Err(eyre::eyre!(
+            "Filter preset '{}' does not take an argument",
+            found.name
+        )),
+        (Expansion::JsonField, Some(argument)) => expand_json_field(argument),
+        (Expansion::JsonField, None) => Err(eyre::eyre!("Usage: {}", found.usage)),
+    }
+}
+
+fn expand_json_field(argument: &str) -> eyre::Result<String> {
+    let Some((field, value)) = argument.split_once('=') else {
+        return Err(eyre::eyre!(
+            "Invalid json-field argument '{}', expected <field>=<value>",
+            argument
+        ));
+    };
+
+    let field = field.trim().trim_start_matches("$.");
+    let value = value.trim();
+
+    let valid_field = !field.is_empty()
+        && field
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '[' | ']'));
+    if !valid_field {
+        return Err(eyre::eyre!("Invalid JSON field name '{}'", field));
+    }
+
+    if parse_number(value).is_some() {
+        Ok(format!("{{ $.{} = {} }}", field, value))
+    } else {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        Ok(format!("{{ $.{} = \"{}\" }}", field, escaped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter_presets::{expand, PRESETS};
+
+    fn expand_err(preset: &str) -> String {
+        expand(preset).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn presets_without_argument_expand_to_their_pattern() {
+        let expanded: Vec<_> = PRESETS
+            .iter()
+            .filter(|p| p.name != "json-field")
+            .map(|p| (p.name, expand(p.name).unwrap()))
+            .collect();
+
+        assert_eq!(
+            expanded,
+            [
+                ("contains-error", "?ERROR ?Error ?error".to_string()),
+                ("contains-exception", "?Exception ?exception".to_string()),
+                (
+                    "json-level-error",
+                    r#"{ $.level = "error" || $.level = "ERROR" }"#.to_string()
+                ),
+                (
+                    "json-status-5xx",
+                    "{ $.status >= 500 && $.status <= 599 }".to_string()
+                ),
+                ("lambda-timeout", r#""Task timed out""#.to_string()),
+                ("lambda-report", r#""REPORT RequestId""#.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_field_compares_numbers_numerically_and_quotes_text() {
+        assert_eq!(
+            expand("json-field:status=404").unwrap(),
+            "{ $.status = 404 }"
+        );
+        assert_eq!(
+            expand(" json-field : $.user.name = a \"b\" ").unwrap(),
+            r#"{ $.user.name = "a \"b\"" }"#
+        );
+    }
+
+    #[test]
+    fn json_field_rejects_a_bad_argument() {
+        assert_eq!(
+            expand_err("json-field"),
+            "Usage: json-field:<field>=<value>"
+        );
+        assert_eq!(
+            expand_err("json-field:status"),
+            "Invalid json-field argument 'status', expected <field>=<value>"
+        );
+        assert_eq!(
+            expand_err("json-field:a b=1"),
+            "Invalid JSON field name 'a b'"
+        );
+    }
+
+    #[test]
+    fn an_argument_to_a_preset_without_one_is_rejected() {
+        assert_eq!(
+            expand_err("contains-error:x"),
+            "Filter preset 'contains-error' does not take an argument"
+        );
+    }
+
+    #[test]
+    fn an_unknown_preset_lists_the_available_ones() {
+        assert_eq!(
+            expand_err("nope"),
+            "Unknown filter preset 'nope', available presets: contains-error, contains-exception, \
+             json-level-error, json-status-5xx, json-field, lambda-timeout, lambda-report"
+        );
+    }
+}
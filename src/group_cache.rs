@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+
+use crate::aws::LogClientBuilder;
+use crate::db::{CacheScope, Database};
+use crate::logs_api::LogsApi;
+
+// How long after a full listing the cache is trusted without listing again.
+const GROUP_CACHE_TTL: chrono::TimeDelta = chrono::TimeDelta::minutes(10);
+
+/// The log group names seen by earlier listings, per profile and region. The cache is a
+/// convenience, failing to read or write it never fails a command.
+pub struct GroupCache<D> {
+    db: D,
+    scope: CacheScope,
+}
+
+impl<D: Database> GroupCache<D> {
+    /// A cache for the profile and region `client` was built for.
+    pub fn new(db: D, builder: &LogClientBuilder, client: &impl LogsApi) -> Self {
+        Self {
+            db,
+            scope: CacheScope {
+                profile: builder.profile_name().unwrap_or_default(),
+                region: client.region().unwrap_or_default(),
+            },
+        }
+    }
+
+    pub async fn cached(&self) -> Vec<String> {
+        self.db
+            .cached_groups(&self.scope)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!(target: "cw", "failed reading the group cache: {:?}", err);
+                Vec::new()
+            })
+    }
+
+    /// Whether a full listing finished less than 10 minutes ago.
+    pub async fn is_fresh(&self) -> bool {
+        match self.db.groups_refreshed_at(&self.scope).await {
+            Ok(Some(refreshed_at)) => Utc::now() - refreshed_at < GROUP_CACHE_TTL,
+            Ok(None) => false,
+            Err(err) => {
+                tracing::warn!(target: "cw", "failed reading the group cache: {:?}", err);
+                false
+            }
+        }
+    }
+
+    pub async fn record(&self, names: &[String]) {
+        if let Err(err) = self.db.cache_groups(&self.scope, names).await {
+            tracing::warn!(target: "cw", "failed writing the group cache: {:?}", err);
+        }
+    }
+
+    pub async fn finish_refresh(&self, started_at: DateTime<Utc>) {
+        if let Err(err) = self.db.finish_group_refresh(&self.scope, started_at).await {
+            tracing::warn!(target: "cw", "failed writing the group cache: {:?}", err);
+        }
+    }
+}
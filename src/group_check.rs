@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::pin::pin;
+
+use eyre::Context;
+use futures_util::{StreamExt, TryStreamExt};
+
+use crate::aws::find_log_group;
+use crate::db::Database;
+use crate::group_cache::GroupCache;
+use crate::logs_api::{describe_log_groups_pages, DescribeLogGroupsRequest, LogsApi};
+use crate::rate_limit::RateLimiter;
+
+// NOTE: The most DescribeLogGroups returns per page, one page of neighbours is plenty to find a
+// typo in.
+const NEIGHBOURS_PAGE_LIMIT: i32 = 50;
+
+/// Fails with a did-you-mean message when any of `names` is not an existing log group. Names a
+/// fresh group cache knows about are taken as is, the others are looked up, see
+/// [`existing_groups`].
+pub async fn ensure_groups_exist(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    cache: &GroupCache<impl Database>,
+    names: &[String],
+) -> eyre::Result<()> {
+    let known = if cache.is_fresh().await {
+        cache.cached().await
+    } else {
+        Vec::new()
+    };
+
+    let unknown: Vec<&str> = names
+        .iter()
+        .filter(|name| !known.contains(*name))
+        .map(String::as_str)
+        .collect();
+    let existing = existing_groups(client, limiter, cache, &unknown).await?;
+    let missing: Vec<String> = unknown
+        .into_iter()
+        .filter(|name| !existing.contains(name))
+        .map(str::to_string)
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(missing_groups_error(client, limiter, cache, &missing).await)
+}
+
+/// The ones of `names` that exist. Names under the same path are looked up together, with a
+/// DescribeLogGroups listing of the prefix they share. The listing comes sorted by name, so it
+/// ends at the last name of the batch, and after as many pages as the batch has names, what
+/// looking them up one by one costs. Names it didn't get to are looked up one by one.
+async fn existing_groups<'a>(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    cache: &GroupCache<impl Database>,
+    names: &[&'a str],
+) -> eyre::Result<Vec<&'a str>> {
+    let mut batches: BTreeMap<Option<&str>, Vec<&'a str>> = BTreeMap::new();
+    for name in names {
+        batches.entry(path_of(name)).or_default().push(*name);
+    }
+
+    let mut existing = Vec::with_capacity(names.len());
+    for (path, batch) in batches {
+        let listing = match (path, batch.iter().copied().reduce(shared_prefix)) {
+            (Some(_), Some(prefix)) if batch.len() > 1 => {
+                list_batch(client, limiter, prefix, &batch).await?
+            }
+            _ => BatchListing::default(),
+        };
+        if !listing.names.is_empty() {
+            cache.record(&listing.names).await;
+        }
+
+        for name in batch {
+            let listed = listing.names.iter().any(|listed| listed == name);
+            if listed
+                || (!listing.covers(name) && find_log_group(client, limiter, name).await?.is_some())
+            {
+                existing.push(name);
+            }
+        }
+    }
+    Ok(existing)
+}
+
+/// The groups a listing for a batch of names found.
+#[derive(Debug, Default)]
+struct BatchListing {
+    names: Vec<String>,
+    /// Every group up to and including this name was listed.
+    up_to: Option<String>,
+    /// The listing reached its last page.
+    complete: bool,
+}
+
+impl BatchListing {
+    /// Whether the listing would have found `name` if it existed.
+    fn covers(&self, name: &str) -> bool {
+        self.complete || self.up_to.as_deref().is_some_and(|up_to| name <= up_to)
+    }
+}
+
+async fn list_batch(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    prefix: &str,
+    batch: &[&str],
+) -> eyre::Result<BatchListing> {
+    let last = batch.iter().copied().max().unwrap_or(prefix);
+    let request = DescribeLogGroupsRequest {
+        name_prefix: Some(prefix.to_string()),
+        ..Default::default()
+    };
+    let mut pages = pin!(describe_log_groups_pages(client, limiter, request).take(batch.len()));
+
+    let mut listing = BatchListing::default();
+    while let Some(page) = pages
+        .try_next()
+        .await
+        .wrap_err_with(|| format!("Failed listing the log groups starting with {}.", prefix))?
+    {
+        listing.names.extend(
+            page.log_groups()
+                .iter()
+                .filter_map(|group| group.log_group_name().map(str::to_string)),
+        );
+        listing.up_to = listing.names.last().cloned();
+        listing.complete = page.next_token().is_none();
+        if listing.complete || listing.covers(last) {
+            break;
+        }
+    }
+    Ok(listing)
+}
+
+/// The path of a group name up to its last `/`, e.g. `/aws/lambda/` for a Lambda group.
+fn path_of(name: &str) -> Option<&str> {
+    name.rfind('/')
+        .and_then(|end| name.get(..=end))
+        .filter(|path| *path != "/")
+}
+
+/// The longest start `a` and `b` have in common.
+fn shared_prefix<'a>(a: &'a str, b: &'a str) -> &'a str {
+    let len = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|((_, ca), cb)| ca != cb)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i);
+    a.get(..len).unwrap_or_default()
+}
+
+/// The error for log groups that don't exist, with the closest existing name for each. Looking
+/// for suggestions never fails, without any the error just says the group doesn't exist.
+pub async fn missing_groups_error(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    cache: &GroupCache<impl Database>,
+    missing: &[String],
+) -> eyre::Report {
+    let mut candidates = cache.cached().await;
+    let mut lines = Vec::with_capacity(missing.len());
+
+    for name in missing {
+        if did_you_mean(name, &candidates).is_none() {
+            candidates.extend(neighbours(client, limiter, cache, name).await);
+        }
+
+        match did_you_mean(name, &candidates) {
+            Some(suggestion) => lines.push(format!(
+                "Log group {} does not exist, did you mean {}?",
+                name, suggestion
+            )),
+            None => lines.push(format!("Log group {} does not exist.", name)),
+        }
+    }
+
+    eyre::eyre!(lines.join("\n"))
+}
+
+/// A page of the groups under the same path as `name`, e.g. `/aws/lambda/` for a Lambda group.
+/// They are recorded in the cache, so the next typo doesn't need the call.
+async fn neighbours(
+    client: &impl LogsApi,
+    limiter: &RateLimiter,
+    cache: &GroupCache<impl Database>,
+    name: &str,
+) -> Vec<String> {
+    let prefix = path_of(name).map(str::to_string);
+
+    limiter.acquire().await;
+    let response = client
+        .describe_log_groups(DescribeLogGroupsRequest {
+            name_prefix: prefix,
+            limit: Some(NEIGHBOURS_PAGE_LIMIT),
+            ..Default::default()
+        })
+        .await;
+
+    match response {
+        Ok(response) => {
+            let names: Vec<String> = response
+                .log_groups()
+                .iter()
+                .filter_map(|group| group.log_group_name().map(str::to_string))
+                .collect();
+            cache.record(&names).await;
+            names
+        }
+        Err(err) => {
+            tracing::debug!(target: "cw", "failed listing groups near {}: {:?}", name, err);
+            Vec::new()
+        }
+    }
+}
+
+/// The candidate `name` most likely meant. A candidate that `name` is the start of wins, a name
+/// cut short is the most common slip, otherwise the one the fewest edits away. Case is ignored
+/// when counting edits and a candidate more than a fifth of the name away, or 2 edits for short
+/// names, is never suggested.
+fn did_you_mean<'a>(name: &str, candidates: &'a [String]) -> Option<&'a str> {
+    let extended = candidates
+        .iter()
+        .filter(|candidate| candidate.len() > name.len() && candidate.starts_with(name))
+        .min_by_key(|candidate| candidate.len());
+    if let Some(candidate) = extended {
+        return Some(candidate);
+    }
+
+    let max_distance = (name.chars().count() / 5).max(2);
+    let lowercase = name.to_lowercase();
+    candidates
+        .iter()
+        .filter(|candidate| candidate.as_str() != name)
+        .map(|candidate| {
+            (
+                edit_distance(&lowercase, &candidate.to_lowercase()),
+                candidate,
+            )
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// The Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::group_check::{did_you_mean, edit_distance, path_of, shared_prefix, BatchListing};
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn edit_distance_counts_characters() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("fucntion", "function"), 2);
+        assert_eq!(edit_distance("über", "uber"), 1);
+    }
+
+    #[test]
+    fn did_you_mean_prefers_the_shortest_extension() {
+        let candidates = names(&[
+            "/aws/lambda/api-prod-v2",
+            "/aws/lambda/api-prod",
+            "/aws/lambda/apx",
+        ]);
+        assert_eq!(
+            did_you_mean("/aws/lambda/api", &candidates),
+            Some("/aws/lambda/api-prod")
+        );
+    }
+
+    #[test]
+    fn did_you_mean_picks_the_closest_typo() {
+        let candidates = names(&["/aws/lambda/my-function", "/aws/lambda/other"]);
+        assert_eq!(
+            did_you_mean("/aws/lambda/my-fucntion", &candidates),
+            Some("/aws/lambda/my-function")
+        );
+        assert_eq!(
+            did_you_mean("/AWS/Lambda/My-Function", &candidates),
+            Some("/aws/lambda/my-function")
+        );
+    }
+
+    #[test]
+    fn did_you_mean_skips_distant_names() {
+        let candidates = names(&["/aws/lambda/payments", "app"]);
+        assert_eq!(did_you_mean("/aws/lambda/orders", &candidates), None);
+        assert_eq!(did_you_mean("api", &names(&["api"])), None);
+    }
+
+    #[test]
+    fn path_of_stops_at_the_last_slash() {
+        assert_eq!(path_of("/aws/lambda/api"), Some("/aws/lambda/"));
+        assert_eq!(path_of("team/app"), Some("team/"));
+        assert_eq!(path_of("/app"), None);
+        assert_eq!(path_of("app"), None);
+    }
+
+    #[test]
+    fn shared_prefix_is_the_common_start() {
+        assert_eq!(
+            shared_prefix("/aws/lambda/api", "/aws/lambda/app"),
+            "/aws/lambda/ap"
+        );
+        assert_eq!(
+            shared_prefix("/aws/lambda/api", "/aws/lambda/api-v2"),
+            "/aws/lambda/api"
+        );
+        assert_eq!(shared_prefix("a/é1", "a/é2"), "a/é");
+        assert_eq!(shared_prefix("a/b", "c/d"), "");
+    }
+
+    #[test]
+    fn batch_listing_covers_names_up_to_the_last_listed() {
+        let listing = BatchListing {
+            names: names(&["/aws/lambda/a", "/aws/lambda/c"]),
+            up_to: Some("/aws/lambda/c".to_string()),
+            complete: false,
+        };
+        assert!(listing.covers("/aws/lambda/b"));
+        assert!(listing.covers("/aws/lambda/c"));
+        assert!(!listing.covers("/aws/lambda/d"));
+
+        let complete = BatchListing {
+            complete: true,
+            ..listing
+        };
+        assert!(complete.covers("/aws/lambda/d"));
+        assert!(!BatchListing::default().covers("/aws/lambda/a"));
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use serde_json::{json, Value};
+
+use crate::utils::parse_timestamp;
+
+const BAR_WIDTH: u64 = 50;
+
+/// Counts events per fixed size time bucket, keyed by the start of the bucket in epoch
+/// milliseconds. Buckets are derived from event timestamps, so the order events arrive in
+/// doesn't matter.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bucket_ms: i64,
+    counts: BTreeMap<i64, u64>,
+}
+
+impl Histogram {
+    pub fn new(bucket: Duration) -> eyre::Result<Self> {
+        let bucket_ms = i64::try_from(bucket.as_millis())?;
+        if bucket_ms == 0 {
+            return Err(eyre::eyre!("The histogram bucket must be at least 1ms."));
+        }
+
+        Ok(Self {
+            bucket_ms,
+            counts: BTreeMap::new(),
+        })
+    }
+
+    /// Counts an event, returning the start of the bucket it landed in.
+    pub fn add(&mut self, timestamp: i64) -> i64 {
+        let bucket_start = timestamp.div_euclid(self.bucket_ms) * self.bucket_ms;
+        *self.counts.entry(bucket_start).or_default() += 1;
+        bucket_start
+    }
+
+    /// The start of the most recent bucket holding at least one event.
+    pub fn latest(&self) -> Option<i64> {
+        self.counts.keys().next_back().copied()
+    }
+
+    pub fn count(&self, bucket_start: i64) -> u64 {
+        self.counts.get(&bucket_start).copied().unwrap_or(0)
+    }
+
+    /// Every bucket from the first to the last one with events, empty buckets included so gaps
+    /// show up in the chart.
+    pub fn buckets(&self) -> Vec<(i64, u64)> {
+        let (Some(first), Some(last)) = (self.counts.keys().next(), self.latest()) else {
+            return Vec::new();
+        };
+
+        (*first..=last)
+            .step_by(self.bucket_ms as usize)
+            .map(|start| (start, self.count(start)))
+            .collect()
+    }
+
+    /// The last `n` buckets up to and including the latest one, empty buckets included.
+    pub fn last_buckets(&self, n: usize) -> Vec<(i64, u64)> {
+        let Some(last) = self.latest() else {
+            return Vec::new();
+        };
+
+        let first = last.saturating_sub(self.bucket_ms * (n.saturating_sub(1) as i64));
+        let first = self.counts.keys().next().map_or(first, |f| first.max(*f));
+        (first..=last)
+            .step_by(self.bucket_ms as usize)
+            .map(|start| (start, self.count(start)))
+            .collect()
+    }
+}
+
+/// Renders `buckets` as an ASCII bar chart, one line per bucket with its start, its count and a
+/// bar scaled to the largest count among `buckets`.
+pub fn render_text(buckets: &[(i64, u64)], use_local_time: bool) -> String {
+    let max = buckets.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    let count_width = max.to_string().len();
+
+    let mut output = String::new();
+    for (start, count) in buckets {
+        let time = parse_timestamp(*start, use_local_time).unwrap_or_else(|| start.to_string());
+        let bar = if max == 0 {
+            0
+        } else {
+            // NOTE: Round up, so a bucket with any events always gets a visible bar.
+            (count * BAR_WIDTH).div_ceil(max)
+        };
+
+        let line = format!(
+            "{}  {:>width$}  {}",
+            time,
+            count,
+            "#".repeat(bar as usize),
+            width = count_width
+        );
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+pub fn bucket_json(bucket_start: i64, count: u64, use_local_time: bool) -> Value {
+    json!({
+        "bucket_start": parse_timestamp(bucket_start, use_local_time),
+        "count": count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::histogram::{bucket_json, render_text, Histogram};
+
+    #[test]
+    fn new_refuses_buckets_under_a_millisecond() {
+        assert!(Histogram::new(Duration::ZERO).is_err());
+        assert!(Histogram::new(Duration::from_micros(999)).is_err());
+        assert!(Histogram::new(Duration::from_millis(1)).is_ok());
+    }
+
+    #[test]
+    fn add_counts_events_in_the_bucket_they_start_in() {
+        let mut histogram = Histogram::new(Duration::from_secs(60)).unwrap();
+        assert_eq!(histogram.add(60_000), 60_000);
+        assert_eq!(histogram.add(119_999), 60_000);
+        assert_eq!(histogram.add(120_000), 120_000);
+        // NOTE: Events before the epoch still land in the bucket below them.
+        assert_eq!(histogram.add(-1), -60_000);
+
+        assert_eq!(histogram.count(60_000), 2);
+        assert_eq!(histogram.count(120_000), 1);
+        assert_eq!(histogram.count(180_000), 0);
+        assert_eq!(histogram.latest(), Some(120_000));
+    }
+
+    #[test]
+    fn buckets_fill_the_gaps() {
+        let mut histogram = Histogram::new(Duration::from_secs(1)).unwrap();
+        assert!(histogram.buckets().is_empty());
+
+        histogram.add(3_500);
+        histogram.add(1_200);
+        histogram.add(1_700);
+        assert_eq!(
+            histogram.buckets(),
+            vec![(1_000, 2), (2_000, 0), (3_000, 1)]
+        );
+    }
+
+    #[test]
+    fn last_buckets_stops_at_the_first_bucket_with_events() {
+        let mut histogram = Histogram::new(Duration::from_secs(1)).unwrap();
+        assert!(histogram.last_buckets(3).is_empty());
+
+        histogram.add(2_000);
+        histogram.add(5_000);
+        assert_eq!(histogram.last_buckets(2), vec![(4_000, 0), (5_000, 1)]);
+        assert_eq!(
+            histogram.last_buckets(10),
+            vec![(2_000, 1), (3_000, 0), (4_000, 0), (5_000, 1)]
+        );
+        assert_eq!(histogram.last_buckets(0), vec![(5_000, 1)]);
+    }
+
+    #[test]
+    fn render_text_scales_bars_to_the_largest_count() {
+        let text = render_text(&[(0, 10), (60_000, 1), (120_000, 0)], false);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!("1970-01-01T00:00:00Z  10  {}", "#".repeat(50))
+        );
+        assert_eq!(lines[1], "1970-01-01T00:01:00Z   1  #####");
+        assert_eq!(lines[2], "1970-01-01T00:02:00Z   0");
+        assert_eq!(render_text(&[], false), "");
+    }
+
+    #[test]
+    fn bucket_json_has_the_start_and_count() {
+        assert_eq!(
+            bucket_json(60_000, 3, false),
+            json!({ "bucket_start": "1970-01-01T00:01:00Z", "count": 3 })
+        );
+    }
+}
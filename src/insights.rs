@@ -0,0 +1,548 @@
+use std::time::Duration;
+
+use crate::utils::{group_thousands, parse_human_time};
+
+// NOTE: Logs Insights rejects query strings longer than 10,000 characters, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_StartQuery.html#CWL-StartQuery-request-queryString
+pub const MAX_QUERY_LENGTH: usize = 10_000;
+
+/// Removes lines whose first non-whitespace character is `#`. Lines that start inside a quoted
+/// string spanning multiple lines are left alone, so a `#` within a string literal survives. A
+/// quote inside a `/…/` regex literal doesn't open a string.
+pub fn strip_comments(query: &str) -> String {
+    let mut output = String::with_capacity(query.len());
+    let mut quote: Option<char> = None;
+
+    for line in query.split_inclusive('\n') {
+        if quote.is_none() && line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        quote = scan_quotes(line, quote);
+        output.push_str(line);
+    }
+
+    output
+}
+
+/// The literal a position in a query is inside of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Literal {
+    Quoted(char),
+    Regex,
+}
+
+/// Tracks which quote (if any) is still open at the end of `line`.
+fn scan_quotes(line: &str, quote: Option<char>) -> Option<char> {
+    let mut literal = quote.map(Literal::Quoted);
+    let mut escaped = false;
+    let mut previous = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match literal {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(Literal::Quoted(open)) if c == open => literal = None,
+            Some(Literal::Regex) if c == '/' => literal = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' || c == '`' => literal = Some(Literal::Quoted(c)),
+            None if c == '/' && starts_regex(previous, chars.peek().copied()) => {
+                literal = Some(Literal::Regex)
+            }
+            None => {}
+        }
+        previous = Some(c);
+    }
+
+    // NOTE: A regex literal can't span lines, one left open ends with its line.
+    match literal {
+        Some(Literal::Quoted(open)) => Some(open),
+        Some(Literal::Regex) | None => None,
+    }
+}
+
+/// Whether a `/` between `previous` and `next` opens a regex literal instead of dividing. A
+/// division follows its operand, as in `sum(bytes)/1024`, or stands apart, as in `a / b`, while
+/// a regex follows a keyword or operator and starts right away, as in `like /it's/`.
+fn starts_regex(previous: Option<char>, next: Option<char>) -> bool {
+    let after_operand =
+        previous.is_some_and(|c| c.is_alphanumeric() || matches!(c, ')' | ']' | '_' | '@' | '.'));
+    !after_operand && next.is_some_and(|c| !c.is_whitespace())
+}
+
+/// Prepares a query for submission: strips comments (unless `keep_comments` is set), trims
+/// surrounding whitespace and enforces the Insights query length limit.
+pub fn sanitize(query: &str, keep_comments: bool) -> eyre::Result<String> {
+    let query = if keep_comments {
+        query.trim().to_string()
+    } else {
+        strip_comments(query).trim().to_string()
+    };
+
+    if query.is_empty() {
+        return Err(eyre::eyre!("Query is empty!"));
+    }
+
+    let length = query.chars().count();
+    if length > MAX_QUERY_LENGTH {
+        return Err(eyre::eyre!(
+            "Query is {} characters long, Logs Insights accepts at most {}.",
+            length,
+            MAX_QUERY_LENGTH
+        ));
+    }
+
+    Ok(query)
+}
+
+/// Defaults read from `#!` directives in the leading comment block of a query, e.g.
+/// `#! groups: /ecs/api, /ecs/worker` or `#! last: 1h`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FrontMatter {
+    pub groups: Vec<String>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub last: Option<Duration>,
+    pub limit: Option<i32>,
+    /// Directives that were not recognized, so the caller can warn about them.
+    pub unknown: Vec<String>,
+}
+
+/// Parses the `#!` directives at the top of `query`, returning them together with the query
+/// without the directive lines. Parsing stops at the first line that isn't blank or a comment.
+pub fn parse_front_matter(query: &str) -> eyre::Result<(FrontMatter, String)> {
+    let mut front_matter = FrontMatter::default();
+    let mut body = String::with_capacity(query.len());
+    let mut in_header = true;
+
+    for line in query.split_inclusive('\n') {
+        let trimmed = line.trim();
+        in_header = in_header && (trimmed.is_empty() || trimmed.starts_with('#'));
+
+        let Some(directive) = trimmed.strip_prefix("#!").filter(|_| in_header) else {
+            body.push_str(line);
+            continue;
+        };
+
+        let (key, value) = directive.split_once(':').unwrap_or((directive, ""));
+        let (key, value) = (key.trim(), value.trim());
+        let invalid = |e: String| eyre::eyre!("Invalid '#! {}' directive: {}", key, e);
+
+        match key {
+            "groups" => front_matter.groups.extend(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|g| !g.is_empty())
+                    .map(String::from),
+            ),
+            "start" => {
+                front_matter.start =
+                    Some(parse_human_time(value).map_err(|e| invalid(e.to_string()))?)
+            }
+            "end" => {
+                front_matter.end =
+                    Some(parse_human_time(value).map_err(|e| invalid(e.to_string()))?)
+            }
+            "last" => {
+                front_matter.last =
+                    Some(humantime::parse_duration(value).map_err(|e| invalid(e.to_string()))?)
+            }
+            "limit" => {
+                front_matter.limit = Some(
+                    value
+                        .parse()
+                        .map_err(|_| invalid(format!("expected a number, got '{}'", value)))?,
+                )
+            }
+            _ => front_matter.unknown.push(key.to_string()),
+        }
+    }
+
+    Ok((front_matter, body))
+}
+
+// NOTE: Insights returns at most 10,000 rows per query, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_StartQuery.html#CWL-StartQuery-request-limit
+pub const MAX_RESULT_ROWS: u32 = 10_000;
+
+/// The commands of a query that decide how many rows it returns.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct QueryShape {
+    pub has_limit: bool,
+    pub has_stats: bool,
+}
+
+/// Scans the `|` separated commands of `query` for `limit` and `stats`. Pipes inside quoted
+/// strings are skipped, the scan doesn't go further than that.
+pub fn query_shape(query: &str) -> QueryShape {
+    let mut shape = QueryShape::default();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut command = String::new();
+
+    let mut inspect = |command: &str| match command
+        .split_whitespace()
+        .next()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("limit") => shape.has_limit = true,
+        Some("stats") => shape.has_stats = true,
+        _ => {}
+    };
+
+    for c in query.chars() {
+        if quote.is_none() && c == '|' {
+            inspect(&command);
+            command.clear();
+            continue;
+        }
+
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' || c == '`' => quote = Some(c),
+            None => {}
+        }
+        command.push(c);
+    }
+    inspect(&command);
+
+    shape
+}
+
+/// Appends `| limit <limit>` to a query that doesn't limit its results yet. Aggregating queries
+/// are refused, a limit after `stats` caps the groups rather than the matched events.
+pub fn ensure_limit(query: &str, limit: u32) -> eyre::Result<String> {
+    let shape = query_shape(query);
+    if shape.has_limit {
+        return Ok(query.to_string());
+    }
+
+    if shape.has_stats {
+        return Err(eyre::eyre!(
+            "--ensure-limit can't be used with a stats query, add a limit to the query itself."
+        ));
+    }
+
+    Ok(format!("{}\n| limit {}", query.trim_end(), limit))
+}
+
+/// Explains why fewer rows came back than the query matched, if they did. Stats queries are
+/// never reported, their matched count is the number of input events rather than rows.
+pub fn truncation_warning(shape: QueryShape, returned: i64, matched: f64) -> Option<String> {
+    if shape.has_stats || (returned as f64) >= matched {
+        return None;
+    }
+
+    let hint = if shape.has_limit {
+        "raise the limit of the query"
+    } else {
+        "add a `| limit` to the query (or use --ensure-limit)"
+    };
+    Some(format!(
+        "Showing {} of {} matched records. Logs Insights returns at most {} rows per query, {} or narrow the time window to see the rest.",
+        group_thousands(returned.max(0) as u64),
+        group_thousands(matched as u64),
+        group_thousands(MAX_RESULT_ROWS as u64),
+        hint
+    ))
+}
+
+/// What the editor opens with when it has field hints, a starter query to edit.
+pub const STARTER_QUERY: &str = "fields @timestamp, @message\n| sort @timestamp desc\n| limit 20\n";
+
+// NOTE: Keeps the hint comments readable in a terminal sized editor.
+const HINT_LINE_WIDTH: usize = 100;
+
+/// The comment lines listing `fields`, each a name and the percentage of events that have it,
+/// e.g. `# available fields: @timestamp, @message, level, requestId (84%)`. Fields are listed
+/// most common first, the percentage is left out for fields every event has.
+pub fn field_hints(fields: &[(String, i32)]) -> String {
+    if fields.is_empty() {
+        return String::new();
+    }
+
+    let mut fields = fields.to_vec();
+    fields.sort_by(|(a_name, a_percent), (b_name, b_percent)| {
+        b_percent.cmp(a_percent).then_with(|| a_name.cmp(b_name))
+    });
+
+    let mut lines = vec!["# available fields:".to_string()];
+    for (i, (name, percent)) in fields.iter().enumerate() {
+        let mut field = if *percent >= 100 {
+            name.clone()
+        } else {
+            format!("{} ({}%)", name, percent)
+        };
+        if i + 1 < fields.len() {
+            field.push(',');
+        }
+
+        match lines.last_mut() {
+            Some(line) if i == 0 || line.len() + 1 + field.len() <= HINT_LINE_WIDTH => {
+                line.push(' ');
+                line.push_str(&field);
+            }
+            _ => lines.push(format!("#   {}", field)),
+        }
+    }
+
+    let mut output = lines.join("\n");
+    output.push('\n');
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::insights::{
+        ensure_limit, parse_front_matter, query_shape, sanitize, strip_comments,
+        truncation_warning, FrontMatter, QueryShape, MAX_QUERY_LENGTH,
+    };
+
+    #[test]
+    fn sanitize_trims_and_strips_comments() {
+        let query = "# vim: ft=lq\n\n  fields @message\n| limit 5\n\n";
+
+        assert_eq!(
+            sanitize(query, false).unwrap(),
+            "fields @message\n| limit 5"
+        );
+        assert_eq!(
+            sanitize(query, true).unwrap(),
+            "# vim: ft=lq\n\n  fields @message\n| limit 5"
+        );
+    }
+
+    #[test]
+    fn sanitize_rejects_empty_queries() {
+        for query in [
+            "",
+            " \n\t\n",
+            "# vim: ft=lq\n",
+            "# owner: team-x\n  # nothing else\n",
+        ] {
+            let err = sanitize(query, false).unwrap_err();
+            assert_eq!(err.to_string(), "Query is empty!", "for {:?}", query);
+        }
+    }
+
+    #[test]
+    fn sanitize_enforces_the_length_limit_on_what_is_sent() {
+        let longest = format!("fields {}", "x".repeat(MAX_QUERY_LENGTH - 7));
+        assert_eq!(sanitize(&longest, false).unwrap().len(), MAX_QUERY_LENGTH);
+
+        // NOTE: Comments and surrounding whitespace don't count, they are never sent.
+        let commented = format!("# {}\n{}\n\n", "c".repeat(500), longest);
+        assert!(sanitize(&commented, false).is_ok());
+
+        let err = sanitize(&format!("{}x", longest), false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Query is 10001 characters long, Logs Insights accepts at most 10000."
+        );
+    }
+
+    #[test]
+    fn comment_lines_go_and_hashes_in_strings_stay() {
+        let query = "# owner: team-x\nfields @message\n  # why\n| filter @message like \"a#b\"\n";
+
+        assert_eq!(
+            strip_comments(query),
+            "fields @message\n| filter @message like \"a#b\"\n"
+        );
+    }
+
+    #[test]
+    fn a_string_spanning_lines_keeps_its_hash_lines() {
+        let query = "filter @message = 'first\n# not a comment'\n# a comment\n| limit 1";
+
+        assert_eq!(
+            strip_comments(query),
+            "filter @message = 'first\n# not a comment'\n| limit 1"
+        );
+    }
+
+    #[test]
+    fn a_quote_inside_a_regex_does_not_open_a_string() {
+        let query = "filter @message like /it's/\n# a comment\n| limit 5";
+        assert_eq!(
+            strip_comments(query),
+            "filter @message like /it's/\n| limit 5"
+        );
+
+        let escaped = "filter @message =~ /a\\/'b/\n# a comment\n| limit 5";
+        assert_eq!(
+            strip_comments(escaped),
+            "filter @message =~ /a\\/'b/\n| limit 5"
+        );
+    }
+
+    #[test]
+    fn comments_next_to_a_parse_regex_with_hashes_are_stripped() {
+        let query = "# issue ids\nparse @message /#(?<issue>\\d+) \"(?<title>[^\"]*)\"/\n# then\n| fields issue";
+
+        assert_eq!(
+            strip_comments(query),
+            "parse @message /#(?<issue>\\d+) \"(?<title>[^\"]*)\"/\n| fields issue"
+        );
+    }
+
+    #[test]
+    fn a_division_is_not_a_regex() {
+        let query = "stats sum(bytes)/1024 by 'kb\n# inside the string'\n# a comment\n| limit 1";
+
+        assert_eq!(
+            strip_comments(query),
+            "stats sum(bytes)/1024 by 'kb\n# inside the string'\n| limit 1"
+        );
+    }
+
+    #[test]
+    fn query_shape_finds_limit_and_stats_commands() {
+        assert_eq!(
+            query_shape("fields @message | LIMIT 20"),
+            QueryShape {
+                has_limit: true,
+                has_stats: false
+            }
+        );
+        assert_eq!(
+            query_shape("filter level = 'error'\n| stats count(*) by bin(5m)"),
+            QueryShape {
+                has_limit: false,
+                has_stats: true
+            }
+        );
+        assert_eq!(
+            query_shape("limit 5"),
+            QueryShape {
+                has_limit: true,
+                has_stats: false
+            }
+        );
+    }
+
+    #[test]
+    fn query_shape_skips_pipes_in_strings() {
+        assert_eq!(
+            query_shape(r#"filter @message like "a | limit 5" | filter x = 'b \' | stats'"#),
+            QueryShape::default()
+        );
+    }
+
+    #[test]
+    fn query_shape_ignores_fields_named_like_commands() {
+        assert_eq!(
+            query_shape("fields limit, stats | sort limit desc"),
+            QueryShape::default()
+        );
+    }
+
+    #[test]
+    fn ensure_limit_appends_a_limit() {
+        assert_eq!(
+            ensure_limit("fields @message\n| sort @timestamp desc\n", 100).unwrap(),
+            "fields @message\n| sort @timestamp desc\n| limit 100"
+        );
+    }
+
+    #[test]
+    fn ensure_limit_keeps_an_existing_limit() {
+        let query = "fields @message | limit 5";
+        assert_eq!(ensure_limit(query, 100).unwrap(), query);
+        assert_eq!(
+            ensure_limit("stats count(*) | limit 5", 100).unwrap(),
+            "stats count(*) | limit 5"
+        );
+    }
+
+    #[test]
+    fn ensure_limit_refuses_stats_queries() {
+        assert!(ensure_limit("stats count(*) by level", 100).is_err());
+    }
+
+    #[test]
+    fn truncation_warning_only_when_rows_are_missing() {
+        let shape = QueryShape::default();
+        assert_eq!(truncation_warning(shape, 20, 20.0), None);
+        assert_eq!(
+            truncation_warning(
+                QueryShape {
+                    has_limit: false,
+                    has_stats: true
+                },
+                10,
+                5000.0
+            ),
+            None
+        );
+
+        let warning = truncation_warning(shape, 10_000, 25_000.0).unwrap();
+        assert!(warning.starts_with("Showing 10,000 of 25,000 matched records."));
+        assert!(warning.contains("add a `| limit`"));
+    }
+
+    #[test]
+    fn parse_front_matter_reads_the_directives_of_the_leading_comments() {
+        let query = concat!(
+            "# Errors of the API\n",
+            "#! groups: /ecs/api, /ecs/worker,\n",
+            "\n",
+            "#! start: 2024-06-01T12:00:00Z\n",
+            "#! end: 2024-06-01T13:00:00Z\n",
+            "#!last:1h 30m\n",
+            "#! limit: 50\n",
+            "#! owner: team-api\n",
+            "fields @message\n",
+            "#! limit: 10\n",
+        );
+
+        let (front_matter, body) = parse_front_matter(query).unwrap();
+
+        assert_eq!(
+            front_matter,
+            FrontMatter {
+                groups: vec!["/ecs/api".to_string(), "/ecs/worker".to_string()],
+                start: Some(1_717_243_200_000),
+                end: Some(1_717_246_800_000),
+                last: Some(Duration::from_secs(90 * 60)),
+                limit: Some(50),
+                unknown: vec!["owner".to_string()],
+            }
+        );
+        assert_eq!(
+            body,
+            "# Errors of the API\n\nfields @message\n#! limit: 10\n"
+        );
+    }
+
+    #[test]
+    fn parse_front_matter_without_directives_keeps_the_query() {
+        let query = "# just a comment\nfields @message\n| limit 5";
+
+        let (front_matter, body) = parse_front_matter(query).unwrap();
+
+        assert_eq!(front_matter, FrontMatter::default());
+        assert_eq!(body, query);
+    }
+
+    #[test]
+    fn parse_front_matter_rejects_malformed_values() {
+        let err = |query: &str| parse_front_matter(query).unwrap_err().to_string();
+
+        assert_eq!(
+            err("#! limit: lots\nfields @message"),
+            "Invalid '#! limit' directive: expected a number, got 'lots'"
+        );
+        assert!(err("#! last: soon\n").starts_with("Invalid '#! last' directive: "));
+        assert!(err("#! start: yesterday-ish\n").starts_with("Invalid '#! start' directive: "));
+        assert!(err("#! end\n").starts_with("Invalid '#! end' directive: "));
+    }
+}
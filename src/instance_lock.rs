@@ -0,0 +1,186 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use eyre::Context;
+
+const LOCK_FILE: &str = "cw.lock";
+
+/// An advisory lock on the data directory, telling a cw process whether another one writes to
+/// the same database. It is an flock on Unix and LockFileEx on Windows. The OS releases it when
+/// the holder exits, so the lock a crashed process left behind is taken over by the next run.
+#[derive(Debug)]
+pub struct InstanceLock {
+    path: PathBuf,
+    state: LockState,
+}
+
+#[derive(Debug)]
+enum LockState {
+    /// This process holds the lock, for as long as the file stays open.
+    Held { _file: File },
+    /// Another process holds the lock, with its PID when it could be read.
+    Contended { pid: Option<u32> },
+}
+
+impl InstanceLock {
+    /// Takes the lock in `data_dir`, or finds out which process holds it.
+    pub fn acquire(data_dir: &Path) -> eyre::Result<Self> {
+        let path = data_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .wrap_err_with(|| format!("Failed opening the lock file {}", path.display()))?;
+
+        // NOTE: Windows doesn't allow reading a locked file, the holder is unknown there.
+        let previous = read_pid(&mut file);
+        let state = match file.try_lock() {
+            Ok(()) => {
+                if let Some(pid) = previous.filter(|pid| *pid != std::process::id()) {
+                    tracing::debug!(target: "cw", "taking over the lock pid {} left behind", pid);
+                }
+                write_pid(&mut file)
+                    .wrap_err_with(|| format!("Failed writing the lock file {}", path.display()))?;
+                LockState::Held { _file: file }
+            }
+            Err(TryLockError::WouldBlock) => LockState::Contended { pid: previous },
+            Err(TryLockError::Error(err)) => {
+                return Err(
+                    eyre::Report::new(err).wrap_err(format!("Failed locking {}", path.display()))
+                )
+            }
+        };
+        Ok(Self { path, state })
+    }
+
+    /// Whether another cw process holds the lock.
+    pub fn is_contended(&self) -> bool {
+        matches!(self.state, LockState::Contended { .. })
+    }
+
+    /// The process holding the lock, e.g. `pid 4242`.
+    pub fn holder(&self) -> String {
+        match self.state {
+            LockState::Held { .. } => format!("pid {}", std::process::id()),
+            LockState::Contended { pid: Some(pid) } => format!("pid {}", pid),
+            LockState::Contended { pid: None } => "another process".to_string(),
+        }
+    }
+
+    /// The lock as `cw info` prints it.
+    pub fn status(&self) -> String {
+        match self.state {
+            LockState::Held { .. } => format!("held by this process ({})", self.holder()),
+            LockState::Contended { .. } => format!(
+                "held by {}, history writes are retried ({})",
+                self.holder(),
+                self.path.display()
+            ),
+        }
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+fn write_pid(file: &mut File) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", std::process::id())?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+
+    use crate::instance_lock::InstanceLock;
+
+    const HOLDER_DIR: &str = "CW_TEST_LOCK_HOLDER_DIR";
+
+    fn lock_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cw-lock-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn a_second_holder_in_the_same_process_is_contended() {
+        let dir = lock_dir("same-process");
+
+        let first = InstanceLock::acquire(&dir).unwrap();
+        let second = InstanceLock::acquire(&dir).unwrap();
+
+        assert!(!first.is_contended());
+        assert!(second.is_contended());
+        // NOTE: Windows doesn't allow reading a locked file, the holder is unknown there.
+        if cfg!(unix) {
+            assert_eq!(second.holder(), format!("pid {}", std::process::id()));
+        }
+
+        drop(first);
+        drop(second);
+        assert!(!InstanceLock::acquire(&dir).unwrap().is_contended());
+    }
+
+    /// Run by [`another_process_holding_the_lock_is_contended`], holds the lock until stdin closes.
+    #[test]
+    #[ignore = "run as a child process"]
+    fn hold_the_lock_until_stdin_closes() {
+        let Some(dir) = std::env::var_os(HOLDER_DIR) else {
+            return;
+        };
+        let lock = InstanceLock::acquire(Path::new(&dir)).unwrap();
+        assert!(!lock.is_contended());
+        println!("\nholding the lock");
+        std::io::stdout().flush().unwrap();
+
+        let _ = std::io::stdin().read_line(&mut String::new());
+    }
+
+    #[test]
+    fn another_process_holding_the_lock_is_contended() {
+        let dir = lock_dir("other-process");
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "instance_lock::tests::hold_the_lock_until_stdin_closes",
+                "--ignored",
+                "--nocapture",
+            ])
+            .env(HOLDER_DIR, &dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        while !line.contains("holding the lock") {
+            line.clear();
+            assert_ne!(
+                stdout.read_line(&mut line).unwrap(),
+                0,
+                "holder exited early"
+            );
+        }
+
+        let contended = InstanceLock::acquire(&dir).unwrap();
+        assert!(contended.is_contended());
+        if cfg!(unix) {
+            assert_eq!(contended.holder(), format!("pid {}", child.id()));
+        }
+
+        drop(child.stdin.take());
+        assert!(child.wait().unwrap().success());
+        assert!(!InstanceLock::acquire(&dir).unwrap().is_contended());
+    }
+}
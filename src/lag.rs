@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+// How far a follow may fall behind the newest events before it is worth a warning.
+const LAG_WARNING_MS: i64 = 60 * 1000;
+
+// NOTE: While behind, the warning is repeated with the current lag, a single line scrolls out
+// of view while the events keep coming.
+const LAG_WARNING_INTERVAL_MS: i64 = 30 * 1000;
+
+/// What a producer should do about how far behind it is, see [`LagTracker::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagAction {
+    None,
+    /// Behind by `lag` milliseconds, tell the user.
+    Warn {
+        lag: i64,
+    },
+    /// Back within the warning threshold after a warning.
+    CaughtUp,
+    /// Behind by more than `--max-lag`, continue from `to` and leave the `skipped` milliseconds
+    /// in between out.
+    Skip {
+        to: i64,
+        skipped: i64,
+    },
+}
+
+/// Follows the lag of a single producer, the wall clock minus the newest event timestamp it
+/// fetched. The lag only counts once the producer caught up with the present a first time, a
+/// backfill from hours ago isn't falling behind.
+#[derive(Debug)]
+pub struct LagTracker {
+    max_lag: Option<i64>,
+    live: bool,
+    warned_at: Option<i64>,
+}
+
+impl LagTracker {
+    pub fn new(max_lag: Option<Duration>) -> Self {
+        Self {
+            max_lag: max_lag.map(|max| i64::try_from(max.as_millis()).unwrap_or(i64::MAX)),
+            live: false,
+            warned_at: None,
+        }
+    }
+
+    /// Checks a page whose newest event is from `newest`, at `now`, both in epoch milliseconds.
+    /// `has_next_token` tells whether more pages are waiting.
+    pub fn check(&mut self, newest: i64, now: i64, has_next_token: bool) -> LagAction {
+        if !self.live {
+            self.live = !has_next_token;
+            return LagAction::None;
+        }
+
+        let lag = now.saturating_sub(newest);
+        if self.max_lag.is_some_and(|max| lag > max) {
+            self.warned_at = None;
+            return LagAction::Skip {
+                to: now,
+                skipped: lag,
+            };
+        }
+
+        if lag < LAG_WARNING_MS {
+            return match self.warned_at.take() {
+                Some(_) => LagAction::CaughtUp,
+                None => LagAction::None,
+            };
+        }
+
+        match self.warned_at {
+            Some(at) if now.saturating_sub(at) < LAG_WARNING_INTERVAL_MS => LagAction::None,
+            _ => {
+                self.warned_at = Some(now);
+                LagAction::Warn { lag }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::lag::{LagAction, LagTracker};
+
+    const NOW: i64 = 1_717_250_591_000;
+
+    /// A tracker that already caught up with the present.
+    fn live(max_lag: Option<Duration>) -> LagTracker {
+        let mut tracker = LagTracker::new(max_lag);
+        assert_eq!(tracker.check(NOW, NOW, false), LagAction::None);
+        tracker
+    }
+
+    #[test]
+    fn a_backfill_is_not_lag() {
+        let mut tracker = LagTracker::new(Some(Duration::from_secs(60)));
+
+        assert_eq!(tracker.check(0, NOW, true), LagAction::None);
+        assert_eq!(tracker.check(1_000, NOW, true), LagAction::None);
+        assert_eq!(tracker.check(NOW - 90_000, NOW, false), LagAction::None);
+        assert_eq!(
+            tracker.check(NOW - 90_000, NOW, false),
+            LagAction::Skip {
+                to: NOW,
+                skipped: 90_000
+            }
+        );
+    }
+
+    #[test]
+    fn lag_is_only_worth_a_warning_from_a_minute() {
+        let mut tracker = live(None);
+
+        assert_eq!(tracker.check(NOW - 59_999, NOW, false), LagAction::None);
+        assert_eq!(
+            tracker.check(NOW - 60_000, NOW, false),
+            LagAction::Warn { lag: 60_000 }
+        );
+    }
+
+    #[test]
+    fn the_warning_repeats_every_30_seconds_while_behind() {
+        let mut tracker = live(None);
+
+        assert_eq!(
+            tracker.check(NOW - 70_000, NOW, false),
+            LagAction::Warn { lag: 70_000 }
+        );
+        assert_eq!(
+            tracker.check(NOW - 70_000, NOW + 29_999, false),
+            LagAction::None
+        );
+        assert_eq!(
+            tracker.check(NOW - 70_000, NOW + 30_000, false),
+            LagAction::Warn { lag: 100_000 }
+        );
+        assert_eq!(
+            tracker.check(NOW + 30_000, NOW + 31_000, false),
+            LagAction::CaughtUp
+        );
+        assert_eq!(
+            tracker.check(NOW + 31_000, NOW + 32_000, false),
+            LagAction::None
+        );
+    }
+
+    #[test]
+    fn beyond_max_lag_the_gap_is_skipped() {
+        let mut tracker = live(Some(Duration::from_secs(120)));
+
+        assert_eq!(
+            tracker.check(NOW - 120_000, NOW, false),
+            LagAction::Warn { lag: 120_000 }
+        );
+        assert_eq!(
+            tracker.check(NOW - 120_001, NOW, false),
+            LagAction::Skip {
+                to: NOW,
+                skipped: 120_001
+            }
+        );
+        // NOTE: A skip clears the warning, so there is nothing to catch up from.
+        assert_eq!(tracker.check(NOW, NOW, false), LagAction::None);
+    }
+}
@@ -0,0 +1,272 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde_json::{json, Value};
+
+// NOTE: The Lambda runtime wraps every invocation in START/END/REPORT lines, ref:
+// https://docs.aws.amazon.com/lambda/latest/dg/monitoring-cloudwatchlogs-logformat.html
+const START_PATTERN: &str = r"^START RequestId: (\S+)";
+const END_PATTERN: &str = r"^END RequestId: \S+";
+const REPORT_PATTERN: &str = r"^REPORT RequestId: (\S+)\s+Duration: ([\d.]+) ms\s+Billed Duration: (\d+) ms\s+Memory Size: (\d+) MB\s+Max Memory Used: (\d+) MB(?:\s+Init Duration: ([\d.]+) ms)?";
+
+pub const LAMBDA_GROUP_PREFIX: &str = "/aws/lambda/";
+
+// NOTE: Every day in the range gets a prefix and a producer of its own, keep that reasonable.
+const MAX_VERSION_PREFIX_DAYS: u64 = 31;
+
+/// The version in a Lambda stream name, `$LATEST` or a published version number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LambdaVersion {
+    Latest,
+    Published(u64),
+}
+
+impl std::str::FromStr for LambdaVersion {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> eyre::Result<Self> {
+        match value.trim() {
+            "$LATEST" | "LATEST" | "latest" => Ok(Self::Latest),
+            version => version.parse().map(Self::Published).map_err(|_| {
+                eyre::eyre!(
+                    "Invalid Lambda version '{}', expected $LATEST or a version number",
+                    value
+                )
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for LambdaVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LambdaVersion::Latest => write!(f, "$LATEST"),
+            LambdaVersion::Published(version) => write!(f, "{}", version),
+        }
+    }
+}
+
+/// The stream name prefixes for `version` between `start_time` and `end_time`. Lambda names its
+/// streams `yyyy/mm/dd/[version]id` after the UTC day the execution environment started, so the
+/// version is only part of a prefix together with a day, one prefix per day in the range. The day
+/// before the range is included too, an environment started then can still be logging.
+pub fn version_stream_prefixes(
+    version: &LambdaVersion,
+    start_time: i64,
+    end_time: i64,
+) -> eyre::Result<Vec<String>> {
+    let day = |ts: i64| {
+        DateTime::<Utc>::from_timestamp_millis(ts)
+            .map(|time| time.date_naive())
+            .ok_or_else(|| eyre::eyre!("{} is not a valid timestamp", ts))
+    };
+    let (first, last) = (day(start_time)?, day(end_time)?);
+
+    let days = (last - first).num_days();
+    if days < 0 || days as u64 >= MAX_VERSION_PREFIX_DAYS {
+        return Err(eyre::eyre!(
+            "--lambda-version covers at most {} days, narrow down the time range",
+            MAX_VERSION_PREFIX_DAYS
+        ));
+    }
+
+    let before = first.pred_opt().unwrap_or(first);
+    Ok(before
+        .iter_days()
+        .take_while(|day| *day <= last)
+        .map(|day| format!("{}/[{}]", day.format("%Y/%m/%d"), version))
+        .collect())
+}
+
+/// The metrics Lambda prints in the REPORT line at the end of an invocation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportMetrics {
+    pub request_id: String,
+    pub duration_ms: f64,
+    pub billed_duration_ms: u64,
+    pub memory_size_mb: u64,
+    pub max_memory_used_mb: u64,
+    pub init_duration_ms: Option<f64>,
+}
+
+impl ReportMetrics {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "request_id": self.request_id,
+            "duration_ms": self.duration_ms,
+            "billed_duration_ms": self.billed_duration_ms,
+            "memory_size_mb": self.memory_size_mb,
+            "max_memory_used_mb": self.max_memory_used_mb,
+            "init_duration_ms": self.init_duration_ms,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Boundary {
+    Start { request_id: String },
+    End,
+    Report(ReportMetrics),
+}
+
+/// Recognizes the invocation boundary lines written by the Lambda runtime.
+#[derive(Debug, Clone)]
+pub struct BoundaryMatcher {
+    start: Regex,
+    end: Regex,
+    report: Regex,
+}
+
+impl BoundaryMatcher {
+    pub fn new() -> eyre::Result<Self> {
+        Ok(Self {
+            start: Regex::new(START_PATTERN)?,
+            end: Regex::new(END_PATTERN)?,
+            report: Regex::new(REPORT_PATTERN)?,
+        })
+    }
+
+    pub fn classify(&self, message: &str) -> Option<Boundary> {
+        if let Some(captures) = self.report.captures(message) {
+            let number = |i: usize| captures.get(i).and_then(|m| m.as_str().parse().ok());
+            let float = |i: usize| captures.get(i).and_then(|m| m.as_str().parse().ok());
+
+            return Some(Boundary::Report(ReportMetrics {
+                request_id: captures.get(1)?.as_str().to_string(),
+                duration_ms: float(2)?,
+                billed_duration_ms: number(3)?,
+                memory_size_mb: number(4)?,
+                max_memory_used_mb: number(5)?,
+                init_duration_ms: float(6),
+            }));
+        }
+
+        if let Some(captures) = self.start.captures(message) {
+            return Some(Boundary::Start {
+                request_id: captures.get(1)?.as_str().to_string(),
+            });
+        }
+
+        self.end.is_match(message).then_some(Boundary::End)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use crate::lambda::{version_stream_prefixes, Boundary, BoundaryMatcher, LambdaVersion};
+
+    fn millis(value: &str) -> i64 {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+            .unwrap()
+            .and_utc()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn lambda_version_parses_latest_and_numbers() {
+        assert_eq!(
+            "$LATEST".parse::<LambdaVersion>().unwrap(),
+            LambdaVersion::Latest
+        );
+        assert_eq!(
+            "latest".parse::<LambdaVersion>().unwrap(),
+            LambdaVersion::Latest
+        );
+        assert_eq!(
+            " 12 ".parse::<LambdaVersion>().unwrap(),
+            LambdaVersion::Published(12)
+        );
+        assert!("v1".parse::<LambdaVersion>().is_err());
+        assert!("-1".parse::<LambdaVersion>().is_err());
+
+        assert_eq!(LambdaVersion::Latest.to_string(), "$LATEST");
+        assert_eq!(LambdaVersion::Published(3).to_string(), "3");
+    }
+
+    #[test]
+    fn version_stream_prefixes_start_the_day_before() {
+        let prefixes = version_stream_prefixes(
+            &LambdaVersion::Latest,
+            millis("2024-03-01 10:00:00"),
+            millis("2024-03-01 11:00:00"),
+        )
+        .unwrap();
+        assert_eq!(prefixes, ["2024/02/29/[$LATEST]", "2024/03/01/[$LATEST]"]);
+    }
+
+    #[test]
+    fn version_stream_prefixes_cover_every_day_of_the_range() {
+        let prefixes = version_stream_prefixes(
+            &LambdaVersion::Published(7),
+            millis("2023-12-31 23:00:00"),
+            millis("2024-01-02 00:00:00"),
+        )
+        .unwrap();
+        assert_eq!(
+            prefixes,
+            [
+                "2023/12/30/[7]",
+                "2023/12/31/[7]",
+                "2024/01/01/[7]",
+                "2024/01/02/[7]"
+            ]
+        );
+    }
+
+    #[test]
+    fn version_stream_prefixes_limit_the_range() {
+        let first = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let at = |days: u64| {
+            first
+                .checked_add_days(chrono::Days::new(days))
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp_millis()
+        };
+
+        let longest = version_stream_prefixes(&LambdaVersion::Latest, at(0), at(30)).unwrap();
+        assert_eq!(longest.len(), 32);
+        assert!(version_stream_prefixes(&LambdaVersion::Latest, at(0), at(31)).is_err());
+        assert!(version_stream_prefixes(&LambdaVersion::Latest, at(1), at(0)).is_err());
+        assert!(version_stream_prefixes(&LambdaVersion::Latest, i64::MAX, i64::MAX).is_err());
+    }
+
+    #[test]
+    fn boundary_matcher_reads_the_report_line() {
+        let matcher = BoundaryMatcher::new().unwrap();
+        let line = "REPORT RequestId: 8f5a-11\tDuration: 102.25 ms\tBilled Duration: 103 ms\tMemory Size: 128 MB\tMax Memory Used: 71 MB\tInit Duration: 250.51 ms";
+
+        let Some(Boundary::Report(metrics)) = matcher.classify(line) else {
+            panic!("not a report: {}", line);
+        };
+        assert_eq!(metrics.request_id, "8f5a-11");
+        assert_eq!(metrics.duration_ms, 102.25);
+        assert_eq!(metrics.billed_duration_ms, 103);
+        assert_eq!(metrics.memory_size_mb, 128);
+        assert_eq!(metrics.max_memory_used_mb, 71);
+        assert_eq!(metrics.init_duration_ms, Some(250.51));
+
+        let warm = "REPORT RequestId: 9 Duration: 1.00 ms Billed Duration: 1 ms Memory Size: 128 MB Max Memory Used: 70 MB";
+        let Some(Boundary::Report(metrics)) = matcher.classify(warm) else {
+            panic!("not a report: {}", warm);
+        };
+        assert_eq!(metrics.init_duration_ms, None);
+    }
+
+    #[test]
+    fn boundary_matcher_reads_start_and_end_lines() {
+        let matcher = BoundaryMatcher::new().unwrap();
+        assert_eq!(
+            matcher.classify("START RequestId: abc Version: $LATEST"),
+            Some(Boundary::Start {
+                request_id: "abc".to_string()
+            })
+        );
+        assert_eq!(matcher.classify("END RequestId: abc"), Some(Boundary::End));
+        assert_eq!(matcher.classify("processing START RequestId: abc"), None);
+        assert_eq!(matcher.classify("hello"), None);
+    }
+}
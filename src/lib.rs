@@ -0,0 +1,63 @@
+//! The building blocks of the `cw` command line tool, to read CloudWatch Logs from other Rust
+//! programs the way `cw` does.
+//!
+//! [`session::TailSession`] reads the events of log groups like `cw tail`,
+//! [`query_runner::QueryRunner`] runs Logs Insights queries like `cw query`. Both talk to
+//! CloudWatch Logs through the [`logs_api::LogsApi`] trait and share a [`rate_limit::RateLimiter`]
+//! to stay under the API limits. Clients configured from a profile and region, with cw's retries,
+//! come from [`aws::LogClientBuilder`]. With the `test-support` feature, [`fake_logs::FakeLogs`]
+//! stands in for CloudWatch Logs in tests.
+//!
+//! Everything else is how the `cw` binary works and is not part of the library, [`cli::main`] is
+//! its entry point.
+
+mod archive;
+mod assertion;
+mod audit;
+pub mod aws;
+mod build_info;
+mod chunked_export;
+pub mod cli;
+mod clock_skew;
+mod commands;
+pub mod compat;
+mod config;
+mod connection_stats;
+mod correlation;
+mod db;
+mod deadline;
+mod dedupe;
+mod editor;
+pub mod event;
+#[cfg(any(test, feature = "test-support"))]
+pub mod fake_logs;
+mod filter_pattern;
+mod filter_presets;
+mod group_cache;
+mod group_check;
+mod histogram;
+mod insights;
+mod instance_lock;
+mod lag;
+mod lambda;
+pub mod logs_api;
+#[cfg(test)]
+mod memory_db;
+mod metrics;
+mod multiline;
+mod notify;
+mod pipeline;
+mod progress;
+mod progress_reporter;
+pub mod query_runner;
+pub mod rate_limit;
+mod row_filter;
+pub mod session;
+mod stable_rows;
+mod summary;
+mod suspend;
+mod table;
+mod time_window;
+mod ui;
+mod update_check;
+mod utils;
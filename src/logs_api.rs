@@ -0,0 +1,286 @@
+use std::future::Future;
+
+use aws_sdk_cloudwatchlogs as cloudwatchlogs;
+use aws_sdk_cloudwatchlogs::error::SdkError;
+use aws_sdk_cloudwatchlogs::operation::delete_retention_policy::{
+    DeleteRetentionPolicyError, DeleteRetentionPolicyOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::describe_log_groups::{
+    DescribeLogGroupsError, DescribeLogGroupsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::describe_log_streams::{
+    DescribeLogStreamsError, DescribeLogStreamsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::filter_log_events::{
+    FilterLogEventsError, FilterLogEventsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::get_log_group_fields::{
+    GetLogGroupFieldsError, GetLogGroupFieldsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::get_query_results::{
+    GetQueryResultsError, GetQueryResultsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::put_retention_policy::{
+    PutRetentionPolicyError, PutRetentionPolicyOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::start_query::{StartQueryError, StartQueryOutput};
+use aws_sdk_cloudwatchlogs::operation::stop_query::{StopQueryError, StopQueryOutput};
+use aws_sdk_cloudwatchlogs::types::OrderBy;
+use futures_util::Stream;
+
+use crate::rate_limit::RateLimiter;
+
+/// A single FilterLogEvents page request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterLogEventsRequest {
+    pub group_name: String,
+    pub stream_name_prefix: Option<String>,
+    pub filter_pattern: Option<String>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub limit: Option<i32>,
+    pub next_token: Option<String>,
+}
+
+/// A single DescribeLogGroups page request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DescribeLogGroupsRequest {
+    pub name_prefix: Option<String>,
+    pub name_pattern: Option<String>,
+    /// Also return the groups of source accounts linked to a monitoring account.
+    pub include_linked_accounts: Option<bool>,
+    pub limit: Option<i32>,
+    pub next_token: Option<String>,
+}
+
+/// A single DescribeLogStreams page request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DescribeLogStreamsRequest {
+    pub group_identifier: String,
+    pub order_by: Option<OrderBy>,
+    pub descending: Option<bool>,
+    pub limit: Option<i32>,
+    pub next_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StartQueryRequest {
+    pub group_names: Vec<String>,
+    pub query: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub limit: Option<i32>,
+}
+
+/// The CloudWatch Logs operations cw depends on. Commands are written against this trait instead
+/// of the SDK client, so they can run against something other than AWS.
+pub trait LogsApi: Clone + Send + Sync + 'static {
+    /// The region requests are sent to, if one is configured.
+    fn region(&self) -> Option<String>;
+
+    fn filter_log_events(
+        &self,
+        request: FilterLogEventsRequest,
+    ) -> impl Future<Output = Result<FilterLogEventsOutput, SdkError<FilterLogEventsError>>> + Send;
+
+    fn describe_log_groups(
+        &self,
+        request: DescribeLogGroupsRequest,
+    ) -> impl Future<Output = Result<DescribeLogGroupsOutput, SdkError<DescribeLogGroupsError>>> + Send;
+
+    fn describe_log_streams(
+        &self,
+        request: DescribeLogStreamsRequest,
+    ) -> impl Future<Output = Result<DescribeLogStreamsOutput, SdkError<DescribeLogStreamsError>>> + Send;
+
+    fn start_query(
+        &self,
+        request: StartQueryRequest,
+    ) -> impl Future<Output = Result<StartQueryOutput, SdkError<StartQueryError>>> + Send;
+
+    fn get_query_results(
+        &self,
+        query_id: &str,
+    ) -> impl Future<Output = Result<GetQueryResultsOutput, SdkError<GetQueryResultsError>>> + Send;
+
+    /// Stops the running query `query_id`, Insights keeps the results it found so far.
+    fn stop_query(
+        &self,
+        query_id: &str,
+    ) -> impl Future<Output = Result<StopQueryOutput, SdkError<StopQueryError>>> + Send;
+
+    /// The fields Logs Insights discovered in `group_identifier` over the last 15 minutes.
+    fn get_log_group_fields(
+        &self,
+        group_identifier: &str,
+    ) -> impl Future<Output = Result<GetLogGroupFieldsOutput, SdkError<GetLogGroupFieldsError>>> + Send;
+
+    /// Keeps the events of `group_name` for `days`, one of the values CloudWatch accepts.
+    fn put_retention_policy(
+        &self,
+        group_name: &str,
+        days: i32,
+    ) -> impl Future<Output = Result<PutRetentionPolicyOutput, SdkError<PutRetentionPolicyError>>> + Send;
+
+    /// Keeps the events of `group_name` forever.
+    fn delete_retention_policy(
+        &self,
+        group_name: &str,
+    ) -> impl Future<
+        Output = Result<DeleteRetentionPolicyOutput, SdkError<DeleteRetentionPolicyError>>,
+    > + Send;
+}
+
+impl LogsApi for cloudwatchlogs::Client {
+    fn region(&self) -> Option<String> {
+        self.config().region().map(|r| r.to_string())
+    }
+
+    fn filter_log_events(
+        &self,
+        request: FilterLogEventsRequest,
+    ) -> impl Future<Output = Result<FilterLogEventsOutput, SdkError<FilterLogEventsError>>> + Send
+    {
+        self.filter_log_events()
+            .log_group_name(request.group_name)
+            .set_log_stream_name_prefix(request.stream_name_prefix)
+            .set_filter_pattern(request.filter_pattern)
+            .set_start_time(request.start_time)
+            .set_end_time(request.end_time)
+            .set_limit(request.limit)
+            .set_next_token(request.next_token)
+            .send()
+    }
+
+    fn describe_log_groups(
+        &self,
+        request: DescribeLogGroupsRequest,
+    ) -> impl Future<Output = Result<DescribeLogGroupsOutput, SdkError<DescribeLogGroupsError>>> + Send
+    {
+        self.describe_log_groups()
+            .set_log_group_name_prefix(request.name_prefix)
+            .set_log_group_name_pattern(request.name_pattern)
+            .set_include_linked_accounts(request.include_linked_accounts)
+            .set_limit(request.limit)
+            .set_next_token(request.next_token)
+            .send()
+    }
+
+    fn describe_log_streams(
+        &self,
+        request: DescribeLogStreamsRequest,
+    ) -> impl Future<Output = Result<DescribeLogStreamsOutput, SdkError<DescribeLogStreamsError>>> + Send
+    {
+        self.describe_log_streams()
+            .log_group_identifier(request.group_identifier)
+            .set_order_by(request.order_by)
+            .set_descending(request.descending)
+            .set_limit(request.limit)
+            .set_next_token(request.next_token)
+            .send()
+    }
+
+    fn start_query(
+        &self,
+        request: StartQueryRequest,
+    ) -> impl Future<Output = Result<StartQueryOutput, SdkError<StartQueryError>>> + Send {
+        self.start_query()
+            .set_log_group_names(Some(request.group_names))
+            .query_string(request.query)
+            .start_time(request.start_time)
+            .end_time(request.end_time)
+            .set_limit(request.limit)
+            .send()
+    }
+
+    fn get_query_results(
+        &self,
+        query_id: &str,
+    ) -> impl Future<Output = Result<GetQueryResultsOutput, SdkError<GetQueryResultsError>>> + Send
+    {
+        self.get_query_results().query_id(query_id).send()
+    }
+
+    fn stop_query(
+        &self,
+        query_id: &str,
+    ) -> impl Future<Output = Result<StopQueryOutput, SdkError<StopQueryError>>> + Send {
+        self.stop_query().query_id(query_id).send()
+    }
+
+    fn get_log_group_fields(
+        &self,
+        group_identifier: &str,
+    ) -> impl Future<Output = Result<GetLogGroupFieldsOutput, SdkError<GetLogGroupFieldsError>>> + Send
+    {
+        self.get_log_group_fields()
+            .log_group_identifier(group_identifier)
+            .send()
+    }
+
+    fn put_retention_policy(
+        &self,
+        group_name: &str,
+        days: i32,
+    ) -> impl Future<Output = Result<PutRetentionPolicyOutput, SdkError<PutRetentionPolicyError>>> + Send
+    {
+        self.put_retention_policy()
+            .log_group_name(group_name)
+            .retention_in_days(days)
+            .send()
+    }
+
+    fn delete_retention_policy(
+        &self,
+        group_name: &str,
+    ) -> impl Future<
+        Output = Result<DeleteRetentionPolicyOutput, SdkError<DeleteRetentionPolicyError>>,
+    > + Send {
+        self.delete_retention_policy()
+            .log_group_name(group_name)
+            .send()
+    }
+}
+
+/// Every DescribeLogGroups page of `request`, following the next tokens until the last page.
+/// Each request waits for `limiter` first. Dropping the stream stops before the next request.
+pub fn describe_log_groups_pages<'a>(
+    client: &'a impl LogsApi,
+    limiter: &'a RateLimiter,
+    request: DescribeLogGroupsRequest,
+) -> impl Stream<Item = Result<DescribeLogGroupsOutput, SdkError<DescribeLogGroupsError>>> + 'a {
+    futures_util::stream::try_unfold(Some(request), move |request| async move {
+        let Some(request) = request else {
+            return Ok(None);
+        };
+        limiter.acquire().await;
+        let response = client.describe_log_groups(request.clone()).await?;
+        let next = response.next_token().map(|token| DescribeLogGroupsRequest {
+            next_token: Some(token.to_string()),
+            ..request
+        });
+        Ok(Some((response, next)))
+    })
+}
+
+/// Every DescribeLogStreams page of `request`, see [`describe_log_groups_pages`].
+pub fn describe_log_streams_pages<'a>(
+    client: &'a impl LogsApi,
+    limiter: &'a RateLimiter,
+    request: DescribeLogStreamsRequest,
+) -> impl Stream<Item = Result<DescribeLogStreamsOutput, SdkError<DescribeLogStreamsError>>> + 'a {
+    futures_util::stream::try_unfold(Some(request), move |request| async move {
+        let Some(request) = request else {
+            return Ok(None);
+        };
+        limiter.acquire().await;
+        let response = client.describe_log_streams(request.clone()).await?;
+        let next = response
+            .next_token()
+            .map(|token| DescribeLogStreamsRequest {
+                next_token: Some(token.to_string()),
+                ..request
+            });
+        Ok(Some((response, next)))
+    })
+}
@@ -0,0 +1,320 @@
+//! A [`Database`] that keeps everything in memory, so code written against the trait can be
+//! tested without SQLite. It follows the SQLite implementation, except that archive text search
+//! only looks for the text in the messages instead of running an FTS5 query.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+
+use crate::db::{ArchiveQuery, ArchiveSummary, AuditEntry, CacheScope, Database, QueryHistory};
+use crate::event::LogEvent;
+
+#[derive(Debug)]
+struct ArchivedEvent {
+    archive: String,
+    event: LogEvent,
+    archived_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct CachedGroup {
+    scope: CacheScope,
+    name: String,
+    seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct Tables {
+    history: Vec<QueryHistory>,
+    audit: Vec<AuditEntry>,
+    // NOTE: Kept in insertion order, which stands in for the autoincrement id of SQLite.
+    archive_events: Vec<ArchivedEvent>,
+    group_cache: Vec<CachedGroup>,
+    group_cache_refresh: Vec<(CacheScope, DateTime<Utc>)>,
+}
+
+/// An in-memory [`Database`]. Clones share their tables, like clones of a pool share a database.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryDb {
+    tables: Arc<Mutex<Tables>>,
+}
+
+impl InMemoryDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // NOTE: A panic while holding the lock can only come from a test that already failed, the
+    // tables it left behind are still good enough to look at.
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Database for InMemoryDb {
+    type Settings = ();
+
+    async fn new(_settings: &Self::Settings) -> eyre::Result<Self> {
+        Ok(Self::default())
+    }
+
+    async fn version(&self) -> eyre::Result<String> {
+        Ok("memory".to_string())
+    }
+
+    fn engine(&self) -> &str {
+        "memory"
+    }
+
+    async fn save(&self, history: &QueryHistory) -> eyre::Result<()> {
+        let mut tables = self.tables();
+        if tables.history.iter().all(|h| h.id() != history.id()) {
+            tables.history.push(history.clone());
+        }
+        Ok(())
+    }
+
+    async fn update(&self, history: &QueryHistory) -> eyre::Result<()> {
+        let mut tables = self.tables();
+        if let Some(saved) = tables.history.iter_mut().find(|h| h.id() == history.id()) {
+            *saved = history.clone();
+        }
+        Ok(())
+    }
+
+    async fn list(&self) -> eyre::Result<Vec<QueryHistory>> {
+        Ok(self.tables().history.clone())
+    }
+
+    async fn get(&self, id: &str) -> eyre::Result<Option<QueryHistory>> {
+        Ok(self
+            .tables()
+            .history
+            .iter()
+            .find(|h| h.query_id == id || h.id() == id)
+            .cloned())
+    }
+
+    async fn flush(&self) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    async fn save_audit(&self, entries: &[AuditEntry]) -> eyre::Result<()> {
+        self.tables().audit.extend_from_slice(entries);
+        Ok(())
+    }
+
+    async fn list_audit(&self, since: DateTime<Utc>) -> eyre::Result<Vec<AuditEntry>> {
+        let mut entries: Vec<_> = self
+            .tables()
+            .audit
+            .iter()
+            .filter(|entry| entry.created_at >= since)
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| entry.created_at);
+        Ok(entries)
+    }
+
+    async fn prune_audit(&self, before: DateTime<Utc>) -> eyre::Result<u64> {
+        let mut tables = self.tables();
+        let count = tables.audit.len();
+        tables.audit.retain(|entry| entry.created_at >= before);
+        Ok((count - tables.audit.len()) as u64)
+    }
+
+    async fn save_archive(&self, archive: &str, events: &[LogEvent]) -> eyre::Result<()> {
+        let archived_at = Utc::now();
+        let mut tables = self.tables();
+        for event in events {
+            // NOTE: Like the unique constraint in SQLite, events without an id never collide.
+            let held = event.event_id.is_some()
+                && tables
+                    .archive_events
+                    .iter()
+                    .any(|a| a.archive == archive && a.event.event_id == event.event_id);
+            if !held {
+                tables.archive_events.push(ArchivedEvent {
+                    archive: archive.to_string(),
+                    event: event.clone(),
+                    archived_at,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn prune_archive(
+        &self,
+        archive: &str,
+        max_events: u64,
+        before: DateTime<Utc>,
+    ) -> eyre::Result<u64> {
+        let mut tables = self.tables();
+        let count = tables.archive_events.len();
+        tables
+            .archive_events
+            .retain(|a| a.archive != archive || a.archived_at >= before);
+
+        let held = tables
+            .archive_events
+            .iter()
+            .filter(|a| a.archive == archive)
+            .count();
+        let mut over_cap = held.saturating_sub(usize::try_from(max_events).unwrap_or(usize::MAX));
+        tables.archive_events.retain(|a| {
+            if a.archive != archive || over_cap == 0 {
+                return true;
+            }
+            over_cap -= 1;
+            false
+        });
+
+        Ok((count - tables.archive_events.len()) as u64)
+    }
+
+    async fn search_archive(
+        &self,
+        archive: &str,
+        query: &ArchiveQuery,
+    ) -> eyre::Result<Vec<LogEvent>> {
+        let tables = self.tables();
+        let mut events: Vec<&LogEvent> = tables
+            .archive_events
+            .iter()
+            .filter(|a| a.archive == archive)
+            .map(|a| &a.event)
+            .filter(|event| {
+                query
+                    .since
+                    .is_none_or(|since| event.timestamp.is_some_and(|t| t >= since))
+                    && query
+                        .until
+                        .is_none_or(|until| event.timestamp.is_some_and(|t| t <= until))
+            })
+            .filter(|event| {
+                let message = event.message.as_deref().unwrap_or_default();
+                query
+                    .text
+                    .as_ref()
+                    .is_none_or(|text| message.to_lowercase().contains(&text.to_lowercase()))
+                    && query.grep.as_ref().is_none_or(|grep| {
+                        event.message.as_deref().is_some_and(|m| grep.is_match(m))
+                    })
+            })
+            .collect();
+        // NOTE: A stable sort keeps the insertion order between equal timestamps, like ordering
+        // on the id does.
+        events.sort_by_key(|event| event.timestamp);
+
+        let limit = query.limit.map_or(usize::MAX, |limit| limit as usize);
+        Ok(events.into_iter().take(limit).cloned().collect())
+    }
+
+    async fn list_archives(&self) -> eyre::Result<Vec<ArchiveSummary>> {
+        let mut summaries: Vec<ArchiveSummary> = Vec::new();
+        for archived in self.tables().archive_events.iter() {
+            let index = match summaries.iter().position(|s| s.archive == archived.archive) {
+                Some(index) => index,
+                None => {
+                    summaries.push(ArchiveSummary {
+                        archive: archived.archive.clone(),
+                        events: 0,
+                        message_bytes: 0,
+                        first_timestamp: None,
+                        last_timestamp: None,
+                    });
+                    summaries.len() - 1
+                }
+            };
+            let summary = &mut summaries[index];
+            summary.events += 1;
+            summary.message_bytes += archived.event.message.as_ref().map_or(0, |m| m.len()) as i64;
+            if let Some(timestamp) = archived.event.timestamp {
+                summary.first_timestamp = Some(
+                    summary
+                        .first_timestamp
+                        .map_or(timestamp, |t| t.min(timestamp)),
+                );
+                summary.last_timestamp = Some(
+                    summary
+                        .last_timestamp
+                        .map_or(timestamp, |t| t.max(timestamp)),
+                );
+            }
+        }
+        summaries.sort_by(|a, b| a.archive.cmp(&b.archive));
+        Ok(summaries)
+    }
+
+    async fn remove_archive(&self, archive: &str) -> eyre::Result<u64> {
+        let mut tables = self.tables();
+        let count = tables.archive_events.len();
+        tables.archive_events.retain(|a| a.archive != archive);
+        Ok((count - tables.archive_events.len()) as u64)
+    }
+
+    async fn cache_groups(&self, scope: &CacheScope, names: &[String]) -> eyre::Result<()> {
+        let seen_at = Utc::now();
+        let mut tables = self.tables();
+        for name in names {
+            let cached = tables
+                .group_cache
+                .iter_mut()
+                .find(|g| g.scope == *scope && g.name == *name);
+            match cached {
+                Some(cached) => cached.seen_at = seen_at,
+                None => tables.group_cache.push(CachedGroup {
+                    scope: scope.clone(),
+                    name: name.clone(),
+                    seen_at,
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    async fn cached_groups(&self, scope: &CacheScope) -> eyre::Result<Vec<String>> {
+        let mut names: Vec<_> = self
+            .tables()
+            .group_cache
+            .iter()
+            .filter(|g| g.scope == *scope)
+            .map(|g| g.name.clone())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    async fn groups_refreshed_at(&self, scope: &CacheScope) -> eyre::Result<Option<DateTime<Utc>>> {
+        Ok(self
+            .tables()
+            .group_cache_refresh
+            .iter()
+            .find(|(s, _)| s == scope)
+            .map(|(_, refreshed_at)| *refreshed_at))
+    }
+
+    async fn finish_group_refresh(
+        &self,
+        scope: &CacheScope,
+        started_at: DateTime<Utc>,
+    ) -> eyre::Result<()> {
+        let mut tables = self.tables();
+        tables
+            .group_cache
+            .retain(|g| g.scope != *scope || g.seen_at >= started_at);
+        let now = Utc::now();
+        match tables
+            .group_cache_refresh
+            .iter_mut()
+            .find(|(s, _)| s == scope)
+        {
+            Some((_, refreshed_at)) => *refreshed_at = now,
+            None => tables.group_cache_refresh.push((scope.clone(), now)),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use aws_sdk_cloudwatchlogs::config::{
+    interceptors::{
+        BeforeDeserializationInterceptorContextRef, BeforeSerializationInterceptorContextRef,
+        BeforeTransmitInterceptorContextRef,
+    },
+    ConfigBag, Intercept, RuntimeComponents,
+};
+use aws_smithy_runtime_api::box_error::BoxError;
+use aws_smithy_runtime_api::client::orchestrator::Metadata;
+use aws_smithy_runtime_api::client::retries::RequestAttempts;
+use eyre::Context;
+use serde_json::{json, Value};
+
+use crate::event::LogEvent;
+use crate::progress_reporter::{ProgressEvent, ProgressReporter};
+
+// NOTE: Bump whenever a field is removed or changes meaning, like the progress events.
+const SCHEMA_VERSION: u32 = 1;
+
+// The window events/sec is averaged over.
+const RATE_WINDOW_SECS: u64 = 60;
+
+#[derive(Debug, Default)]
+struct Counters {
+    api_calls: u64,
+    attempts: u64,
+    throttles: u64,
+    events: u64,
+    channel_depth: usize,
+    /// Events per second since the start, only the seconds within the rate window are kept.
+    recent: VecDeque<(u64, u64)>,
+    last_timestamps: BTreeMap<String, i64>,
+    /// Milliseconds between the newest event of a group and when the writer received it.
+    lags: BTreeMap<String, i64>,
+}
+
+/// Counters describing a running command, written out by `cw tail --metrics-file`. The API
+/// counters are filled in by [`MetricsInterceptor`], the event counters by the writer.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    started: Instant,
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            counters: Arc::default(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&self, update: impl FnOnce(&mut Counters)) {
+        if let Ok(mut counters) = self.counters.lock() {
+            update(&mut counters);
+        }
+    }
+
+    /// Counts an event the writer received, `channel_depth` being the events still queued
+    /// behind it.
+    pub fn record_event(&self, event: &LogEvent, channel_depth: usize) {
+        let second = self.started.elapsed().as_secs();
+        let now = chrono::Utc::now().timestamp_millis();
+        self.update(|counters| {
+            counters.events += 1;
+            counters.channel_depth = channel_depth;
+
+            match counters.recent.back_mut() {
+                Some((last, count)) if *last == second => *count += 1,
+                _ => counters.recent.push_back((second, 1)),
+            }
+            while counters
+                .recent
+                .front()
+                .is_some_and(|(s, _)| s + RATE_WINDOW_SECS <= second)
+            {
+                counters.recent.pop_front();
+            }
+
+            if let Some(timestamp) = event.timestamp {
+                let last = counters
+                    .last_timestamps
+                    .entry(event.group_name.clone())
+                    .or_insert(timestamp);
+                *last = (*last).max(timestamp);
+                let last = *last;
+                counters
+                    .lags
+                    .insert(event.group_name.clone(), now.saturating_sub(last));
+            }
+        });
+    }
+
+    /// The metrics document, see the README for its fields.
+    pub fn to_json(&self) -> Value {
+        let uptime = self.started.elapsed();
+        let Ok(counters) = self.counters.lock() else {
+            return json!({ "v": SCHEMA_VERSION });
+        };
+
+        let second = uptime.as_secs();
+        let recent: u64 = counters
+            .recent
+            .iter()
+            .filter(|(s, _)| s + RATE_WINDOW_SECS > second)
+            .map(|(_, count)| count)
+            .sum();
+        let window = uptime.as_secs_f64().clamp(1.0, RATE_WINDOW_SECS as f64);
+
+        json!({
+            "v": SCHEMA_VERSION,
+            "uptime_secs": uptime.as_secs(),
+            "events": counters.events,
+            "events_per_sec": recent as f64 / window,
+            "api_calls": counters.api_calls,
+            "retries": counters.attempts.saturating_sub(counters.api_calls),
+            "throttles": counters.throttles,
+            "channel_depth": counters.channel_depth,
+            "last_event_timestamp": counters.last_timestamps,
+            "lag_ms": counters.lags,
+        })
+    }
+
+    /// Replaces the file at `path` with the current document. It is written next to it first
+    /// and renamed into place, so a reader never sees half a document.
+    pub fn write_file(&self, path: &Path) -> eyre::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, self.to_json().to_string())
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .with_context(|| format!("Failed writing metrics to {}", path.display()))
+    }
+
+    /// Rewrites the file at `path` every `interval`, until the task is aborted.
+    pub async fn write_every(self, path: PathBuf, interval: Duration) -> eyre::Result<()> {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.write_file(&path)?;
+        }
+    }
+
+    /// Logs the API counters, called once the command is done.
+    pub fn report(&self) {
+        let Ok(counters) = self.counters.lock() else {
+            return;
+        };
+        tracing::debug!(
+            target: "cw",
+            "api calls: {}, retries: {}, throttles: {}",
+            counters.api_calls,
+            counters.attempts.saturating_sub(counters.api_calls),
+            counters.throttles
+        );
+    }
+}
+
+/// Counts the calls, attempts and throttled responses of a client into a shared [`Metrics`], and
+/// reports the retries and throttled responses as progress events.
+pub struct MetricsInterceptor {
+    metrics: Metrics,
+    reporter: Arc<dyn ProgressReporter>,
+}
+
+impl MetricsInterceptor {
+    pub fn new(metrics: Metrics, reporter: Arc<dyn ProgressReporter>) -> Self {
+        Self { metrics, reporter }
+    }
+}
+
+impl std::fmt::Debug for MetricsInterceptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsInterceptor")
+            .field("metrics", &self.metrics)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The operation and attempt number of the call `cfg` belongs to.
+fn attempt_of(cfg: &ConfigBag) -> (&str, u32) {
+    let operation = cfg.load::<Metadata>().map_or("unknown", Metadata::name);
+    let attempt = cfg
+        .load::<RequestAttempts>()
+        .map_or(1, RequestAttempts::attempts);
+    (operation, attempt)
+}
+
+impl Intercept for MetricsInterceptor {
+    fn name(&self) -> &'static str {
+        "MetricsInterceptor"
+    }
+
+    fn read_before_execution(
+        &self,
+        _context: &BeforeSerializationInterceptorContextRef<'_>,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        self.metrics.update(|counters| counters.api_calls += 1);
+        Ok(())
+    }
+
+    fn read_before_attempt(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        self.metrics.update(|counters| counters.attempts += 1);
+        let (operation, attempt) = attempt_of(cfg);
+        if attempt > 1 {
+            self.reporter.report(ProgressEvent::Retrying {
+                operation,
+                attempt,
+                delay_ms: None,
+            });
+        }
+        Ok(())
+    }
+
+    fn read_before_deserialization(
+        &self,
+        context: &BeforeDeserializationInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        // NOTE: CloudWatch Logs names the error in the x-amzn-ErrorType header, the body isn't
+        // read yet at this point.
+        let response = context.response();
+        let throttled = response.status().as_u16() == 429
+            || response
+                .headers()
+                .get("x-amzn-errortype")
+                .is_some_and(|error_type| error_type.starts_with("ThrottlingException"));
+        if throttled {
+            self.metrics.update(|counters| counters.throttles += 1);
+            let (operation, attempt) = attempt_of(cfg);
+            self.reporter
+                .report(ProgressEvent::Throttled { operation, attempt });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::time::{Duration, Instant};
+
+    use crate::event::LogEvent;
+    use crate::metrics::{Metrics, SCHEMA_VERSION};
+
+    fn event(group: &str, timestamp: i64) -> LogEvent {
+        LogEvent {
+            group_name: group.to_string(),
+            log_stream_name: Some("web-1".to_string()),
+            timestamp: Some(timestamp),
+            message: Some("ok".to_string()),
+            ingestion_time: None,
+            event_id: None,
+        }
+    }
+
+    #[test]
+    fn events_are_counted_with_the_newest_timestamp_per_group() {
+        let metrics = Metrics::new();
+
+        metrics.record_event(&event("/ecs/api", 2_000), 3);
+        metrics.record_event(&event("/ecs/api", 1_000), 2);
+        metrics.record_event(&event("/ecs/worker", 1_500), 0);
+        let document = metrics.to_json();
+
+        assert_eq!(document["events"], 3);
+        assert_eq!(document["channel_depth"], 0);
+        assert_eq!(document["last_event_timestamp"]["/ecs/api"], 2_000);
+        assert_eq!(document["last_event_timestamp"]["/ecs/worker"], 1_500);
+        assert!(document["lag_ms"]["/ecs/api"].as_i64().unwrap() > 0);
+    }
+
+    #[test]
+    fn retries_are_the_attempts_beyond_the_first() {
+        let metrics = Metrics::new();
+        metrics.update(|counters| {
+            counters.api_calls = 4;
+            counters.attempts = 7;
+            counters.throttles = 2;
+        });
+        let document = metrics.to_json();
+
+        assert_eq!(document["api_calls"], 4);
+        assert_eq!(document["retries"], 3);
+        assert_eq!(document["throttles"], 2);
+    }
+
+    #[test]
+    fn the_rate_only_counts_the_last_minute() {
+        let metrics = Metrics {
+            started: Instant::now() - Duration::from_secs(130),
+            ..Metrics::new()
+        };
+        metrics.update(|counters| counters.recent = VecDeque::from([(10, 50), (100, 30)]));
+
+        assert_eq!(metrics.to_json()["events_per_sec"], 0.5);
+    }
+
+    #[test]
+    fn the_rate_of_a_fresh_run_is_not_inflated() {
+        let metrics = Metrics::new();
+        metrics.record_event(&event("/ecs/api", 1_000), 0);
+        metrics.record_event(&event("/ecs/api", 1_001), 0);
+
+        assert_eq!(metrics.to_json()["events_per_sec"], 2.0);
+    }
+
+    #[test]
+    fn the_document_has_every_field() {
+        let document = Metrics::new().to_json();
+        let mut keys: Vec<_> = document.as_object().unwrap().keys().collect();
+        keys.sort();
+
+        assert_eq!(document["v"], SCHEMA_VERSION);
+        assert_eq!(
+            keys,
+            [
+                "api_calls",
+                "channel_depth",
+                "events",
+                "events_per_sec",
+                "lag_ms",
+                "last_event_timestamp",
+                "retries",
+                "throttles",
+                "uptime_secs",
+                "v",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_file_is_replaced_without_leaving_the_temporary_file() {
+        let dir = std::env::temp_dir().join(format!("cw-metrics-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("metrics.json");
+        let metrics = Metrics::new();
+
+        metrics.write_file(&path).unwrap();
+        metrics.record_event(&event("/ecs/api", 1_000), 0);
+        metrics.write_file(&path).unwrap();
+
+        let document: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(document["events"], 1);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::event::LogEvent;
+
+#[derive(Debug)]
+struct PendingEvent {
+    event: LogEvent,
+    last_line: Instant,
+}
+
+/// Puts events that were split per line back together, e.g. a stack trace the agent shipped as
+/// one event per line. A line matching `start` begins a new event, the lines after it are
+/// appended with a newline until the next start line of the same stream. Streams are buffered
+/// apart, so lines of interleaved streams never end up in each other's events.
+#[derive(Debug)]
+pub struct MultilineJoiner {
+    start: Regex,
+    timeout: Duration,
+    pending: HashMap<(String, Option<String>), PendingEvent>,
+}
+
+impl MultilineJoiner {
+    pub fn new(start: Regex, timeout: Duration) -> Self {
+        Self {
+            start,
+            timeout,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Buffers `event`, returning the event it completed if it starts a new one.
+    pub fn process(&mut self, event: LogEvent, now: Instant) -> Option<LogEvent> {
+        let key = (event.group_name.clone(), event.log_stream_name.clone());
+        let is_start = event
+            .message
+            .as_deref()
+            .is_some_and(|message| self.start.is_match(message));
+
+        match self.pending.get_mut(&key) {
+            Some(pending) if !is_start => {
+                let message = pending.event.message.get_or_insert_with(String::new);
+                message.push('\n');
+                message.push_str(event.message.as_deref().unwrap_or_default());
+                pending.last_line = now;
+                None
+            }
+            // NOTE: Lines before the first start line of a stream are buffered the same way, the
+            // lines after them most likely belong to them.
+            _ => self
+                .pending
+                .insert(
+                    key,
+                    PendingEvent {
+                        event,
+                        last_line: now,
+                    },
+                )
+                .map(|previous| previous.event),
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Returns the events of streams that had no new line for the timeout.
+    pub fn expired(&mut self, now: Instant) -> Vec<LogEvent> {
+        let timeout = self.timeout;
+        let expired = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.last_line) >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        expired
+            .into_iter()
+            .filter_map(|key| self.pending.remove(&key))
+            .map(|pending| pending.event)
+            .collect()
+    }
+
+    /// Returns every event still buffered, in timestamp order.
+    pub fn flush(&mut self) -> Vec<LogEvent> {
+        let mut events = self
+            .pending
+            .drain()
+            .map(|(_, pending)| pending.event)
+            .collect::<Vec<_>>();
+        events.sort_by_key(|event| event.timestamp);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use regex::Regex;
+
+    use crate::event::LogEvent;
+    use crate::multiline::MultilineJoiner;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn joiner() -> MultilineJoiner {
+        MultilineJoiner::new(Regex::new(r"^\d{4}-").unwrap(), TIMEOUT)
+    }
+
+    fn line(group: &str, stream: &str, timestamp: i64, message: &str) -> LogEvent {
+        LogEvent {
+            group_name: group.to_string(),
+            log_stream_name: Some(stream.to_string()),
+            timestamp: Some(timestamp),
+            message: Some(message.to_string()),
+            ingestion_time: None,
+            event_id: None,
+        }
+    }
+
+    fn messages(events: &[LogEvent]) -> Vec<&str> {
+        events
+            .iter()
+            .map(|e| e.message.as_deref().unwrap_or_default())
+            .collect()
+    }
+
+    /// Feeds `lines` to the joiner at `now`, returning the events they completed.
+    fn feed(joiner: &mut MultilineJoiner, lines: Vec<LogEvent>, now: Instant) -> Vec<LogEvent> {
+        lines
+            .into_iter()
+            .filter_map(|line| joiner.process(line, now))
+            .collect()
+    }
+
+    #[test]
+    fn a_stack_trace_is_joined_until_the_next_start_line() {
+        let mut joiner = joiner();
+        let now = Instant::now();
+
+        let completed = feed(
+            &mut joiner,
+            vec![
+                line("/ecs/api", "web-1", 1, "2024-06-01 ERROR boom"),
+                line("/ecs/api", "web-1", 2, "  at a.B.run(B.java:10)"),
+                line("/ecs/api", "web-1", 3, "  at a.C.main(C.java:3)"),
+                line("/ecs/api", "web-1", 4, "2024-06-01 INFO ok"),
+            ],
+            now,
+        );
+
+        assert_eq!(
+            messages(&completed),
+            ["2024-06-01 ERROR boom\n  at a.B.run(B.java:10)\n  at a.C.main(C.java:3)"]
+        );
+        assert_eq!(completed[0].timestamp, Some(1));
+        assert_eq!(messages(&joiner.flush()), ["2024-06-01 INFO ok"]);
+    }
+
+    #[test]
+    fn interleaved_streams_are_joined_apart() {
+        let mut joiner = joiner();
+        let now = Instant::now();
+
+        let completed = feed(
+            &mut joiner,
+            vec![
+                line("/ecs/api", "web-1", 1, "2024-06-01 ERROR one"),
+                line("/ecs/api", "web-2", 2, "2024-06-01 ERROR two"),
+                line("/ecs/api", "web-1", 3, "  at one"),
+                line("/ecs/worker", "web-1", 4, "2024-06-01 ERROR three"),
+                line("/ecs/api", "web-2", 5, "  at two"),
+                line("/ecs/worker", "web-1", 6, "  at three"),
+                line("/ecs/api", "web-2", 7, "2024-06-01 INFO two done"),
+            ],
+            now,
+        );
+
+        assert_eq!(messages(&completed), ["2024-06-01 ERROR two\n  at two"]);
+        assert_eq!(
+            messages(&joiner.flush()),
+            [
+                "2024-06-01 ERROR one\n  at one",
+                "2024-06-01 ERROR three\n  at three",
+                "2024-06-01 INFO two done",
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_before_the_first_start_line_are_kept_together() {
+        let mut joiner = joiner();
+        let now = Instant::now();
+
+        let completed = feed(
+            &mut joiner,
+            vec![
+                line("/ecs/api", "web-1", 1, "  at a.B.run(B.java:10)"),
+                line("/ecs/api", "web-1", 2, "  at a.C.main(C.java:3)"),
+                line("/ecs/api", "web-1", 3, "2024-06-01 INFO ok"),
+            ],
+            now,
+        );
+
+        assert_eq!(
+            messages(&completed),
+            ["  at a.B.run(B.java:10)\n  at a.C.main(C.java:3)"]
+        );
+    }
+
+    #[test]
+    fn a_stream_is_written_once_it_had_no_line_for_the_timeout() {
+        let mut joiner = joiner();
+        let now = Instant::now();
+
+        joiner.process(line("/ecs/api", "web-1", 1, "2024-06-01 ERROR boom"), now);
+        joiner.process(line("/ecs/api", "web-2", 2, "2024-06-01 INFO ok"), now);
+        joiner.process(
+            line("/ecs/api", "web-1", 3, "  at a.B.run(B.java:10)"),
+            now + Duration::from_secs(1),
+        );
+
+        assert_eq!(
+            messages(&joiner.expired(now + TIMEOUT)),
+            ["2024-06-01 INFO ok"]
+        );
+        assert!(joiner.has_pending());
+        assert_eq!(
+            messages(&joiner.expired(now + Duration::from_secs(3))),
+            ["2024-06-01 ERROR boom\n  at a.B.run(B.java:10)"]
+        );
+        assert!(!joiner.has_pending());
+    }
+}
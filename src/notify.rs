@@ -0,0 +1,220 @@
+use std::io::Write;
+use std::time::Duration;
+
+use regex::Regex;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
+
+use crate::event::LogEvent;
+use crate::utils::truncate_text;
+
+// NOTE: Desktop notifications get cut off well before this, the rest of the event is in the
+// terminal anyway.
+const MESSAGE_WIDTH: usize = 200;
+
+/// Allows one notification per `interval` and counts the matches in between.
+#[derive(Debug)]
+pub struct NotifyThrottle {
+    interval: Duration,
+    last_sent: Option<Instant>,
+    suppressed: u64,
+}
+
+impl NotifyThrottle {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_sent: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Records a match at `now`. Returns how many matches were suppressed since the last
+    /// notification when this one should go out, none when it is suppressed too.
+    pub fn check(&mut self, now: Instant) -> Option<u64> {
+        if self
+            .last_sent
+            .is_some_and(|sent| now.saturating_duration_since(sent) < self.interval)
+        {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last_sent = Some(now);
+        Some(std::mem::take(&mut self.suppressed))
+    }
+
+    /// The matches suppressed since the last notification.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+}
+
+/// The title and text of a notification about a matching event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+}
+
+impl Notification {
+    /// A notification naming the group and stream of `event`, with its message cut down to fit.
+    /// `suppressed` matches since the last notification are counted at the end.
+    pub fn new(event: &LogEvent, suppressed: u64) -> Self {
+        let summary = match &event.log_stream_name {
+            Some(stream) => format!("cw: {} {}", event.group_name, stream),
+            None => format!("cw: {}", event.group_name),
+        };
+        let message = event.message.as_deref().unwrap_or_default().trim();
+        let mut body = truncate_text(message, MESSAGE_WIDTH);
+        match suppressed {
+            0 => {}
+            1 => body.push_str("\n(1 more match since the last notification)"),
+            n => body.push_str(&format!(
+                "\n({} more matches since the last notification)",
+                n
+            )),
+        }
+        Self { summary, body }
+    }
+}
+
+/// Passes events on from a follow and notifies about the ones whose message matches `pattern`,
+/// see `cw tail --notify`.
+#[derive(Debug, Clone)]
+pub struct NotifySink {
+    pub pattern: Regex,
+    pub interval: Duration,
+}
+
+impl NotifySink {
+    pub async fn run(
+        self,
+        mut receiver: UnboundedReceiver<LogEvent>,
+        sender: UnboundedSender<LogEvent>,
+    ) -> eyre::Result<()> {
+        let mut throttle = NotifyThrottle::new(self.interval);
+        while let Some(event) = receiver.recv().await {
+            let matches = event
+                .message
+                .as_deref()
+                .is_some_and(|message| self.pattern.is_match(message));
+            if matches {
+                if let Some(suppressed) = throttle.check(Instant::now()) {
+                    notify(Notification::new(&event, suppressed)).await;
+                }
+            }
+
+            // NOTE: This only errors when the writer is gone, which already failed the command.
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+
+        if throttle.suppressed() > 0 {
+            tracing::debug!(target: "cw", "{} matches went without a notification", throttle.suppressed());
+        }
+        Ok(())
+    }
+}
+
+/// Shows `notification` on the desktop, ringing the terminal bell when that isn't possible.
+async fn notify(notification: Notification) {
+    #[cfg(feature = "desktop-notifications")]
+    {
+        let shown = tokio::task::spawn_blocking(move || {
+            notify_rust::Notification::new()
+                .summary(&notification.summary)
+                .body(&notification.body)
+                .show()
+                .map(|_| ())
+        })
+        .await;
+        match shown {
+            Ok(Ok(())) => return,
+            Ok(Err(err)) => {
+                tracing::debug!(target: "cw", "desktop notification failed: {:?}", err)
+            }
+            Err(err) => tracing::debug!(target: "cw", "desktop notification failed: {:?}", err),
+        }
+    }
+    #[cfg(not(feature = "desktop-notifications"))]
+    tracing::debug!(target: "cw", "notifying with the bell: {}", notification.summary);
+
+    ring_bell();
+}
+
+fn ring_bell() {
+    let mut stderr = std::io::stderr();
+    if let Err(err) = stderr.write_all(b"\x07").and_then(|_| stderr.flush()) {
+        tracing::debug!(target: "cw", "failed ringing the bell: {:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::event::LogEvent;
+    use crate::notify::{Notification, NotifyThrottle};
+
+    fn event(stream: Option<&str>, message: &str) -> LogEvent {
+        LogEvent {
+            group_name: "/ecs/api".to_string(),
+            log_stream_name: stream.map(str::to_string),
+            timestamp: Some(1_000),
+            message: Some(message.to_string()),
+            ingestion_time: None,
+            event_id: None,
+        }
+    }
+
+    #[test]
+    fn throttle_counts_the_matches_in_between() {
+        let start = Instant::now();
+        let mut throttle = NotifyThrottle::new(Duration::from_secs(10));
+
+        assert_eq!(throttle.check(start), Some(0));
+        assert_eq!(throttle.check(start + Duration::from_secs(1)), None);
+        assert_eq!(throttle.check(start + Duration::from_secs(9)), None);
+        assert_eq!(throttle.suppressed(), 2);
+
+        assert_eq!(throttle.check(start + Duration::from_secs(10)), Some(2));
+        assert_eq!(throttle.suppressed(), 0);
+        assert_eq!(throttle.check(start + Duration::from_secs(15)), None);
+        assert_eq!(throttle.check(start + Duration::from_secs(20)), Some(1));
+    }
+
+    #[test]
+    fn notification_names_the_group_and_stream() {
+        let notification = Notification::new(&event(Some("web/1"), "  boom\n"), 0);
+        assert_eq!(notification.summary, "cw: /ecs/api web/1");
+        assert_eq!(notification.body, "boom");
+
+        let notification = Notification::new(&event(None, "boom"), 0);
+        assert_eq!(notification.summary, "cw: /ecs/api");
+    }
+
+    #[test]
+    fn notification_counts_the_suppressed_matches() {
+        let notification = Notification::new(&event(None, "boom"), 1);
+        assert_eq!(
+            notification.body,
+            "boom\n(1 more match since the last notification)"
+        );
+
+        let notification = Notification::new(&event(None, "boom"), 12);
+        assert_eq!(
+            notification.body,
+            "boom\n(12 more matches since the last notification)"
+        );
+    }
+
+    #[test]
+    fn notification_cuts_long_messages() {
+        let notification = Notification::new(&event(None, &"x".repeat(500)), 0);
+        assert_eq!(notification.body.chars().count(), 200);
+        assert!(notification.body.ends_with('…'));
+    }
+}
@@ -0,0 +1,3362 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::Write,
+    future::Future,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, SecondsFormat};
+use clap::{Args, ValueEnum};
+use eyre::Context;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc::UnboundedReceiver,
+    task::JoinHandle,
+};
+use yansi::Paint;
+
+use crate::config::TailConfig;
+use crate::correlation::{Correlated, CorrelationGrouper, JoinKey, Joined};
+use crate::dedupe::{DedupeKey, DedupeWindow};
+use crate::event::LogEvent;
+use crate::histogram::{self, Histogram};
+use crate::lambda::{Boundary, BoundaryMatcher, ReportMetrics};
+use crate::metrics::Metrics;
+use crate::multiline::MultilineJoiner;
+use crate::ui::{supports_ansi, Ui};
+use crate::utils::{
+    escape_non_printable, group_thousands, parse_timestamp, truncate_text, PrefixField, TimeStyle,
+};
+
+// NOTE: CRI log lines look like `<timestamp> <stdout|stderr> <F|P> <message>`, where `P` marks a
+// partial line that continues in the next event and `F` marks the final (or only) part.
+const CRI_PREFIX_PATTERN: &str = r"^\S+ (?:stdout|stderr) ([FP]) ?";
+
+// NOTE: A stream that stops after a partial line never sends its final part, while following such
+// lines would pile up forever. The parts joined so far are written after this long instead.
+const PARTIAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Rewrites events before they reach the writer: drops --dedupe-window duplicates, strips
+/// configured message prefixes, joins CRI partial lines and --multiline events back together per
+/// stream and counts, or drops, masked events.
+struct Preprocessor {
+    dedupe: Option<DedupeWindow>,
+    cri_prefix: Option<Regex>,
+    strip_prefix: Option<Regex>,
+    join_partial: bool,
+    multiline: Option<MultilineJoiner>,
+    drop_masked: bool,
+    partials: HashMap<(String, Option<String>), PendingPartial>,
+    masked: u64,
+}
+
+/// The parts of a CRI line joined so far, waiting for its final part.
+struct PendingPartial {
+    event: LogEvent,
+    started: Instant,
+}
+
+impl Preprocessor {
+    pub fn new(
+        dedupe: Option<DedupeWindow>,
+        strip_cri_prefix: bool,
+        strip_prefix: Option<Regex>,
+        join_partial: bool,
+        multiline: Option<MultilineJoiner>,
+        drop_masked: bool,
+    ) -> eyre::Result<Self> {
+        let cri_prefix = if strip_cri_prefix {
+            Some(Regex::new(CRI_PREFIX_PATTERN)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            dedupe,
+            cri_prefix,
+            strip_prefix,
+            join_partial,
+            multiline,
+            drop_masked,
+            partials: HashMap::new(),
+            masked: 0,
+        })
+    }
+
+    pub fn process(&mut self, event: LogEvent) -> Option<LogEvent> {
+        if let Some(dedupe) = &mut self.dedupe {
+            if dedupe.is_duplicate(&event) {
+                return None;
+            }
+        }
+        let mut event = self.rewrite(event)?;
+        if let Some(multiline) = &mut self.multiline {
+            event = multiline.process(event, Instant::now())?;
+        }
+        self.screen_masked(event)
+    }
+
+    /// Whether events are held back until a timeout, waiting for more lines.
+    pub fn has_pending(&self) -> bool {
+        !self.partials.is_empty()
+            || self
+                .multiline
+                .as_ref()
+                .is_some_and(MultilineJoiner::has_pending)
+    }
+
+    /// Returns partial lines that had no final part for [`PARTIAL_TIMEOUT`] and --multiline
+    /// events that had no new line for the timeout.
+    pub fn expired(&mut self, now: Instant) -> Vec<LogEvent> {
+        let stale = self
+            .partials
+            .iter()
+            .filter(|(_, p)| now.duration_since(p.started) >= PARTIAL_TIMEOUT)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        let mut expired = Vec::new();
+        for key in stale {
+            let Some(partial) = self.partials.remove(&key) else {
+                continue;
+            };
+            match &mut self.multiline {
+                Some(multiline) => expired.extend(multiline.process(partial.event, now)),
+                None => expired.push(partial.event),
+            }
+        }
+        if let Some(multiline) = &mut self.multiline {
+            expired.extend(multiline.expired(now));
+        }
+        expired
+            .into_iter()
+            .filter_map(|event| self.screen_masked(event))
+            .collect()
+    }
+
+    fn rewrite(&mut self, mut event: LogEvent) -> Option<LogEvent> {
+        let mut partial = false;
+
+        if let (Some(re), Some(message)) = (&self.cri_prefix, event.message.as_mut()) {
+            let found = re.captures(message).map(|captures| {
+                (
+                    captures.get(1).is_some_and(|tag| tag.as_str() == "P"),
+                    captures.get(0).map_or(0, |m| m.end()),
+                )
+            });
+
+            if let Some((is_partial, end)) = found {
+                partial = is_partial;
+                message.drain(..end);
+            }
+        }
+
+        if let (Some(re), Some(message)) = (&self.strip_prefix, event.message.as_mut()) {
+            if let Some(end) = re.find(message).filter(|m| m.start() == 0).map(|m| m.end()) {
+                message.drain(..end);
+            }
+        }
+
+        if !self.join_partial {
+            return Some(event);
+        }
+
+        let key = (event.group_name.clone(), event.log_stream_name.clone());
+        match self.partials.remove(&key) {
+            Some(mut pending) => {
+                if let Some(message) = &event.message {
+                    pending
+                        .event
+                        .message
+                        .get_or_insert_with(String::new)
+                        .push_str(message);
+                }
+
+                if partial {
+                    self.partials.insert(key, pending);
+                    None
+                } else {
+                    Some(pending.event)
+                }
+            }
+            None if partial => {
+                let started = Instant::now();
+                self.partials.insert(key, PendingPartial { event, started });
+                None
+            }
+            None => Some(event),
+        }
+    }
+
+    fn screen_masked(&mut self, event: LogEvent) -> Option<LogEvent> {
+        if !event.is_masked() {
+            return Some(event);
+        }
+
+        self.masked += 1;
+        if self.drop_masked {
+            None
+        } else {
+            Some(event)
+        }
+    }
+
+    /// Returns partial lines that never received their final part and the --multiline events
+    /// still waiting for more lines.
+    pub fn flush(&mut self) -> Vec<LogEvent> {
+        let mut pending = Vec::new();
+        for (_, partial) in self.partials.drain().collect::<Vec<_>>() {
+            match &mut self.multiline {
+                Some(multiline) => pending.extend(multiline.process(partial.event, Instant::now())),
+                None => pending.push(partial.event),
+            }
+        }
+        if let Some(multiline) = &mut self.multiline {
+            pending.extend(multiline.flush());
+        }
+        pending
+            .into_iter()
+            .filter_map(|event| self.screen_masked(event))
+            .collect()
+    }
+
+    /// Tells how many duplicates were dropped and how many masked events went by, if any.
+    pub fn report(&self, ui: Ui) {
+        if let Some(dropped) = self.dedupe.as_ref().map(DedupeWindow::dropped) {
+            ui.note(format!(
+                "Dropped {} duplicate events.",
+                group_thousands(dropped)
+            ));
+        }
+
+        match (self.masked, self.drop_masked) {
+            (0, _) => {}
+            (masked, true) => ui.note(format!(
+                "Dropped {} events masked by a data protection policy.",
+                group_thousands(masked)
+            )),
+            (masked, false) => ui.note(format!(
+                "{} events were masked by a data protection policy, pass --drop-masked to leave them out.",
+                group_thousands(masked)
+            )),
+        }
+    }
+}
+
+/// All events of a single Lambda invocation, from its START line up to and including REPORT.
+#[derive(Debug)]
+struct Invocation {
+    request_id: String,
+    events: Vec<LogEvent>,
+    report: Option<ReportMetrics>,
+}
+
+#[derive(Debug)]
+struct PendingInvocation {
+    invocation: Invocation,
+    started: Instant,
+}
+
+enum Grouped {
+    Event(LogEvent),
+    Invocation(Invocation),
+    Correlated(Correlated),
+}
+
+/// Holds back events to write them as blocks, either per Lambda invocation or per `--join-on`
+/// key.
+enum Grouper {
+    Invocation(InvocationGrouper),
+    Correlation(CorrelationGrouper),
+}
+
+impl Grouper {
+    fn process(&mut self, event: LogEvent) -> Vec<Grouped> {
+        match self {
+            Grouper::Invocation(grouper) => grouper.process(event),
+            Grouper::Correlation(grouper) => grouper
+                .process(event, Instant::now())
+                .into_iter()
+                .map(|joined| match joined {
+                    Joined::Event(event) => Grouped::Event(event),
+                    Joined::Correlated(correlated) => Grouped::Correlated(correlated),
+                })
+                .collect(),
+        }
+    }
+
+    fn has_pending(&self) -> bool {
+        match self {
+            Grouper::Invocation(grouper) => grouper.has_pending(),
+            Grouper::Correlation(grouper) => grouper.has_pending(),
+        }
+    }
+
+    fn expired(&mut self, now: Instant) -> Vec<Grouped> {
+        match self {
+            Grouper::Invocation(grouper) => grouper
+                .expired(now)
+                .into_iter()
+                .map(Grouped::Invocation)
+                .collect(),
+            Grouper::Correlation(grouper) => grouper
+                .expired(now)
+                .into_iter()
+                .map(Grouped::Correlated)
+                .collect(),
+        }
+    }
+
+    fn flush(&mut self) -> Vec<Grouped> {
+        match self {
+            Grouper::Invocation(grouper) => grouper
+                .flush()
+                .into_iter()
+                .map(Grouped::Invocation)
+                .collect(),
+            Grouper::Correlation(grouper) => grouper
+                .flush()
+                .into_iter()
+                .map(Grouped::Correlated)
+                .collect(),
+        }
+    }
+}
+
+/// Buffers the events of each stream between a Lambda START and REPORT line, so an invocation can
+/// be written as one block. Events outside of an invocation pass through untouched.
+struct InvocationGrouper {
+    matcher: BoundaryMatcher,
+    timeout: Duration,
+    pending: HashMap<(String, Option<String>), PendingInvocation>,
+}
+
+impl InvocationGrouper {
+    pub fn new(timeout: Duration) -> eyre::Result<Self> {
+        Ok(Self {
+            matcher: BoundaryMatcher::new()?,
+            timeout,
+            pending: HashMap::new(),
+        })
+    }
+
+    pub fn process(&mut self, event: LogEvent) -> Vec<Grouped> {
+        let key = (event.group_name.clone(), event.log_stream_name.clone());
+        let boundary = event
+            .message
+            .as_deref()
+            .and_then(|m| self.matcher.classify(m));
+
+        match boundary {
+            Some(Boundary::Start { request_id }) => {
+                // NOTE: A new START means the previous invocation of this stream never reported.
+                let mut output = Vec::new();
+                if let Some(previous) = self.pending.remove(&key) {
+                    output.push(Grouped::Invocation(previous.invocation));
+                }
+
+                self.pending.insert(
+                    key,
+                    PendingInvocation {
+                        invocation: Invocation {
+                            request_id,
+                            events: vec![event],
+                            report: None,
+                        },
+                        started: Instant::now(),
+                    },
+                );
+                output
+            }
+            Some(Boundary::Report(metrics)) => match self.pending.remove(&key) {
+                Some(mut pending) => {
+                    pending.invocation.events.push(event);
+                    pending.invocation.report = Some(metrics);
+                    vec![Grouped::Invocation(pending.invocation)]
+                }
+                None => vec![Grouped::Event(event)],
+            },
+            Some(Boundary::End) | None => match self.pending.get_mut(&key) {
+                Some(pending) => {
+                    pending.invocation.events.push(event);
+                    Vec::new()
+                }
+                None => vec![Grouped::Event(event)],
+            },
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Returns invocations that have been waiting for their REPORT line longer than the timeout.
+    pub fn expired(&mut self, now: Instant) -> Vec<Invocation> {
+        let timeout = self.timeout;
+        let expired = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now.duration_since(p.started) >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        expired
+            .into_iter()
+            .filter_map(|key| self.pending.remove(&key))
+            .map(|p| p.invocation)
+            .collect()
+    }
+
+    pub fn flush(&mut self) -> Vec<Invocation> {
+        self.pending.drain().map(|(_, p)| p.invocation).collect()
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum OutputType {
+    Text,
+    /// Plain text in columns of a fixed width, for grep, awk and cut.
+    TextAligned,
+    Json,
+}
+
+/// Which of an event's times gets printed and, with --order-by, sorted on.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeField {
+    #[default]
+    Timestamp,
+    Ingestion,
+}
+
+impl TimeField {
+    pub fn of(self, event: &LogEvent) -> Option<i64> {
+        match self {
+            Self::Timestamp => event.timestamp,
+            Self::Ingestion => event.ingestion_time,
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Self::Timestamp => Self::Ingestion,
+            Self::Ingestion => Self::Timestamp,
+        }
+    }
+
+    /// Whether the event lacks this time, only worth pointing out for the ingestion time as
+    /// every event has a timestamp.
+    fn is_missing(self, event: &LogEvent) -> bool {
+        self == Self::Ingestion && event.ingestion_time.is_none()
+    }
+}
+
+/// Sorts events on `field`, stable so events with the same time keep their order. Events
+/// without that time go last.
+fn sort_events(events: &mut [LogEvent], field: TimeField) {
+    events.sort_by_key(|event| {
+        let time = field.of(event);
+        (time.is_none(), time)
+    });
+}
+
+#[derive(Debug, Default)]
+struct JsonHighlighter;
+
+impl JsonHighlighter {
+    fn format_json(value: &Value, output: &mut String, escape: bool) {
+        let text = |s: &str| {
+            if escape {
+                escape_non_printable(s).into_owned()
+            } else {
+                s.to_string()
+            }
+        };
+
+        match value {
+            Value::Object(map) => {
+                let _ = write!(output, "{}", Paint::new("{").dim());
+                let mut first = true;
+                for (key, val) in map {
+                    if !first {
+                        let _ = write!(output, "{}", Paint::new(",").dim());
+                    }
+                    first = false;
+
+                    let _ = write!(output, " ");
+                    let _ = write!(output, "{}", "\"".yellow());
+                    let _ = write!(output, "{}", text(key).yellow());
+                    let _ = write!(output, "{}", "\"".yellow());
+                    let _ = write!(output, "{} ", Paint::new(":").dim());
+
+                    Self::format_json(val, output, escape);
+                }
+                let _ = write!(output, " {}", Paint::new("}").dim());
+            }
+            Value::Array(array) => {
+                let _ = write!(output, "{}", Paint::new("[").dim());
+                let mut first = true;
+                for item in array {
+                    if !first {
+                        let _ = write!(output, "{} ", Paint::new(",").dim());
+                    }
+                    first = false;
+
+                    Self::format_json(item, output, escape);
+                }
+                let _ = write!(output, "{}", Paint::new("]").dim());
+            }
+            Value::String(value) => {
+                let _ = write!(output, "{}", "\"".green());
+                let _ = write!(output, "{}", text(value).green());
+                let _ = write!(output, "{}", "\"".green());
+            }
+            Value::Number(value) => {
+                let _ = write!(output, "{}", value.to_string().cyan());
+            }
+            Value::Bool(value) => {
+                let _ = write!(output, "{}", value.to_string().blue());
+            }
+            Value::Null => {
+                let _ = write!(output, "{}", "null".blue());
+            }
+        }
+    }
+}
+
+fn highlight_json_if_applicable(message: &str, escape: bool) -> Option<String> {
+    let trimmed = message.trim_start();
+    if !(trimmed.starts_with('{') || trimmed.starts_with('[')) {
+        return None;
+    }
+
+    let value: Value = serde_json::from_str(trimmed).ok()?;
+    let mut output = String::new();
+    let leading_len = message.len().saturating_sub(trimmed.len());
+    if leading_len > 0 {
+        output.push_str(&message[..leading_len]);
+    }
+
+    JsonHighlighter::format_json(&value, &mut output, escape);
+    Some(output)
+}
+
+/// The region and account events were read from, printed when requested so output from multiple
+/// invocations can be told apart.
+#[derive(Clone, Debug, Default)]
+pub struct Origin {
+    pub region: Option<String>,
+    pub account: Option<String>,
+}
+
+impl Origin {
+    fn is_empty(&self) -> bool {
+        self.region.is_none() && self.account.is_none()
+    }
+}
+
+/// Where formatted events end up. Keeps track of what was written, so a failure can say how far
+/// it got, and optionally falls back to stdout when the configured destination stops working.
+pub struct Sink {
+    description: String,
+    inner: Box<dyn AsyncWrite + Unpin + Send>,
+    fallback: Option<Box<dyn AsyncWrite + Unpin + Send>>,
+    events_written: u64,
+    last_timestamp: Option<i64>,
+    ui: Ui,
+}
+
+impl Sink {
+    pub fn new(
+        description: impl Into<String>,
+        inner: Box<dyn AsyncWrite + Unpin + Send>,
+        fallback_stdout: bool,
+        ui: Ui,
+    ) -> Self {
+        Self {
+            description: description.into(),
+            inner,
+            fallback: fallback_stdout
+                .then(|| Box::new(tokio::io::stdout()) as Box<dyn AsyncWrite + Unpin + Send>),
+            ui,
+            events_written: 0,
+            last_timestamp: None,
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(
+            "stdout",
+            Box::new(tokio::io::stdout()),
+            false,
+            Ui::default(),
+        )
+    }
+
+    fn summary(&self) -> String {
+        let last_event = self
+            .last_timestamp
+            .and_then(|ts| parse_timestamp(ts, false))
+            .unwrap_or_else(|| "none".to_string());
+
+        format!(
+            "Failed writing to {} after {} events (last event timestamp: {})",
+            self.description,
+            group_thousands(self.events_written),
+            last_event
+        )
+    }
+
+    pub async fn write(&mut self, line: &[u8], timestamp: Option<i64>) -> eyre::Result<()> {
+        let written = match self.inner.write_all(line).await {
+            // NOTE: A file hands the bytes to a background task and only reports a failure on a
+            // later call, flushing right away pins the failure on this line so it is the one that
+            // gets written again on stdout instead of being lost.
+            Ok(()) if self.fallback.is_some() => self.inner.flush().await,
+            written => written,
+        };
+
+        if let Err(err) = written {
+            let summary = self.summary();
+            tracing::error!(target: "cw", "{}: {}", summary, err);
+
+            let Some(fallback) = self.fallback.take() else {
+                return Err(eyre::Report::new(err).wrap_err(summary));
+            };
+
+            self.ui
+                .warn(format!("{} ({}), falling back to stdout.", summary, err));
+            self.description = "stdout".to_string();
+            self.inner = fallback;
+            self.inner
+                .write_all(line)
+                .await
+                .context("Failed writing to stdout")?;
+        }
+
+        self.events_written += 1;
+        if timestamp.is_some() {
+            self.last_timestamp = timestamp;
+        }
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> eyre::Result<()> {
+        let description = &self.description;
+        self.inner
+            .flush()
+            .await
+            .with_context(|| format!("Failed flushing {}", description))
+    }
+}
+
+trait LogEventWriter {
+    fn write<'a>(
+        &'a mut self,
+        event: &'a LogEvent,
+    ) -> impl Future<Output = eyre::Result<()>> + Send + 'a;
+
+    fn write_invocation<'a>(
+        &'a mut self,
+        invocation: &'a Invocation,
+    ) -> impl Future<Output = eyre::Result<()>> + Send + 'a;
+
+    fn write_correlated<'a>(
+        &'a mut self,
+        correlated: &'a Correlated,
+    ) -> impl Future<Output = eyre::Result<()>> + Send + 'a;
+
+    /// Flushes everything written so far to the writer's destination.
+    fn flush(&mut self) -> impl Future<Output = eyre::Result<()>> + Send + '_;
+
+    /// Writes whatever the writer held back, called once after the last event.
+    fn finish(&mut self) -> impl Future<Output = eyre::Result<()>> + Send + '_ {
+        std::future::ready(Ok(()))
+    }
+
+    /// The number of the last event written with --line-numbers, if any.
+    fn last_line_number(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Numbers the events a writer emits for --line-numbers, starting at 1. Numbers are handed out
+/// as events are written, after filtering and sorting, so they match the output line for line.
+#[derive(Debug, Clone, Copy, Default)]
+struct LineNumbers {
+    enabled: bool,
+    last: u64,
+}
+
+impl LineNumbers {
+    fn new(enabled: bool) -> Self {
+        Self { enabled, last: 0 }
+    }
+
+    fn next(&mut self) -> Option<u64> {
+        if !self.enabled {
+            return None;
+        }
+        self.last += 1;
+        Some(self.last)
+    }
+
+    fn last(&self) -> Option<u64> {
+        Some(self.last).filter(|last| self.enabled && *last > 0)
+    }
+}
+
+struct TextWriter {
+    use_local_time: bool,
+    time_style: TimeStyle,
+    /// The prefix components, in the order they are written.
+    prefix: Vec<PrefixField>,
+    time_field: TimeField,
+    with_other_time: bool,
+    use_color: bool,
+    escape_non_printable: bool,
+    origin: Origin,
+    line_numbers: LineNumbers,
+
+    sink: Sink,
+}
+
+impl TextWriter {
+    pub fn new(
+        use_local_time: bool,
+        time_style: TimeStyle,
+        prefix: Vec<PrefixField>,
+        time_field: TimeField,
+        with_other_time: bool,
+        use_color: bool,
+        escape_non_printable: bool,
+        origin: Origin,
+        line_numbers: bool,
+        sink: Sink,
+    ) -> Self {
+        Self {
+            use_local_time,
+            time_style,
+            prefix,
+            time_field,
+            with_other_time,
+            use_color,
+            escape_non_printable,
+            origin,
+            line_numbers: LineNumbers::new(line_numbers),
+            sink,
+        }
+    }
+}
+
+impl LogEventWriter for TextWriter {
+    async fn write(&mut self, event: &LogEvent) -> eyre::Result<()> {
+        let mut line = String::new();
+
+        if let Some(number) = self.line_numbers.next() {
+            write!(&mut line, "{} ", Paint::new(format!("{:>6}", number)).dim())?;
+        }
+
+        if self.time_field.is_missing(event) {
+            write!(&mut line, "{} - ", "[no ingestion time]".red())?;
+        }
+
+        for field in &self.prefix {
+            match field {
+                PrefixField::Timestamp => {
+                    if let Some(time) = self
+                        .time_field
+                        .of(event)
+                        .and_then(|ts| self.time_style.render_millis(ts, self.use_local_time))
+                    {
+                        write!(&mut line, "{} - ", time.green())?;
+                    }
+
+                    if let Some(time) = self
+                        .time_field
+                        .other()
+                        .of(event)
+                        .filter(|_| self.with_other_time)
+                        .and_then(|ts| self.time_style.render_millis(ts, self.use_local_time))
+                    {
+                        write!(&mut line, "{} - ", time.dim())?;
+                    }
+                }
+                PrefixField::Group => write!(&mut line, "{} - ", event.group_name.blue())?,
+                PrefixField::Stream => {
+                    if let Some(stream_name) = event.log_stream_name.as_deref() {
+                        write!(&mut line, "{} - ", stream_name.cyan())?;
+                    }
+                }
+                PrefixField::Id => {
+                    if let Some(event_id) = event.event_id.as_deref() {
+                        write!(&mut line, "{} - ", event_id.yellow())?;
+                    }
+                }
+            }
+        }
+
+        if let Some(msg) = &event.message {
+            let highlighted = if !self.use_color {
+                None
+            } else if event.is_masked() {
+                // NOTE: Masking can turn a number into asterisks, which leaves invalid JSON.
+                tracing::debug!(target: "cw", "not highlighting masked event {:?}", event.event_id);
+                None
+            } else {
+                highlight_json_if_applicable(msg, self.escape_non_printable)
+            };
+
+            match highlighted {
+                Some(highlighted) => line.push_str(&highlighted),
+                None if self.escape_non_printable => line.push_str(&escape_non_printable(msg)),
+                None => line.push_str(msg),
+            }
+        }
+
+        if !self.origin.is_empty() {
+            let origin = [&self.origin.region, &self.origin.account]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ");
+            write!(&mut line, " {}", Paint::new(format!("[{}]", origin)).dim())?;
+        }
+
+        line.push('\n');
+        self.sink.write(line.as_bytes(), event.timestamp).await
+    }
+
+    async fn write_invocation(&mut self, invocation: &Invocation) -> eyre::Result<()> {
+        for event in &invocation.events {
+            self.write(event).await?;
+        }
+
+        let last_timestamp = invocation.events.last().and_then(|e| e.timestamp);
+        self.sink.write(b"\n", last_timestamp).await
+    }
+
+    async fn write_correlated(&mut self, correlated: &Correlated) -> eyre::Result<()> {
+        let header = format!(
+            "--- {} ({}) ---",
+            correlated.key,
+            correlated.groups().join(", ")
+        );
+        let mut line = if self.use_color {
+            Paint::new(header).bold().to_string()
+        } else {
+            header
+        };
+        line.push('\n');
+        let first_timestamp = correlated.events.first().and_then(|e| e.timestamp);
+        self.sink.write(line.as_bytes(), first_timestamp).await?;
+
+        for event in &correlated.events {
+            self.write(event).await?;
+        }
+
+        let last_timestamp = correlated.events.last().and_then(|e| e.timestamp);
+        self.sink.write(b"\n", last_timestamp).await
+    }
+
+    async fn flush(&mut self) -> eyre::Result<()> {
+        self.sink.flush().await
+    }
+
+    fn last_line_number(&self) -> Option<u64> {
+        self.line_numbers.last()
+    }
+}
+
+/// A column of `--output text-aligned`, in front of the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AlignedColumn {
+    Time(TimeField),
+    Group,
+    Stream,
+    Id,
+    Region,
+    Account,
+}
+
+#[derive(Debug)]
+struct Column {
+    kind: AlignedColumn,
+    width: usize,
+    /// Set with a flag, rather than learned from the events.
+    fixed: bool,
+}
+
+/// Writes `--output text-aligned` lines: the prefix components in columns of a fixed width,
+/// padded or truncated and a single space apart, followed by the message. There is no color,
+/// and a missing value is written as `-`, so awk finds every field at the same position. Widths
+/// not set with a flag grow with the first `learn_events` events and are frozen after that.
+struct AlignedTextWriter {
+    use_local_time: bool,
+    time_style: TimeStyle,
+    columns: Vec<Column>,
+    learn_events: u64,
+    learned: u64,
+    escape_non_printable: bool,
+    origin: Origin,
+    line_numbers: LineNumbers,
+
+    sink: Sink,
+}
+
+impl AlignedTextWriter {
+    pub fn new(
+        use_local_time: bool,
+        time_style: TimeStyle,
+        prefix: Vec<PrefixField>,
+        time_field: TimeField,
+        with_other_time: bool,
+        widths: ColumnWidths,
+        escape_non_printable: bool,
+        origin: Origin,
+        line_numbers: bool,
+        sink: Sink,
+    ) -> Self {
+        let mut kinds = Vec::new();
+        for field in prefix {
+            match field {
+                PrefixField::Timestamp => {
+                    kinds.push(AlignedColumn::Time(time_field));
+                    if with_other_time {
+                        kinds.push(AlignedColumn::Time(time_field.other()));
+                    }
+                }
+                PrefixField::Group => kinds.push(AlignedColumn::Group),
+                PrefixField::Stream => kinds.push(AlignedColumn::Stream),
+                PrefixField::Id => kinds.push(AlignedColumn::Id),
+            }
+        }
+        if origin.region.is_some() {
+            kinds.push(AlignedColumn::Region);
+        }
+        if origin.account.is_some() {
+            kinds.push(AlignedColumn::Account);
+        }
+
+        let columns = kinds
+            .into_iter()
+            .map(|kind| {
+                let fixed = match kind {
+                    AlignedColumn::Group => widths.group,
+                    AlignedColumn::Stream => widths.stream,
+                    _ => None,
+                };
+                Column {
+                    kind,
+                    width: fixed.unwrap_or(0),
+                    fixed: fixed.is_some(),
+                }
+            })
+            .collect();
+
+        Self {
+            use_local_time,
+            time_style,
+            columns,
+            learn_events: widths.learn_events,
+            learned: 0,
+            escape_non_printable,
+            origin,
+            line_numbers: LineNumbers::new(line_numbers),
+            sink,
+        }
+    }
+
+    fn cell(&self, kind: AlignedColumn, event: &LogEvent) -> String {
+        let value = match kind {
+            AlignedColumn::Time(field) => field
+                .of(event)
+                .and_then(|ts| self.time_style.render_millis(ts, self.use_local_time)),
+            AlignedColumn::Group => Some(event.group_name.clone()),
+            AlignedColumn::Stream => event.log_stream_name.clone(),
+            AlignedColumn::Id => event.event_id.clone(),
+            AlignedColumn::Region => self.origin.region.clone(),
+            AlignedColumn::Account => self.origin.account.clone(),
+        };
+        // NOTE: A space inside a column would shift every awk field after it.
+        match value.filter(|value| !value.is_empty()) {
+            Some(value) => value.replace(char::is_whitespace, "_"),
+            None => "-".to_string(),
+        }
+    }
+}
+
+impl LogEventWriter for AlignedTextWriter {
+    async fn write(&mut self, event: &LogEvent) -> eyre::Result<()> {
+        let mut line = String::new();
+
+        if let Some(number) = self.line_numbers.next() {
+            write!(&mut line, "{:>6} ", number)?;
+        }
+
+        let cells: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| self.cell(column.kind, event))
+            .collect();
+        let learning = self.learned < self.learn_events;
+        for (column, cell) in self.columns.iter_mut().zip(cells) {
+            if learning && !column.fixed {
+                column.width = column.width.max(cell.chars().count());
+            }
+            write!(
+                &mut line,
+                "{:<width$} ",
+                truncate_text(&cell, column.width),
+                width = column.width
+            )?;
+        }
+        if learning {
+            self.learned += 1;
+        }
+
+        if let Some(msg) = &event.message {
+            if self.escape_non_printable {
+                line.push_str(&escape_non_printable(msg));
+            } else {
+                line.push_str(msg);
+            }
+        }
+
+        line.push('\n');
+        self.sink.write(line.as_bytes(), event.timestamp).await
+    }
+
+    // NOTE: Blocks are written as their events alone, without the headers and blank lines of
+    // text output, so every line stays an event line.
+    async fn write_invocation(&mut self, invocation: &Invocation) -> eyre::Result<()> {
+        for event in &invocation.events {
+            self.write(event).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_correlated(&mut self, correlated: &Correlated) -> eyre::Result<()> {
+        for event in &correlated.events {
+            self.write(event).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> eyre::Result<()> {
+        self.sink.flush().await
+    }
+
+    fn last_line_number(&self) -> Option<u64> {
+        self.line_numbers.last()
+    }
+}
+
+/// The column widths `--output text-aligned` starts with.
+#[derive(Debug, Clone, Copy)]
+struct ColumnWidths {
+    group: Option<usize>,
+    stream: Option<usize>,
+    learn_events: u64,
+}
+
+/// The shape of the objects `--output json` writes.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum JsonSchema {
+    /// cw's own flat shape, with the fields picked by the print flags.
+    #[default]
+    Cw,
+    /// Elastic Common Schema names (`@timestamp`, `log.group`, `event.id`, ...), always with
+    /// every field the event has.
+    Ecs,
+}
+
+/// Turns an event into the JSON object [`JsonWriter`] writes for it. The object borrows from the
+/// event and is serialized straight into the output, without building a [`Value`] first.
+trait EventSchema: Send + Sync {
+    type Json<'a>: Serialize;
+
+    fn event_json<'a>(
+        &self,
+        event: &'a LogEvent,
+        origin: &'a Origin,
+        seq: Option<u64>,
+    ) -> Self::Json<'a>;
+}
+
+struct CwSchema {
+    use_local_time: bool,
+    time_style: TimeStyle,
+    with_timestamp: bool,
+    time_field: TimeField,
+    with_other_time: bool,
+    with_group_name: bool,
+    with_stream_name: bool,
+    with_event_id: bool,
+}
+
+// NOTE: The fields are in alphabetical order, the order the serde_json maps this replaced wrote
+// them in, so the output stays the same byte for byte.
+#[derive(Serialize)]
+struct CwEventJson<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ingestion_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ingestion_time_missing: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    masked: Option<bool>,
+    message: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+}
+
+impl EventSchema for CwSchema {
+    type Json<'a> = CwEventJson<'a>;
+
+    fn event_json<'a>(
+        &self,
+        event: &'a LogEvent,
+        origin: &'a Origin,
+        seq: Option<u64>,
+    ) -> CwEventJson<'a> {
+        let render = |field: TimeField| {
+            let printed = field == self.time_field
+                || (self.with_other_time && field == self.time_field.other());
+            if !self.with_timestamp || !printed {
+                return None;
+            }
+            field
+                .of(event)
+                .and_then(|ts| self.time_style.render_millis(ts, self.use_local_time))
+        };
+
+        CwEventJson {
+            account: origin.account.as_deref(),
+            group: self.with_group_name.then_some(event.group_name.as_str()),
+            id: event.event_id.as_deref().filter(|_| self.with_event_id),
+            ingestion_time: render(TimeField::Ingestion),
+            ingestion_time_missing: self.time_field.is_missing(event).then_some(true),
+            masked: event.is_masked().then_some(true),
+            message: event.message.as_deref(),
+            region: origin.region.as_deref(),
+            seq,
+            stream: event
+                .log_stream_name
+                .as_deref()
+                .filter(|_| self.with_stream_name),
+            timestamp: render(TimeField::Timestamp),
+        }
+    }
+}
+
+/// ECS shaped events for SIEMs, times are always ISO8601 in UTC with milliseconds. See
+/// https://www.elastic.co/guide/en/ecs/current/ecs-field-reference.html
+struct EcsSchema;
+
+impl EcsSchema {
+    fn iso_millis(timestamp_ms: Option<i64>) -> Option<String> {
+        timestamp_ms
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true))
+    }
+}
+
+// NOTE: Alphabetical like [`CwEventJson`], `@` sorts before the letters.
+#[derive(Serialize)]
+struct EcsEventJson<'a> {
+    #[serde(rename = "@timestamp", skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cloud: Option<EcsCloud<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<EcsEvent<'a>>,
+    log: EcsLog<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    masked: Option<bool>,
+    message: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct EcsCloud<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account: Option<EcsAccount<'a>>,
+    provider: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct EcsAccount<'a> {
+    id: &'a str,
+}
+
+#[derive(Serialize)]
+struct EcsEvent<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ingested: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EcsLog<'a> {
+    group: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<&'a str>,
+}
+
+impl EventSchema for EcsSchema {
+    type Json<'a> = EcsEventJson<'a>;
+
+    fn event_json<'a>(
+        &self,
+        event: &'a LogEvent,
+        origin: &'a Origin,
+        seq: Option<u64>,
+    ) -> EcsEventJson<'a> {
+        let cloud = (origin.region.is_some() || origin.account.is_some()).then(|| EcsCloud {
+            account: origin.account.as_deref().map(|id| EcsAccount { id }),
+            provider: "aws",
+            region: origin.region.as_deref(),
+        });
+        let ecs_event = EcsEvent {
+            id: event.event_id.as_deref(),
+            ingested: Self::iso_millis(event.ingestion_time),
+        };
+
+        EcsEventJson {
+            timestamp: Self::iso_millis(event.timestamp),
+            cloud,
+            event: (ecs_event.id.is_some() || ecs_event.ingested.is_some()).then_some(ecs_event),
+            log: EcsLog {
+                group: &event.group_name,
+                stream: event.log_stream_name.as_deref(),
+            },
+            masked: event.is_masked().then_some(true),
+            message: event.message.as_deref(),
+            seq,
+        }
+    }
+}
+
+struct JsonWriter<S> {
+    schema: S,
+    origin: Origin,
+    line_numbers: LineNumbers,
+    // Reused for every line, so writing an event doesn't allocate once it grew large enough.
+    buffer: Vec<u8>,
+
+    sink: Sink,
+}
+
+impl<S: EventSchema> JsonWriter<S> {
+    pub fn new(schema: S, origin: Origin, line_numbers: bool, sink: Sink) -> Self {
+        Self {
+            schema,
+            origin,
+            line_numbers: LineNumbers::new(line_numbers),
+            buffer: Vec::new(),
+            sink,
+        }
+    }
+
+    /// The events of a block, numbered like lines.
+    fn events_json(&mut self, events: &[LogEvent]) -> eyre::Result<Vec<Value>> {
+        events
+            .iter()
+            .map(|event| {
+                let seq = self.line_numbers.next();
+                let json = serde_json::to_value(self.schema.event_json(event, &self.origin, seq))?;
+                Ok(json)
+            })
+            .collect()
+    }
+}
+
+impl<S: EventSchema> LogEventWriter for JsonWriter<S> {
+    async fn write(&mut self, event: &LogEvent) -> eyre::Result<()> {
+        let seq = self.line_numbers.next();
+        // NOTE: `self.buffer.clear()` resolves to `yansi::Paint::clear`, which takes `&self`,
+        // instead of emptying the Vec.
+        Vec::clear(&mut self.buffer);
+        serde_json::to_writer(
+            &mut self.buffer,
+            &self.schema.event_json(event, &self.origin, seq),
+        )?;
+        self.buffer.push(b'\n');
+        self.sink.write(&self.buffer, event.timestamp).await
+    }
+
+    async fn write_invocation(&mut self, invocation: &Invocation) -> eyre::Result<()> {
+        let events = self.events_json(&invocation.events)?;
+        let json = json!({
+            "request_id": invocation.request_id,
+            "events": events,
+            "report": invocation.report.as_ref().map(ReportMetrics::to_json),
+        });
+
+        let mut line = json.to_string();
+        line.push('\n');
+        let last_timestamp = invocation.events.last().and_then(|e| e.timestamp);
+        self.sink.write(line.as_bytes(), last_timestamp).await
+    }
+
+    async fn write_correlated(&mut self, correlated: &Correlated) -> eyre::Result<()> {
+        let events = self.events_json(&correlated.events)?;
+        let json = json!({
+            "join_key": correlated.key,
+            "groups": correlated.groups(),
+            "events": events,
+        });
+
+        let mut line = json.to_string();
+        line.push('\n');
+        let last_timestamp = correlated.events.last().and_then(|e| e.timestamp);
+        self.sink.write(line.as_bytes(), last_timestamp).await
+    }
+
+    async fn flush(&mut self) -> eyre::Result<()> {
+        self.sink.flush().await
+    }
+
+    fn last_line_number(&self) -> Option<u64> {
+        self.line_numbers.last()
+    }
+}
+
+// Number of buckets reprinted in follow mode every time a new bucket starts.
+const HISTOGRAM_FOLLOW_BUCKETS: usize = 10;
+
+/// Counts events per time bucket instead of writing them. The chart is written once all events
+/// are in, or in follow mode every time a new bucket starts.
+struct HistogramWriter {
+    histogram: Histogram,
+    output_type: OutputType,
+    use_local_time: bool,
+    follow: bool,
+    // Buckets that changed since they were last written as JSON.
+    dirty: BTreeSet<i64>,
+
+    sink: Sink,
+}
+
+impl HistogramWriter {
+    pub fn new(
+        histogram: Histogram,
+        output_type: OutputType,
+        use_local_time: bool,
+        follow: bool,
+        sink: Sink,
+    ) -> Self {
+        Self {
+            histogram,
+            output_type,
+            use_local_time,
+            follow,
+            dirty: BTreeSet::new(),
+            sink,
+        }
+    }
+
+    async fn count(&mut self, event: &LogEvent) -> eyre::Result<()> {
+        let Some(timestamp) = event.timestamp else {
+            return Ok(());
+        };
+
+        let previous_latest = self.histogram.latest();
+        let bucket_start = self.histogram.add(timestamp);
+        self.dirty.insert(bucket_start);
+
+        let new_bucket = previous_latest.is_some_and(|latest| bucket_start > latest);
+        if self.follow && new_bucket {
+            self.write_chart(false).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the chart. In text mode that is every bucket (or the last few in follow mode). In
+    /// JSON mode it is every bucket that changed since the last write, leaving out the newest one
+    /// unless `all` is set, as it is most likely still filling up.
+    async fn write_chart(&mut self, all: bool) -> eyre::Result<()> {
+        let latest = self.histogram.latest();
+        let output = match self.output_type {
+            OutputType::Text | OutputType::TextAligned => {
+                let buckets = if self.follow {
+                    self.histogram.last_buckets(HISTOGRAM_FOLLOW_BUCKETS)
+                } else {
+                    self.histogram.buckets()
+                };
+                let mut output = histogram::render_text(&buckets, self.use_local_time);
+                if self.follow {
+                    output.push('\n');
+                }
+                output
+            }
+            OutputType::Json => {
+                let ready: Vec<i64> = self
+                    .dirty
+                    .iter()
+                    .copied()
+                    .filter(|start| all || Some(*start) != latest)
+                    .collect();
+
+                let mut output = String::new();
+                for start in ready {
+                    self.dirty.remove(&start);
+                    let json = histogram::bucket_json(
+                        start,
+                        self.histogram.count(start),
+                        self.use_local_time,
+                    );
+                    output.push_str(&json.to_string());
+                    output.push('\n');
+                }
+                output
+            }
+        };
+
+        if output.is_empty() {
+            return Ok(());
+        }
+        self.sink.write(output.as_bytes(), latest).await?;
+        self.sink.flush().await
+    }
+}
+
+impl LogEventWriter for HistogramWriter {
+    async fn write(&mut self, event: &LogEvent) -> eyre::Result<()> {
+        self.count(event).await
+    }
+
+    async fn write_invocation(&mut self, invocation: &Invocation) -> eyre::Result<()> {
+        for event in &invocation.events {
+            self.count(event).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_correlated(&mut self, correlated: &Correlated) -> eyre::Result<()> {
+        for event in &correlated.events {
+            self.count(event).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> eyre::Result<()> {
+        self.sink.flush().await
+    }
+
+    async fn finish(&mut self) -> eyre::Result<()> {
+        self.write_chart(true).await
+    }
+}
+
+// Used as the stream name of events that didn't come with one.
+const UNKNOWN_STREAM: &str = "unknown-stream";
+
+// NOTE: Most filesystems allow 255 bytes per name, this leaves room for a counter and the
+// extension.
+const MAX_FILE_NAME_LEN: usize = 200;
+// The bytes of the stream name's SHA-256 that end a shortened file name, in hex.
+const FILE_NAME_HASH_BYTES: usize = 8;
+
+/// Replaces everything but ASCII letters, digits, `-`, `_` and `.` in a stream name, so it can
+/// be used as a file name. Lambda streams like `2024/01/01/[$LATEST]abc` become
+/// `2024_01_01___LATEST_abc`. Names longer than [`MAX_FILE_NAME_LEN`] are cut short and end in a
+/// hash of the stream name, so long names sharing a prefix stay apart.
+fn sanitize_file_name(stream_name: &str) -> String {
+    let name: String = stream_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        return "_".to_string();
+    }
+    if name.len() <= MAX_FILE_NAME_LEN {
+        return name.to_string();
+    }
+
+    let hash: String = Sha256::digest(stream_name.as_bytes())
+        .iter()
+        .take(FILE_NAME_HASH_BYTES)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let kept = MAX_FILE_NAME_LEN - hash.len() - 1;
+    format!("{}-{}", name.chars().take(kept).collect::<String>(), hash)
+}
+
+#[derive(Debug)]
+struct StreamFile {
+    file_name: String,
+    events: u64,
+}
+
+/// A log stream, stream names are only unique within their group.
+type StreamKey = (String, String);
+
+/// Writes the events of every stream to a file of its own in `output_dir`, formatted by the
+/// writer `make_writer` creates for the file. Files are named after the group and the stream,
+/// so the same stream name in two groups gets two files. Files are opened on the first event of
+/// a stream and at most `max_open_files` are kept open, the least recently used one is closed to
+/// make room and reopened for appending when its stream shows up again.
+struct SplitByStreamWriter<W, F> {
+    output_dir: PathBuf,
+    extension: &'static str,
+    max_open_files: usize,
+    make_writer: F,
+    ui: Ui,
+    open: HashMap<StreamKey, W>,
+    // Streams with an open file, least recently used first.
+    recently_used: VecDeque<StreamKey>,
+    files: BTreeMap<StreamKey, StreamFile>,
+    file_names: HashSet<String>,
+}
+
+impl<W, F> SplitByStreamWriter<W, F>
+where
+    W: LogEventWriter + Send,
+    F: Fn(Sink) -> W + Send,
+{
+    pub fn new(
+        output_dir: PathBuf,
+        extension: &'static str,
+        max_open_files: usize,
+        make_writer: F,
+        ui: Ui,
+    ) -> Self {
+        Self {
+            output_dir,
+            extension,
+            max_open_files: max_open_files.max(1),
+            make_writer,
+            ui,
+            open: HashMap::new(),
+            recently_used: VecDeque::new(),
+            files: BTreeMap::new(),
+            file_names: HashSet::new(),
+        }
+    }
+
+    /// The stream an event belongs to.
+    fn key_of(event: &LogEvent) -> StreamKey {
+        (
+            event.group_name.clone(),
+            event
+                .log_stream_name
+                .clone()
+                .unwrap_or_else(|| UNKNOWN_STREAM.to_string()),
+        )
+    }
+
+    /// A file name for a new stream, the group and stream name joined by a `/` and sanitized.
+    /// Different streams can sanitize to the same name, those get a counter appended.
+    fn file_name(&self, (group_name, stream_name): &StreamKey) -> String {
+        let base = sanitize_file_name(&format!("{}/{}", group_name, stream_name));
+        let mut file_name = format!("{}.{}", base, self.extension);
+        let mut counter = 2;
+        while self.file_names.contains(&file_name) {
+            file_name = format!("{}-{}.{}", base, counter, self.extension);
+            counter += 1;
+        }
+        file_name
+    }
+
+    async fn writer(&mut self, key: &StreamKey) -> eyre::Result<&mut W> {
+        if self.open.contains_key(key) {
+            self.recently_used.retain(|k| k != key);
+        } else {
+            while self.open.len() >= self.max_open_files {
+                let Some(evicted) = self.recently_used.pop_front() else {
+                    break;
+                };
+                if let Some(mut writer) = self.open.remove(&evicted) {
+                    writer.flush().await?;
+                }
+            }
+
+            let mut options = tokio::fs::OpenOptions::new();
+            let path = match self.files.get(key) {
+                Some(file) => {
+                    options.append(true);
+                    self.output_dir.join(&file.file_name)
+                }
+                None => {
+                    let file_name = self.file_name(key);
+                    self.file_names.insert(file_name.clone());
+                    self.files.insert(
+                        key.clone(),
+                        StreamFile {
+                            file_name: file_name.clone(),
+                            events: 0,
+                        },
+                    );
+                    options.create(true).write(true).truncate(true);
+                    self.output_dir.join(file_name)
+                }
+            };
+
+            let file = options
+                .open(&path)
+                .await
+                .with_context(|| format!("Failed opening {}", path.display()))?;
+            let sink = Sink::new(path.display().to_string(), Box::new(file), false, self.ui);
+            self.open.insert(key.clone(), (self.make_writer)(sink));
+        }
+
+        self.recently_used.push_back(key.clone());
+        self.open
+            .get_mut(key)
+            .ok_or_else(|| eyre::eyre!("No file open for stream {}", key.1))
+    }
+
+    fn count(&mut self, key: &StreamKey, events: usize) {
+        if let Some(file) = self.files.get_mut(key) {
+            file.events += events as u64;
+        }
+    }
+
+    /// The contents of manifest.json, mapping every stream to its file and the number of events
+    /// in it.
+    fn manifest(&self) -> eyre::Result<String> {
+        let streams = self
+            .files
+            .iter()
+            .map(|((group_name, stream_name), file)| {
+                json!({
+                    "group": group_name,
+                    "stream": stream_name,
+                    "file": file.file_name,
+                    "events": file.events,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(serde_json::to_string_pretty(
+            &json!({ "streams": streams }),
+        )?)
+    }
+}
+
+impl<W, F> LogEventWriter for SplitByStreamWriter<W, F>
+where
+    W: LogEventWriter + Send,
+    F: Fn(Sink) -> W + Send,
+{
+    async fn write(&mut self, event: &LogEvent) -> eyre::Result<()> {
+        let key = Self::key_of(event);
+        self.writer(&key).await?.write(event).await?;
+        self.count(&key, 1);
+        Ok(())
+    }
+
+    async fn write_invocation(&mut self, invocation: &Invocation) -> eyre::Result<()> {
+        // NOTE: An invocation runs in a single Lambda execution environment, which logs to a
+        // single stream.
+        let key = invocation
+            .events
+            .first()
+            .map(Self::key_of)
+            .unwrap_or_else(|| (String::new(), UNKNOWN_STREAM.to_string()));
+        self.writer(&key)
+            .await?
+            .write_invocation(invocation)
+            .await?;
+        self.count(&key, invocation.events.len());
+        Ok(())
+    }
+
+    async fn write_correlated(&mut self, correlated: &Correlated) -> eyre::Result<()> {
+        // NOTE: The events of a key come from several streams, every file gets its own share.
+        for event in &correlated.events {
+            self.write(event).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> eyre::Result<()> {
+        for writer in self.open.values_mut() {
+            writer.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> eyre::Result<()> {
+        for writer in self.open.values_mut() {
+            writer.finish().await?;
+        }
+        // NOTE: Built before the write, a `&self` held across the await would need W: Sync.
+        let manifest = self.manifest()?;
+        let path = self.output_dir.join("manifest.json");
+        tokio::fs::write(&path, manifest)
+            .await
+            .with_context(|| format!("Failed writing {}", path.display()))
+    }
+}
+
+/// Output flags shared by every command that writes log events.
+#[derive(Args, Clone, Debug)]
+pub struct OutputArgs {
+    #[arg(short, long = "timestamp", help = "Print the event timestamp.")]
+    pub print_timestamp: bool,
+
+    #[arg(short = 'i', long = "event-id", help = "Print the event id.")]
+    pub print_event_id: bool,
+
+    #[arg(
+        long = "stream-name",
+        help = "Print the log stream name that this event belongs to."
+    )]
+    pub print_stream_name: bool,
+
+    #[arg(
+        long = "group-name",
+        help = "Print the log group name that this event belongs to."
+    )]
+    pub print_group_name: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "LIST",
+        help = "Comma separated prefix components, in the order to print them (e.g. stream,timestamp). Replaces --timestamp, --group-name, --stream-name and --event-id."
+    )]
+    pub prefix_order: Option<Vec<PrefixField>>,
+
+    #[arg(long = "output", short = 'o', value_enum, default_value_t=OutputType::Text)]
+    pub output_type: OutputType,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "The width of the group column of --output text-aligned, longer names are truncated. Learned from the first events when unset."
+    )]
+    pub group_width: Option<u16>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "The width of the stream column of --output text-aligned, longer names are truncated. Learned from the first events when unset."
+    )]
+    pub stream_width: Option<u16>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 100,
+        help = "How many events --output text-aligned learns the column widths from, they are frozen after that."
+    )]
+    pub learn_widths: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = JsonSchema::Cw,
+        help = "The field names --output json uses, ecs writes Elastic Common Schema objects with every field."
+    )]
+    pub json_schema: JsonSchema,
+
+    #[arg(short, long, help = "Treat date and time in local timezone.")]
+    pub local: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "local",
+        help = "Treat date and time in UTC, also when tail.local is set in the config file."
+    )]
+    pub utc: bool,
+
+    #[arg(long, value_enum, help = "How to print timestamps, defaults to iso.")]
+    pub time_style: Option<TimeStyle>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Sort the events on this time before writing them, and print it with --timestamp. Events without an ingestion time go last."
+    )]
+    pub order_by: Option<TimeField>,
+
+    #[arg(
+        long,
+        requires = "print_timestamp",
+        help = "With --timestamp, also print the time --order-by didn't pick."
+    )]
+    pub other_time: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write events to this file instead of stdout."
+    )]
+    pub output_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "output_file",
+        help = "Continue writing to stdout when writing to --output-file fails."
+    )]
+    pub fallback_stdout: bool,
+
+    #[arg(
+        long,
+        help = "Strip the CRI prefix (`<timestamp> <stdout|stderr> <F|P> `) from messages."
+    )]
+    pub strip_cri_prefix: bool,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        help = "Strip a leading prefix matching this regex from messages."
+    )]
+    pub strip_prefix_regex: Option<Regex>,
+
+    #[arg(
+        long,
+        requires = "strip_cri_prefix",
+        help = "Join CRI partial (P) lines into a single event. Parts still missing their final line after 5s are written as they are."
+    )]
+    pub join_partial: bool,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        help = "Join events split per line, like stack traces, back together. A line matching this regex (e.g. '^\\d{4}-') starts a new event, the lines after it in the same stream are appended to it."
+    )]
+    pub multiline: Option<Regex>,
+
+    #[arg(
+        long,
+        value_parser = humantime::parse_duration,
+        default_value = "2s",
+        requires = "multiline",
+        help = "How long a --multiline event waits for more lines before it is written."
+    )]
+    pub multiline_timeout: Duration,
+
+    #[arg(
+        long,
+        help = "Write the events of each Lambda invocation, from START up to REPORT, as a single block."
+    )]
+    pub group_by_invocation: bool,
+
+    #[arg(
+        long,
+        value_parser = humantime::parse_duration,
+        default_value = "60s",
+        help = "How long to wait for the REPORT line of an invocation before writing what was received."
+    )]
+    pub invocation_timeout: Duration,
+
+    #[arg(
+        long,
+        value_name = "FIELD_OR_REGEX",
+        conflicts_with_all = ["group_by_invocation", "histogram"],
+        help = "Write events sharing a correlation key as a single block, with a header naming the key and the log groups. The key is a JSON field (dotted for nested fields) or the first capture of a regex, events without one are written as they come."
+    )]
+    pub join_on: Option<JoinKey>,
+
+    #[arg(
+        long,
+        value_parser = humantime::parse_duration,
+        default_value = "5s",
+        requires = "join_on",
+        help = "How long to wait for more events of a --join-on key before writing its block."
+    )]
+    pub join_window: Duration,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1000,
+        requires = "join_on",
+        help = "How many --join-on keys to hold at once, the oldest block is written early to make room."
+    )]
+    pub join_max_keys: usize,
+
+    #[arg(
+        long,
+        value_name = "BUCKET",
+        value_parser = humantime::parse_duration,
+        conflicts_with = "group_by_invocation",
+        help = "Count events per time bucket (e.g. 1m) and print a bar chart instead of the events."
+    )]
+    pub histogram: Option<Duration>,
+
+    #[arg(
+        long,
+        help = "Print control characters in text output as escapes like \\x1b, so messages can't mess with the terminal."
+    )]
+    pub escape_non_printable: bool,
+
+    #[arg(
+        long,
+        help = "Leave out events a data protection policy masked part of, they are recognized by a run of asterisks (****) in the message."
+    )]
+    pub drop_masked: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        help = "Drop events already seen within this much event time (e.g. 30m), for pipelines that ship events more than once. Memory stays bounded by the events in the window."
+    )]
+    pub dedupe_window: Option<Duration>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DedupeKey::Id,
+        requires = "dedupe_window",
+        help = "What makes events the same for --dedupe-window."
+    )]
+    pub dedupe_by: DedupeKey,
+
+    #[arg(
+        long,
+        value_name = "RATE",
+        default_value_t = 0.0001,
+        value_parser = parse_rate,
+        requires = "dedupe_window",
+        help = "The share of new events --dedupe-window may mistake for a duplicate. Lower rates use more memory."
+    )]
+    pub dedupe_fp_rate: f64,
+
+    #[arg(
+        long,
+        conflicts_with = "histogram",
+        help = "Number the events written, starting at 1. Text output starts every line with the number, JSON output adds it as seq. With --split-by-stream every file is numbered on its own."
+    )]
+    pub line_numbers: bool,
+
+    #[arg(
+        long,
+        requires = "output_dir",
+        conflicts_with_all = ["output_file", "histogram"],
+        help = "Write the events of every log stream to a file of its own in --output-dir, with a manifest.json listing them."
+    )]
+    pub split_by_stream: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "split_by_stream",
+        help = "The directory --split-by-stream writes its files to."
+    )]
+    pub output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 64,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "How many stream files --split-by-stream keeps open at once."
+    )]
+    pub max_open_files: u16,
+}
+
+/// Parses a --dedupe-fp-rate, a share between 0 and 1 excluding both.
+fn parse_rate(value: &str) -> eyre::Result<f64> {
+    let rate: f64 = value
+        .parse()
+        .map_err(|_| eyre::eyre!("'{}' isn't a number", value))?;
+    if rate <= 0.0 || rate >= 1.0 {
+        return Err(eyre::eyre!(
+            "The rate must be between 0 and 1, got {}",
+            value
+        ));
+    }
+    Ok(rate)
+}
+
+impl OutputArgs {
+    /// Turns on the prefixes and local time enabled in the config file, flags can only add to them.
+    pub fn with_defaults(&self, defaults: &TailConfig) -> Self {
+        let flagged = self.print_timestamp
+            || self.print_event_id
+            || self.print_stream_name
+            || self.print_group_name;
+        let mut output = self.clone();
+        output.print_timestamp |= defaults.timestamp.unwrap_or(false);
+        output.print_event_id |= defaults.event_id.unwrap_or(false);
+        output.print_stream_name |= defaults.stream_name.unwrap_or(false);
+        output.print_group_name |= defaults.group_name.unwrap_or(false);
+        output.local = !self.utc && (self.local || defaults.local.unwrap_or(false));
+        output.time_style = self.time_style.or(defaults.time_style);
+        // NOTE: The config file is validated when it is loaded, so this can't fail here. Prefix
+        // flags on the command line win over the configured order, like --prefix-order does.
+        output.prefix_order = match &self.prefix_order {
+            Some(order) => Some(order.clone()),
+            None if flagged => None,
+            None => defaults.prefix_order().ok().flatten(),
+        };
+        output
+    }
+
+    /// Spawns the writer task that preprocesses and prints every event sent over `receiver` to
+    /// stdout. With `follow` set, events keep coming until the receiver closes, so a histogram is
+    /// written as it grows.
+    pub async fn spawn_writer(
+        &self,
+        receiver: UnboundedReceiver<LogEvent>,
+        origin: Origin,
+        follow: bool,
+        metrics: Option<Metrics>,
+        ui: Ui,
+    ) -> eyre::Result<JoinHandle<eyre::Result<()>>> {
+        let preprocessor = Preprocessor::new(
+            self.dedupe_window
+                .map(|window| DedupeWindow::new(window, self.dedupe_by, self.dedupe_fp_rate)),
+            self.strip_cri_prefix,
+            self.strip_prefix_regex.clone(),
+            self.join_partial,
+            self.multiline
+                .clone()
+                .map(|start| MultilineJoiner::new(start, self.multiline_timeout)),
+            self.drop_masked,
+        )?;
+        let grouper = match (&self.join_on, self.group_by_invocation) {
+            (Some(key), _) => Some(Grouper::Correlation(CorrelationGrouper::new(
+                key.clone(),
+                self.join_window,
+                self.join_max_keys,
+            ))),
+            (None, true) => Some(Grouper::Invocation(InvocationGrouper::new(
+                self.invocation_timeout,
+            )?)),
+            (None, false) => None,
+        };
+        if let (true, Some(output_dir)) = (self.split_by_stream, &self.output_dir) {
+            tokio::fs::create_dir_all(output_dir)
+                .await
+                .with_context(|| format!("Failed creating {}", output_dir.display()))?;
+            return Ok(self.spawn_split_writer(
+                receiver,
+                preprocessor,
+                grouper,
+                output_dir.clone(),
+                origin,
+                metrics,
+                ui,
+            ));
+        }
+
+        let (sink, use_color) = match &self.output_file {
+            Some(path) => match tokio::fs::File::create(path).await {
+                Ok(file) => {
+                    let sink = Sink::new(
+                        path.display().to_string(),
+                        Box::new(file),
+                        self.fallback_stdout,
+                        ui,
+                    );
+                    (sink, false)
+                }
+                Err(err) if self.fallback_stdout => {
+                    ui.warn(format!(
+                        "Failed creating {} ({}), falling back to stdout.",
+                        path.display(),
+                        err
+                    ));
+                    (Sink::stdout(), supports_ansi(&std::io::stdout()))
+                }
+                Err(err) => {
+                    return Err(eyre::Report::new(err)
+                        .wrap_err(format!("Failed creating {}", path.display())))
+                }
+            },
+            None => (Sink::stdout(), supports_ansi(&std::io::stdout())),
+        };
+
+        if let Some(bucket) = self.histogram {
+            let w = HistogramWriter::new(
+                Histogram::new(bucket)?,
+                self.output_type.clone(),
+                self.local,
+                follow,
+                sink,
+            );
+            return Ok(tokio::spawn(write_log_event(
+                receiver,
+                preprocessor,
+                self.order_by,
+                grouper,
+                w,
+                metrics.clone(),
+                ui,
+            )));
+        }
+
+        let handle = match self.output_type {
+            OutputType::Text => {
+                let w = TextWriter::new(
+                    self.local,
+                    self.time_style.unwrap_or_default(),
+                    self.prefix_fields(),
+                    self.order_by.unwrap_or_default(),
+                    self.other_time,
+                    use_color,
+                    self.escape_non_printable,
+                    origin,
+                    self.line_numbers,
+                    sink,
+                );
+                tokio::spawn(write_log_event(
+                    receiver,
+                    preprocessor,
+                    self.order_by,
+                    grouper,
+                    w,
+                    metrics.clone(),
+                    ui,
+                ))
+            }
+            OutputType::TextAligned => {
+                let w = AlignedTextWriter::new(
+                    self.local,
+                    self.time_style.unwrap_or_default(),
+                    self.prefix_fields(),
+                    self.order_by.unwrap_or_default(),
+                    self.other_time,
+                    self.column_widths(),
+                    self.escape_non_printable,
+                    origin,
+                    self.line_numbers,
+                    sink,
+                );
+                tokio::spawn(write_log_event(
+                    receiver,
+                    preprocessor,
+                    self.order_by,
+                    grouper,
+                    w,
+                    metrics.clone(),
+                    ui,
+                ))
+            }
+            OutputType::Json => match self.json_schema {
+                JsonSchema::Cw => {
+                    let w = JsonWriter::new(self.cw_schema(), origin, self.line_numbers, sink);
+                    tokio::spawn(write_log_event(
+                        receiver,
+                        preprocessor,
+                        self.order_by,
+                        grouper,
+                        w,
+                        metrics.clone(),
+                        ui,
+                    ))
+                }
+                JsonSchema::Ecs => {
+                    let w = JsonWriter::new(EcsSchema, origin, self.line_numbers, sink);
+                    tokio::spawn(write_log_event(
+                        receiver,
+                        preprocessor,
+                        self.order_by,
+                        grouper,
+                        w,
+                        metrics.clone(),
+                        ui,
+                    ))
+                }
+            },
+        };
+
+        Ok(handle)
+    }
+
+    /// The prefix components in the order they are written. --prefix-order picks and orders
+    /// them when set, otherwise the individual flags pick them in the default order.
+    fn prefix_fields(&self) -> Vec<PrefixField> {
+        match &self.prefix_order {
+            Some(order) => order.iter().fold(Vec::new(), |mut fields, field| {
+                if !fields.contains(field) {
+                    fields.push(*field);
+                }
+                fields
+            }),
+            None => [
+                (self.print_timestamp, PrefixField::Timestamp),
+                (self.print_group_name, PrefixField::Group),
+                (self.print_stream_name, PrefixField::Stream),
+                (self.print_event_id, PrefixField::Id),
+            ]
+            .into_iter()
+            .filter_map(|(enabled, field)| enabled.then_some(field))
+            .collect(),
+        }
+    }
+
+    fn column_widths(&self) -> ColumnWidths {
+        ColumnWidths {
+            group: self.group_width.map(usize::from),
+            stream: self.stream_width.map(usize::from),
+            learn_events: self.learn_widths,
+        }
+    }
+
+    fn cw_schema(&self) -> CwSchema {
+        let fields = self.prefix_fields();
+        CwSchema {
+            use_local_time: self.local,
+            time_style: self.time_style.unwrap_or_default(),
+            with_timestamp: fields.contains(&PrefixField::Timestamp),
+            time_field: self.order_by.unwrap_or_default(),
+            with_other_time: self.other_time,
+            with_group_name: fields.contains(&PrefixField::Group),
+            with_stream_name: fields.contains(&PrefixField::Stream),
+            with_event_id: fields.contains(&PrefixField::Id),
+        }
+    }
+
+    fn spawn_split_writer(
+        &self,
+        receiver: UnboundedReceiver<LogEvent>,
+        preprocessor: Preprocessor,
+        grouper: Option<Grouper>,
+        output_dir: PathBuf,
+        origin: Origin,
+        metrics: Option<Metrics>,
+        ui: Ui,
+    ) -> JoinHandle<eyre::Result<()>> {
+        let max_open_files = usize::from(self.max_open_files);
+        let output = self.clone();
+        let time_style = self.time_style.unwrap_or_default();
+        let time_field = self.order_by.unwrap_or_default();
+
+        match self.output_type {
+            OutputType::Text => {
+                let make_writer = move |sink| {
+                    TextWriter::new(
+                        output.local,
+                        time_style,
+                        output.prefix_fields(),
+                        time_field,
+                        output.other_time,
+                        false,
+                        output.escape_non_printable,
+                        origin.clone(),
+                        output.line_numbers,
+                        sink,
+                    )
+                };
+                let w =
+                    SplitByStreamWriter::new(output_dir, "log", max_open_files, make_writer, ui);
+                tokio::spawn(write_log_event(
+                    receiver,
+                    preprocessor,
+                    self.order_by,
+                    grouper,
+                    w,
+                    metrics.clone(),
+                    ui,
+                ))
+            }
+            OutputType::TextAligned => {
+                let widths = self.column_widths();
+                let make_writer = move |sink| {
+                    AlignedTextWriter::new(
+                        output.local,
+                        time_style,
+                        output.prefix_fields(),
+                        time_field,
+                        output.other_time,
+                        widths,
+                        output.escape_non_printable,
+                        origin.clone(),
+                        output.line_numbers,
+                        sink,
+                    )
+                };
+                let w =
+                    SplitByStreamWriter::new(output_dir, "log", max_open_files, make_writer, ui);
+                tokio::spawn(write_log_event(
+                    receiver,
+                    preprocessor,
+                    self.order_by,
+                    grouper,
+                    w,
+                    metrics.clone(),
+                    ui,
+                ))
+            }
+            OutputType::Json => match self.json_schema {
+                JsonSchema::Cw => {
+                    let make_writer = move |sink| {
+                        JsonWriter::new(
+                            output.cw_schema(),
+                            origin.clone(),
+                            output.line_numbers,
+                            sink,
+                        )
+                    };
+                    let w = SplitByStreamWriter::new(
+                        output_dir,
+                        "jsonl",
+                        max_open_files,
+                        make_writer,
+                        ui,
+                    );
+                    tokio::spawn(write_log_event(
+                        receiver,
+                        preprocessor,
+                        self.order_by,
+                        grouper,
+                        w,
+                        metrics.clone(),
+                        ui,
+                    ))
+                }
+                JsonSchema::Ecs => {
+                    let make_writer = move |sink| {
+                        JsonWriter::new(EcsSchema, origin.clone(), output.line_numbers, sink)
+                    };
+                    let w = SplitByStreamWriter::new(
+                        output_dir,
+                        "jsonl",
+                        max_open_files,
+                        make_writer,
+                        ui,
+                    );
+                    tokio::spawn(write_log_event(
+                        receiver,
+                        preprocessor,
+                        self.order_by,
+                        grouper,
+                        w,
+                        metrics.clone(),
+                        ui,
+                    ))
+                }
+            },
+        }
+    }
+}
+
+// How often pending invocations, --join-on keys and --multiline events are checked for their
+// timeout while no events arrive.
+const INVOCATION_EXPIRY_CHECK: Duration = Duration::from_secs(1);
+
+/// Preprocesses and writes every event coming in over `receiver`. With `order_by` set, events are
+/// held back until the receiver closes, so they can be sorted first.
+async fn write_log_event(
+    mut receiver: UnboundedReceiver<LogEvent>,
+    mut preprocessor: Preprocessor,
+    order_by: Option<TimeField>,
+    mut grouper: Option<Grouper>,
+    mut writer: impl LogEventWriter,
+    metrics: Option<Metrics>,
+    ui: Ui,
+) -> eyre::Result<()> {
+    tracing::info!(target: "cw", "starting log writer");
+    let mut sorted: Vec<LogEvent> = Vec::new();
+
+    loop {
+        let has_pending =
+            preprocessor.has_pending() || grouper.as_ref().is_some_and(Grouper::has_pending);
+        let received = if has_pending {
+            tokio::time::timeout(INVOCATION_EXPIRY_CHECK, receiver.recv())
+                .await
+                .ok()
+        } else {
+            Some(receiver.recv().await)
+        };
+
+        match received {
+            Some(Some(event)) => {
+                if let Some(metrics) = &metrics {
+                    metrics.record_event(&event, receiver.len());
+                }
+                match preprocessor.process(event) {
+                    Some(event) if order_by.is_some() => sorted.push(event),
+                    Some(event) => write_grouped(&mut grouper, &mut writer, event).await?,
+                    None => {}
+                }
+            }
+            Some(None) => break,
+            None => {}
+        }
+
+        for event in preprocessor.expired(Instant::now()) {
+            match order_by {
+                Some(_) => sorted.push(event),
+                None => write_grouped(&mut grouper, &mut writer, event).await?,
+            }
+        }
+        if let Some(grouper) = &mut grouper {
+            for grouped in grouper.expired(Instant::now()) {
+                write_block(&mut writer, grouped).await?;
+            }
+        }
+    }
+
+    sorted.extend(preprocessor.flush());
+    if let Some(field) = order_by {
+        sort_events(&mut sorted, field);
+    }
+    for event in sorted {
+        write_grouped(&mut grouper, &mut writer, event).await?;
+    }
+
+    if let Some(grouper) = &mut grouper {
+        for grouped in grouper.flush() {
+            write_block(&mut writer, grouped).await?;
+        }
+    }
+
+    writer.finish().await?;
+    writer.flush().await?;
+    preprocessor.report(ui);
+    if let Some(last) = writer.last_line_number() {
+        ui.note(format!("Last line number: {}", last));
+    }
+    Ok(())
+}
+
+async fn write_grouped(
+    grouper: &mut Option<Grouper>,
+    writer: &mut impl LogEventWriter,
+    event: LogEvent,
+) -> eyre::Result<()> {
+    let Some(grouper) = grouper else {
+        return writer.write(&event).await;
+    };
+
+    for grouped in grouper.process(event) {
+        write_block(writer, grouped).await?;
+    }
+
+    Ok(())
+}
+
+async fn write_block(writer: &mut impl LogEventWriter, grouped: Grouped) -> eyre::Result<()> {
+    match grouped {
+        Grouped::Event(event) => writer.write(&event).await,
+        Grouped::Invocation(invocation) => writer.write_invocation(&invocation).await,
+        Grouped::Correlated(correlated) => writer.write_correlated(&correlated).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+    use std::time::{Duration, Instant};
+
+    use clap::Parser;
+    use tokio::io::AsyncWrite;
+
+    use crate::config::TailConfig;
+    use crate::event::LogEvent;
+    use crate::pipeline::{
+        sanitize_file_name, sort_events, write_log_event, AlignedTextWriter, ColumnWidths,
+        CwSchema, EcsSchema, Grouped, InvocationGrouper, JsonWriter, LogEventWriter, Origin,
+        OutputArgs, Preprocessor, Sink, SplitByStreamWriter, TimeField, MAX_FILE_NAME_LEN,
+        PARTIAL_TIMEOUT,
+    };
+    use crate::ui::Ui;
+    use crate::utils::{PrefixField, TimeStyle};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        output: OutputArgs,
+    }
+
+    fn output(args: &[&str]) -> OutputArgs {
+        Cli::parse_from(std::iter::once("cw").chain(args.iter().copied())).output
+    }
+
+    /// A destination in memory, what was written stays readable after a [`Sink`] took it.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Captured {
+        fn sink(&self) -> Sink {
+            Sink::new("memory", Box::new(self.clone()), false, Ui::default())
+        }
+
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl AsyncWrite for Captured {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// A destination that stops working. With `on_flush` set it takes every write, like a file
+    /// handing bytes to a background task, and only reports the failure on the flush.
+    struct Failing {
+        on_flush: bool,
+    }
+
+    impl AsyncWrite for Failing {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            if self.on_flush {
+                Poll::Ready(Ok(buf.len()))
+            } else {
+                Poll::Ready(Err(io::Error::other("disk full")))
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Err(io::Error::other("disk full")))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// A sink writing to `Failing` that falls back to `fallback` instead of stdout.
+    fn failing_sink(on_flush: bool, fallback: Option<&Captured>) -> Sink {
+        Sink {
+            fallback: fallback.map(|c| Box::new(c.clone()) as Box<dyn AsyncWrite + Unpin + Send>),
+            ..Sink::new(
+                "out.log",
+                Box::new(Failing { on_flush }),
+                false,
+                Ui::new(true),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_without_fallback_says_how_far_it_got() {
+        let mut sink = failing_sink(false, None);
+
+        let err = sink.write(b"first\n", Some(1_000)).await.unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Failed writing to out.log after 0 events (last event timestamp: none)"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_is_written_again_on_the_fallback() {
+        let fallback = Captured::default();
+        let mut sink = failing_sink(false, Some(&fallback));
+
+        sink.write(b"first\n", Some(1_000)).await.unwrap();
+        sink.write(b"second\n", Some(2_000)).await.unwrap();
+
+        assert_eq!(fallback.text(), "first\nsecond\n");
+        assert_eq!(sink.events_written, 2);
+        assert_eq!(sink.description, "stdout");
+    }
+
+    #[tokio::test]
+    async fn a_write_that_only_fails_on_flush_is_not_lost() {
+        let fallback = Captured::default();
+        let mut sink = failing_sink(true, Some(&fallback));
+
+        sink.write(b"first\n", Some(1_000)).await.unwrap();
+
+        assert_eq!(fallback.text(), "first\n");
+        assert_eq!(sink.events_written, 1);
+    }
+
+    #[tokio::test]
+    async fn an_output_file_that_cannot_be_created_falls_back_to_stdout() {
+        let path = std::env::temp_dir()
+            .join(format!("cw-missing-{}", std::process::id()))
+            .join("out.log");
+        let path = path.to_str().unwrap();
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        drop(sender);
+
+        let err = output(&["--output-file", path])
+            .spawn_writer(receiver, Origin::default(), false, None, Ui::new(true))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Failed creating"), "{err}");
+
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        drop(sender);
+        let writer = output(&["--output-file", path, "--fallback-stdout"])
+            .spawn_writer(receiver, Origin::default(), false, None, Ui::new(true))
+            .await
+            .unwrap();
+        writer.await.unwrap().unwrap();
+    }
+
+    fn split_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cw-split-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Splits into `dir`, writing the message of every event as JSON.
+    fn split_writer(
+        dir: &Path,
+        max_open_files: usize,
+    ) -> SplitByStreamWriter<JsonWriter<CwSchema>, impl Fn(Sink) -> JsonWriter<CwSchema> + Send>
+    {
+        let make_writer = |sink| {
+            let schema = output(&["--output", "json"]).cw_schema();
+            JsonWriter::new(schema, Origin::default(), false, sink)
+        };
+        SplitByStreamWriter::new(
+            dir.to_path_buf(),
+            "jsonl",
+            max_open_files,
+            make_writer,
+            Ui::default(),
+        )
+    }
+
+    fn event(stream: &str, timestamp: i64, message: &str) -> LogEvent {
+        LogEvent {
+            group_name: "/ecs/api".to_string(),
+            log_stream_name: Some(stream.to_string()),
+            timestamp: Some(timestamp),
+            message: Some(message.to_string()),
+            ingestion_time: Some(timestamp + 5),
+            event_id: Some(format!("id-{}", timestamp)),
+        }
+    }
+
+    #[test]
+    fn sort_events_orders_on_the_chosen_time() {
+        let mut events = vec![
+            event("a", 30, "c"),
+            event("a", 10, "a"),
+            event("a", 20, "b"),
+        ];
+        events[0].ingestion_time = Some(1);
+
+        sort_events(&mut events, TimeField::Timestamp);
+        assert_eq!(messages(&events), ["a", "b", "c"]);
+
+        sort_events(&mut events, TimeField::Ingestion);
+        assert_eq!(messages(&events), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn sort_events_keeps_the_order_of_equal_times() {
+        let mut events = vec![
+            event("a", 20, "late"),
+            event("a", 10, "first"),
+            event("b", 10, "second"),
+            event("c", 10, "third"),
+        ];
+
+        sort_events(&mut events, TimeField::Timestamp);
+
+        assert_eq!(messages(&events), ["first", "second", "third", "late"]);
+    }
+
+    #[test]
+    fn sort_events_puts_events_without_the_time_last() {
+        let mut events = vec![
+            event("a", 10, "no time"),
+            event("a", 30, "b"),
+            event("a", 20, "a"),
+        ];
+        events[0].ingestion_time = None;
+        events.push(LogEvent {
+            ingestion_time: None,
+            ..event("a", 5, "no time either")
+        });
+
+        sort_events(&mut events, TimeField::Ingestion);
+
+        assert_eq!(messages(&events), ["a", "b", "no time", "no time either"]);
+    }
+
+    fn ordered_config() -> TailConfig {
+        TailConfig {
+            prefix_order: Some("stream,timestamp".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prefix_flags_pick_fields_in_the_default_order() {
+        let fields = output(&["--event-id", "--stream-name", "--timestamp"]).prefix_fields();
+        assert_eq!(
+            fields,
+            [PrefixField::Timestamp, PrefixField::Stream, PrefixField::Id]
+        );
+    }
+
+    #[test]
+    fn prefix_order_drops_repeated_fields() {
+        let fields = output(&["--prefix-order", "id,group,id"]).prefix_fields();
+        assert_eq!(fields, [PrefixField::Id, PrefixField::Group]);
+    }
+
+    #[test]
+    fn configured_prefix_order_applies_without_prefix_flags() {
+        let fields = output(&[]).with_defaults(&ordered_config()).prefix_fields();
+        assert_eq!(fields, [PrefixField::Stream, PrefixField::Timestamp]);
+    }
+
+    #[test]
+    fn prefix_flags_win_over_the_configured_order() {
+        let fields = output(&["--event-id"])
+            .with_defaults(&ordered_config())
+            .prefix_fields();
+        assert_eq!(fields, [PrefixField::Id]);
+
+        let config = TailConfig {
+            timestamp: Some(true),
+            ..ordered_config()
+        };
+        let fields = output(&["--group-name"])
+            .with_defaults(&config)
+            .prefix_fields();
+        assert_eq!(fields, [PrefixField::Timestamp, PrefixField::Group]);
+    }
+
+    #[test]
+    fn prefix_order_flag_wins_over_the_configured_order() {
+        let fields = output(&["--prefix-order", "group"])
+            .with_defaults(&ordered_config())
+            .prefix_fields();
+        assert_eq!(fields, [PrefixField::Group]);
+    }
+
+    #[test]
+    fn sanitize_file_name_replaces_path_characters() {
+        assert_eq!(
+            sanitize_file_name("2024/01/01/[$LATEST]abc"),
+            "2024_01_01___LATEST_abc"
+        );
+        assert_eq!(sanitize_file_name("web.1-a_b"), "web.1-a_b");
+        assert_eq!(sanitize_file_name("ünïcode"), "_n_code");
+    }
+
+    #[test]
+    fn sanitize_file_name_never_starts_with_a_dot() {
+        assert_eq!(sanitize_file_name("..hidden"), "hidden");
+        assert_eq!(sanitize_file_name("..."), "_");
+        assert_eq!(sanitize_file_name(""), "_");
+    }
+
+    #[test]
+    fn sanitize_file_name_keeps_names_up_to_the_cap() {
+        let name = "a".repeat(MAX_FILE_NAME_LEN);
+        assert_eq!(sanitize_file_name(&name), name);
+    }
+
+    #[test]
+    fn sanitize_file_name_shortens_long_names_with_a_hash() {
+        let prefix = "ecs/web/".repeat(40);
+        let first = sanitize_file_name(&format!("{}one", prefix));
+        let second = sanitize_file_name(&format!("{}two", prefix));
+
+        assert_eq!(first.len(), MAX_FILE_NAME_LEN);
+        assert_eq!(second.len(), MAX_FILE_NAME_LEN);
+        assert_ne!(first, second);
+        assert!(first.starts_with("ecs_web_ecs_web_"));
+        assert_eq!(first, sanitize_file_name(&format!("{}one", prefix)));
+
+        let (_, hash) = first.rsplit_once('-').unwrap();
+        assert_eq!(hash.len(), 16);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn sanitize_file_name_counts_bytes_of_the_sanitized_name() {
+        // NOTE: Every non-ASCII character becomes a single `_`, so this fits.
+        let name = "é".repeat(MAX_FILE_NAME_LEN);
+        assert_eq!(sanitize_file_name(&name), "_".repeat(MAX_FILE_NAME_LEN));
+    }
+
+    fn partial_joiner() -> Preprocessor {
+        Preprocessor::new(None, true, None, true, None, false).unwrap()
+    }
+
+    fn messages(events: &[LogEvent]) -> Vec<&str> {
+        events
+            .iter()
+            .map(|e| e.message.as_deref().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn partial_lines_are_joined_with_their_final_part() {
+        let mut preprocessor = partial_joiner();
+
+        assert!(preprocessor
+            .process(event("web-1", 1_000, "2024-01-01T00:00:00Z stdout P one "))
+            .is_none());
+        assert!(preprocessor.has_pending());
+        let joined = preprocessor
+            .process(event("web-1", 1_001, "2024-01-01T00:00:00Z stdout F two"))
+            .unwrap();
+
+        assert_eq!(joined.message.as_deref(), Some("one two"));
+        assert!(!preprocessor.has_pending());
+    }
+
+    #[test]
+    fn a_partial_line_without_final_part_is_written_after_the_timeout() {
+        let mut preprocessor = partial_joiner();
+        preprocessor.process(event("web-1", 1_000, "2024-01-01T00:00:00Z stdout P one "));
+        preprocessor.process(event("web-1", 1_001, "2024-01-01T00:00:00Z stdout P two"));
+        let started = Instant::now();
+
+        assert!(preprocessor.expired(started).is_empty());
+        let expired = preprocessor.expired(started + PARTIAL_TIMEOUT);
+
+        assert_eq!(messages(&expired), ["one two"]);
+        assert!(!preprocessor.has_pending());
+    }
+
+    #[test]
+    fn partial_lines_of_many_streams_do_not_pile_up() {
+        let mut preprocessor = partial_joiner();
+        for i in 0..100 {
+            let stream = format!("web-{}", i);
+            preprocessor.process(event(&stream, i, "2024-01-01T00:00:00Z stdout P cut off"));
+        }
+
+        let expired = preprocessor.expired(Instant::now() + PARTIAL_TIMEOUT);
+
+        assert_eq!(expired.len(), 100);
+        assert!(preprocessor.partials.is_empty());
+    }
+
+    #[test]
+    fn flush_writes_partial_lines_still_waiting() {
+        let mut preprocessor = partial_joiner();
+        preprocessor.process(event("web-1", 1_000, "2024-01-01T00:00:00Z stdout P one"));
+        preprocessor.process(event("web-2", 1_001, "2024-01-01T00:00:00Z stdout F two"));
+
+        assert_eq!(messages(&preprocessor.flush()), ["one"]);
+    }
+
+    const REPORT: &str = "REPORT RequestId: r-1\tDuration: 1.50 ms\tBilled Duration: 2 ms\tMemory Size: 128 MB\tMax Memory Used: 64 MB";
+
+    /// The messages of what the grouper let through, an invocation as `[request id: messages]`.
+    fn grouped(output: Vec<Grouped>) -> Vec<String> {
+        output
+            .into_iter()
+            .map(|grouped| match grouped {
+                Grouped::Event(event) => event.message.unwrap_or_default(),
+                Grouped::Invocation(invocation) => format!(
+                    "[{}: {}]",
+                    invocation.request_id,
+                    messages(&invocation.events).join(" | ")
+                ),
+                Grouped::Correlated(_) => unreachable!("not grouped on a key"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn invocation_grouper_writes_an_invocation_once_it_reports() {
+        let mut grouper = InvocationGrouper::new(Duration::from_secs(30)).unwrap();
+
+        assert_eq!(
+            grouped(grouper.process(event("a", 1, "before"))),
+            ["before"]
+        );
+        assert!(grouper
+            .process(event("a", 2, "START RequestId: r-1 Version: $LATEST"))
+            .is_empty());
+        assert!(grouper.process(event("a", 3, "working")).is_empty());
+        assert_eq!(
+            grouped(grouper.process(event("b", 4, "other stream"))),
+            ["other stream"]
+        );
+        assert!(grouper
+            .process(event("a", 5, "END RequestId: r-1"))
+            .is_empty());
+        let output = grouper.process(event("a", 6, REPORT));
+
+        assert_eq!(
+            grouped(output),
+            [format!(
+                "[r-1: START RequestId: r-1 Version: $LATEST | working | END RequestId: r-1 | {}]",
+                REPORT
+            )]
+        );
+        assert!(!grouper.has_pending());
+    }
+
+    #[test]
+    fn invocation_grouper_writes_an_invocation_without_report_after_the_timeout() {
+        let mut grouper = InvocationGrouper::new(Duration::from_secs(30)).unwrap();
+        grouper.process(event("a", 1, "START RequestId: r-1 Version: $LATEST"));
+        grouper.process(event("a", 2, "Task timed out after 30.00 seconds"));
+        let started = Instant::now();
+
+        assert!(grouper
+            .expired(started + Duration::from_secs(29))
+            .is_empty());
+        let expired = grouper.expired(started + Duration::from_secs(30));
+
+        assert_eq!(expired.len(), 1);
+        assert_eq!(
+            messages(&expired[0].events),
+            [
+                "START RequestId: r-1 Version: $LATEST",
+                "Task timed out after 30.00 seconds"
+            ]
+        );
+        assert!(expired[0].report.is_none());
+        assert!(!grouper.has_pending());
+    }
+
+    #[test]
+    fn invocation_grouper_flushes_unfinished_invocations_at_the_end() {
+        let mut grouper = InvocationGrouper::new(Duration::from_secs(30)).unwrap();
+        grouper.process(event("a", 1, "START RequestId: r-1 Version: $LATEST"));
+        grouper.process(event("b", 2, "START RequestId: r-2 Version: $LATEST"));
+
+        let mut flushed: Vec<_> = grouper.flush().into_iter().map(|i| i.request_id).collect();
+        flushed.sort();
+
+        assert_eq!(flushed, ["r-1", "r-2"]);
+        assert!(!grouper.has_pending());
+    }
+
+    #[test]
+    fn invocation_grouper_writes_an_invocation_a_new_start_cut_short() {
+        let mut grouper = InvocationGrouper::new(Duration::from_secs(30)).unwrap();
+        grouper.process(event("a", 1, "START RequestId: r-1 Version: $LATEST"));
+
+        let output = grouper.process(event("a", 2, "START RequestId: r-2 Version: $LATEST"));
+
+        assert_eq!(
+            grouped(output),
+            ["[r-1: START RequestId: r-1 Version: $LATEST]"]
+        );
+        assert!(grouper.has_pending());
+    }
+
+    #[tokio::test]
+    async fn json_writer_writes_every_event_on_a_line_of_its_own() {
+        let captured = Captured::default();
+        let schema = output(&["--output", "json", "--stream-name", "--timestamp"]).cw_schema();
+        let mut writer = JsonWriter::new(schema, Origin::default(), false, captured.sink());
+
+        writer.write(&event("web-1", 1_000, "first")).await.unwrap();
+        writer
+            .write(&event("web-1", 2_000, "second"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            captured.text(),
+            concat!(
+                r#"{"message":"first","stream":"web-1","timestamp":"1970-01-01T00:00:01Z"}"#,
+                "\n",
+                r#"{"message":"second","stream":"web-1","timestamp":"1970-01-01T00:00:02Z"}"#,
+                "\n",
+            )
+        );
+    }
+
+    fn origin() -> Origin {
+        Origin {
+            region: Some("eu-west-1".to_string()),
+            account: Some("123456789012".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn cw_schema_golden() {
+        let captured = Captured::default();
+        let schema = CwSchema {
+            use_local_time: false,
+            time_style: TimeStyle::default(),
+            with_timestamp: true,
+            time_field: TimeField::Timestamp,
+            with_other_time: true,
+            with_group_name: true,
+            with_stream_name: true,
+            with_event_id: true,
+        };
+        let mut writer = JsonWriter::new(schema, origin(), true, captured.sink());
+
+        writer
+            .write(&event("web-1", 1_700_000_000_123, "token=****"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            captured.text(),
+            concat!(
+                r#"{"account":"123456789012","group":"/ecs/api","id":"id-1700000000123","#,
+                r#""ingestion_time":"2023-11-14T22:13:20Z","masked":true,"#,
+                r#""message":"token=****","region":"eu-west-1","seq":1,"stream":"web-1","#,
+                r#""timestamp":"2023-11-14T22:13:20Z"}"#,
+                "\n",
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn aligned_text_golden() {
+        let captured = Captured::default();
+        let widths = ColumnWidths {
+            group: Some(6),
+            stream: None,
+            learn_events: 2,
+        };
+        let mut writer = AlignedTextWriter::new(
+            false,
+            TimeStyle::default(),
+            vec![
+                PrefixField::Timestamp,
+                PrefixField::Group,
+                PrefixField::Stream,
+                PrefixField::Id,
+            ],
+            TimeField::Timestamp,
+            false,
+            widths,
+            true,
+            Origin::default(),
+            false,
+            captured.sink(),
+        );
+
+        let mut without_stream = event("web-1", 1_700_000_003_000, "has  two\tgaps");
+        without_stream.log_stream_name = None;
+        for e in [
+            event("web-1", 1_700_000_000_000, "first line"),
+            event("web-10", 1_700_000_001_000, "second"),
+            event("web-100-long", 1_700_000_002_000, "third"),
+            without_stream,
+        ] {
+            writer.write(&e).await.unwrap();
+        }
+
+        let text = captured.text();
+        assert_eq!(
+            text,
+            concat!(
+                "2023-11-14T22:13:20Z /ecs/… web-1 id-1700000000000 first line\n",
+                "2023-11-14T22:13:21Z /ecs/… web-10 id-1700000001000 second\n",
+                "2023-11-14T22:13:22Z /ecs/… web-1… id-1700000002000 third\n",
+                "2023-11-14T22:13:23Z /ecs/… -      id-1700000003000 has  two\tgaps\n",
+            )
+        );
+        // NOTE: What `awk '{ print $3, $5 }'` sees.
+        let fields: Vec<(&str, &str)> = text
+            .lines()
+            .map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                (fields[2], fields[4])
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("web-1", "first"),
+                ("web-10", "second"),
+                ("web-1…", "third"),
+                ("-", "has"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn aligned_text_replaces_whitespace_inside_a_column() {
+        let captured = Captured::default();
+        let widths = ColumnWidths {
+            group: None,
+            stream: None,
+            learn_events: 100,
+        };
+        let mut writer = AlignedTextWriter::new(
+            false,
+            TimeStyle::default(),
+            vec![PrefixField::Stream, PrefixField::Id],
+            TimeField::Timestamp,
+            false,
+            widths,
+            true,
+            origin(),
+            true,
+            captured.sink(),
+        );
+
+        let mut e = event("web 1", 0, "ok");
+        e.event_id = Some(String::new());
+        writer.write(&e).await.unwrap();
+
+        assert_eq!(
+            captured.text(),
+            "     1 web_1 - eu-west-1 123456789012 ok\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn ecs_schema_golden() {
+        let captured = Captured::default();
+        let mut writer = JsonWriter::new(EcsSchema, origin(), true, captured.sink());
+
+        writer
+            .write(&event("web-1", 1_700_000_000_123, "token=****"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            captured.text(),
+            concat!(
+                r#"{"@timestamp":"2023-11-14T22:13:20.123Z","#,
+                r#""cloud":{"account":{"id":"123456789012"},"provider":"aws","#,
+                r#""region":"eu-west-1"},"#,
+                r#""event":{"id":"id-1700000000123","ingested":"2023-11-14T22:13:20.128Z"},"#,
+                r#""log":{"group":"/ecs/api","stream":"web-1"},"masked":true,"#,
+                r#""message":"token=****","seq":1}"#,
+                "\n",
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn ecs_schema_leaves_out_what_the_event_lacks() {
+        let captured = Captured::default();
+        let mut writer = JsonWriter::new(EcsSchema, Origin::default(), false, captured.sink());
+
+        writer
+            .write(&LogEvent {
+                group_name: "/ecs/api".to_string(),
+                log_stream_name: None,
+                timestamp: Some(0),
+                message: Some("boot".to_string()),
+                ingestion_time: None,
+                event_id: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            captured.text(),
+            concat!(
+                r#"{"@timestamp":"1970-01-01T00:00:00.000Z","log":{"group":"/ecs/api"},"#,
+                r#""message":"boot"}"#,
+                "\n",
+            )
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn line_numbers_stay_contiguous_with_concurrent_producers_and_filtering() {
+        let captured = Captured::default();
+        let schema = output(&["--output", "json", "--stream-name"]).cw_schema();
+        let writer = JsonWriter::new(schema, Origin::default(), true, captured.sink());
+        let drop_masked = Preprocessor::new(None, false, None, false, None, true).unwrap();
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let producers: Vec<_> = ["web-1", "web-2", "web-3", "web-4"]
+            .into_iter()
+            .map(|stream| {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    for i in 0..250 {
+                        let message = if i % 3 == 0 { "token=****" } else { "ok" };
+                        sender.send(event(stream, i, message)).unwrap();
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+        let written = tokio::spawn(write_log_event(
+            receiver,
+            drop_masked,
+            None,
+            None,
+            writer,
+            None,
+            Ui::new(true),
+        ));
+        for producer in producers {
+            producer.await.unwrap();
+        }
+        written.await.unwrap().unwrap();
+
+        let lines: Vec<serde_json::Value> = captured
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let seqs: Vec<u64> = lines
+            .iter()
+            .map(|line| line["seq"].as_u64().unwrap())
+            .collect();
+        assert_eq!(seqs, (1..=4 * 166).collect::<Vec<_>>());
+        assert!(lines.iter().all(|line| line["message"] == "ok"));
+    }
+
+    #[tokio::test]
+    async fn split_by_stream_writer_keeps_a_stream_name_in_two_groups_apart() {
+        let dir = split_dir("groups");
+        let mut writer = split_writer(&dir, 4);
+        let worker = LogEvent {
+            group_name: "/ecs/worker".to_string(),
+            ..event("web-1", 2_000, "from the worker")
+        };
+
+        writer
+            .write(&event("web-1", 1_000, "from the api"))
+            .await
+            .unwrap();
+        writer.write(&worker).await.unwrap();
+        writer.finish().await.unwrap();
+
+        let read = |file: &str| std::fs::read_to_string(dir.join(file)).unwrap();
+        assert_eq!(
+            read("_ecs_api_web-1.jsonl"),
+            "{\"message\":\"from the api\"}\n"
+        );
+        assert_eq!(
+            read("_ecs_worker_web-1.jsonl"),
+            "{\"message\":\"from the worker\"}\n"
+        );
+        let manifest: serde_json::Value = serde_json::from_str(&read("manifest.json")).unwrap();
+        assert_eq!(
+            manifest,
+            serde_json::json!({ "streams": [
+                { "group": "/ecs/api", "stream": "web-1", "file": "_ecs_api_web-1.jsonl", "events": 1 },
+                { "group": "/ecs/worker", "stream": "web-1", "file": "_ecs_worker_web-1.jsonl", "events": 1 },
+            ]})
+        );
+    }
+
+    #[tokio::test]
+    async fn split_by_stream_writer_closes_the_least_recently_used_file() {
+        let dir = split_dir("lru");
+        let mut writer = split_writer(&dir, 2);
+
+        for (stream, timestamp) in [("a", 1), ("b", 2), ("a", 3), ("c", 4)] {
+            writer
+                .write(&event(stream, timestamp, stream))
+                .await
+                .unwrap();
+        }
+
+        let mut open = writer
+            .open
+            .keys()
+            .map(|(_, stream)| stream.as_str())
+            .collect::<Vec<_>>();
+        open.sort();
+        assert_eq!(open, ["a", "c"]);
+        let recently_used = writer
+            .recently_used
+            .iter()
+            .map(|(_, stream)| stream.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(recently_used, ["a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn split_by_stream_writer_appends_to_a_reopened_file() {
+        let dir = split_dir("reopen");
+        let mut writer = split_writer(&dir, 1);
+
+        for (stream, timestamp) in [("a", 1), ("b", 2), ("a", 3)] {
+            let message = format!("{}{}", stream, timestamp);
+            writer
+                .write(&event(stream, timestamp, &message))
+                .await
+                .unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        assert_eq!(writer.open.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("_ecs_api_a.jsonl")).unwrap(),
+            "{\"message\":\"a1\"}\n{\"message\":\"a3\"}\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("_ecs_api_b.jsonl")).unwrap(),
+            "{\"message\":\"b2\"}\n"
+        );
+    }
+
+    // NOTE: A benchmark, run with `cargo test --release -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn json_writer_throughput() {
+        let captured = Captured::default();
+        let schema = output(&["--output", "json", "--stream-name", "--timestamp"]).cw_schema();
+        let mut writer = JsonWriter::new(schema, Origin::default(), false, captured.sink());
+        let events = (0..200_000)
+            .map(|i| {
+                event(
+                    "web-1",
+                    i,
+                    r#"{"level":"info","msg":"request done","status":200}"#,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let started = std::time::Instant::now();
+        for event in &events {
+            writer.write(event).await.unwrap();
+        }
+        let elapsed = started.elapsed();
+
+        println!(
+            "{} events in {:?}, {:.0} events/s",
+            events.len(),
+            elapsed,
+            events.len() as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::{fmt::Display, io::Write};
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// A single line spinner written to stderr. Created through [`crate::ui::Ui::progress`], which
+/// disables it when stderr is not a TTY, so piped or redirected output is never polluted.
+#[derive(Debug)]
+pub struct Progress {
+    enabled: bool,
+    frame: usize,
+    visible: bool,
+}
+
+impl Progress {
+    pub fn with_enabled(enabled: bool) -> Self {
+        Self {
+            enabled,
+            frame: 0,
+            visible: false,
+        }
+    }
+
+    pub fn update(&mut self, message: impl Display) {
+        if !self.enabled {
+            return;
+        }
+
+        let line = Self::render(self.frame, message);
+        self.frame = self.frame.wrapping_add(1);
+        self.visible = true;
+
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K{}", line);
+        let _ = stderr.flush();
+    }
+
+    pub fn clear(&mut self) {
+        if !self.visible {
+            return;
+        }
+
+        self.visible = false;
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+    }
+
+    fn render(frame: usize, message: impl Display) -> String {
+        format!("{} {}", SPINNER[frame % SPINNER.len()], message)
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
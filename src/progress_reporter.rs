@@ -0,0 +1,246 @@
+use std::{io::Write, path::Path, sync::Mutex};
+
+use eyre::Context;
+use serde_json::{json, Value};
+
+// NOTE: Bump whenever a field is removed or changes meaning, consumers key off this.
+const SCHEMA_VERSION: u32 = 1;
+
+/// A progress event emitted on the `--progress-fd`/`--progress-file` side channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent<'a> {
+    ProducerStarted {
+        group: &'a str,
+    },
+    ProducerStopped {
+        group: &'a str,
+        events: u64,
+    },
+    PageFetched {
+        group: &'a str,
+        count: usize,
+        has_next_token: bool,
+        /// Milliseconds between the newest event of the page and the time it was fetched.
+        lag_ms: Option<i64>,
+    },
+    QueryStatus {
+        query_id: &'a str,
+        status: &'a str,
+        records_matched: Option<f64>,
+        records_scanned: Option<f64>,
+        bytes_scanned: Option<f64>,
+    },
+    QuerySummary {
+        query_id: &'a str,
+        records_returned: usize,
+        duration_ms: i64,
+    },
+    /// A response CloudWatch throttled, the SDK retries the call after its backoff.
+    Throttled {
+        operation: &'a str,
+        attempt: u32,
+    },
+    /// A call sent again after a failed attempt. `delay_ms` is the wait before it when cw chose
+    /// it, like while the concurrent query limit is reached.
+    Retrying {
+        operation: &'a str,
+        attempt: u32,
+        delay_ms: Option<u64>,
+    },
+}
+
+impl ProgressEvent<'_> {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Self::ProducerStarted { group } => json!({
+                "v": SCHEMA_VERSION,
+                "event": "producer_started",
+                "group": group,
+            }),
+            Self::ProducerStopped { group, events } => json!({
+                "v": SCHEMA_VERSION,
+                "event": "producer_stopped",
+                "group": group,
+                "events": events,
+            }),
+            Self::PageFetched {
+                group,
+                count,
+                has_next_token,
+                lag_ms,
+            } => json!({
+                "v": SCHEMA_VERSION,
+                "event": "page_fetched",
+                "group": group,
+                "count": count,
+                "has_next_token": has_next_token,
+                "lag_ms": lag_ms,
+            }),
+            Self::QueryStatus {
+                query_id,
+                status,
+                records_matched,
+                records_scanned,
+                bytes_scanned,
+            } => json!({
+                "v": SCHEMA_VERSION,
+                "event": "query_status",
+                "query_id": query_id,
+                "status": status,
+                "records_matched": records_matched,
+                "records_scanned": records_scanned,
+                "bytes_scanned": bytes_scanned,
+            }),
+            Self::QuerySummary {
+                query_id,
+                records_returned,
+                duration_ms,
+            } => json!({
+                "v": SCHEMA_VERSION,
+                "event": "query_summary",
+                "query_id": query_id,
+                "records_returned": records_returned,
+                "duration_ms": duration_ms,
+            }),
+            Self::Throttled { operation, attempt } => json!({
+                "v": SCHEMA_VERSION,
+                "event": "throttled",
+                "operation": operation,
+                "attempt": attempt,
+            }),
+            Self::Retrying {
+                operation,
+                attempt,
+                delay_ms,
+            } => json!({
+                "v": SCHEMA_VERSION,
+                "event": "retrying",
+                "operation": operation,
+                "attempt": attempt,
+                "delay_ms": delay_ms,
+            }),
+        }
+    }
+}
+
+/// Receives progress events from producers and the query polling loop. Reporting never fails the
+/// command, a broken side channel only gets logged.
+pub trait ProgressReporter: Send + Sync {
+    fn report(&self, event: ProgressEvent<'_>);
+}
+
+/// The default reporter, used when no progress side channel was requested.
+#[derive(Debug, Default)]
+pub struct NoopReporter;
+
+impl ProgressReporter for NoopReporter {
+    fn report(&self, _event: ProgressEvent<'_>) {}
+}
+
+/// Writes every event as a single line of JSON.
+pub struct JsonReporter {
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonReporter {
+    pub fn new(sink: Box<dyn Write + Send>) -> Self {
+        Self {
+            sink: Mutex::new(sink),
+        }
+    }
+
+    pub fn from_path(path: &Path) -> eyre::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed opening progress file {}", path.display()))?;
+        Ok(Self::new(Box::new(file)))
+    }
+
+    #[cfg(unix)]
+    pub fn from_fd(fd: i32) -> eyre::Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        if fd < 3 {
+            return Err(eyre::eyre!(
+                "--progress-fd must not be stdin, stdout or stderr (got {})",
+                fd
+            ));
+        }
+
+        // SAFETY: F_GETFD only reads the flags of the descriptor, it fails with EBADF when the
+        // descriptor isn't open.
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            return Err(
+                eyre::Report::new(std::io::Error::last_os_error()).wrap_err(format!(
+                    "--progress-fd {} is not an open file descriptor",
+                    fd
+                )),
+            );
+        }
+
+        // SAFETY: The descriptor is handed to us by the parent process for the lifetime of cw and
+        // nothing else in cw opens or closes it.
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        Ok(Self::new(Box::new(file)))
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_fd(_fd: i32) -> eyre::Result<Self> {
+        Err(eyre::eyre!(
+            "--progress-fd is only supported on unix, use --progress-file instead"
+        ))
+    }
+}
+
+impl ProgressReporter for JsonReporter {
+    fn report(&self, event: ProgressEvent<'_>) {
+        let Ok(mut sink) = self.sink.lock() else {
+            return;
+        };
+
+        let line = event.to_json().to_string();
+        if let Err(err) = writeln!(sink, "{}", line).and_then(|_| sink.flush()) {
+            tracing::warn!(target: "cw", "failed writing progress event: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress_reporter::{JsonReporter, ProgressEvent, ProgressReporter};
+
+    #[cfg(unix)]
+    #[test]
+    fn from_fd_rejects_a_closed_descriptor() {
+        let err = JsonReporter::from_fd(i32::MAX).err().unwrap();
+
+        assert_eq!(
+            err.to_string(),
+            format!("--progress-fd {} is not an open file descriptor", i32::MAX)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_fd_writes_events_to_a_pipe() {
+        use std::io::Read;
+        use std::os::fd::FromRawFd;
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let mut read_end = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+
+        let reporter = JsonReporter::from_fd(fds[1]).unwrap();
+        reporter.report(ProgressEvent::ProducerStarted { group: "/aws/app" });
+        drop(reporter);
+
+        let mut written = String::new();
+        read_end.read_to_string(&mut written).unwrap();
+        assert_eq!(
+            written,
+            "{\"event\":\"producer_started\",\"group\":\"/aws/app\",\"v\":1}\n"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use eyre::Context;
+use serde_json::{Map, Value};
+
+use crate::compat::{result_fields, QueryState, QueryStats};
+use crate::logs_api::{LogsApi, StartQueryRequest};
+use crate::rate_limit::RateLimiter;
+
+// NOTE: Same interval `cw query` polls at.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A Logs Insights query result row, field name to value. `@ptr` is left out.
+pub type Row = Map<String, Value>;
+
+/// The state of a query as of the last poll.
+#[derive(Debug, Clone)]
+pub struct QueryPoll {
+    pub status: QueryState,
+    /// The rows found so far, all of them once the status is complete.
+    pub rows: Vec<Row>,
+    pub statistics: Option<QueryStats>,
+}
+
+impl QueryPoll {
+    /// Whether the query stopped, successfully or not, and polling again won't change anything.
+    pub fn is_done(&self) -> bool {
+        self.status.is_done()
+    }
+}
+
+/// Starts Logs Insights queries and waits for their results, like `cw query` without the
+/// history and output around it.
+///
+/// ```no_run
+/// use chrono::{TimeDelta, Utc};
+/// use cw::logs_api::StartQueryRequest;
+/// use cw::query_runner::QueryRunner;
+///
+/// # async fn run(client: aws_sdk_cloudwatchlogs::Client) -> eyre::Result<()> {
+/// let runner = QueryRunner::new(client);
+/// let query_id = runner
+///     .start(StartQueryRequest {
+///         group_names: vec!["/aws/lambda/my-function".to_string()],
+///         query: "fields @timestamp, @message | limit 20".to_string(),
+///         start_time: (Utc::now() - TimeDelta::hours(1)).timestamp_millis(),
+///         end_time: Utc::now().timestamp_millis(),
+///         limit: None,
+///     })
+///     .await?;
+///
+/// for row in runner.wait(&query_id).await? {
+///     println!("{:?}", row.get("@message"));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct QueryRunner<C: LogsApi> {
+    client: C,
+    limiter: Arc<RateLimiter>,
+    poll_interval: Duration,
+}
+
+impl<C: LogsApi> QueryRunner<C> {
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            limiter: Arc::new(RateLimiter::default()),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Shares a rate limiter with other runners or sessions, so together they stay under the
+    /// API limits.
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// How long [`QueryRunner::wait`] sleeps between polls.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Starts the query, returning its id.
+    pub async fn start(&self, request: StartQueryRequest) -> eyre::Result<String> {
+        self.limiter.acquire().await;
+        let output = self
+            .client
+            .start_query(request)
+            .await
+            .context("Failed starting the query")?;
+        output
+            .query_id()
+            .map(str::to_string)
+            .ok_or_else(|| eyre::eyre!("StartQuery returned no query id"))
+    }
+
+    /// Fetches the status and the rows found so far.
+    pub async fn poll(&self, query_id: &str) -> eyre::Result<QueryPoll> {
+        self.limiter.acquire().await;
+        let output = self
+            .client
+            .get_query_results(query_id)
+            .await
+            .with_context(|| format!("Failed getting the results of query {}", query_id))?;
+
+        Ok(QueryPoll {
+            status: QueryState::of(output.status()),
+            rows: output
+                .results()
+                .iter()
+                .map(|row| {
+                    result_fields(row)
+                        .map(|(name, value)| (name.to_string(), Value::String(value.to_string())))
+                        .collect()
+                })
+                .collect(),
+            statistics: output.statistics().map(QueryStats::from),
+        })
+    }
+
+    /// Polls until the query is done, returning its rows. A query that failed, timed out or was
+    /// cancelled is an error.
+    pub async fn wait(&self, query_id: &str) -> eyre::Result<Vec<Row>> {
+        loop {
+            let poll = self.poll(query_id).await?;
+            if !poll.is_done() {
+                tokio::time::sleep(self.poll_interval).await;
+                continue;
+            }
+
+            return match poll.status {
+                QueryState::Complete => Ok(poll.rows),
+                status => Err(eyre::eyre!(
+                    "Query {} ended with status {}",
+                    query_id,
+                    status
+                )),
+            };
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use tokio::{sync::Mutex, time::Instant};
+
+// NOTE: FilterLogEvents, GetQueryResults and the Describe* calls are all limited to 5 TPS per
+// account and region by default, ref:
+// https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/cloudwatch_limits_cwl.html
+pub const DEFAULT_TPS: u32 = 5;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token bucket shared by every task that talks to CloudWatch Logs. The bucket holds at most
+/// one second worth of tokens. Waiters queue up on a fair mutex, so concurrent producers take
+/// turns instead of one of them starving the others.
+#[derive(Debug)]
+pub struct RateLimiter {
+    tps: u32,
+    bucket: Mutex<Bucket>,
+    acquired: AtomicU64,
+    waited_ms: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(tps: u32) -> Self {
+        let tps = tps.max(1);
+        Self {
+            tps,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(tps),
+                refilled_at: Instant::now(),
+            }),
+            acquired: AtomicU64::new(0),
+            waited_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Waits until a request may be sent.
+    pub async fn acquire(&self) {
+        let started = Instant::now();
+        let mut bucket = self.bucket.lock().await;
+
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+            bucket.tokens =
+                (bucket.tokens + elapsed * f64::from(self.tps)).min(f64::from(self.tps));
+            bucket.refilled_at = now;
+
+            if bucket.tokens >= 1.0 {
+                break;
+            }
+
+            let deficit = (1.0 - bucket.tokens) / f64::from(self.tps);
+            tokio::time::sleep(Duration::from_secs_f64(deficit)).await;
+        }
+
+        bucket.tokens -= 1.0;
+
+        let waited = started.elapsed();
+        self.acquired.fetch_add(1, Ordering::Relaxed);
+        self.waited_ms
+            .fetch_add(waited.as_millis() as u64, Ordering::Relaxed);
+        if !waited.is_zero() {
+            tracing::trace!(target: "cw", "rate limited for {}ms", waited.as_millis());
+        }
+    }
+
+    pub fn tps(&self) -> u32 {
+        self.tps
+    }
+
+    pub fn acquired(&self) -> u64 {
+        self.acquired.load(Ordering::Relaxed)
+    }
+
+    pub fn waited(&self) -> Duration {
+        Duration::from_millis(self.waited_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn report(&self) {
+        tracing::info!(
+            target: "cw",
+            "rate limiter: {} requests at {} TPS, waited {}ms in total",
+            self.acquired(),
+            self.tps,
+            self.waited().as_millis()
+        );
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_TPS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::rate_limit::RateLimiter;
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_bucket_allows_a_second_worth_of_requests_at_once() {
+        let limiter = RateLimiter::new(5);
+        let started = Instant::now();
+
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        limiter.acquire().await;
+        assert_eq!(started.elapsed(), Duration::from_millis(200));
+        assert_eq!(limiter.acquired(), 6);
+        assert_eq!(limiter.waited(), Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_bucket_refills_up_to_a_second_worth_of_tokens() {
+        let limiter = RateLimiter::new(2);
+        limiter.acquire().await;
+        limiter.acquire().await;
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let started = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        limiter.acquire().await;
+        assert_eq!(started.elapsed(), Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiting_tasks_take_turns() {
+        let limiter = Arc::new(RateLimiter::new(1));
+        limiter.acquire().await;
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let started = Instant::now();
+
+        let tasks = ["a", "b"].map(|name| {
+            let limiter = limiter.clone();
+            let order = order.clone();
+            tokio::spawn(async move {
+                for _ in 0..3 {
+                    limiter.acquire().await;
+                    order.lock().unwrap().push(name);
+                }
+            })
+        });
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), ["a", "b", "a", "b", "a", "b"]);
+        assert_eq!(started.elapsed(), Duration::from_secs(6));
+    }
+}
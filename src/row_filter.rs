@@ -0,0 +1,384 @@
+use std::cmp::Ordering;
+
+use serde_json::{Map, Value};
+
+use crate::utils::parse_number;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    Op(CompareOp),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Field(String),
+    Literal(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CompareOp, Operand),
+    /// A bare operand, true when it is set and not empty.
+    Present(Operand),
+}
+
+/// A `--where` expression evaluated against the rows of a query result, e.g.
+/// `status >= 500 && path != "/health"`.
+///
+/// Bare words are field names, quoted words are strings and anything that reads as a number is
+/// a number. When both sides of a comparison are numbers they are compared numerically,
+/// otherwise as strings. A comparison against a missing field is false, except for `!=`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowFilter {
+    expr: Expr,
+}
+
+impl RowFilter {
+    pub fn parse(source: &str) -> eyre::Result<Self> {
+        let tokens = tokenize(source)
+            .map_err(|(position, message)| syntax_error(source, position, &message))?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            next: 0,
+            end: source.chars().count(),
+        };
+
+        let expr = parser
+            .parse_or()
+            .and_then(|expr| match parser.peek() {
+                None => Ok(expr),
+                Some((_, position)) => Err((position, "expected && or ||".to_string())),
+            })
+            .map_err(|(position, message)| syntax_error(source, position, &message))?;
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, row: &Map<String, Value>) -> bool {
+        evaluate(&self.expr, row)
+    }
+}
+
+fn syntax_error(source: &str, position: usize, message: &str) -> eyre::Report {
+    eyre::eyre!(
+        "{} at column {}\n  {}\n  {}^",
+        message,
+        position + 1,
+        source,
+        " ".repeat(position)
+    )
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace()
+        && !matches!(
+            c,
+            '(' | ')' | '!' | '=' | '<' | '>' | '&' | '|' | '"' | '\''
+        )
+}
+
+/// Splits `source` into tokens with their character position, or fails with the position and
+/// reason of the first character that doesn't fit.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, (usize, String)> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (Token::LParen, 1),
+            ')' => (Token::RParen, 1),
+            '&' if next == Some('&') => (Token::And, 2),
+            '|' if next == Some('|') => (Token::Or, 2),
+            '&' => return Err((start, "expected &&".to_string())),
+            '|' => return Err((start, "expected ||".to_string())),
+            '=' if next == Some('=') => (Token::Op(CompareOp::Eq), 2),
+            '=' => return Err((start, "expected ==".to_string())),
+            '!' if next == Some('=') => (Token::Op(CompareOp::Ne), 2),
+            '!' => (Token::Not, 1),
+            '<' if next == Some('=') => (Token::Op(CompareOp::Le), 2),
+            '<' => (Token::Op(CompareOp::Lt), 1),
+            '>' if next == Some('=') => (Token::Op(CompareOp::Ge), 2),
+            '>' => (Token::Op(CompareOp::Gt), 1),
+            quote @ ('"' | '\'') => {
+                let mut value = String::new();
+                let mut end = i + 1;
+                loop {
+                    match chars.get(end) {
+                        None => return Err((start, "unterminated string".to_string())),
+                        Some('\\') if end + 1 < chars.len() => {
+                            value.push(chars[end + 1]);
+                            end += 2;
+                        }
+                        Some(c) if *c == quote => break,
+                        Some(c) => {
+                            value.push(*c);
+                            end += 1;
+                        }
+                    }
+                }
+                (Token::Str(value), end + 1 - i)
+            }
+            _ => {
+                let word: String = chars[i..]
+                    .iter()
+                    .take_while(|c| is_word_char(**c))
+                    .collect();
+                let len = word.chars().count();
+                if word == "contains" {
+                    (Token::Op(CompareOp::Contains), len)
+                } else {
+                    (Token::Word(word), len)
+                }
+            }
+        };
+
+        tokens.push((token, start));
+        i += len;
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [(Token, usize)],
+    next: usize,
+    /// Position reported for errors at the end of the input.
+    end: usize,
+}
+
+type ParseResult<T> = Result<T, (usize, String)>;
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<(&Token, usize)> {
+        self.tokens
+            .get(self.next)
+            .map(|(token, position)| (token, *position))
+    }
+
+    fn advance(&mut self) -> ParseResult<(Token, usize)> {
+        let token = self
+            .tokens
+            .get(self.next)
+            .cloned()
+            .ok_or_else(|| (self.end, "unexpected end of expression".to_string()))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn parse_or(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_and()?;
+        while let Some((Token::Or, _)) = self.peek() {
+            self.next += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_unary()?;
+        while let Some((Token::And, _)) = self.peek() {
+            self.next += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Expr> {
+        match self.peek() {
+            Some((Token::Not, _)) => {
+                self.next += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some((Token::LParen, _)) => {
+                self.next += 1;
+                let expr = self.parse_or()?;
+                match self.advance()? {
+                    (Token::RParen, _) => Ok(expr),
+                    (_, position) => Err((position, "expected )".to_string())),
+                }
+            }
+            _ => self.parse_comparison(),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> ParseResult<Expr> {
+        let left = self.parse_operand()?;
+        match self.peek() {
+            Some((Token::Op(op), _)) => {
+                let op = *op;
+                self.next += 1;
+                Ok(Expr::Compare(left, op, self.parse_operand()?))
+            }
+            _ => Ok(Expr::Present(left)),
+        }
+    }
+
+    fn parse_operand(&mut self) -> ParseResult<Operand> {
+        match self.advance()? {
+            (Token::Str(value), _) => Ok(Operand::Literal(value)),
+            (Token::Word(word), _) if parse_number(&word).is_some() => Ok(Operand::Literal(word)),
+            (Token::Word(word), _) => Ok(Operand::Field(word)),
+            (_, position) => Err((position, "expected a field, string or number".to_string())),
+        }
+    }
+}
+
+fn resolve(operand: &Operand, row: &Map<String, Value>) -> Option<String> {
+    match operand {
+        Operand::Literal(value) => Some(value.clone()),
+        Operand::Field(name) => match row.get(name)? {
+            Value::Null => None,
+            Value::String(value) => Some(value.clone()),
+            other => Some(other.to_string()),
+        },
+    }
+}
+
+fn evaluate(expr: &Expr, row: &Map<String, Value>) -> bool {
+    match expr {
+        Expr::And(left, right) => evaluate(left, row) && evaluate(right, row),
+        Expr::Or(left, right) => evaluate(left, row) || evaluate(right, row),
+        Expr::Not(inner) => !evaluate(inner, row),
+        Expr::Present(operand) => resolve(operand, row).is_some_and(|value| !value.is_empty()),
+        Expr::Compare(left, op, right) => {
+            let (Some(left), Some(right)) = (resolve(left, row), resolve(right, row)) else {
+                return *op == CompareOp::Ne;
+            };
+
+            if *op == CompareOp::Contains {
+                return left.contains(right.as_str());
+            }
+
+            let ordering = match (parse_number(&left), parse_number(&right)) {
+                (Some(left), Some(right)) => left.partial_cmp(&right),
+                _ => Some(left.cmp(&right)),
+            };
+            match (op, ordering) {
+                (CompareOp::Eq, Some(ordering)) => ordering == Ordering::Equal,
+                (CompareOp::Ne, Some(ordering)) => ordering != Ordering::Equal,
+                (CompareOp::Lt, Some(ordering)) => ordering == Ordering::Less,
+                (CompareOp::Le, Some(ordering)) => ordering != Ordering::Greater,
+                (CompareOp::Gt, Some(ordering)) => ordering == Ordering::Greater,
+                (CompareOp::Ge, Some(ordering)) => ordering != Ordering::Less,
+                _ => false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::row_filter::RowFilter;
+
+    fn matches(filter: &str, row: Value) -> bool {
+        RowFilter::parse(filter)
+            .unwrap()
+            .matches(row.as_object().unwrap())
+    }
+
+    #[test]
+    fn compares_numbers_numerically() {
+        assert!(matches("status >= 500", json!({ "status": "503" })));
+        assert!(!matches("status >= 500", json!({ "status": "99" })));
+        assert!(matches("duration < 1.5", json!({ "duration": 0.25 })));
+        assert!(matches("status == 200", json!({ "status": "200.0" })));
+    }
+
+    #[test]
+    fn compares_other_values_as_strings() {
+        assert!(matches(r#"path != "/health""#, json!({ "path": "/api" })));
+        assert!(!matches("path != '/health'", json!({ "path": "/health" })));
+        assert!(matches(r#"level == "error""#, json!({ "level": "error" })));
+        assert!(matches("level > 'a'", json!({ "level": "b" })));
+        assert!(matches(
+            r#"message contains "time out""#,
+            json!({ "message": "a time out" })
+        ));
+        assert!(matches(
+            r#"message == "say \"hi\"""#,
+            json!({ "message": "say \"hi\"" })
+        ));
+    }
+
+    #[test]
+    fn missing_fields_only_match_not_equal() {
+        assert!(!matches("status == 500", json!({})));
+        assert!(!matches("status < 500", json!({ "status": null })));
+        assert!(matches("status != 500", json!({})));
+    }
+
+    #[test]
+    fn bare_fields_match_when_set_and_not_empty() {
+        assert!(matches("error", json!({ "error": "boom" })));
+        assert!(!matches("error", json!({ "error": "" })));
+        assert!(!matches("error", json!({})));
+        assert!(matches("!error", json!({})));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let filter = "a == 1 || b == 1 && c == 1";
+        assert!(matches(filter, json!({ "a": 1 })));
+        assert!(!matches(filter, json!({ "b": 1 })));
+        assert!(matches(filter, json!({ "b": 1, "c": 1 })));
+        assert!(!matches("(a == 1 || b == 1) && c == 1", json!({ "a": 1 })));
+        assert!(matches("!(a == 1 && b == 1)", json!({ "a": 1 })));
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_column() {
+        let err = RowFilter::parse("status = 500").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected == at column 8\n  status = 500\n         ^"
+        );
+
+        let err = RowFilter::parse("status == 500 level").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("expected && or || at column 15"));
+
+        let err = RowFilter::parse("(a == 1").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("unexpected end of expression at column 8"));
+
+        let err = RowFilter::parse("a == 'b").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("unterminated string at column 6"));
+
+        assert!(RowFilter::parse("a ==").is_err());
+        assert!(RowFilter::parse("a & b").is_err());
+        assert!(RowFilter::parse("").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use futures_util::{stream::FuturesUnordered, Stream, StreamExt};
+use tokio::{sync::mpsc::UnboundedReceiver, task::JoinHandle};
+
+use crate::commands::tail;
+use crate::event::LogEvent;
+use crate::logs_api::LogsApi;
+use crate::progress_reporter::NoopReporter;
+use crate::rate_limit::RateLimiter;
+use crate::ui::Ui;
+use crate::utils::default_start_time;
+
+/// The tasks reading the groups of a session.
+type Producers = FuturesUnordered<JoinHandle<eyre::Result<()>>>;
+
+/// The events of one or more log groups, read the same way `cw tail` reads them.
+///
+/// ```no_run
+/// use chrono::{TimeDelta, Utc};
+/// use cw::session::TailSession;
+/// use futures_util::StreamExt;
+///
+/// # async fn run(client: aws_sdk_cloudwatchlogs::Client) -> eyre::Result<()> {
+/// let events = TailSession::new(client)
+///     .group("/aws/lambda/my-function")
+///     .filter("ERROR")
+///     .start_time((Utc::now() - TimeDelta::hours(1)).timestamp_millis())
+///     .stream();
+/// let mut events = std::pin::pin!(events);
+///
+/// while let Some(event) = events.next().await {
+///     println!("{:?}", event?.message);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TailSession<C: LogsApi> {
+    client: C,
+    limiter: Arc<RateLimiter>,
+    groups: Vec<(String, Option<String>)>,
+    filter: Option<String>,
+    start_time: Option<i64>,
+    end_time: Option<i64>,
+    follow: bool,
+}
+
+impl<C: LogsApi> TailSession<C> {
+    /// A session reading from 30 seconds ago until now, like `cw tail` without flags.
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            limiter: Arc::new(RateLimiter::default()),
+            groups: Vec::new(),
+            filter: None,
+            start_time: None,
+            end_time: None,
+            follow: false,
+        }
+    }
+
+    /// Adds a log group to read from.
+    pub fn group(mut self, name: impl Into<String>) -> Self {
+        self.groups.push((name.into(), None));
+        self
+    }
+
+    /// Adds a log group, reading only the streams whose name starts with `stream_prefix`.
+    pub fn group_with_stream_prefix(
+        mut self,
+        name: impl Into<String>,
+        stream_prefix: impl Into<String>,
+    ) -> Self {
+        self.groups.push((name.into(), Some(stream_prefix.into())));
+        self
+    }
+
+    /// A CloudWatch Logs filter pattern events have to match.
+    pub fn filter(mut self, pattern: impl Into<String>) -> Self {
+        self.filter = Some(pattern.into());
+        self
+    }
+
+    /// The start time in milliseconds since the epoch, see [`crate::parse_human_time`].
+    pub fn start_time(mut self, start_time: i64) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    /// The end time in milliseconds since the epoch, can't be combined with following.
+    pub fn end_time(mut self, end_time: i64) -> Self {
+        self.end_time = Some(end_time);
+        self
+    }
+
+    /// Keep polling for new events instead of ending at the last one.
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// Shares a rate limiter with other sessions, so together they stay under the API limits.
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Starts reading every group and returns their events as they come in. A group that fails
+    /// doesn't stop the others, the first error is the last item of the stream. Once the stream
+    /// is dropped, every read stops at its next event.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn stream(self) -> impl Stream<Item = eyre::Result<LogEvent>> {
+        let start_time = self
+            .start_time
+            .unwrap_or_else(|| default_start_time(Utc::now(), true));
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let producers = FuturesUnordered::new();
+
+        if self.follow && self.end_time.is_some() {
+            producers.push(tokio::spawn(async {
+                Err(eyre::eyre!("A tail can't follow and have an end time"))
+            }));
+        } else {
+            for (group_name, stream_prefix) in self.groups {
+                producers.push(tokio::spawn(tail::Cmd::tail_log_producer(
+                    self.client.clone(),
+                    sender.clone(),
+                    Arc::new(NoopReporter),
+                    self.limiter.clone(),
+                    start_time,
+                    self.end_time,
+                    self.filter.clone(),
+                    self.follow,
+                    group_name,
+                    stream_prefix,
+                    None,
+                    false,
+                    false,
+                    None,
+                    Ui::new(true),
+                )));
+            }
+        }
+
+        futures_util::stream::unfold(
+            (receiver, Some(producers)),
+            |(mut receiver, producers): (UnboundedReceiver<LogEvent>, Option<Producers>)| async move {
+                if let Some(event) = receiver.recv().await {
+                    return Some((Ok(event), (receiver, producers)));
+                }
+
+                // NOTE: The channel only closes once every producer is done, so this doesn't
+                // wait on any of them.
+                let mut producers = producers?;
+                while let Some(result) = producers.next().await {
+                    let error = match result {
+                        Ok(Ok(())) => continue,
+                        Ok(Err(err)) => err,
+                        Err(err) => eyre::eyre!(err),
+                    };
+                    return Some((Err(error), (receiver, None)));
+                }
+                None
+            },
+        )
+    }
+}
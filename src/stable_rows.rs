@@ -0,0 +1,128 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::utils::parse_number;
+
+/// The field `cw query --row-hash` adds to every row.
+pub const ROW_HASH_FIELD: &str = "_hash";
+
+// NOTE: A pointer to the event in CloudWatch, it differs between runs for the same row.
+const PTR_FIELD: &str = "@ptr";
+
+/// The fields `cw query --sort-output` orders the result rows by, e.g. `status,@timestamp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowSort {
+    fields: Vec<String>,
+}
+
+impl FromStr for RowSort {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> eyre::Result<Self> {
+        let fields: Vec<String> = value.split(',').map(|f| f.trim().to_string()).collect();
+        if fields.iter().any(String::is_empty) {
+            return Err(eyre::eyre!(
+                "Invalid sort fields '{}', expected field[,field...]",
+                value
+            ));
+        }
+        Ok(Self { fields })
+    }
+}
+
+impl RowSort {
+    /// Sorts `rows` by each field in turn, numerically when both values are numbers and as
+    /// strings otherwise. Rows without a field go after the rows that have it. Rows tying on
+    /// every field are ordered by their contents, so the order doesn't depend on the one
+    /// Insights returned them in.
+    pub fn sort(&self, rows: Vec<Map<String, Value>>) -> Vec<Map<String, Value>> {
+        let mut keyed: Vec<(String, Map<String, Value>)> = rows
+            .into_iter()
+            .map(|row| (canonical_row(&row), row))
+            .collect();
+        keyed.sort_by(|(a_key, a), (b_key, b)| self.compare(a, b).then_with(|| a_key.cmp(b_key)));
+        keyed.into_iter().map(|(_, row)| row).collect()
+    }
+
+    fn compare(&self, a: &Map<String, Value>, b: &Map<String, Value>) -> Ordering {
+        self.fields
+            .iter()
+            .map(|field| compare_values(text(a, field), text(b, field)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+fn text(row: &Map<String, Value>, field: &str) -> Option<String> {
+    match row.get(field)? {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn compare_values(a: Option<String>, b: Option<String>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => match (parse_number(&a), parse_number(&b)) {
+            (Some(a_number), Some(b_number)) => a_number.total_cmp(&b_number),
+            _ => a.cmp(&b),
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// The hex SHA-256 of the canonical form of `row`, the same for the same fields and values
+/// whatever order they are in. `@ptr` and an earlier hash are left out.
+pub fn row_hash(row: &Map<String, Value>) -> String {
+    format!("{:x}", Sha256::digest(canonical_row(row).as_bytes()))
+}
+
+/// `row` as compact JSON with the keys of every object in order, without `@ptr` and the hash.
+fn canonical_row(row: &Map<String, Value>) -> String {
+    let mut canonical = String::new();
+    write_object(
+        row.iter()
+            .filter(|(key, _)| *key != PTR_FIELD && *key != ROW_HASH_FIELD),
+        &mut canonical,
+    );
+    canonical
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => write_object(map.iter(), out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+// NOTE: The keys are sorted here rather than relying on the map, its order depends on the
+// serde_json features enabled in the build.
+fn write_object<'a>(entries: impl Iterator<Item = (&'a String, &'a Value)>, out: &mut String) {
+    let mut entries: Vec<_> = entries.collect();
+    entries.sort_by_key(|(key, _)| *key);
+    out.push('{');
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&Value::String(key.clone()).to_string());
+        out.push(':');
+        write_value(value, out);
+    }
+    out.push('}');
+}
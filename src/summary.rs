@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::utils::parse_number;
+
+pub const TOP_VALUES: usize = 5;
+
+/// Distribution of a single result field over all rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSummary {
+    pub field: String,
+    /// Rows where the field is present and not empty.
+    pub non_empty: u64,
+    pub distinct: usize,
+    /// Smallest and largest value, only set when every non empty value is a number.
+    pub range: Option<(f64, f64)>,
+    /// The most frequent values with their count, most frequent first.
+    pub top: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Default)]
+struct Accumulator {
+    non_empty: u64,
+    counts: HashMap<String, u64>,
+    range: Option<(f64, f64)>,
+    numeric: bool,
+}
+
+/// Summarizes every field of the rows it is given one at a time, in the order the fields
+/// first show up. Only the distinct values are kept, not the rows.
+#[derive(Debug, Default)]
+pub struct Summarizer {
+    order: Vec<String>,
+    fields: HashMap<String, Accumulator>,
+}
+
+impl Summarizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, row: &Map<String, Value>) {
+        for (field, value) in row {
+            let accumulator = self.fields.entry(field.clone()).or_insert_with(|| {
+                self.order.push(field.clone());
+                Accumulator {
+                    numeric: true,
+                    ..Accumulator::default()
+                }
+            });
+
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            if value.is_empty() {
+                continue;
+            }
+
+            accumulator.non_empty += 1;
+            match parse_number(&value) {
+                Some(n) if accumulator.numeric => {
+                    accumulator.range = Some(match accumulator.range {
+                        Some((min, max)) => (min.min(n), max.max(n)),
+                        None => (n, n),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    accumulator.numeric = false;
+                    accumulator.range = None;
+                }
+            }
+            *accumulator.counts.entry(value).or_default() += 1;
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<FieldSummary> {
+        self.order
+            .into_iter()
+            .filter_map(|field| {
+                let accumulator = self.fields.remove(&field)?;
+                let distinct = accumulator.counts.len();
+
+                let mut top: Vec<(String, u64)> = accumulator.counts.into_iter().collect();
+                // NOTE: Break ties on the value, so the report doesn't change between runs.
+                top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                top.truncate(TOP_VALUES);
+
+                Some(FieldSummary {
+                    field,
+                    non_empty: accumulator.non_empty,
+                    distinct,
+                    range: accumulator.range,
+                    top,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+
+    use crate::summary::{FieldSummary, Summarizer, TOP_VALUES};
+
+    fn summarize(rows: &[Value]) -> Vec<FieldSummary> {
+        let mut summarizer = Summarizer::new();
+        for row in rows {
+            let row: &Map<String, Value> = row.as_object().unwrap();
+            summarizer.add(row);
+        }
+        summarizer.finish()
+    }
+
+    #[test]
+    fn counts_levels_in_the_order_fields_first_show_up() {
+        let summaries = summarize(&[
+            json!({"level": "INFO", "status": "200"}),
+            json!({"level": "ERROR", "status": "500"}),
+            json!({"level": "INFO", "user": "alice"}),
+            json!({"level": "", "status": null}),
+        ]);
+
+        assert_eq!(
+            summaries,
+            [
+                FieldSummary {
+                    field: "level".to_string(),
+                    non_empty: 3,
+                    distinct: 2,
+                    range: None,
+                    top: vec![("INFO".to_string(), 2), ("ERROR".to_string(), 1)],
+                },
+                FieldSummary {
+                    field: "status".to_string(),
+                    non_empty: 2,
+                    distinct: 2,
+                    range: Some((200.0, 500.0)),
+                    top: vec![("200".to_string(), 1), ("500".to_string(), 1)],
+                },
+                FieldSummary {
+                    field: "user".to_string(),
+                    non_empty: 1,
+                    distinct: 1,
+                    range: None,
+                    top: vec![("alice".to_string(), 1)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_value_that_is_not_a_number_drops_the_range() {
+        let summaries = summarize(&[
+            json!({"duration": 12.5}),
+            json!({"duration": "n/a"}),
+            json!({"duration": 3}),
+        ]);
+
+        assert_eq!(summaries[0].range, None);
+        assert_eq!(summaries[0].non_empty, 3);
+    }
+
+    #[test]
+    fn top_values_are_the_most_frequent_with_ties_broken_on_the_value() {
+        let mut rows = Vec::new();
+        for (value, count) in [("e", 1), ("d", 3), ("a", 2), ("c", 2), ("b", 3), ("f", 2)] {
+            rows.extend(std::iter::repeat_n(json!({ "host": value }), count));
+        }
+
+        let summary = &summarize(&rows)[0];
+
+        assert_eq!(summary.distinct, 6);
+        assert_eq!(summary.top.len(), TOP_VALUES);
+        assert_eq!(
+            summary.top,
+            [("b", 3), ("d", 3), ("a", 2), ("c", 2), ("f", 2)]
+                .map(|(value, count)| (value.to_string(), count))
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::ui::Ui;
+
+// How often the watcher notes the time, the suspension is measured from the last note.
+const TICK: Duration = Duration::from_secs(1);
+
+/// The process continued after being stopped, e.g. with Ctrl-Z and `fg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resumed {
+    /// When the process continued, in milliseconds since the epoch.
+    pub at: i64,
+    pub suspended_for: Duration,
+}
+
+/// The receiver told about every resume, and the task watching for them.
+pub type ResumeWatch = (watch::Receiver<Option<Resumed>>, JoinHandle<()>);
+
+/// Watches for SIGCONT and tells every receiver when the process resumes, `skip` only changes
+/// what the note says the producers do with the gap. Returns `None` where there are no such
+/// signals.
+pub fn watch_resume(ui: Ui, skip: bool) -> eyre::Result<Option<ResumeWatch>> {
+    imp::watch_resume(ui, skip)
+}
+
+/// The times the watcher noted, the suspension is measured from them.
+#[derive(Debug, Clone, Copy)]
+struct Ticks {
+    last: DateTime<Utc>,
+    // The gap before the last tick when it was well past TICK, zero otherwise.
+    last_gap: Duration,
+}
+
+impl Ticks {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            last: now,
+            last_gap: Duration::ZERO,
+        }
+    }
+
+    fn tick(&mut self, now: DateTime<Utc>) {
+        let gap = (now - self.last).to_std().unwrap_or_default();
+        // NOTE: Only a gap well past the tick counts, a busy runtime is late too.
+        self.last_gap = if gap > TICK * 3 { gap } else { Duration::ZERO };
+        self.last = now;
+    }
+
+    /// How long the process was suspended when it resumed at `now`: the time since the last
+    /// tick, or the gap before it when that was longer. The timer can run before the signal is
+    /// seen after a resume, the gap it leaves is the suspension.
+    fn resume(&mut self, now: DateTime<Utc>) -> Duration {
+        let suspended_for = (now - self.last)
+            .to_std()
+            .unwrap_or_default()
+            .max(self.last_gap);
+        *self = Self::new(now);
+        suspended_for
+    }
+}
+
+fn note_resumed(ui: Ui, resumed: &Resumed, skip: bool) {
+    let suspended_for = Duration::from_secs(resumed.suspended_for.as_secs());
+    ui.note(format!(
+        "Resumed after being suspended for {}, {}.",
+        humantime::format_duration(suspended_for),
+        if skip {
+            "skipping the events in between"
+        } else {
+            "catching up"
+        }
+    ));
+}
+
+#[cfg(unix)]
+mod imp {
+    use chrono::Utc;
+    use tokio::signal::unix::{signal, SignalKind};
+    use tokio::sync::watch;
+
+    use crate::suspend::{note_resumed, ResumeWatch, Resumed, Ticks, TICK};
+    use crate::ui::Ui;
+
+    pub fn watch_resume(ui: Ui, skip: bool) -> eyre::Result<Option<ResumeWatch>> {
+        // NOTE: SignalKind has no constructor for SIGCONT, its number differs between platforms.
+        let mut continued = signal(SignalKind::from_raw(libc::SIGCONT))?;
+        let (sender, receiver) = watch::channel(None);
+
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(TICK);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut ticks = Ticks::new(Utc::now());
+
+            loop {
+                tokio::select! {
+                    biased;
+                    received = continued.recv() => {
+                        if received.is_none() {
+                            break;
+                        }
+                        let now = Utc::now();
+                        let resumed = Resumed {
+                            at: now.timestamp_millis(),
+                            suspended_for: ticks.resume(now),
+                        };
+                        note_resumed(ui, &resumed, skip);
+                        sender.send_replace(Some(resumed));
+                    }
+                    _ = ticker.tick() => ticks.tick(Utc::now()),
+                }
+            }
+        });
+
+        Ok(Some((receiver, handle)))
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use crate::suspend::ResumeWatch;
+    use crate::ui::Ui;
+
+    pub fn watch_resume(_ui: Ui, _skip: bool) -> eyre::Result<Option<ResumeWatch>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, TimeDelta, Utc};
+
+    use crate::suspend::Ticks;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn resume_counts_the_time_since_the_last_tick() {
+        let mut ticks = Ticks::new(at(0));
+        ticks.tick(at(1));
+        ticks.tick(at(2));
+
+        assert_eq!(ticks.resume(at(62)), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn resume_counts_a_late_tick_as_the_suspension() {
+        let mut ticks = Ticks::new(at(0));
+        ticks.tick(at(1));
+        // NOTE: The timer fired right after the process continued, before the signal was seen.
+        ticks.tick(at(61));
+
+        assert_eq!(ticks.resume(at(61)), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn a_slightly_late_tick_is_not_a_suspension() {
+        let mut ticks = Ticks::new(at(0));
+        ticks.tick(at(3));
+
+        assert_eq!(ticks.last_gap, Duration::ZERO);
+        assert_eq!(ticks.resume(at(3)), Duration::ZERO);
+    }
+
+    #[test]
+    fn resume_starts_measuring_again() {
+        let mut ticks = Ticks::new(at(0));
+        ticks.tick(at(30));
+        assert_eq!(ticks.resume(at(30)), Duration::from_secs(30));
+
+        assert_eq!(ticks.last, at(30));
+        assert_eq!(ticks.last_gap, Duration::ZERO);
+        assert_eq!(ticks.resume(at(31)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn a_clock_set_back_is_no_suspension() {
+        let mut ticks = Ticks::new(at(10));
+
+        assert_eq!(ticks.resume(at(10) - TimeDelta::seconds(5)), Duration::ZERO);
+    }
+}
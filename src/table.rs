@@ -0,0 +1,216 @@
+use std::{fmt::Display, io::Write};
+
+use eyre::Context;
+use yansi::{Paint, Style};
+
+use crate::ui::supports_ansi;
+
+// NOTE: Same gap between columns as the TabWriter tables, padding(2).
+const COLUMN_GAP: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+}
+
+/// A table cell, its style is only applied when the table is rendered with color.
+#[derive(Debug, Clone)]
+pub struct Cell {
+    text: String,
+    style: Option<Style>,
+}
+
+impl Cell {
+    pub fn new(text: impl Display) -> Self {
+        Self {
+            text: text.to_string(),
+            style: None,
+        }
+    }
+
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = Some(style);
+        self
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Self::new(text)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Self::new(text)
+    }
+}
+
+/// Column aligned output for listings such as `cw query history`. Widths are measured on the
+/// plain text, so colored cells line up the same as uncolored ones.
+#[derive(Debug, Clone)]
+pub struct Table {
+    headers: Vec<String>,
+    align: Vec<Align>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            align: vec![Align::default(); headers.len()],
+            rows: Vec::new(),
+        }
+    }
+
+    /// Aligns `column` (zero based) to the right, for counts and other numbers.
+    pub fn align_right(mut self, column: usize) -> Self {
+        if let Some(align) = self.align.get_mut(column) {
+            *align = Align::Right;
+        }
+        self
+    }
+
+    pub fn push(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    /// Renders the header and rows, one line each. The last column is never padded, so lines
+    /// don't end in whitespace.
+    pub fn render(&self, use_color: bool) -> String {
+        let columns = self
+            .rows
+            .iter()
+            .map(Vec::len)
+            .chain([self.headers.len()])
+            .max()
+            .unwrap_or_default();
+        let mut widths = vec![0; columns];
+        for (i, header) in self.headers.iter().enumerate() {
+            widths[i] = widths[i].max(header.chars().count());
+        }
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.text.chars().count());
+            }
+        }
+
+        let header = self.headers.iter().map(Cell::new).collect::<Vec<_>>();
+        let mut output = String::new();
+        for row in [&header].into_iter().chain(&self.rows) {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                let padding = widths[i] - cell.text.chars().count();
+                let last = i + 1 == row.len();
+                let text = match (cell.style, use_color) {
+                    (Some(style), true) => cell.text.paint(style).to_string(),
+                    _ => cell.text.clone(),
+                };
+
+                match self.align.get(i).copied().unwrap_or_default() {
+                    Align::Right => {
+                        line.push_str(&" ".repeat(padding));
+                        line.push_str(&text);
+                    }
+                    Align::Left if last => line.push_str(&text),
+                    Align::Left => {
+                        line.push_str(&text);
+                        line.push_str(&" ".repeat(padding));
+                    }
+                }
+                if !last {
+                    line.push_str(&" ".repeat(COLUMN_GAP));
+                }
+            }
+            output.push_str(line.trim_end());
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Writes the table to stdout, colored when stdout is a terminal.
+    pub fn print(&self) -> eyre::Result<()> {
+        let mut stdout = std::io::stdout();
+        let use_color = supports_ansi(&stdout);
+        stdout
+            .write_all(self.render(use_color).as_bytes())
+            .and_then(|_| stdout.flush())
+            .context("failed to write to stdout")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use yansi::{Paint, Style};
+
+    use crate::table::{Cell, Table};
+
+    fn history() -> Table {
+        let mut table = Table::new(&["ID", "EVENTS", "STATUS"]).align_right(1);
+        table.push(vec![
+            Cell::new("q-1").style(Style::new().dim()),
+            Cell::new(5),
+            Cell::new("Complete").style(Style::new().green()),
+        ]);
+        table.push(vec!["q-long-id".into(), Cell::new(1200), "Failed".into()]);
+        table
+    }
+
+    #[test]
+    fn columns_are_as_wide_as_their_widest_cell() {
+        assert_eq!(
+            history().render(false),
+            concat!(
+                "ID         EVENTS  STATUS\n",
+                "q-1             5  Complete\n",
+                "q-long-id    1200  Failed\n",
+            )
+        );
+    }
+
+    #[test]
+    fn styles_only_apply_with_color_and_never_change_the_widths() {
+        assert_eq!(
+            history().render(true),
+            format!(
+                "ID         EVENTS  STATUS\n{}{}5  {}\nq-long-id    1200  Failed\n",
+                "q-1".dim(),
+                " ".repeat(13),
+                "Complete".green(),
+            )
+        );
+    }
+
+    #[test]
+    fn lines_never_end_in_whitespace() {
+        let mut table = Table::new(&["NAME", "CLASS"]);
+        table.push(vec!["/ecs/api".into(), "".into()]);
+        table.push(vec!["/a".into()]);
+        table.push(vec!["/ecs/worker-prod".into(), "STANDARD".into()]);
+
+        assert_eq!(
+            table.render(false),
+            concat!(
+                "NAME              CLASS\n",
+                "/ecs/api\n",
+                "/a\n",
+                "/ecs/worker-prod  STANDARD\n",
+            )
+        );
+    }
+
+    #[test]
+    fn widths_count_characters_not_bytes() {
+        let mut table = Table::new(&["GROUP", "N"]).align_right(1);
+        table.push(vec!["/ecs/café".into(), Cell::new(1)]);
+        table.push(vec!["/ecs/api".into(), Cell::new(10)]);
+
+        assert_eq!(
+            table.render(false),
+            concat!("GROUP       N\n", "/ecs/café   1\n", "/ecs/api   10\n")
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Local, NaiveTime, TimeDelta};
+use eyre::Context;
+use serde::Deserialize;
+
+use crate::utils::TimeRange;
+
+// NOTE: Epoch values below this are taken as seconds, anything above as milliseconds. In
+// milliseconds this is early 1970, in seconds it is the year 2286.
+const EPOCH_SECONDS_CUTOFF: i64 = 10_000_000_000;
+
+/// A named time window as written in the `[windows]` table of the config file, e.g.
+/// `[windows.business-hours]` with `start = "09:00"` and `end = "17:30"`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub last: Option<String>,
+    pub from_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeWindow {
+    /// A range of local wall clock time on the current day. An end before the start runs into
+    /// the next day.
+    Daily { start: NaiveTime, end: NaiveTime },
+    /// Everything in the last duration.
+    Last(Duration),
+    /// Everything since the epoch or RFC3339 time stored in a file, e.g. written by a deploy.
+    SinceFile(PathBuf),
+}
+
+impl TryFrom<&WindowConfig> for TimeWindow {
+    type Error = eyre::Report;
+
+    fn try_from(config: &WindowConfig) -> eyre::Result<Self> {
+        let parse_time = |value: &str| {
+            NaiveTime::parse_from_str(value, "%H:%M")
+                .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+                .wrap_err_with(|| format!("expected a time like 09:00, got '{}'", value))
+        };
+
+        match config {
+            WindowConfig {
+                start: Some(start),
+                end: Some(end),
+                last: None,
+                from_file: None,
+            } => Ok(Self::Daily {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            }),
+            WindowConfig {
+                start: None,
+                end: None,
+                last: Some(last),
+                from_file: None,
+            } => Ok(Self::Last(humantime::parse_duration(last)?)),
+            WindowConfig {
+                start: None,
+                end: None,
+                last: None,
+                from_file: Some(path),
+            } => Ok(Self::SinceFile(path.clone())),
+            _ => Err(eyre::eyre!("set either start and end, last or from_file")),
+        }
+    }
+}
+
+impl TimeWindow {
+    /// The range the window covers at `now`, it always has a start. Windows without an end run
+    /// up to now.
+    pub fn resolve(&self, now: DateTime<Local>) -> eyre::Result<TimeRange> {
+        match self {
+            Self::Daily { start, end } => {
+                // NOTE: Before the start of today's window, the most recent one is yesterday's.
+                let mut day = now.date_naive();
+                if now.time() < *start {
+                    day = day.pred_opt().unwrap_or(day);
+                }
+                let end_day = if end <= start {
+                    day.succ_opt().unwrap_or(day)
+                } else {
+                    day
+                };
+
+                let start = day
+                    .and_time(*start)
+                    .and_local_timezone(Local)
+                    .earliest()
+                    .ok_or_else(|| eyre::eyre!("{} doesn't exist in the local timezone", start))?;
+                let end = end_day
+                    .and_time(*end)
+                    .and_local_timezone(Local)
+                    .latest()
+                    .ok_or_else(|| eyre::eyre!("{} doesn't exist in the local timezone", end))?;
+
+                Ok(TimeRange::new(
+                    Some(start.timestamp_millis()),
+                    Some(end.timestamp_millis()),
+                ))
+            }
+            Self::Last(duration) => Ok(TimeRange::new(
+                Some((now - TimeDelta::from_std(*duration)?).timestamp_millis()),
+                None,
+            )),
+            Self::SinceFile(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .wrap_err_with(|| format!("Failed reading {}", path.display()))?;
+                let start = parse_epoch_or_rfc3339(contents.trim()).wrap_err_with(|| {
+                    format!("{} holds no epoch or RFC3339 time", path.display())
+                })?;
+
+                Ok(TimeRange::new(Some(start), None))
+            }
+        }
+    }
+}
+
+fn parse_epoch_or_rfc3339(value: &str) -> eyre::Result<i64> {
+    if let Ok(epoch) = value.parse::<i64>() {
+        return Ok(if epoch < EPOCH_SECONDS_CUTOFF {
+            epoch * 1000
+        } else {
+            epoch
+        });
+    }
+
+    Ok(DateTime::parse_from_rfc3339(value)?.timestamp_millis())
+}
+
+/// Resolves the window called `name`, listing the available windows when there is none by
+/// that name.
+pub fn resolve_window(
+    windows: &BTreeMap<String, WindowConfig>,
+    name: &str,
+    now: DateTime<Local>,
+) -> eyre::Result<TimeRange> {
+    let Some(config) = windows.get(name) else {
+        let available = if windows.is_empty() {
+            "none, add them to the [windows] table of the config file".to_string()
+        } else {
+            windows.keys().cloned().collect::<Vec<_>>().join(", ")
+        };
+        return Err(eyre::eyre!(
+            "Unknown window '{}', available windows: {}",
+            name,
+            available
+        ));
+    };
+
+    TimeWindow::try_from(config)
+        .and_then(|window| window.resolve(now))
+        .wrap_err_with(|| format!("Can't resolve window '{}'", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone};
+
+    use crate::time_window::{parse_epoch_or_rfc3339, resolve_window, TimeWindow, WindowConfig};
+
+    fn local(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .from_local_datetime(
+                &NaiveDate::from_ymd_opt(2024, 3, day)
+                    .unwrap()
+                    .and_hms_opt(hour, minute, 0)
+                    .unwrap(),
+            )
+            .earliest()
+            .unwrap()
+    }
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M:%S").unwrap()
+    }
+
+    fn config(start: Option<&str>, end: Option<&str>, last: Option<&str>) -> WindowConfig {
+        WindowConfig {
+            start: start.map(str::to_string),
+            end: end.map(str::to_string),
+            last: last.map(str::to_string),
+            from_file: None,
+        }
+    }
+
+    #[test]
+    fn config_parses_each_kind_of_window() {
+        assert_eq!(
+            TimeWindow::try_from(&config(Some("09:00"), Some("17:30:15"), None)).unwrap(),
+            TimeWindow::Daily {
+                start: time("09:00:00"),
+                end: time("17:30:15")
+            }
+        );
+        assert_eq!(
+            TimeWindow::try_from(&config(None, None, Some("2h"))).unwrap(),
+            TimeWindow::Last(Duration::from_secs(7200))
+        );
+        let since = WindowConfig {
+            from_file: Some("deploy.txt".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            TimeWindow::try_from(&since).unwrap(),
+            TimeWindow::SinceFile("deploy.txt".into())
+        );
+    }
+
+    #[test]
+    fn config_refuses_mixed_or_incomplete_windows() {
+        assert!(TimeWindow::try_from(&config(Some("09:00"), None, None)).is_err());
+        assert!(TimeWindow::try_from(&config(Some("09:00"), Some("17:00"), Some("1h"))).is_err());
+        assert!(TimeWindow::try_from(&config(Some("9am"), Some("17:00"), None)).is_err());
+        assert!(TimeWindow::try_from(&WindowConfig::default()).is_err());
+    }
+
+    #[test]
+    fn daily_window_is_todays_once_it_started() {
+        let window = TimeWindow::Daily {
+            start: time("09:00:00"),
+            end: time("17:00:00"),
+        };
+        let range = window.resolve(local(12, 10, 0)).unwrap();
+        assert_eq!(range.start, Some(local(12, 9, 0).timestamp_millis()));
+        assert_eq!(range.end, Some(local(12, 17, 0).timestamp_millis()));
+    }
+
+    #[test]
+    fn daily_window_is_yesterdays_before_it_started() {
+        let window = TimeWindow::Daily {
+            start: time("09:00:00"),
+            end: time("17:00:00"),
+        };
+        let range = window.resolve(local(12, 8, 0)).unwrap();
+        assert_eq!(range.start, Some(local(11, 9, 0).timestamp_millis()));
+        assert_eq!(range.end, Some(local(11, 17, 0).timestamp_millis()));
+    }
+
+    #[test]
+    fn daily_window_ending_before_it_starts_runs_into_the_next_day() {
+        let window = TimeWindow::Daily {
+            start: time("22:00:00"),
+            end: time("06:00:00"),
+        };
+        let range = window.resolve(local(12, 23, 0)).unwrap();
+        assert_eq!(range.start, Some(local(12, 22, 0).timestamp_millis()));
+        assert_eq!(range.end, Some(local(13, 6, 0).timestamp_millis()));
+
+        let range = window.resolve(local(12, 3, 0)).unwrap();
+        assert_eq!(range.start, Some(local(11, 22, 0).timestamp_millis()));
+        assert_eq!(range.end, Some(local(12, 6, 0).timestamp_millis()));
+    }
+
+    #[test]
+    fn last_window_runs_up_to_now() {
+        let now = local(12, 10, 0);
+        let range = TimeWindow::Last(Duration::from_secs(3600))
+            .resolve(now)
+            .unwrap();
+        assert_eq!(range.start, Some(local(12, 9, 0).timestamp_millis()));
+        assert_eq!(range.end, None);
+    }
+
+    #[test]
+    fn since_file_window_reads_the_start_from_the_file() {
+        let path = std::env::temp_dir().join(format!("cw-window-{}.txt", std::process::id()));
+        std::fs::write(&path, "1700000000\n").unwrap();
+        let range = TimeWindow::SinceFile(path.clone())
+            .resolve(local(12, 10, 0))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(range.start, Some(1_700_000_000_000));
+        assert_eq!(range.end, None);
+        assert!(TimeWindow::SinceFile(path)
+            .resolve(local(12, 10, 0))
+            .is_err());
+    }
+
+    #[test]
+    fn epoch_values_are_seconds_or_milliseconds() {
+        assert_eq!(
+            parse_epoch_or_rfc3339("1700000000").unwrap(),
+            1_700_000_000_000
+        );
+        assert_eq!(
+            parse_epoch_or_rfc3339("1700000000123").unwrap(),
+            1_700_000_000_123
+        );
+        assert_eq!(
+            parse_epoch_or_rfc3339("2023-11-14T22:13:20+00:00").unwrap(),
+            1_700_000_000_000
+        );
+        assert!(parse_epoch_or_rfc3339("yesterday").is_err());
+    }
+
+    #[test]
+    fn resolve_window_lists_the_available_windows() {
+        let mut windows = BTreeMap::new();
+        windows.insert("recent".to_string(), config(None, None, Some("1h")));
+        windows.insert("broken".to_string(), config(Some("09:00"), None, None));
+        let now = local(12, 10, 0);
+
+        assert!(resolve_window(&windows, "recent", now).is_ok());
+        let err = resolve_window(&windows, "missing", now).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown window 'missing', available windows: broken, recent"
+        );
+        let err = resolve_window(&windows, "broken", now).unwrap_err();
+        assert_eq!(err.to_string(), "Can't resolve window 'broken'");
+
+        let err = resolve_window(&BTreeMap::new(), "recent", now).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("add them to the [windows] table of the config file"));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    ffi::OsString,
+    fmt::{Display, Write},
+    io::IsTerminal,
+};
+
+use yansi::Paint;
+
+use crate::progress::Progress;
+
+/// Decides what informational output goes to stderr. Errors are not printed through here, they
+/// always reach the user via main.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ui {
+    quiet: bool,
+}
+
+impl Ui {
+    pub fn new(quiet: bool) -> Self {
+        Self { quiet }
+    }
+
+    /// Prints an informational message, unless running with --quiet.
+    pub fn note(&self, message: impl Display) {
+        if let Some(line) = self.note_line(message) {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Prints a warning, unless running with --quiet.
+    pub fn warn(&self, message: impl Display) {
+        if let Some(line) = self.warning_line(message, supports_ansi(&std::io::stderr())) {
+            eprintln!("{}", line);
+        }
+    }
+
+    fn note_line(&self, message: impl Display) -> Option<String> {
+        (!self.quiet).then(|| message.to_string())
+    }
+
+    fn warning_line(&self, message: impl Display, use_color: bool) -> Option<String> {
+        if self.quiet {
+            return None;
+        }
+
+        Some(if use_color {
+            format!("{} {}", "Warning:".yellow(), message)
+        } else {
+            format!("Warning: {}", message)
+        })
+    }
+
+    /// A progress spinner, only shown on a terminal and when not running with --quiet.
+    pub fn progress(&self) -> Progress {
+        Progress::with_enabled(!self.quiet && supports_ansi(&std::io::stderr()))
+    }
+}
+
+/// Whether colors and other escape codes can be written to `stream`. On Windows this also turns
+/// on virtual terminal processing for the console, older consoles print the codes verbatim
+/// otherwise.
+pub fn supports_ansi(stream: &impl IsTerminal) -> bool {
+    color_allowed(stream.is_terminal(), std::env::var_os("NO_COLOR"))
+        && yansi::Condition::os_support()
+}
+
+/// Colors are only written to a terminal, and not when `NO_COLOR` is set to anything but an empty
+/// string (https://no-color.org).
+fn color_allowed(is_terminal: bool, no_color: Option<OsString>) -> bool {
+    is_terminal && no_color.is_none_or(|value| value.is_empty())
+}
+
+/// Renders `err` with every cause in its chain, skipping a cause that repeats the message before
+/// it, followed by a hint when the root cause is a well-known one.
+pub fn render_error(err: &eyre::Report, use_color: bool) -> String {
+    let mut messages: Vec<String> = Vec::new();
+    for cause in err.chain() {
+        let message = cause.to_string();
+        if messages.last() != Some(&message) {
+            messages.push(message);
+        }
+    }
+
+    let mut output = String::new();
+    let mut messages = messages.iter();
+    if let Some(message) = messages.next() {
+        let _ = writeln!(output, "Error: {}", message);
+    }
+
+    let causes = messages.collect::<Vec<_>>();
+    if !causes.is_empty() {
+        let _ = writeln!(output, "\nCaused by:");
+        for (i, cause) in causes.iter().enumerate() {
+            let _ = writeln!(output, "  {}: {}", i, cause.replace('\n', "\n     "));
+        }
+    }
+
+    if use_color {
+        output = format!("{}\n", output.trim_end().red());
+    }
+
+    if let Some(hint) = hint(err) {
+        let label = if use_color {
+            "Hint:".yellow().to_string()
+        } else {
+            "Hint:".to_string()
+        };
+        let _ = writeln!(output, "\n{} {}", label, hint);
+    }
+    output
+}
+
+fn hint(err: &eyre::Report) -> Option<&'static str> {
+    let root = err.root_cause().to_string().to_lowercase();
+
+    if root.contains("connection refused") {
+        Some("Nothing is listening at the endpoint, check your network and proxy settings (HTTPS_PROXY, NO_PROXY).")
+    } else if root.contains("expired") && root.contains("token") {
+        Some("Your AWS credentials have expired, run `aws sso login` or refresh them another way.")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use eyre::WrapErr;
+
+    use crate::ui::{color_allowed, render_error, Ui};
+
+    fn refused() -> eyre::Report {
+        Err::<(), _>(std::io::Error::new(
+            std::io::ErrorKind::ConnectionRefused,
+            "Connection refused",
+        ))
+        .wrap_err("dispatch failure")
+        .wrap_err("Failed describing log groups")
+        .unwrap_err()
+    }
+
+    #[test]
+    fn render_error_lists_the_causes_and_a_hint() {
+        assert_eq!(
+            render_error(&refused(), false),
+            concat!(
+                "Error: Failed describing log groups\n",
+                "\n",
+                "Caused by:\n",
+                "  0: dispatch failure\n",
+                "  1: Connection refused\n",
+                "\n",
+                "Hint: Nothing is listening at the endpoint, check your network and proxy settings ",
+                "(HTTPS_PROXY, NO_PROXY).\n",
+            )
+        );
+    }
+
+    #[test]
+    fn render_error_skips_a_cause_repeating_the_message_before_it() {
+        let err = Err::<(), _>(eyre::eyre!("Token has expired"))
+            .wrap_err("Token has expired")
+            .unwrap_err();
+
+        assert_eq!(
+            render_error(&err, false),
+            concat!(
+                "Error: Token has expired\n",
+                "\n",
+                "Hint: Your AWS credentials have expired, run `aws sso login` or refresh them ",
+                "another way.\n",
+            )
+        );
+    }
+
+    #[test]
+    fn render_error_colors_the_error_and_the_hint_label() {
+        assert_eq!(
+            render_error(&refused(), true),
+            concat!(
+                "\u{1b}[31mError: Failed describing log groups\n",
+                "\n",
+                "Caused by:\n",
+                "  0: dispatch failure\n",
+                "  1: Connection refused\u{1b}[0m\n",
+                "\n",
+                "\u{1b}[33mHint:\u{1b}[0m Nothing is listening at the endpoint, check your network ",
+                "and proxy settings (HTTPS_PROXY, NO_PROXY).\n",
+            )
+        );
+    }
+
+    #[test]
+    fn render_error_without_causes_or_hint_is_a_single_line() {
+        assert_eq!(
+            render_error(&eyre::eyre!("No log groups match"), false),
+            "Error: No log groups match\n"
+        );
+    }
+
+    #[test]
+    fn quiet_silences_notes_and_warnings() {
+        let quiet = Ui::new(true);
+
+        assert_eq!(quiet.note_line("Fetched 3 groups."), None);
+        assert_eq!(quiet.warning_line("slow", false), None);
+        assert_eq!(quiet.warning_line("slow", true), None);
+    }
+
+    #[test]
+    fn notes_and_warnings_are_printed_without_quiet() {
+        let ui = Ui::new(false);
+
+        assert_eq!(
+            ui.note_line("Fetched 3 groups.").as_deref(),
+            Some("Fetched 3 groups.")
+        );
+        assert_eq!(
+            ui.warning_line("slow", false).as_deref(),
+            Some("Warning: slow")
+        );
+        assert_eq!(
+            ui.warning_line("slow", true).as_deref(),
+            Some("\u{1b}[33mWarning:\u{1b}[0m slow")
+        );
+    }
+
+    #[test]
+    fn color_is_only_allowed_on_a_terminal_without_no_color() {
+        assert!(color_allowed(true, None));
+        assert!(color_allowed(true, Some(OsString::new())));
+        assert!(!color_allowed(true, Some(OsString::from("1"))));
+        assert!(!color_allowed(false, None));
+    }
+}
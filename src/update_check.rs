@@ -0,0 +1,238 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use aws_smithy_runtime_api::client::http::HttpConnector;
+use aws_smithy_runtime_api::http::Request;
+use aws_smithy_types::byte_stream::ByteStream;
+use chrono::Utc;
+use eyre::Context;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+use crate::aws::https_connector;
+use crate::build_info;
+use crate::config::{Config, ConfigManager, LocalConfigManager};
+
+// NOTE: Checking once a day is plenty for a CLI, and keeps the artifact server out of every run.
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// How long `cw --version` waits for the manifest, the check is what the user asked for there.
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+const CACHE_FILE: &str = "update-check.json";
+
+/// The manifest published at `update.check_url`, e.g.
+/// `{"version": "0.3.0", "url": "https://artifacts.example.com/cw/0.3.0"}`.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    version: String,
+    /// Where to get the release, the check URL itself when left out.
+    url: Option<String>,
+}
+
+/// The outcome of the last check, kept in the state directory.
+#[derive(Debug, Deserialize)]
+struct CachedCheck {
+    check_url: String,
+    /// When the last check ran, in milliseconds since the epoch, also when it failed.
+    checked_at: i64,
+    latest: Option<String>,
+    url: Option<String>,
+}
+
+/// Looks for newer cw releases in the manifest at a configured URL. Nothing is downloaded, the
+/// check only tells where a newer version is.
+#[derive(Debug, Clone)]
+pub struct UpdateCheck {
+    check_url: String,
+    cache_path: PathBuf,
+}
+
+impl UpdateCheck {
+    pub fn new(check_url: &str, state_dir: &Path) -> Self {
+        Self {
+            check_url: check_url.to_string(),
+            cache_path: state_dir.join(CACHE_FILE),
+        }
+    }
+
+    /// The check for `config`, none unless `update.check_url` is set.
+    pub fn from_config(config: &impl ConfigManager, settings: &Config) -> Option<Self> {
+        let check_url = settings.update.check_url.as_deref()?;
+        match config.get_state_path() {
+            Ok(state_dir) => Some(Self::new(check_url, &state_dir)),
+            Err(err) => {
+                tracing::debug!(target: "cw", "skipping the update check: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Whether the last check is more than a day old, or was made against another URL.
+    pub fn is_due(&self) -> bool {
+        let interval = i64::try_from(CHECK_INTERVAL.as_millis()).unwrap_or(i64::MAX);
+        self.cached()
+            .is_none_or(|cached| Utc::now().timestamp_millis() - cached.checked_at >= interval)
+    }
+
+    /// Refreshes the cached outcome in the background, see [`UpdateCheck::finish`].
+    pub fn spawn_refresh(&self) -> JoinHandle<()> {
+        let check = self.clone();
+        tokio::spawn(async move {
+            check.refresh().await;
+        })
+    }
+
+    /// Lets a refresh that is still running go, the next run tries again, and returns the notice
+    /// for the cached outcome.
+    pub fn finish(&self, refresh: JoinHandle<()>) -> Option<String> {
+        if !refresh.is_finished() {
+            tracing::debug!(target: "cw", "the update check didn't finish in time");
+            refresh.abort();
+        }
+        self.notice()
+    }
+
+    /// Fetches the manifest and caches the outcome. A failed fetch is cached too, so an
+    /// unreachable server is only asked once a day.
+    async fn refresh(&self) {
+        let previous = self.cached();
+        let (latest, url) = match self.fetch().await {
+            Ok(manifest) => (Some(manifest.version), manifest.url),
+            Err(err) => {
+                tracing::debug!(target: "cw", "update check against {} failed: {:?}", self.check_url, err);
+                previous
+                    .map(|cached| (cached.latest, cached.url))
+                    .unwrap_or_default()
+            }
+        };
+
+        let cached = json!({
+            "check_url": self.check_url,
+            "checked_at": Utc::now().timestamp_millis(),
+            "latest": latest,
+            "url": url,
+        });
+        if let Err(err) = std::fs::write(&self.cache_path, cached.to_string()) {
+            tracing::debug!(target: "cw", "failed caching the update check: {:?}", err);
+        }
+    }
+
+    async fn fetch(&self) -> eyre::Result<Manifest> {
+        let request = Request::get(&self.check_url)
+            .wrap_err_with(|| format!("Invalid update.check_url '{}'", self.check_url))?;
+        let response = https_connector()?.call(request).await?;
+        if !response.status().is_success() {
+            return Err(eyre::eyre!(
+                "{} responded with {}",
+                self.check_url,
+                response.status().as_u16()
+            ));
+        }
+
+        let body = ByteStream::new(response.into_body())
+            .collect()
+            .await?
+            .into_bytes();
+        serde_json::from_slice(&body)
+            .wrap_err_with(|| format!("Invalid update manifest at {}", self.check_url))
+    }
+
+    fn cached(&self) -> Option<CachedCheck> {
+        let contents = std::fs::read(&self.cache_path).ok()?;
+        serde_json::from_slice::<CachedCheck>(&contents)
+            .ok()
+            .filter(|cached| cached.check_url == self.check_url)
+    }
+
+    /// The line telling about a newer version, when the last check found one.
+    fn notice(&self) -> Option<String> {
+        let cached = self.cached()?;
+        let latest = cached.latest?;
+        if compare_versions(&latest, build_info::VERSION) != Ordering::Greater {
+            return None;
+        }
+        Some(format!(
+            "cw {} is available at {}, this is {}.",
+            latest,
+            cached.url.as_deref().unwrap_or(&self.check_url),
+            build_info::VERSION
+        ))
+    }
+}
+
+/// The notice `cw --version` prints, checking right away instead of waiting for the daily check.
+/// None when no check URL is configured, or when anything about the check fails.
+pub fn version_notice() -> Option<String> {
+    let config = LocalConfigManager::new();
+    let settings = Config::load(&config.get_config_path().ok()?).ok()?;
+    let check = UpdateCheck::from_config(&config, &settings)?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .ok()?;
+    runtime.block_on(async {
+        if tokio::time::timeout(VERSION_CHECK_TIMEOUT, check.refresh())
+            .await
+            .is_err()
+        {
+            tracing::debug!(target: "cw", "the update check timed out");
+        }
+    });
+    check.notice()
+}
+
+/// Compares dotted versions like `0.12.1` by their numbers, ignoring a leading `v` and anything
+/// after a `-` or `+`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .trim()
+            .trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::update_check::compare_versions;
+
+    #[test]
+    fn compare_versions_compares_numbers_not_text() {
+        assert_eq!(compare_versions("0.10.0", "0.9.1"), Ordering::Greater);
+        assert_eq!(compare_versions("0.9.1", "0.10.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.2.3", "1.2.3"), Ordering::Equal);
+    }
+
+    #[test]
+    fn compare_versions_fills_missing_parts_with_zero() {
+        assert_eq!(compare_versions("1.2", "1.2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2", "1.2.1"), Ordering::Less);
+    }
+
+    #[test]
+    fn compare_versions_ignores_the_prefix_and_suffixes() {
+        assert_eq!(compare_versions("v0.12.1", "0.12.1"), Ordering::Equal);
+        assert_eq!(compare_versions(" 0.12.1-rc.1 ", "0.12.1"), Ordering::Equal);
+        assert_eq!(
+            compare_versions("0.12.1+abc123", "0.12.0"),
+            Ordering::Greater
+        );
+    }
+}
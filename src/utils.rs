@@ -1,9 +1,51 @@
-use std::time::UNIX_EPOCH;
+use std::borrow::Cow;
+use std::time::{Duration, UNIX_EPOCH};
 
-use chrono::{DateTime, Local, SecondsFormat, Utc};
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+// NOTE: Logs Insights renders @timestamp and @ingestionTime as `2024-06-01 12:34:56.789` in UTC.
+const INSIGHTS_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// How far past now an end time may be, a clock that runs a little ahead shouldn't fail a query.
+const MAX_END_TIME_SKEW_MS: i64 = 60 * 1000;
+
+// NOTE: Without a start time tail and query look back a little, so the first request is more
+// likely to return something.
+const DEFAULT_LOOKBACK_SECS: i64 = 30;
+
+/// The start time when none is given, 30 seconds before `now`, or `now` itself without
+/// `lookback`.
+pub fn default_start_time(now: DateTime<Utc>, lookback: bool) -> i64 {
+    if lookback {
+        (now.timestamp() - DEFAULT_LOOKBACK_SECS) * 1000
+    } else {
+        now.timestamp_millis()
+    }
+}
+
+/// The shortest retention a log group can have, a start time more recent than this is within
+/// the retention of every group.
+pub const MIN_RETENTION_MS: i64 = 24 * 60 * 60 * 1000;
 
 pub fn parse_human_time(h_time: &str) -> eyre::Result<i64> {
-    if let Ok(duration) = humantime::parse_duration(h_time) {
+    let h_time = h_time.trim();
+    if h_time.eq_ignore_ascii_case("now") {
+        return Ok(Utc::now().timestamp_millis());
+    }
+
+    let future = h_time
+        .strip_prefix("in ")
+        .or_else(|| h_time.strip_prefix('+'))
+        .map(str::trim_start);
+
+    if let Some(future) = future {
+        let duration = humantime::parse_duration(future)?;
+        let future_time = Utc::now() + duration;
+
+        Ok(future_time.timestamp() * 1000)
+    } else if let Ok(duration) = humantime::parse_duration(h_time) {
         let now = Utc::now();
         let past_time = now - duration;
 
@@ -17,14 +59,761 @@ pub fn parse_human_time(h_time: &str) -> eyre::Result<i64> {
 }
 
 pub fn parse_timestamp(timestamp_ms: i64, to_local_time: bool) -> Option<String> {
-    if let Some(time) = DateTime::from_timestamp_millis(timestamp_ms) {
-        if to_local_time {
-            let local = time.with_timezone(&Local);
-            return Some(local.to_rfc3339_opts(SecondsFormat::Secs, true));
+    DateTime::from_timestamp_millis(timestamp_ms)
+        .map(|time| format_rfc3339(time, SecondsFormat::Secs, to_local_time))
+}
+
+/// RFC3339 with milliseconds, keeping the precision Insights and the audit log record.
+pub fn format_datetime(time: DateTime<Utc>, to_local_time: bool) -> String {
+    format_rfc3339(time, SecondsFormat::Millis, to_local_time)
+}
+
+/// RFC3339 ending in `Z` for UTC and in the numeric offset, e.g. `+02:00`, for local time.
+fn format_rfc3339(time: DateTime<Utc>, precision: SecondsFormat, to_local_time: bool) -> String {
+    if to_local_time {
+        return format_rfc3339_in(time, &Local, precision);
+    }
+
+    time.to_rfc3339_opts(precision, true)
+}
+
+/// RFC3339 in `zone`, always ending in its numeric offset.
+fn format_rfc3339_in<Tz: TimeZone>(
+    time: DateTime<Utc>,
+    zone: &Tz,
+    precision: SecondsFormat,
+) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    // NOTE: Chrono writes `Z` for any zero offset, which would make local time in e.g.
+    // London during winter look like UTC.
+    time.with_timezone(zone).to_rfc3339_opts(precision, false)
+}
+
+/// Presets for how timestamps are printed, see --time-style.
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeStyle {
+    /// RFC3339 with seconds, `2024-06-01T14:03:11Z` or `2024-06-01T16:03:11+02:00` in local time.
+    #[default]
+    Iso,
+    /// RFC3339 with milliseconds, `2024-06-01T14:03:11.123Z`.
+    IsoMillis,
+    /// Month, day and time, `06-01 14:03:11`.
+    Compact,
+    /// Seconds since the epoch.
+    Unix,
+    /// Time relative to `now`, `5m 3s ago`.
+    Relative,
+}
+
+/// The parts of the prefix written before a message in text output, see --prefix-order.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrefixField {
+    Timestamp,
+    Group,
+    Stream,
+    Id,
+}
+
+impl PrefixField {
+    /// Reads a comma separated list like `stream,timestamp`, as set in `tail.prefix_order`.
+    pub fn parse_list(value: &str) -> eyre::Result<Vec<Self>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                Self::from_str(part, false).map_err(|_| {
+                    eyre::eyre!(
+                        "Unknown prefix component '{}', expected timestamp, group, stream or id",
+                        part
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl TimeStyle {
+    pub fn render(self, time: DateTime<Utc>, to_local_time: bool, now: DateTime<Utc>) -> String {
+        match self {
+            Self::Iso => format_rfc3339(time, SecondsFormat::Secs, to_local_time),
+            Self::IsoMillis => format_rfc3339(time, SecondsFormat::Millis, to_local_time),
+            Self::Compact if to_local_time => format_compact(time.with_timezone(&Local)),
+            Self::Compact => format_compact(time),
+            Self::Unix => time.timestamp().to_string(),
+            Self::Relative => {
+                let seconds = (now - time).num_seconds();
+                let duration =
+                    humantime::format_duration(Duration::from_secs(seconds.unsigned_abs()));
+                if seconds < 0 {
+                    format!("in {}", duration)
+                } else {
+                    format!("{} ago", duration)
+                }
+            }
+        }
+    }
+
+    /// Renders an epoch milliseconds timestamp, see [`TimeStyle::render`].
+    pub fn render_millis(self, timestamp_ms: i64, to_local_time: bool) -> Option<String> {
+        DateTime::from_timestamp_millis(timestamp_ms)
+            .map(|time| self.render(time, to_local_time, Utc::now()))
+    }
+}
+
+fn format_compact<Tz: TimeZone>(time: DateTime<Tz>) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    time.format("%m-%d %H:%M:%S").to_string()
+}
+
+pub fn parse_insights_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value.trim(), INSIGHTS_TIMESTAMP_FORMAT)
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Replaces control characters other than newline and tab with `\x1b` style escapes, so a
+/// message can't ring the bell or move the cursor of the terminal it is printed to.
+pub fn escape_non_printable(value: &str) -> Cow<'_, str> {
+    let is_unsafe = |c: char| c.is_control() && c != '\n' && c != '\t';
+    if !value.chars().any(is_unsafe) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut escaped = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        if is_unsafe(c) {
+            escaped.push_str(&format!("\\x{:02x}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Cuts `s` down to `width` characters, ending in `…` when anything was cut.
+pub fn truncate_text(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+
+    let mut clean: String = s.chars().take(width.saturating_sub(1)).collect();
+    clean.push('…');
+    clean
+}
+
+/// Renders a duration for people, e.g. `842ms`, `3.007s`, `1m 12.4s` or `2h 5m 13s`. Negative
+/// durations, which only come from clock adjustments, render as `0ms`.
+pub fn format_duration_ms(duration_ms: i64) -> String {
+    let ms = duration_ms.max(0);
+    let with_fraction = |whole: i64, fraction: String| {
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            whole.to_string()
+        } else {
+            format!("{}.{}", whole, fraction)
+        }
+    };
+
+    match ms {
+        0..=999 => format!("{}ms", ms),
+        1_000..=59_999 => format!(
+            "{}s",
+            with_fraction(ms / 1_000, format!("{:03}", ms % 1_000))
+        ),
+        60_000..=3_599_999 => {
+            let seconds = ms % 60_000;
+            format!(
+                "{}m {}s",
+                ms / 60_000,
+                with_fraction(seconds / 1_000, ((seconds % 1_000) / 100).to_string())
+            )
+        }
+        _ => format!(
+            "{}h {}m {}s",
+            ms / 3_600_000,
+            (ms % 3_600_000) / 60_000,
+            (ms % 60_000) / 1_000
+        ),
+    }
+}
+
+/// Renders a byte count for people in binary units with one decimal, e.g. `512 B`, `1.0 KiB` or
+/// `11.9 GiB`.
+pub fn humanize_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // NOTE: Compare the rounded value, so 1048575 bytes becomes `1.0 MiB` instead of `1024.0 KiB`.
+    while (value * 10.0).round() >= 10240.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Renders `value` with a comma between every group of three digits, e.g. `1,240,000`. The
+/// separator doesn't depend on the locale, so the output reads the same everywhere.
+pub fn group_thousands(value: u64) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Reads `value` as a number when it looks like one, e.g. `42`, `-1.5` or `3e8`. NaN and
+/// infinity are not considered numbers.
+pub fn parse_number(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Outcome of comparing a requested start time against a log group's retention window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionCheck {
+    Within,
+    Predates { earliest: i64 },
+}
+
+impl RetentionCheck {
+    /// Returns the start time to use, moving it up to the earliest retained event when `clamp`
+    /// is set.
+    pub fn start_time(&self, start_time: i64, clamp: bool) -> i64 {
+        match self {
+            RetentionCheck::Predates { earliest } if clamp => *earliest,
+            _ => start_time,
+        }
+    }
+
+    /// The warning to show when the start time predates the retention of `groups`, e.g. a log
+    /// group name, `None` when it doesn't.
+    pub fn warning(&self, groups: &str, clamp: bool, local: bool) -> Option<String> {
+        let RetentionCheck::Predates { earliest } = self else {
+            return None;
+        };
+
+        let earliest_time = parse_timestamp(*earliest, local).unwrap_or_default();
+        Some(if clamp {
+            format!(
+                "start time predates the retention of {}, starting from {} instead.",
+                groups, earliest_time
+            )
+        } else {
+            format!(
+                "start time predates the retention of {}, the earliest available event is from {}. Use --clamp-to-retention to start from there.",
+                groups, earliest_time
+            )
+        })
+    }
+}
+
+pub fn check_retention(start_time: i64, retention_days: Option<i32>, now: i64) -> RetentionCheck {
+    let Some(days) = retention_days.filter(|d| *d > 0) else {
+        return RetentionCheck::Within;
+    };
+
+    let earliest = now.saturating_sub(i64::from(days) * 24 * 60 * 60 * 1000);
+    if start_time < earliest {
+        RetentionCheck::Predates { earliest }
+    } else {
+        RetentionCheck::Within
+    }
+}
+
+/// Like [`check_retention`] for several log groups read at once, only predating when the start
+/// time predates all of them. The earliest time is then the oldest one any group still has.
+pub fn check_retention_all(
+    start_time: i64,
+    retention_days: &[Option<i32>],
+    now: i64,
+) -> RetentionCheck {
+    let mut earliest = None;
+    for days in retention_days {
+        match check_retention(start_time, *days, now) {
+            RetentionCheck::Within => return RetentionCheck::Within,
+            RetentionCheck::Predates { earliest: group } => {
+                earliest = Some(earliest.map_or(group, |e: i64| e.min(group)));
+            }
+        }
+    }
+
+    earliest.map_or(RetentionCheck::Within, |earliest| {
+        RetentionCheck::Predates { earliest }
+    })
+}
+
+/// A start and end time in epoch milliseconds as asked for, either can be left out. Tail and
+/// query build one from their flags, fill the gaps from a `--window` or query file and resolve
+/// it once, so both default and validate the same way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+/// A [`TimeRange`] with its start time filled in. Without an end it runs up to now, or on
+/// while following.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedRange {
+    pub start: i64,
+    pub end: Option<i64>,
+}
+
+/// What a [`TimeRange`] is resolved and checked against.
+#[derive(Debug, Clone, Copy)]
+pub struct RangeRules {
+    pub now: DateTime<Utc>,
+    /// Whether the default start time looks back a little, see [`default_start_time`].
+    pub lookback: bool,
+    /// Following has no end, so an end time is an error.
+    pub follow: bool,
+    /// Whether an end time more than a minute past now is allowed.
+    pub future_end: bool,
+    /// Render times in errors in the local timezone.
+    pub local: bool,
+}
+
+impl TimeRange {
+    pub fn new(start: Option<i64>, end: Option<i64>) -> Self {
+        Self { start, end }
+    }
+
+    /// Takes the times this range leaves out from `fallback`, e.g. flags over a `--window`.
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            start: self.start.or(fallback.start),
+            end: self.end.or(fallback.end),
+        }
+    }
+
+    /// Fills in the default start time and checks the end comes after it.
+    pub fn resolve(self, rules: RangeRules) -> eyre::Result<ResolvedRange> {
+        let start = self
+            .start
+            .unwrap_or_else(|| default_start_time(rules.now, rules.lookback));
+
+        if let Some(end) = self.end {
+            if rules.follow {
+                return Err(eyre::eyre!(
+                    "You can not use --end-time together with --follow!"
+                ));
+            }
+            if end <= start {
+                return Err(eyre::eyre!("--end-time must be after the start time!"));
+            }
+            if !rules.future_end {
+                check_end_time(end, rules.now.timestamp_millis(), rules.local)?;
+            }
+        }
+
+        Ok(ResolvedRange {
+            start,
+            end: self.end,
+        })
+    }
+}
+
+impl ResolvedRange {
+    /// The end time, `now` for a range without one.
+    pub fn end_or(&self, now: i64) -> i64 {
+        self.end.unwrap_or(now)
+    }
+}
+
+/// Fails when `end_time` is more than a minute past `now`, which is more often a
+/// start and end time mixed up than a query meant to wait for events.
+fn check_end_time(end_time: i64, now: i64, local: bool) -> eyre::Result<()> {
+    if end_time > now.saturating_add(MAX_END_TIME_SKEW_MS) {
+        return Err(eyre::eyre!(
+            "end time {} is in the future, did you mix up --start-time and --end-time?",
+            parse_timestamp(end_time, local).unwrap_or_else(|| end_time.to_string())
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, FixedOffset, Local, SecondsFormat, TimeZone, Utc};
+
+    use crate::utils::{
+        check_retention, check_retention_all, default_start_time, format_datetime,
+        format_duration_ms, format_rfc3339_in, group_thousands, humanize_bytes, parse_human_time,
+        parse_timestamp, RangeRules, ResolvedRange, RetentionCheck, TimeRange, TimeStyle,
+    };
+
+    fn at(seconds: i64, millis: u32) -> DateTime<Utc> {
+        Utc.timestamp_opt(seconds, millis * 1_000_000).unwrap()
+    }
+
+    #[test]
+    fn format_duration_ms_picks_the_unit() {
+        assert_eq!(format_duration_ms(0), "0ms");
+        assert_eq!(format_duration_ms(842), "842ms");
+        assert_eq!(format_duration_ms(1_000), "1s");
+        assert_eq!(format_duration_ms(3_007), "3.007s");
+        assert_eq!(format_duration_ms(3_500), "3.5s");
+        assert_eq!(format_duration_ms(59_999), "59.999s");
+        assert_eq!(format_duration_ms(60_000), "1m 0s");
+        assert_eq!(format_duration_ms(72_450), "1m 12.4s");
+        assert_eq!(format_duration_ms(3_599_999), "59m 59.9s");
+        assert_eq!(format_duration_ms(3_600_000), "1h 0m 0s");
+        assert_eq!(format_duration_ms(7_513_999), "2h 5m 13s");
+    }
+
+    #[test]
+    fn format_duration_ms_renders_negative_durations_as_zero() {
+        assert_eq!(format_duration_ms(-250), "0ms");
+    }
+
+    #[test]
+    fn time_style_renders_utc() {
+        let time = at(1_717_250_591, 123);
+        let render = |style: TimeStyle| style.render(time, false, time);
+
+        assert_eq!(render(TimeStyle::Iso), "2024-06-01T14:03:11Z");
+        assert_eq!(render(TimeStyle::IsoMillis), "2024-06-01T14:03:11.123Z");
+        assert_eq!(render(TimeStyle::Compact), "06-01 14:03:11");
+        assert_eq!(render(TimeStyle::Unix), "1717250591");
+    }
+
+    #[test]
+    fn time_style_renders_local_time_with_its_offset() {
+        let time = at(1_717_250_591, 123);
+        let local = time.with_timezone(&Local);
+        let render = |style: TimeStyle| style.render(time, true, time);
+
+        assert_eq!(
+            render(TimeStyle::Iso),
+            local.format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+        );
+        assert_eq!(
+            render(TimeStyle::IsoMillis),
+            local.format("%Y-%m-%dT%H:%M:%S%.3f%:z").to_string()
+        );
+        assert_eq!(
+            render(TimeStyle::Compact),
+            local.format("%m-%d %H:%M:%S").to_string()
+        );
+        assert_eq!(render(TimeStyle::Unix), "1717250591");
+    }
+
+    #[test]
+    fn rfc3339_renderers_end_in_z_for_utc() {
+        let time = at(1_717_250_591, 123);
+
+        assert_eq!(
+            parse_timestamp(1_717_250_591_123, false).as_deref(),
+            Some("2024-06-01T14:03:11Z")
+        );
+        assert_eq!(format_datetime(time, false), "2024-06-01T14:03:11.123Z");
+        assert_eq!(
+            TimeStyle::Iso.render(time, false, time),
+            "2024-06-01T14:03:11Z"
+        );
+        assert_eq!(
+            TimeStyle::IsoMillis.render(time, false, time),
+            "2024-06-01T14:03:11.123Z"
+        );
+    }
+
+    #[test]
+    fn rfc3339_in_a_zone_ends_in_its_offset() {
+        let time = at(1_717_250_591, 123);
+        let zone = |seconds: i32| FixedOffset::east_opt(seconds).unwrap();
+
+        assert_eq!(
+            format_rfc3339_in(time, &zone(2 * 3600), SecondsFormat::Secs),
+            "2024-06-01T16:03:11+02:00"
+        );
+        assert_eq!(
+            format_rfc3339_in(time, &zone(-(3 * 3600 + 1800)), SecondsFormat::Millis),
+            "2024-06-01T10:33:11.123-03:30"
+        );
+        // NOTE: A zone that happens to sit at UTC still isn't UTC.
+        assert_eq!(
+            format_rfc3339_in(time, &zone(0), SecondsFormat::Secs),
+            "2024-06-01T14:03:11+00:00"
+        );
+    }
+
+    #[test]
+    fn time_style_renders_relative_to_now() {
+        let now = at(1_717_250_591, 0);
+        let render = |time| TimeStyle::Relative.render(time, false, now);
+
+        assert_eq!(render(at(1_717_250_591 - 303, 0)), "5m 3s ago");
+        assert_eq!(render(at(1_717_250_591 + 90, 0)), "in 1m 30s");
+        assert_eq!(render(now), "0s ago");
+    }
+
+    #[test]
+    fn time_style_renders_epoch_millis() {
+        assert_eq!(
+            TimeStyle::IsoMillis.render_millis(0, false).as_deref(),
+            Some("1970-01-01T00:00:00.000Z")
+        );
+        assert_eq!(
+            TimeStyle::Unix
+                .render_millis(1_717_250_591_999, false)
+                .as_deref(),
+            Some("1717250591")
+        );
+        assert_eq!(TimeStyle::Iso.render_millis(i64::MAX, false), None);
+    }
+
+    fn rules(now: DateTime<Utc>) -> RangeRules {
+        RangeRules {
+            now,
+            lookback: true,
+            follow: false,
+            future_end: false,
+            local: false,
+        }
+    }
+
+    #[test]
+    fn time_range_without_start_looks_back_from_now() {
+        let now = at(1_717_250_591, 500);
+
+        assert_eq!(
+            TimeRange::default().resolve(rules(now)).unwrap(),
+            ResolvedRange {
+                start: 1_717_250_561_000,
+                end: None
+            }
+        );
+        assert_eq!(
+            TimeRange::default()
+                .resolve(RangeRules {
+                    lookback: false,
+                    ..rules(now)
+                })
+                .unwrap()
+                .start,
+            1_717_250_591_500
+        );
+    }
+
+    #[test]
+    fn time_range_takes_the_missing_times_from_the_fallback() {
+        let range = TimeRange::new(Some(1), None).or(TimeRange::new(Some(2), Some(3)));
+
+        assert_eq!(range, TimeRange::new(Some(1), Some(3)));
+    }
+
+    #[test]
+    fn time_range_parsed_from_human_times_up_to_now() {
+        let range = TimeRange::new(
+            Some(parse_human_time("5m").unwrap()),
+            Some(parse_human_time("now").unwrap()),
+        );
+
+        let resolved = range.resolve(rules(Utc::now())).unwrap();
+
+        // NOTE: A relative time is cut to the second, now keeps its milliseconds.
+        let length = resolved.end.unwrap() - resolved.start;
+        assert!((300_000..=301_000).contains(&length), "{resolved:?}");
+    }
+
+    #[test]
+    fn time_range_rejects_an_end_at_or_before_the_start() {
+        let now = at(1_717_250_591, 0);
+        let start = 1_717_250_000_000;
+
+        for end in [start, start - 1] {
+            let err = TimeRange::new(Some(start), Some(end))
+                .resolve(rules(now))
+                .unwrap_err();
+            assert_eq!(err.to_string(), "--end-time must be after the start time!");
         }
+    }
+
+    #[test]
+    fn time_range_rejects_an_end_while_following() {
+        let err = TimeRange::new(None, Some(1_717_250_591_000))
+            .resolve(RangeRules {
+                follow: true,
+                ..rules(at(1_717_250_591, 0))
+            })
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "You can not use --end-time together with --follow!"
+        );
+    }
+
+    #[test]
+    fn time_range_allows_an_end_up_to_a_minute_past_now() {
+        let now = at(1_717_250_591, 0);
+        let range = |end_ms| TimeRange::new(Some(0), Some(now.timestamp_millis() + end_ms));
 
-        return Some(time.to_rfc3339_opts(SecondsFormat::Secs, true));
+        assert!(range(60_000).resolve(rules(now)).is_ok());
+        assert_eq!(
+            range(60_001).resolve(rules(now)).unwrap_err().to_string(),
+            "end time 2024-06-01T14:04:11Z is in the future, did you mix up --start-time and \
+             --end-time?"
+        );
+        assert!(range(3_600_000)
+            .resolve(RangeRules {
+                future_end: true,
+                ..rules(now)
+            })
+            .is_ok());
     }
 
-    None
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+    #[test]
+    fn check_retention_predates_only_before_the_earliest_retained_event() {
+        let now = 100 * DAY_MS;
+
+        assert_eq!(
+            check_retention(now - 7 * DAY_MS, Some(7), now),
+            RetentionCheck::Within
+        );
+        assert_eq!(
+            check_retention(now - 7 * DAY_MS - 1, Some(7), now),
+            RetentionCheck::Predates {
+                earliest: now - 7 * DAY_MS
+            }
+        );
+        assert_eq!(check_retention(0, None, now), RetentionCheck::Within);
+        assert_eq!(check_retention(0, Some(0), now), RetentionCheck::Within);
+    }
+
+    #[test]
+    fn check_retention_all_predates_only_when_every_group_does() {
+        let now = 100 * DAY_MS;
+        let start = now - 40 * DAY_MS;
+
+        assert_eq!(
+            check_retention_all(start, &[Some(7), Some(30)], now),
+            RetentionCheck::Predates {
+                earliest: now - 30 * DAY_MS
+            }
+        );
+        assert_eq!(
+            check_retention_all(start, &[Some(7), Some(60)], now),
+            RetentionCheck::Within
+        );
+        assert_eq!(
+            check_retention_all(start, &[Some(7), None], now),
+            RetentionCheck::Within
+        );
+        assert_eq!(check_retention_all(start, &[], now), RetentionCheck::Within);
+    }
+
+    #[test]
+    fn a_predating_start_is_only_clamped_when_asked() {
+        let check = RetentionCheck::Predates { earliest: 1_000 };
+
+        assert_eq!(check.start_time(10, true), 1_000);
+        assert_eq!(check.start_time(10, false), 10);
+        assert_eq!(RetentionCheck::Within.start_time(10, true), 10);
+    }
+
+    #[test]
+    fn the_retention_warning_says_what_happens_to_the_start_time() {
+        let check = RetentionCheck::Predates {
+            earliest: 1_717_250_591_000,
+        };
+
+        assert_eq!(
+            check.warning("/ecs/api", false, false).unwrap(),
+            "start time predates the retention of /ecs/api, the earliest available event is from \
+             2024-06-01T14:03:11Z. Use --clamp-to-retention to start from there."
+        );
+        assert_eq!(
+            check.warning("/ecs/api", true, false).unwrap(),
+            "start time predates the retention of /ecs/api, starting from 2024-06-01T14:03:11Z \
+             instead."
+        );
+        assert_eq!(
+            RetentionCheck::Within.warning("/ecs/api", true, false),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_human_time_takes_now_as_the_current_time() {
+        for now in ["now", "NOW", " Now "] {
+            let before = Utc::now().timestamp_millis();
+            let parsed = parse_human_time(now).unwrap();
+            let after = Utc::now().timestamp_millis();
+
+            assert!((before..=after).contains(&parsed), "{}", now);
+        }
+    }
+
+    #[test]
+    fn parse_human_time_takes_durations_back_and_forward_from_now() {
+        let in_seconds = |input: &str| {
+            let now = Utc::now().timestamp();
+            parse_human_time(input).unwrap() / 1000 - now
+        };
+
+        // NOTE: Relative times are truncated to the second, allow for one ticking over.
+        assert!((-300..=-299).contains(&in_seconds("5m")));
+        assert!((-5400..=-5399).contains(&in_seconds("1h 30m")));
+        assert!((600..=601).contains(&in_seconds("in 10m")));
+        assert!((600..=601).contains(&in_seconds("+10m")));
+        assert!((600..=601).contains(&in_seconds("+ 10m")));
+    }
+
+    #[test]
+    fn parse_human_time_takes_dates_and_times() {
+        assert_eq!(
+            parse_human_time("2024-06-01T14:03:11Z").unwrap(),
+            1_717_250_591_000
+        );
+        assert_eq!(
+            parse_human_time("2024-06-01 14:03:11.123").unwrap(),
+            1_717_250_591_123
+        );
+        assert!(parse_human_time("yesterday-ish").is_err());
+        assert!(parse_human_time("").is_err());
+    }
+
+    #[test]
+    fn default_start_time_looks_back_30_seconds_unless_told_not_to() {
+        let now = at(1_717_250_591, 456);
+
+        assert_eq!(default_start_time(now, true), 1_717_250_561_000);
+        assert_eq!(default_start_time(now, false), 1_717_250_591_456);
+    }
+
+    #[test]
+    fn humanize_bytes_switches_units_at_1024() {
+        assert_eq!(humanize_bytes(0), "0 B");
+        assert_eq!(humanize_bytes(1023), "1023 B");
+        assert_eq!(humanize_bytes(1024), "1.0 KiB");
+        assert_eq!(humanize_bytes(1_048_575), "1.0 MiB");
+        assert_eq!(humanize_bytes(1_610_612_736), "1.5 GiB");
+        assert_eq!(humanize_bytes(u64::MAX), "16.0 EiB");
+    }
+
+    #[test]
+    fn group_thousands_puts_a_comma_every_three_digits() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1023), "1,023");
+        assert_eq!(group_thousands(1024), "1,024");
+        assert_eq!(group_thousands(1_240_000), "1,240,000");
+        assert_eq!(group_thousands(u64::MAX), "18,446,744,073,709,551,615");
+    }
 }
@@ -0,0 +1,128 @@
+//! The library the way another crate uses it, against the in-memory client.
+
+use std::time::Duration;
+
+use aws_sdk_cloudwatchlogs::operation::filter_log_events::{
+    FilterLogEventsError, FilterLogEventsOutput,
+};
+use aws_sdk_cloudwatchlogs::operation::get_query_results::GetQueryResultsOutput;
+use aws_sdk_cloudwatchlogs::operation::start_query::StartQueryOutput;
+use aws_sdk_cloudwatchlogs::types::{FilteredLogEvent, QueryStatus, ResultField};
+use cw::fake_logs::{service_error, FakeLogs};
+use cw::logs_api::StartQueryRequest;
+use cw::query_runner::QueryRunner;
+use cw::session::TailSession;
+use futures_util::StreamExt;
+
+fn page(events: &[(&str, i64, &str)], next_token: Option<&str>) -> FilterLogEventsOutput {
+    FilterLogEventsOutput::builder()
+        .set_events(Some(
+            events
+                .iter()
+                .map(|(stream, timestamp, message)| {
+                    FilteredLogEvent::builder()
+                        .log_stream_name(*stream)
+                        .timestamp(*timestamp)
+                        .message(*message)
+                        .build()
+                })
+                .collect(),
+        ))
+        .set_next_token(next_token.map(str::to_string))
+        .build()
+}
+
+#[tokio::test]
+async fn a_tail_session_reads_every_page_of_a_group() {
+    let logs = FakeLogs::new();
+    logs.push_filter_log_events(Ok(page(
+        &[("web-1", 1_000, "first"), ("web-2", 2_000, "second")],
+        Some("t1"),
+    )))
+    .push_filter_log_events(Ok(page(&[("web-1", 3_000, "third")], None)));
+
+    let events = TailSession::new(logs.clone())
+        .group("/ecs/api")
+        .filter("ERROR")
+        .start_time(0)
+        .end_time(10_000)
+        .stream();
+    let events: Vec<_> = events.collect().await;
+
+    let messages: Vec<_> = events
+        .into_iter()
+        .map(|event| event.unwrap())
+        .map(|event| (event.group_name, event.message.unwrap()))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            ("/ecs/api".to_string(), "first".to_string()),
+            ("/ecs/api".to_string(), "second".to_string()),
+            ("/ecs/api".to_string(), "third".to_string()),
+        ]
+    );
+    let requests = logs.filter_log_events_requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].filter_pattern.as_deref(), Some("ERROR"));
+    assert_eq!(requests[1].next_token.as_deref(), Some("t1"));
+}
+
+#[tokio::test]
+async fn a_tail_session_ends_with_the_error_of_a_failed_group() {
+    let logs = FakeLogs::new();
+    logs.push_filter_log_events(Err(service_error::<FilterLogEventsError>(
+        "ResourceNotFoundException",
+        "The specified log group does not exist.",
+    )));
+
+    let events: Vec<_> = TailSession::new(logs)
+        .group("/ecs/missing")
+        .start_time(0)
+        .end_time(10_000)
+        .stream()
+        .collect()
+        .await;
+
+    assert_eq!(events.len(), 1);
+    let err = events[0].as_ref().unwrap_err();
+    assert!(format!("{:#}", err).contains("/ecs/missing"), "{:#}", err);
+}
+
+#[tokio::test]
+async fn a_query_runner_waits_for_the_rows() {
+    let logs = FakeLogs::new();
+    logs.push_start_query(Ok(StartQueryOutput::builder().query_id("q-1").build()))
+        .push_get_query_results(Ok(GetQueryResultsOutput::builder()
+            .status(QueryStatus::Running)
+            .build()))
+        .push_get_query_results(Ok(GetQueryResultsOutput::builder()
+            .status(QueryStatus::Complete)
+            .results(vec![
+                ResultField::builder()
+                    .field("@message")
+                    .value("hello")
+                    .build(),
+                ResultField::builder().field("@ptr").value("abc").build(),
+            ])
+            .build()));
+    let runner = QueryRunner::new(logs.clone()).poll_interval(Duration::from_millis(1));
+
+    let query_id = runner
+        .start(StartQueryRequest {
+            group_names: vec!["/ecs/api".to_string()],
+            query: "fields @message".to_string(),
+            start_time: 0,
+            end_time: 10_000,
+            limit: None,
+        })
+        .await
+        .unwrap();
+    let rows = runner.wait(&query_id).await.unwrap();
+
+    assert_eq!(query_id, "q-1");
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get("@message").unwrap(), "hello");
+    assert!(rows[0].get("@ptr").is_none());
+    assert_eq!(logs.get_query_results_requests(), ["q-1", "q-1"]);
+}